@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Parsing speed of the synthetic payloads from `telemetry_parser::bench_data`.
+// Run with `cargo bench --features bench-data`, and compare against a baseline with `--save-baseline <name>` / `--baseline <name>`
+
+use std::io::Cursor;
+use std::sync::{ Arc, atomic::AtomicBool };
+use criterion::{ criterion_group, criterion_main, Criterion, Throughput, black_box };
+use telemetry_parser::{ bench_data, Input };
+
+fn gpmf(c: &mut Criterion) {
+    let payloads = bench_data::gpmf_stream(60, 200);
+    let mut group = c.benchmark_group("gpmf");
+    group.throughput(Throughput::Bytes(payloads.iter().map(|x| x.len() as u64).sum()));
+    group.bench_function("60 s, 200 Hz", |b| b.iter(|| {
+        for p in &payloads {
+            black_box(bench_data::parse_gpmf(p).unwrap());
+        }
+    }));
+    group.finish();
+}
+
+fn rtmd(c: &mut Criterion) {
+    let samples = bench_data::rtmd_samples(1500, 40);
+    let mut group = c.benchmark_group("rtmd");
+    group.throughput(Throughput::Bytes(samples.iter().map(|x| x.len() as u64).sum()));
+    group.bench_function("1500 frames, 1 kHz", |b| b.iter(|| {
+        for s in &samples {
+            black_box(bench_data::parse_rtmd(s).unwrap());
+        }
+    }));
+    group.finish();
+}
+
+fn camm(c: &mut Criterion) {
+    let file = bench_data::camm_file(60_000);
+    let mut group = c.benchmark_group("camm");
+    group.throughput(Throughput::Bytes(file.len() as u64));
+    group.bench_function("60 s, 1 kHz", |b| b.iter(|| {
+        let mut stream = Cursor::new(&file);
+        black_box(Input::from_stream(&mut stream, file.len(), "bench.mp4", |_| (), Arc::new(AtomicBool::new(false))).unwrap());
+    }));
+    group.finish();
+}
+
+fn bbl(c: &mut Criterion) {
+    let log = bench_data::bbl_log(120_000);
+    let mut group = c.benchmark_group("bbl");
+    group.throughput(Throughput::Bytes(log.len() as u64));
+    group.bench_function("60 s, 2 kHz", |b| b.iter(|| {
+        black_box(bench_data::parse_bbl(&log).unwrap());
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, gpmf, rtmd, camm, bbl);
+criterion_main!(benches);
@@ -15,7 +15,7 @@ Extract gyro data from Sony, GoPro and Insta360 cameras to betaflight blackbox c
 */
 #[derive(FromArgs)]
 struct Opts {
-    /// input file
+    /// input file, `-` to read from stdin (eg. `cat log.gcsv | gyro2bb -`), output files are then named `stdin.*`
     #[argh(positional)]
     input: String,
 
@@ -26,16 +26,37 @@ struct Opts {
     /// IMU orientation (XYZ, ZXY etc, lowercase is negative, eg. xZy)
     #[argh(option)]
     imuo: Option<String>,
+
+    /// export all time series to a file instead of blackbox csv: `jsonl` (JSON lines) or `influx` (InfluxDB line protocol)
+    #[argh(option)]
+    export: Option<String>,
+
+    /// write a binary blackbox log (.bbl) instead of csv
+    #[argh(switch)]
+    bbl: bool,
+
+    /// parse the input as this format regardless of the extension and detection, eg. `WitMotion` or `Camm`
+    #[argh(option)]
+    format: Option<String>,
 }
 
 fn main() {
-    let opts: Opts = argh::from_env();
+    let mut opts: Opts = argh::from_env();
     let _time = Instant::now();
 
-    let mut stream = std::fs::File::open(&opts.input).unwrap();
-    let filesize = stream.metadata().unwrap().len() as usize;
+    let mut options = InputOptions::default();
+    if let Some(format) = &opts.format {
+        options = options.with_forced_format(format);
+    }
+    let input = if opts.input == "-" {
+        opts.input = "stdin".into();
+        Input::from_reader(std::io::stdin().lock(), filesystem::spool::DEFAULT_MEMORY_LIMIT, &opts.input, |_|(), Arc::new(AtomicBool::new(false)), options).unwrap()
+    } else {
+        let mut stream = std::fs::File::open(&opts.input).unwrap();
+        let filesize = stream.metadata().unwrap().len() as usize;
 
-    let input = Input::from_stream(&mut stream, filesize, &opts.input, |_|(), Arc::new(AtomicBool::new(false))).unwrap();
+        Input::from_stream_with_options(&mut stream, filesize, &opts.input, |_|(), Arc::new(AtomicBool::new(false)), options).unwrap()
+    };
 
     let mut i = 0;
     println!("Detected camera: {} {}", input.camera_type(), input.camera_model().unwrap_or(&"".into()));
@@ -49,12 +70,32 @@ fn main() {
 
             for (group, map) in grouped_tag_map {
                 for (tagid, taginfo) in map {
-                    println!("{: <25} {: <25} {: <50}: {}", format!("{}", group), format!("{}", tagid), taginfo.description, &taginfo.value.to_string());
+                    println!("{: <25} {: <25} {: <50}: {}", format!("{}", group), format!("{}", tagid), taginfo.description, taginfo.format().unwrap_or_else(|e| format!("<{e}>")));
                 }
             }
         }
     }
 
+    if let Some(format) = opts.export.as_deref() {
+        let (format, ext) = match format {
+            "influx" => (export::ExportFormat::InfluxLineProtocol, "lp"),
+            _        => (export::ExportFormat::JsonLines, "jsonl"),
+        };
+        let path = format!("{}.{ext}", std::path::Path::new(&opts.input).to_path_buf().to_string_lossy());
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        let count = export::export_time_series(&input, &export::ExportOptions { format, ..Default::default() }, &mut file).unwrap();
+        println!("Exported {count} records to {path} in {:.3} ms", _time.elapsed().as_micros() as f64 / 1000.0);
+        return;
+    }
+
+    if opts.bbl {
+        let path = format!("{}.bbl", std::path::Path::new(&opts.input).to_path_buf().to_string_lossy());
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        let count = export::bbl::write_bbl(&input, opts.imuo, &mut file).unwrap();
+        println!("Written {count} frames to {path} in {:.3} ms", _time.elapsed().as_micros() as f64 / 1000.0);
+        return;
+    }
+
     let imu_data = util::normalized_imu(&input, opts.imuo).unwrap();
 
     let mut csv = String::with_capacity(2*1024*1024);
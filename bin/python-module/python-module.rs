@@ -2,7 +2,6 @@
 // Copyright © 2021 Adrian <adrian.eddy at gmail>
 
 use pyo3::prelude::*;
-use std::collections::BTreeMap;
 use pythonize::pythonize;
 use std::sync::{ Arc, atomic::AtomicBool };
 
@@ -33,32 +32,14 @@ impl Parser {
         })
     }
 
-    fn telemetry(&self, human_readable: Option<bool>) -> PyResult<Py<PyAny>> {
+    fn telemetry(&self, human_readable: Option<bool>, columnar: Option<bool>) -> PyResult<Py<PyAny>> {
         if self.input.samples.is_none() { return Err(pyo3::exceptions::PyValueError::new_err("No metadata")); }
 
-        let samples = self.input.samples.as_ref().unwrap();
-        let mut output = Vec::with_capacity(samples.len());
-
-        for info in samples {
-            if info.tag_map.is_none() { continue; }
-
-            let mut groups = BTreeMap::new();
-            let groups_map = info.tag_map.as_ref().unwrap();
-
-            for (group, map) in groups_map {
-                let group_map = groups.entry(group).or_insert_with(BTreeMap::new);
-                for (tagid, info) in map {
-                    let value = if human_readable.unwrap_or(false) {
-                        serde_json::to_value(info.value.to_string())
-                    } else {
-                        serde_json::to_value(info.value.clone())
-                    }.unwrap();
-                    group_map.insert(tagid, value);
-                }
-            }
-
-            output.push(groups);
-        }
+        let output = export::telemetry_json(&self.input, &export::TelemetryJsonOptions {
+            human_readable: human_readable.unwrap_or(false),
+            columnar: columnar.unwrap_or(false),
+            ..Default::default()
+        });
 
         Python::with_gil(|py| {
             Ok(pythonize(py, &output)?)
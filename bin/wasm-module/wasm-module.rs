@@ -5,7 +5,6 @@
 
 use wasm_bindgen::prelude::*;
 use std::sync::{ Arc, atomic::AtomicBool };
-use std::collections::BTreeMap;
 
 use telemetry_parser::*;
 
@@ -31,34 +30,19 @@ impl Parser {
         })
     }
 
-    pub fn telemetry(&self, human_readable: Option<bool>) -> Result<JsValue, JsValue> {
+    /// All tags as a versioned object, see `export::TELEMETRY_SCHEMA_CHANGELOG`.
+    /// With `columnar`, time series are returned as arrays per field (`{t: [], x: [], ...}`), which is much faster to use from JS
+    pub fn telemetry(&self, human_readable: Option<bool>, columnar: Option<bool>) -> Result<JsValue, JsValue> {
         if self.input.samples.is_none() { return Err(JsValue::from("No metadata")); }
 
-        let samples = self.input.samples.as_ref().unwrap();
-        let mut output = Vec::with_capacity(samples.len());
+        let output = export::telemetry_json(&self.input, &export::TelemetryJsonOptions {
+            human_readable: human_readable.unwrap_or(false),
+            columnar: columnar.unwrap_or(false),
+            ..Default::default()
+        });
 
-        for info in samples {
-            if info.tag_map.is_none() { continue; }
-
-            let mut groups = BTreeMap::new();
-            let groups_map = info.tag_map.as_ref().unwrap();
-
-            for (group, map) in groups_map {
-                let group_map = groups.entry(group).or_insert_with(BTreeMap::new);
-                for (tagid, info) in map {
-                    let value = if human_readable.unwrap_or(false) {
-                        serde_json::to_value(info.value.to_string())
-                    } else {
-                        serde_json::to_value(info.value.clone())
-                    }.unwrap();
-                    group_map.insert(tagid, value);
-                }
-            }
-
-            output.push(groups);
-        }
-
-        Ok(serde_wasm_bindgen::to_value(&output).unwrap())
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        serde::Serialize::serialize(&output, &serializer).map_err(|e| JsValue::from(e.to_string()))
     }
 
     pub fn normalized_imu(&self, orientation: Option<String>) -> Result<JsValue, JsValue> {
@@ -0,0 +1,364 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+//! Data model of the telemetry-parser tags: ids of the groups and tags, and the types of the values, without the parsers.
+//! It only needs `alloc`, so the firmware of the recorders (EspLog, SenseFlow etc.) can use the same definitions to write compatible logs.
+//! The `serde` feature implements `Serialize` for all types, with the same output as telemetry-parser
+
+#![no_std]
+extern crate alloc;
+
+use alloc::{ borrow::ToOwned, boxed::Box, format, string::{ String, ToString } };
+
+macro_rules! declare_groups {
+    ($($field:ident),*,) => {
+        #[allow(dead_code)]
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+        pub enum GroupId {
+            $($field,)*
+            UnknownGroup(u32),
+            Custom(String),
+            Any // For filtering, shouldn't be used directly
+        }
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for GroupId {
+            fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+                match self {
+                    GroupId::UnknownGroup(x) => s.serialize_str(&format!("0x{:x}", x)),
+                    GroupId::Custom(x)       => s.serialize_str(x),
+                    GroupId::Any             => s.serialize_str("*"),
+                    $(GroupId::$field        => s.serialize_str(stringify!($field)),)*
+                }
+            }
+        }
+        impl core::fmt::Display for GroupId {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    GroupId::UnknownGroup(x) => f.write_str(&format!("0x{:x}", x)),
+                    GroupId::Custom(x)       => f.write_str(x),
+                    GroupId::Any             => f.write_str("*"),
+                    $(GroupId::$field        => f.write_str(stringify!($field)),)*
+                }
+            }
+        }
+        impl core::str::FromStr for GroupId {
+            type Err = core::num::ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(match s {
+                    $(stringify!($field) => GroupId::$field,)*
+                    "*" => GroupId::Any,
+                    _ if s.starts_with("0x") => GroupId::UnknownGroup(u32::from_str_radix(&s[2..], 16)?),
+                    _ => GroupId::Custom(s.to_string())
+                })
+            }
+        }
+    }
+}
+
+macro_rules! declare_ids {
+    ($($field:ident),*,) => {
+        #[allow(dead_code)]
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+        pub enum TagId {
+            $($field,)*
+            Unknown(u32),
+            File(String),
+            Custom(String),
+            Namespaced(String, Box<TagId>), // Tag id prefixed with the source (eg. format name) as `namespace::id`, to avoid collisions
+            Any // For filtering, shouldn't be used directly
+        }
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for TagId {
+            fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+                match self {
+                    TagId::Unknown(x)      => s.serialize_str(&format!("0x{:x}", x)),
+                    TagId::Custom(x)       => s.serialize_str(x),
+                    TagId::File(x)         => s.serialize_str(x),
+                    TagId::Namespaced(n, x) => s.serialize_str(&format!("{}::{}", n, x)),
+                    TagId::Any             => s.serialize_str("*"),
+                    $(TagId::$field        => s.serialize_str(stringify!($field)),)*
+                }
+            }
+        }
+        impl core::fmt::Display for TagId {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    TagId::Unknown(x)      => f.write_str(&format!("0x{:x}", x)),
+                    TagId::Custom(x)       => f.write_str(x),
+                    TagId::File(x)         => f.write_str(x),
+                    TagId::Namespaced(n, x) => write!(f, "{}::{}", n, x),
+                    TagId::Any             => f.write_str("*"),
+                    $(TagId::$field        => f.write_str(stringify!($field)),)*
+                }
+            }
+        }
+        impl core::str::FromStr for TagId {
+            type Err = core::num::ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(match s {
+                    $(stringify!($field) => TagId::$field,)*
+                    "*" => TagId::Any,
+                    // `::` can't appear in format tag names, unlike a single `:`
+                    _ if s.contains("::") => {
+                        let (namespace, id) = s.split_once("::").unwrap();
+                        TagId::Namespaced(namespace.to_string(), Box::new(id.parse()?))
+                    },
+                    _ if s.starts_with("0x") => TagId::Unknown(u32::from_str_radix(&s[2..], 16)?),
+                    _ => TagId::Custom(s.to_string())
+                })
+            }
+        }
+    }
+}
+
+include!("tags.rs");
+
+impl TagId {
+    /// Prefixes the id with a namespace (eg. format name), so it doesn't collide with the same id from other sources
+    pub fn namespaced(self, namespace: &str) -> TagId {
+        match self {
+            TagId::Namespaced(_, id) => TagId::Namespaced(namespace.to_owned(), id),
+            id => TagId::Namespaced(namespace.to_owned(), Box::new(id))
+        }
+    }
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            TagId::Namespaced(n, _) => Some(n),
+            _ => None
+        }
+    }
+    /// The id without the namespace
+    pub fn base(&self) -> &TagId {
+        match self {
+            TagId::Namespaced(_, id) => id.as_ref(),
+            id => id
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[allow(non_camel_case_types)]
+pub enum Scalar {
+    u8(u8),   i8(i8),
+    u16(u16), i16(i16),
+    u32(u32), i32(i32),
+    u64(u64), i64(i64),
+    f32(f32), f64(f64),
+    String(String),
+    bool(bool),
+}
+impl Scalar {
+    pub fn as_f64(&self) -> Option<f64> {
+        Some(match self {
+            Scalar::u8(v)  => *v as f64, Scalar::i8(v)  => *v as f64,
+            Scalar::u16(v) => *v as f64, Scalar::i16(v) => *v as f64,
+            Scalar::u32(v) => *v as f64, Scalar::i32(v) => *v as f64,
+            Scalar::u64(v) => *v as f64, Scalar::i64(v) => *v as f64,
+            Scalar::f32(v) => *v as f64, Scalar::f64(v) => *v,
+            Scalar::bool(v) => *v as u8 as f64,
+            Scalar::String(_) => { return None; }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Vector3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+impl<T: core::convert::Into<f64>> Vector3<T> {
+    pub fn into_scaled(self, raw2unit: &f64, unit2deg: &f64) -> Vector3<f64> {
+        Vector3 {
+            x: self.x.into() / raw2unit * unit2deg,
+            y: self.y.into() / raw2unit * unit2deg,
+            z: self.z.into() / raw2unit * unit2deg,
+        }
+    }
+}
+impl Vector3<f64> {
+    pub fn orient(&self, io: &[u8]) -> Vector3<f64> {
+        let map = |o: u8| -> f64 {
+            match o as char {
+                'X' => self.x, 'x' => -self.x,
+                'Y' => self.y, 'y' => -self.y,
+                'Z' => self.z, 'z' => -self.z,
+                err => { panic!("Invalid orientation {}", err); }
+            }
+        };
+        Vector3 { x: map(io[0]), y: map(io[1]), z: map(io[2]) }
+    }
+}
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeVector3<T, TT = T> {
+    pub t: TT,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+impl<T: core::convert::Into<f64>> TimeVector3<T> {
+    pub fn into_scaled(self, raw2unit: &f64, unit2deg: &f64) -> Vector3<f64> {
+        Vector3 {
+            x: self.x.into() / raw2unit * unit2deg,
+            y: self.y.into() / raw2unit * unit2deg,
+            z: self.z.into() / raw2unit * unit2deg,
+        }
+    }
+}
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeArray8<T> {
+    pub t: f64,
+    pub v: [T; 8]
+}
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeArray4<T> {
+    pub t: f64,
+    pub v: [T; 4]
+}
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeArray2<T> {
+    pub t: f64,
+    pub v: [T; 2]
+}
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeScalar<T> {
+    pub t: f64,
+    pub v: T
+}
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Quaternion<T> {
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+impl<T: Copy + core::ops::Mul<Output = T> + core::ops::Sub<Output = T> + core::ops::Add<Output = T>> core::ops::Mul for Quaternion<T> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w
+        }
+    }
+}
+impl<T: Copy + core::ops::Sub<Output = T>> core::ops::Sub for Quaternion<T> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            w: self.w - rhs.w,
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z
+        }
+    }
+}
+impl<T: Copy + core::ops::Neg<Output = T>> core::ops::Neg for Quaternion<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self { w: -self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+}
+impl<T: Copy + core::ops::Mul<Output = T> + core::ops::Add<Output = T>> Quaternion<T> {
+    pub fn norm_squared(&self) -> T {
+        self.w * self.w +
+        self.x * self.x +
+        self.y * self.y +
+        self.z * self.z
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimeQuaternion<T> {
+    pub t: f64,
+    pub v: Quaternion<T>
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GpsData {
+    pub is_acquired: bool,
+    pub unix_timestamp: f64,
+    pub lat: f64,
+    pub lon: f64,
+    pub speed: f64, // in km/h
+    pub track: f64,
+    pub altitude: f64, // in m
+    // Only in formats which have them, eg. GoPro GPS9
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub speed_3d: Option<f64>, // in km/h
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dop: Option<f64>, // Dilution of precision
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub fix: Option<u8>, // 0: no fix, 2: 2D, 3: 3D
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum EventSeverity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CameraEvent {
+    pub t: f64, // in seconds from the start of the clip
+    pub severity: EventSeverity,
+    pub code: String, // eg. "RecordStart", "DroppedFrame", "Overheating"
+    pub text: String,
+}
+
+/// Digital lens (field of view) mode selected in the camera, the lens profile depends on it.
+/// GoPro `VFOV` letters: W (Wide), S (SuperView), H (HyperView), L (Linear), M (Medium), N (Narrow).
+/// DJI `FOVType`: Normal, Narrow, Wide, the others are `Other`. Insta360 `ImageFovType`: Wide, Linear, UltraWide, Narrow,
+/// LinearHorizonLeveling, SuperView, MaxView, other modes (eg. Pov, FPV, TinyPlanet) are `Other` with their name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DigitalLens {
+    #[default]
+    Unknown,
+    Wide,
+    UltraWide,
+    SuperView,
+    HyperView,
+    MaxView,
+    Linear,
+    LinearHorizonLeveling,
+    Medium,
+    Narrow,
+    Normal,
+    Other(String),
+}
+impl DigitalLens {
+    pub fn as_str(&self) -> &str {
+        match self {
+            DigitalLens::Unknown               => "Unknown",
+            DigitalLens::Wide                  => "Wide",
+            DigitalLens::UltraWide             => "UltraWide",
+            DigitalLens::SuperView             => "SuperView",
+            DigitalLens::HyperView             => "HyperView",
+            DigitalLens::MaxView               => "MaxView",
+            DigitalLens::Linear                => "Linear",
+            DigitalLens::LinearHorizonLeveling => "LinearHorizonLeveling",
+            DigitalLens::Medium                => "Medium",
+            DigitalLens::Narrow                => "Narrow",
+            DigitalLens::Normal                => "Normal",
+            DigitalLens::Other(x)              => x,
+        }
+    }
+}
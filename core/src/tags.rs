@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2021 Adrian <adrian.eddy at gmail>
+
+declare_groups! {
+    Default,
+    Exposure,
+    Autofocus,
+    Colors,
+    Lens,
+    GPS,
+    Gyroscope,
+    Accelerometer,
+    Magnetometer,
+    Quaternion,
+    Position3D,
+    IBIS,
+    Imager,
+    LensOSS,
+    GravityVector,
+    CameraOrientation,
+    ImageOrientation,
+    Cooke,
+    Events,
+    Pressure,
+    Altitude,
+    WhiteBalance,
+    Temperature,
+    Markers,
+    Timecode,
+}
+
+declare_ids! {
+    Metadata,
+    IrisFStop,
+    IrisTStop,
+    FocusDistance,
+    MacroEnabled,
+    FocalLength,
+    LensZoom35mm,
+    LensZoomNative,
+    OpticalZoomPercent,
+    LensAttributes,
+    IrisRingPosition,
+    FocusRingPosition,
+    ZoomRingPosition,
+    ColorPrimaries,
+    CodingEquation,
+    CaptureGammaEquation,
+    AutoExposureMode,
+    AutoFocusMode,
+    ColorCorrectionSetting,
+    NDFilterSetting,
+    SensorWidth,
+    SensorHeight,
+    FrameRate,
+    SensorReadoutMode,
+    ShutterAngle,
+    ShutterSpeed,
+    ISOValue,
+    AutoWBMode,
+    WhiteBalance,
+    Tint,
+    MasterBlackLevel,
+    KneePoint,
+    KneeSlope,
+    LuminanceDynamicRange,
+    CameraAttributes,
+    ColorMatrix,
+    GroupIdentifier,
+    ImageStabilizer,
+    CaptureTimestamp,
+    RecordingStartLocal,
+    RecordingStartUtc,
+    TimezoneOffsetMinutes,
+    FirstFrameTimestamp,
+    FrameReadoutTime,
+    FrameReadoutTimes,
+    ExposureTime,
+    TimeOffset,
+    StartOffset,
+    SensorSizePixels,
+    PixelPitch,
+    CaptureAreaOrigin,
+    CaptureAreaSize,
+    AnamorphicSqueeze,
+    Shading,
+    Distortion,
+    DigitalLens,
+    StabilizerShift,
+    SerialNumber,
+    OwnerName,
+    Firmware,
+
+    Name,
+    Enabled,
+    Data,
+    Data2,
+    Unit,
+    Matrix,
+    Temperature,
+    Velocity,
+    Scale,
+    FullScale,
+    Bias,
+    Frequency,
+    TimestampMs,
+    TimestampUs,
+    SampleTimestamps,
+    Offset,
+    Count,
+    Orientation,
+    OrientationIn,
+    OrientationOut,
+    Convention,
+}
@@ -222,7 +222,7 @@ pub fn parse_full<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progr
                     });
                 }
             } else {
-                log::warn!("Unknown msg: {}", id);
+                crate::parse_warning!("Unknown msg: {}", id);
             }
         } else {
             log::error!("Unexpected bytes at pos: {}", stream.stream_position()?);
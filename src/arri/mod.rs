@@ -0,0 +1,194 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// MXF OP1a files from ARRI cameras (ALEXA Mini, ALEXA Mini LF, ALEXA 35).
+// The per-frame lens and camera metadata is stored in its own data essence element of each content package,
+// as SMPTE RDD 18 acquisition metadata sets, so the standard items are decoded by the same code as Sony's RTMD.
+// ARRI specific items (eg. camera tilt and roll) use dynamic local tags, which are resolved to their ULs
+// through the Primer Pack of the header partition and exposed in the `ARRI` group, keyed by the UL in hex
+
+use std::collections::BTreeMap;
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+use byteorder::{ ReadBytesExt, BigEndian };
+use memchr::memmem;
+
+use crate::tags_impl::*;
+use crate::*;
+use crate::sony::mxf;
+
+const PRIMER_PACK:    [u8; 16] = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x02, 0x01, 0x01, 0x05, 0x01, 0x00];
+const IDENTIFICATION: [u8; 16] = [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x30, 0x00];
+// Data element of the generic container (SMPTE ST 379), followed by the element count, type and number
+const DATA_ELEMENT:   [u8; 13] = [0x06, 0x0e, 0x2b, 0x34, 0x01, 0x02, 0x01, 0x01, 0x0d, 0x01, 0x03, 0x01, 0x17];
+
+#[derive(Default)]
+pub struct Arri {
+    pub model: Option<String>,
+}
+
+impl Arri {
+    pub fn camera_type(&self) -> String {
+        "ARRI".to_owned()
+    }
+    pub fn has_accurate_timestamps(&self) -> bool {
+        false
+    }
+    pub fn possible_extensions() -> Vec<&'static str> {
+        vec!["mxf"]
+    }
+    pub fn frame_readout_time(&self) -> Option<f64> {
+        None
+    }
+    pub fn normalize_imu_orientation(v: String) -> String {
+        v
+    }
+
+    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+        if !buffer.starts_with(&[0x06, 0x0e, 0x2b, 0x34]) { return None; }
+
+        let pos = memmem::find(buffer, &IDENTIFICATION)?;
+        let mut d = Cursor::new(&buffer[pos + 16..]);
+        let length = mxf::read_ber(&mut d).ok()?;
+        let start = pos + 16 + d.position() as usize;
+        let identification = Self::parse_identification(buffer.get(start..start + length)?);
+
+        if identification.get("CompanyName")?.starts_with("ARRI") {
+            Some(Self { model: identification.get("ProductName").cloned() })
+        } else {
+            None
+        }
+    }
+
+    // Items of a local set, as (local tag, value)
+    fn local_set(buffer: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut ret = Vec::new();
+        let mut d = Cursor::new(buffer);
+        while let (Ok(tag), Ok(length)) = (d.read_u16::<BigEndian>(), d.read_u16::<BigEndian>()) {
+            let pos = d.position() as usize;
+            let Some(value) = buffer.get(pos..pos + length as usize) else { break; };
+            ret.push((tag, value));
+            d.set_position((pos + length as usize) as u64);
+        }
+        ret
+    }
+
+    fn parse_identification(buffer: &[u8]) -> BTreeMap<&'static str, String> {
+        let mut ret = BTreeMap::new();
+        for (tag, value) in Self::local_set(buffer) {
+            let name = match tag {
+                0x3c01 => "CompanyName",
+                0x3c02 => "ProductName",
+                0x3c04 => "VersionString",
+                _ => continue
+            };
+            let utf16 = value.chunks_exact(2).map(|x| u16::from_be_bytes([x[0], x[1]])).take_while(|x| *x != 0).collect::<Vec<u16>>();
+            ret.insert(name, String::from_utf16_lossy(&utf16));
+        }
+        ret
+    }
+
+    // Mapping of the local tags to the ULs: item count (4), item size (4), then local tag (2) and UL (16) of each item
+    fn parse_primer(buffer: &[u8]) -> BTreeMap<u16, [u8; 16]> {
+        buffer.get(8..).unwrap_or_default().chunks_exact(18).filter_map(|x| {
+            Some((u16::from_be_bytes([x[0], x[1]]), x[2..18].try_into().ok()?))
+        }).collect()
+    }
+
+    // Items which aren't standard RDD 18 tags are moved to the `ARRI` group, with the id being the UL from the Primer Pack
+    fn resolve_dynamic_tags(map: &mut GroupedTagMap, primer: &BTreeMap<u16, [u8; 16]>) {
+        let Some(unknown) = map.remove(&GroupId::UnknownGroup(0)) else { return; };
+        let mut rest = TagMap::new();
+        for (id, mut desc) in unknown {
+            match desc.native_id.and_then(|x| primer.get(&(x as u16))) {
+                Some(ul) => {
+                    desc.group = GroupId::Custom("ARRI".into());
+                    desc.id = TagId::Custom(ul.iter().map(|x| format!("{:02x}", x)).collect());
+                    desc.description = "ARRI dynamic metadata".into();
+                    util::insert_tag(map, desc);
+                }
+                None => { rest.insert(id, desc); }
+            }
+        }
+        if !rest.is_empty() {
+            map.insert(GroupId::UnknownGroup(0), rest);
+        }
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        let mut stream = BufReader::with_capacity(128*1024, stream);
+        let mut samples = Vec::new();
+
+        let mut primer = BTreeMap::new();
+        let mut identification = BTreeMap::new();
+        let mut frame_rate = 25.0;
+        let mut index = 0;
+        let mut id = [0u8; 16];
+        while stream.read_exact(&mut id).is_ok() {
+            if id[0..4] != [0x06, 0x0e, 0x2b, 0x34] {
+                crate::parse_warning!(at stream.stream_position()? - 16; "Unknown ID {}", util::to_hex(&id));
+                break;
+            }
+            let length = mxf::read_ber(&mut stream)?;
+
+            if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
+            if size > 0 {
+                progress_cb(stream.stream_position()? as f64 / size as f64);
+            }
+
+            if id == PRIMER_PACK || id == IDENTIFICATION || mxf::is_picture_descriptor(&id) || id[..13] == DATA_ELEMENT {
+                let file_position = stream.stream_position()?;
+                let mut data = vec![0; length];
+                stream.read_exact(&mut data)?;
+
+                if id == PRIMER_PACK {
+                    // Every partition repeats the header metadata, the first Primer Pack is enough
+                    if primer.is_empty() { primer = Self::parse_primer(&data); }
+                } else if id == IDENTIFICATION {
+                    if identification.is_empty() { identification = Self::parse_identification(&data); }
+                } else if mxf::is_picture_descriptor(&id) {
+                    if let Some(v) = mxf::parse_set(&data).ok().and_then(|x| x.get(&mxf::MxfMetaTag::SampleRate).and_then(|x| x.as_f64())) {
+                        frame_rate = v;
+                    }
+                } else {
+                    // Element type 0x02 is the ST 436 ancillary data, otherwise the sets are stored directly
+                    let data = if id[14] == 0x02 { mxf::parse_ancillary(&data)? } else { data };
+                    if !data.starts_with(&[0x06, 0x0e, 0x2b, 0x34]) { continue; }
+
+                    if let Ok(mut map) = sony::Sony::parse_metadata(&data) {
+                        Self::resolve_dynamic_tags(&mut map, &primer);
+                        let duration_ms = 1000.0 / frame_rate;
+                        util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("FrameIndex".into()), "Continuous frame index", u64, |v| v.to_string(), index, vec![]));
+
+                        samples.push(SampleInfo {
+                            sample_index: index,
+                            duration_ms,
+                            timestamp_ms: index as f64 * duration_ms,
+                            file_position,
+                            sample_size: length,
+                            tag_map: Some(map),
+                            ..Default::default()
+                        });
+                        index += 1;
+                    }
+                }
+            } else {
+                stream.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+
+        if samples.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "No ARRI dynamic metadata found"));
+        }
+
+        if let Some(map) = samples[0].tag_map.as_mut() {
+            let md = serde_json::to_value(&identification).unwrap_or_default();
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Metadata, "Extra metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), md, vec![]));
+        }
+        if let Some(model) = identification.get("ProductName") {
+            self.model = Some(model.clone());
+        }
+
+        Ok(samples)
+    }
+}
@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+//! Synthetic payloads for the benchmarks in `benches/`, enabled with the `bench-data` feature.
+//! The data is generated deterministically, so the results are comparable between runs and machines.
+//! The parsers of the individual formats aren't public, so this module also provides entry points to them,
+//! which decode all tags, to include the lazy parsing in the measurement
+
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+use byteorder::{ WriteBytesExt, BigEndian };
+
+use crate::tags_impl::*;
+use crate::util::SampleInfo;
+use crate::camm::writer::{ CammPacket, CammWriter };
+use crate::export::bbl::{ write_unsigned_vb, write_signed_vb };
+
+// Smooth, but not constant motion, so the values don't compress into a pattern
+fn motion(i: usize, axis: usize) -> f64 {
+    let t = i as f64 / 200.0;
+    (t * (1.0 + axis as f64)).sin() * 0.5 + (t * 7.3 + axis as f64).cos() * 0.1
+}
+
+fn klv(key: &[u8; 4], data_type: u8, size: usize, repeat: usize, data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len() + 12);
+    ret.extend_from_slice(key);
+    ret.push(data_type);
+    ret.push(size as u8);
+    ret.extend_from_slice(&(repeat as u16).to_be_bytes());
+    ret.extend_from_slice(data);
+    ret.resize(ret.len() + (4 - data.len() % 4) % 4, 0); // Aligned to 4 bytes
+    ret
+}
+
+/// GPMF payloads (`DEVC` containers) as stored in the `gpmd` track of GoPro files, one per second of footage.
+/// Each has gyroscope and accelerometer streams with `samples_per_block` readings and a shutter speed stream with 30 values
+pub fn gpmf_stream(blocks: usize, samples_per_block: usize) -> Vec<Vec<u8>> {
+    (0..blocks).map(|block| {
+        let stmp = ((block * 1_000_000) as u64).to_be_bytes();
+        let imu_stream = |key: &[u8; 4], axis_offset: usize| {
+            let mut data = Vec::with_capacity(samples_per_block * 6);
+            for i in 0..samples_per_block {
+                for axis in 0..3 {
+                    let _ = data.write_i16::<BigEndian>((motion(block * samples_per_block + i, axis + axis_offset) * 4000.0) as i16);
+                }
+            }
+            let mut strm = klv(b"STMP", b'J', 8, 1, &stmp);
+            strm.extend(klv(b"SCAL", b's', 2, 1, &1000i16.to_be_bytes()));
+            strm.extend(klv(key, b's', 6, samples_per_block, &data));
+            klv(b"STRM", 0, 1, strm.len(), &strm)
+        };
+        let shutter = (0..30).flat_map(|i| (1.0f32 / (100.0 + i as f32)).to_be_bytes()).collect::<Vec<u8>>();
+        let mut shut_strm = klv(b"STMP", b'J', 8, 1, &stmp);
+        shut_strm.extend(klv(b"SHUT", b'f', 4, 30, &shutter));
+
+        let mut devc = klv(b"DVID", b'L', 4, 1, &1u32.to_be_bytes());
+        devc.extend(imu_stream(b"GYRO", 0));
+        devc.extend(imu_stream(b"ACCL", 3));
+        devc.extend(klv(b"STRM", 0, 1, shut_strm.len(), &shut_strm));
+        klv(b"DEVC", 0, 1, devc.len(), &devc)
+    }).collect()
+}
+
+fn rtmd_tag(tag: u16, data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len() + 4);
+    ret.extend_from_slice(&tag.to_be_bytes());
+    ret.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    ret.extend_from_slice(data);
+    ret
+}
+
+/// RTMD samples (one per video frame) as stored in the metadata track of Sony files,
+/// with gyroscope and accelerometer containers of `samples_per_frame` readings each
+pub fn rtmd_samples(frames: usize, samples_per_frame: usize) -> Vec<Vec<u8>> {
+    (0..frames).map(|frame| {
+        let mut sample = rtmd_tag(0xe40e, &26_000i32.to_be_bytes()); // Frame readout time
+        for (freq_tag, scale_tag, data_tag, axis_offset) in [(0xe435, 0xe439, 0xe43b, 0), (0xe445, 0xe449, 0xe44b, 3)] {
+            let mut data = Vec::with_capacity(8 + samples_per_frame * 6);
+            let _ = data.write_i32::<BigEndian>(samples_per_frame as i32);
+            let _ = data.write_i32::<BigEndian>(6);
+            for i in 0..samples_per_frame {
+                for axis in 0..3 {
+                    let _ = data.write_i16::<BigEndian>((motion(frame * samples_per_frame + i, axis + axis_offset) * 4000.0) as i16);
+                }
+            }
+            let mut container = rtmd_tag(freq_tag, &((samples_per_frame * 25) as i32).to_be_bytes());
+            container.extend(rtmd_tag(scale_tag, &1000.0f32.to_be_bytes()));
+            container.extend(rtmd_tag(data_tag, &data));
+            sample.extend(rtmd_tag(0x8300, &container));
+        }
+        sample
+    }).collect()
+}
+
+fn mp4_box(typ: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+    let len = parts.iter().map(|x| x.len()).sum::<usize>();
+    let mut ret = Vec::with_capacity(len + 8);
+    ret.extend_from_slice(&(len as u32 + 8).to_be_bytes());
+    ret.extend_from_slice(typ);
+    for x in parts { ret.extend_from_slice(x); }
+    ret
+}
+
+/// MP4 file with a single `camm` track containing `count` gyroscope and `count` accelerometer packets, at 1 kHz
+pub fn camm_file(count: usize) -> Vec<u8> {
+    let mut writer = CammWriter::new();
+    for i in 0..count {
+        let v = |offset: usize| [motion(i, offset) as f32, motion(i, offset + 1) as f32, motion(i, offset + 2) as f32];
+        writer.add(i as f64, CammPacket::Gyro(v(0)));
+        writer.add(i as f64 + 0.5, CammPacket::Acceleration(v(3)));
+    }
+    let samples = writer.samples();
+    let data = samples.iter().flat_map(|x| x.data.iter().copied()).collect::<Vec<u8>>();
+    let timescale = 1000u32;
+    let duration = (count as u32).to_be_bytes();
+    let matrix = [0x10000u32, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000].iter().flat_map(|x| x.to_be_bytes()).collect::<Vec<u8>>();
+
+    let ftyp = mp4_box(b"ftyp", &[b"isom", &[0, 0, 2, 0], b"isomiso2mp41"]);
+    let moov = |chunk_offset: u32| {
+        let mvhd = mp4_box(b"mvhd", &[&[0; 12], &timescale.to_be_bytes(), &duration, &[0, 1, 0, 0, 1, 0], &[0; 10], &matrix, &[0; 24], &2u32.to_be_bytes()]);
+        let tkhd = mp4_box(b"tkhd", &[&[0, 0, 0, 3], &[0; 8], &1u32.to_be_bytes(), &[0; 4], &duration, &[0; 16], &matrix, &[0; 8]]);
+        let mdhd = mp4_box(b"mdhd", &[&[0; 12], &timescale.to_be_bytes(), &duration, &[0x55, 0xc4, 0, 0]]);
+        let hdlr = mp4_box(b"hdlr", &[&[0; 8], b"meta", &[0; 13]]);
+        let dinf = mp4_box(b"dinf", &[&mp4_box(b"dref", &[&[0; 4], &1u32.to_be_bytes(), &mp4_box(b"url ", &[&[0, 0, 0, 1]])])]);
+        let stsc = mp4_box(b"stsc", &[&[0; 4], &1u32.to_be_bytes(), &1u32.to_be_bytes(), &(samples.len() as u32).to_be_bytes(), &1u32.to_be_bytes()]);
+        let stco = mp4_box(b"stco", &[&[0; 4], &1u32.to_be_bytes(), &chunk_offset.to_be_bytes()]);
+        let stbl = mp4_box(b"stbl", &[&writer.stsd(), &writer.stts(timescale), &stsc, &writer.stsz(), &stco]);
+        let minf = mp4_box(b"minf", &[&mp4_box(b"nmhd", &[&[0; 4]]), &dinf, &stbl]);
+        let mdia = mp4_box(b"mdia", &[&mdhd, &hdlr, &minf]);
+        mp4_box(b"moov", &[&mvhd, &mp4_box(b"trak", &[&tkhd, &mdia])])
+    };
+    // Size of `moov` doesn't depend on the offset, so it's built once to get the position of the `mdat` data
+    let chunk_offset = ftyp.len() + moov(0).len() + 8;
+    let mut ret = ftyp;
+    ret.extend(moov(chunk_offset as u32));
+    ret.extend(mp4_box(b"mdat", &[&data]));
+    ret
+}
+
+/// Betaflight blackbox log with `frames` intra frames at 2 kHz, with the loop iteration, time, gyroscope and accelerometer fields
+pub fn bbl_log(frames: usize) -> Vec<u8> {
+    let mut ret = [
+        "H Product:Blackbox flight data recorder by Nicholas Sherlock",
+        "H Data version:2",
+        "H I interval:1",
+        "H P interval:1/1",
+        "H Field I name:loopIteration,time,gyroADC[0],gyroADC[1],gyroADC[2],accSmooth[0],accSmooth[1],accSmooth[2]",
+        "H Field I signed:0,0,1,1,1,1,1,1",
+        "H Field I predictor:0,0,0,0,0,0,0,0",
+        "H Field I encoding:1,1,0,0,0,0,0,0",
+        "H Field P predictor:6,2,1,1,1,1,1,1",
+        "H Field P encoding:9,0,0,0,0,0,0,0",
+        "H Firmware type:Cleanflight",
+        "H Firmware revision:Betaflight 4.4.0 (8d4f005) STM32F7X2",
+        "H Firmware date:Jan  1 2024 00:00:00",
+        "H Board information:BENCH BENCH",
+        "H Craft name:bench",
+        "H minthrottle:1070",
+        "H vbatref:420",
+        "H looptime:500",
+        "H gyro_scale:0x3f800000",
+        "H acc_1G:2048",
+        "H motorOutput:48,2047",
+    ].iter().map(|x| format!("{x}\n")).collect::<String>().into_bytes();
+
+    for i in 0..frames {
+        ret.push(b'I');
+        write_unsigned_vb(&mut ret, i as u32);
+        write_unsigned_vb(&mut ret, (i * 500) as u32);
+        for axis in 0..6 {
+            write_signed_vb(&mut ret, (motion(i, axis) * 2000.0) as i32);
+        }
+    }
+    ret.extend_from_slice(b"E\xffEnd of log\0");
+    ret
+}
+
+fn decode_all(map: &GroupedTagMap) {
+    for desc in map.values().flat_map(|x| x.values()) {
+        desc.value.ensure_parsed();
+    }
+}
+
+/// Parses a GPMF payload from `gpmf_stream`
+pub fn parse_gpmf(payload: &[u8]) -> Result<GroupedTagMap> {
+    let map = crate::gopro::GoPro::parse_metadata(payload.get(8..).unwrap_or_default(), GroupId::Default, false)?;
+    decode_all(&map);
+    Ok(map)
+}
+
+/// Parses an RTMD sample from `rtmd_samples`
+pub fn parse_rtmd(payload: &[u8]) -> Result<GroupedTagMap> {
+    let map = crate::sony::Sony::parse_metadata(payload)?;
+    decode_all(&map);
+    Ok(map)
+}
+
+/// Parses a blackbox log from `bbl_log`
+pub fn parse_bbl(log: &[u8]) -> Result<Vec<SampleInfo>> {
+    let mut parser = crate::blackbox::BlackBox::detect(log, "bench.bbl").ok_or_else(|| Error::new(ErrorKind::InvalidData, "Not a blackbox log"))?;
+    let samples = parser.parse(&mut Cursor::new(log), log.len(), |_| (), Arc::new(AtomicBool::new(false)))?;
+    for map in samples.iter().filter_map(|x| x.tag_map.as_ref()) {
+        decode_all(map);
+    }
+    Ok(samples)
+}
@@ -20,16 +20,33 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
     let mut first_timestamp = None;
     let mut last_timestamp = None;
 
-    for (i, mut bbox) in MultiSegmentBlackboxReader::from_bytes(&bytes).successful_only().enumerate() {
+    // Headers of each log, for the ones which the reader doesn't keep in `other_headers`
+    let log_starts = memchr::memmem::find_iter(&bytes, b"H Product:Blackbox").collect::<Vec<usize>>();
+    let raw_header = |i: usize, name: &str| -> Option<String> {
+        let start = *log_starts.get(i)?;
+        let end = log_starts.get(i + 1).copied().unwrap_or(bytes.len());
+        util::find_between(&bytes[start..end], format!("H {name}:").as_bytes(), b'\n').map(|x| x.trim_end_matches('\r').to_owned())
+    };
+
+    // Segments are enumerated before the failed ones are skipped, so `i` matches the index in `log_starts`
+    for (i, mut bbox) in MultiSegmentBlackboxReader::from_bytes(&bytes).enumerate().filter_map(|(i, x)| Some((i, x.ok()?))) {
         // Remove acc_1G from `other_headers` because we will have it in Accelerometer/Scale tag, instead of in metadata
         let accl_scale = bbox.header.other_headers.remove("acc_1G").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
         let gyro_scale = bbox.header.raw_gyro_scale as f64;
+        for name in ["Craft name", "Log start datetime"] {
+            if !bbox.header.other_headers.contains_key(name) {
+                if let Some(v) = raw_header(i, name) {
+                    bbox.header.other_headers.insert(name.to_owned(), v);
+                }
+            }
+        }
 
         let mut map = GroupedTagMap::new();
 
         util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Extra metadata", Json, |v| format!("{:?}", v), {
             serde_json::to_value(&bbox.header.other_headers).map_err(|_| Error::new(ErrorKind::Other, "Serialize error"))?
         }, vec![]));
+        super::BlackBox::insert_log_info(&mut map, bbox.header.other_headers.get("Craft name"), bbox.header.other_headers.get("Log start datetime"));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Scale, "Gyroscope scale",     f64, |v| format!("{:?}", v), gyro_scale, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Scale, "Accelerometer scale", f64, |v| format!("{:?}", v), accl_scale, vec![]));
 
@@ -40,12 +57,14 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
 
         let mut prev_iteration = -1;
         let mut prev_time = -1;
+        let mut markers = Vec::new();
 
         while let Some(record) = bbox.next() {
             match record {
                 BlackboxRecord::Main(values) => {
                     // In normal circumstances iterations and time go up, so if they doesn't, the rest of the log is corrupted
-                    if prev_iteration > values[0] || prev_time > values[1] { break; }
+                    if prev_iteration > values[0] || prev_time > values[1] { util::report_checksum(false); break; }
+                    util::report_checksum(true);
 
                     prev_iteration = values[0];
                     prev_time = values[1];
@@ -63,6 +82,13 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
                 BlackboxRecord::Event(fc_blackbox::frame::event::Frame::EndOfLog) => {
                     break;
                 }
+                // The beep is commonly used to sync the log with the audio of the video
+                BlackboxRecord::Event(fc_blackbox::frame::event::Frame::SyncBeep(time)) => {
+                    markers.push(MarkerData { t: time as f64 / 1_000_000.0, kind: "SyncBeep".into(), label: "Sync beep".into(), payload: serde_json::Value::Null });
+                }
+                BlackboxRecord::Event(fc_blackbox::frame::event::Frame::Disarm { .. }) => {
+                    markers.push(MarkerData { t: last_timestamp.unwrap_or_default(), kind: "Disarm".into(), label: "Disarmed".into(), payload: serde_json::Value::Null });
+                }
                 _ => {}
             }
         }
@@ -73,6 +99,10 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
             let desc = Rc::try_unwrap(desc).unwrap().into_inner();
             util::insert_tag(&mut map, desc);
         }
+        util::insert_markers(&mut map, markers);
+        if !gyro_only {
+            super::BlackBox::insert_field_names(&mut map, &column_struct.field_names, bbox.header.other_headers.get("debug_mode"));
+        }
 
         let map = if prev_iteration == -1 {
             None // no usable data
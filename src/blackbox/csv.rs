@@ -54,6 +54,9 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
     let accl_scale = metadata.remove("acc_1G")    .unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
     let gyro_scale = metadata.remove("gyro_scale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
 
+    let debug_mode = metadata.get("debug_mode").cloned();
+    super::BlackBox::insert_log_info(&mut map, metadata.get("Craft name"), metadata.get("Log start datetime"));
+
     util::insert_tag(&mut map,
         tag!(parsed GroupId::Default, TagId::Metadata, "Extra metadata", Json, |v| format!("{:?}", v), serde_json::to_value(metadata).map_err(|_| Error::new(ErrorKind::Other, "Serialize error"))?, vec![])
     );
@@ -72,6 +75,9 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, _size: usize, _progress
                 util::insert_tag(&mut map, desc.into_inner());
             }
         }
+        if !gyro_only {
+            super::BlackBox::insert_field_names(&mut map, &column_struct.field_names, debug_mode.as_ref());
+        }
 
         Ok(vec![
             SampleInfo { tag_map: Some(map), ..Default::default() }
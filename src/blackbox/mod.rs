@@ -4,6 +4,7 @@
 use std::cell::*;
 use std::rc::*;
 use std::io::*;
+use std::collections::BTreeMap;
 use std::sync::{ Arc, atomic::AtomicBool };
 
 use crate::tags_impl::*;
@@ -13,7 +14,7 @@ use memchr::memmem;
 mod binary;
 mod csv;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct BlackBox {
     pub model: Option<String>,
     csv: bool
@@ -65,7 +66,7 @@ impl BlackBox {
 
     fn parse_field_name(field: &str) -> FieldType {
         if let Some(pos) = field.find('[') {
-            let idx = (&field[pos+1..pos+2]).parse::<u8>().unwrap();
+            let idx = field[pos+1..].trim_end_matches(']').parse::<u8>().unwrap_or_default();
             match &field[..pos] {
                 "GPS_coord" |
                 "GPS_home" => FieldType::Vector2(field[..pos].to_owned(), idx),
@@ -86,7 +87,12 @@ impl BlackBox {
     fn tag_id(name: &str) -> TagId {
         match name {
             "gyroADC" |
-            "accSmooth" => TagId::Data,
+            "accSmooth" |
+            "motor" |
+            "rcCommand" |
+            "rcCommands" |
+            "setpoint" |
+            "debug" => TagId::Data,
 
             _ => TagId::Custom(name.to_owned())
         }
@@ -95,9 +101,48 @@ impl BlackBox {
         match name {
             "gyroADC" => GroupId::Gyroscope,
             "accSmooth" => GroupId::Accelerometer,
+            "motor" => GroupId::Custom("Motors".into()),
+            "rcCommand" |
+            "rcCommands" => GroupId::Custom("RCCommands".into()),
+            "setpoint" => GroupId::Custom("Setpoint".into()),
+            "debug" => GroupId::Custom("Debug".into()),
             _ => GroupId::Custom(name.to_owned())
         }
     }
+    fn field_name(name: &str, index: u8) -> String {
+        match (name, index) {
+            ("rcCommand" | "rcCommands" | "setpoint", 0) => "roll".into(),
+            ("rcCommand" | "rcCommands" | "setpoint", 1) => "pitch".into(),
+            ("rcCommand" | "rcCommands" | "setpoint", 2) => "yaw".into(),
+            ("rcCommand" | "rcCommands" | "setpoint", 3) => "throttle".into(),
+            _ => format!("{name}[{index}]")
+        }
+    }
+
+    // Names of the array elements, so `Motors/Data` etc. can be interpreted without the log header.
+    // For the `Debug` group, the meaning of the values depends on `DebugMode`
+    fn insert_field_names(map: &mut GroupedTagMap, field_names: &BTreeMap<GroupId, Vec<String>>, debug_mode: Option<&String>) {
+        for (group, names) in field_names {
+            util::insert_tag(map, tag!(parsed group.clone(), TagId::Custom("FieldNames".into()), "Field names", Vec_String, |v| v.join(", "), names.clone(), vec![]));
+        }
+        if let Some(mode) = debug_mode {
+            if field_names.contains_key(&GroupId::Custom("Debug".into())) {
+                util::insert_tag(map, tag!(parsed GroupId::Custom("Debug".into()), TagId::Custom("DebugMode".into()), "Debug mode", String, |v| v.to_string(), mode.clone(), vec![]));
+            }
+        }
+    }
+
+    // `Log start datetime` is set only when the flight controller knows the time (from the RTC, GPS or the configurator on connection),
+    // otherwise it's `0000-01-01T00:00:00.000+00:00`
+    fn insert_log_info(map: &mut GroupedTagMap, craft_name: Option<&String>, log_start: Option<&String>) {
+        use chrono::Datelike;
+        if let Some(name) = craft_name.map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("CraftName".into()), "Craft name", String, |v| v.to_string(), name.to_owned(), vec![]));
+        }
+        if let Some(dt) = log_start.and_then(|x| chrono::DateTime::parse_from_rfc3339(x.trim()).ok()).filter(|x| x.year() > 1970) {
+            util::insert_recording_start(map, Some(dt.naive_local()), Some(dt.naive_utc()), Some(dt.offset().local_minus_utc() / 60));
+        }
+    }
 
     fn prepare_vectors_from_headers(headers: &[&str]) -> Columns {
         let mut columns = Columns::default();
@@ -132,14 +177,17 @@ impl BlackBox {
                 FieldType::Vector4(ref hdr, c) => { insert_entry!(c, hdr, Vec_TimeArray4_f64); }
                 FieldType::Vector8(ref hdr, c) => { insert_entry!(c, hdr, Vec_TimeArray8_f64); }
             }
+            if let FieldType::Vector2(ref hdr, c) | FieldType::Vector4(ref hdr, c) | FieldType::Vector8(ref hdr, c) = Self::parse_field_name(x) {
+                columns.field_names.entry(Self::group_from_key(hdr)).or_default().push(Self::field_name(hdr, c));
+            }
         }
 
         columns
     }
 
     fn insert_value_to_vec(desc: &mut TagDescription, time: f64, val: f64, i: u8, gyro_only: bool) {
-        if desc.group == GroupId::Gyroscope     && val.abs() > 3600.0   { log::warn!("Rejecting gyro {val}"); return; }
-        if desc.group == GroupId::Accelerometer && val.abs() > 100000.0 { log::warn!("Rejecting accl {val}"); return; }
+        if desc.group == GroupId::Gyroscope     && val.abs() > 3600.0   { crate::parse_warning!("Rejecting gyro {val}"); return; }
+        if desc.group == GroupId::Accelerometer && val.abs() > 100000.0 { crate::parse_warning!("Rejecting accl {val}"); return; }
 
         if gyro_only && desc.group != GroupId::Gyroscope && desc.group != GroupId::Accelerometer { return; }
 
@@ -149,7 +197,7 @@ impl BlackBox {
             },
             TagValue::Vec_TimeArray2_f64(vec) => match i {
                 0 => vec.get_mut().push(TimeArray2 { t: time, v: [val as f64, 0.0] }),
-                _ => vec.get_mut().last_mut().unwrap().v[i as usize] = val as f64,
+                _ => if let Some(v) = vec.get_mut().last_mut().and_then(|x| x.v.get_mut(i as usize)) { *v = val as f64; },
             },
             TagValue::Vec_TimeVector3_f64(vec) => match i {
                 0 => vec.get_mut().push(TimeVector3 { t: time, x: val as f64, ..Default::default() }),
@@ -159,11 +207,11 @@ impl BlackBox {
             },
             TagValue::Vec_TimeArray4_f64(vec) => match i {
                 0 => vec.get_mut().push(TimeArray4 { t: time, v: [val as f64, 0.0, 0.0, 0.0] }),
-                _ => vec.get_mut().last_mut().unwrap().v[i as usize] = val as f64,
+                _ => if let Some(v) = vec.get_mut().last_mut().and_then(|x| x.v.get_mut(i as usize)) { *v = val as f64; },
             }
             TagValue::Vec_TimeArray8_f64(vec) => match i {
                 0 => vec.get_mut().push(TimeArray8 { t: time, v: [val as f64, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0] }),
-                _ => vec.get_mut().last_mut().unwrap().v[i as usize] = val as f64,
+                _ => if let Some(v) = vec.get_mut().last_mut().and_then(|x| x.v.get_mut(i as usize)) { *v = val as f64; },
             }
             _ => { panic!("Unknown field type"); }
         }
@@ -186,5 +234,6 @@ struct HeaderTagDesc {
 #[derive(Default)]
 struct Columns {
     columns: Vec<HeaderTagDesc>,
-    descriptions: Vec<Rc<RefCell<TagDescription>>>
+    descriptions: Vec<Rc<RefCell<TagDescription>>>,
+    field_names: BTreeMap<GroupId, Vec<String>>
 }
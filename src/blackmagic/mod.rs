@@ -12,7 +12,8 @@ use memchr::memmem;
 #[derive(Default)]
 pub struct BlackmagicBraw {
     pub model: Option<String>,
-    frame_readout_time: Option<f64>
+    frame_readout_time: Option<f64>,
+    is_prores: bool
 }
 
 impl BlackmagicBraw {
@@ -30,7 +31,7 @@ impl BlackmagicBraw {
         }
     }
     pub fn possible_extensions() -> Vec<&'static str> {
-        vec!["braw"]
+        vec!["braw", "mov"]
     }
     pub fn frame_readout_time(&self) -> Option<f64> {
         self.frame_readout_time
@@ -40,11 +41,17 @@ impl BlackmagicBraw {
     }
 
     pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
-        if memmem::find(buffer, b"Blackmagic Design").is_some() && memmem::find(buffer, b"braw_codec_bitrate").is_some() {
-            Some(Self::default())
-        } else {
-            None
+        if memmem::find(buffer, b"Blackmagic Design").is_some() {
+            if memmem::find(buffer, b"braw_codec_bitrate").is_some() {
+                return Some(Self::default());
+            }
+            // ProRes from a BMD camera has the camera metadata keys (as BRAW has `braw_codec_bitrate`), not only the "Blackmagic Design" string
+            let has_camera_keys = memmem::find(buffer, b"sensor_line_time").is_some() && memmem::find(buffer, b"sensor_area_captured").is_some();
+            if has_camera_keys && [b"apch", b"apcn", b"apcs", b"apco", b"ap4h", b"ap4x"].iter().any(|x| memmem::find(buffer, *x).is_some()) {
+                return Some(Self { is_prores: true, ..Default::default() });
+            }
         }
+        None
     }
 
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
@@ -90,39 +97,36 @@ impl BlackmagicBraw {
                 _ => { }
             }
 
-            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap(), meta, vec![]));
+            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), meta, vec![]));
         }
 
-        let _ = util::get_track_samples(stream, size, mp4parse::TrackType::Video, true, Some(8192), |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+        let per_frame = |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
             if size > 0 {
                 progress_cb(file_position as f64 / size as f64 / 3.0);
             }
             if let Ok(md) = Self::parse_per_frame_meta(data) {
-                let mut map = GroupedTagMap::new();
-
-                if let Some(v) = md.get("sensor_rate").and_then(|v| v.as_array()) {
-                    if v.len() == 2 {
-                        frame_rate = v[0].as_u64().zip(v[1].as_u64()).map(|(a, b)| a as f64 / b.max(1) as f64);
-                    }
-                }
-                if let Some(v) = md.get("focal_length").and_then(|v| v.as_str()) {
-                    let v = v.replace("mm", "");
-                    if let Ok(v) = v.parse::<f32>() {
-                        util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::FocalLength, "Focal length", f32, |v| format!("{v:.2} mm"), v, vec![]));
-                    }
-                }
-
-                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap(), md, vec![]));
-                info.tag_map = Some(map);
+                info.tag_map = Some(Self::per_frame_tags(md, &mut frame_rate));
                 samples.push(info);
             }
-        }, cancel_flag.clone());
+        };
+        if self.is_prores {
+            // ProRes clips store the per-frame metadata in a timed metadata track instead of the video frames
+            let _ = util::get_metadata_track_samples(stream, size, false, per_frame, cancel_flag.clone());
+        } else {
+            let _ = util::get_track_samples(stream, size, mp4parse::TrackType::Video, true, Some(8192), per_frame, cancel_flag.clone());
+        }
+
+        let fw_quirks = quirks::find(&self.camera_type(), self.model.as_deref(), Some(firmware_version.as_str()));
+        let mut applied_quirks = Vec::new();
 
         if let Some(fr) = frame_rate {
             util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::FrameRate, "Frame rate", f64, |v| format!("{:?}", v), fr, vec![]));
             if let Some(rs) = self.frame_readout_time {
-                if firmware_version == "7.9" && rs > (1000.0 / fr) {
-                    self.frame_readout_time = Some(rs / 2.0); // Bug in firmware v7.9.0
+                if rs > (1000.0 / fr) {
+                    if let Some(q) = fw_quirks.iter().find(|x| x.quirk == quirks::Quirk::HalvedReadoutTime) {
+                        self.frame_readout_time = Some(rs / 2.0);
+                        applied_quirks.push(*q);
+                    }
                 }
             }
         }
@@ -160,15 +164,23 @@ impl BlackmagicBraw {
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "m/s²".into(),  Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "rad/s".into(), Vec::new()));
 
-        let imu_orientation = match self.model.as_deref() {
-            Some("Micro Studio Camera 4K G2") if firmware_version == "8.4" => "yXZ",
-            _ => "yxz"
+        let imu_orientation = match quirks::imu_orientation(&fw_quirks) {
+            Some(o) => { applied_quirks.extend(fw_quirks.iter().filter(|x| matches!(x.quirk, quirks::Quirk::ImuOrientation(_)))); o },
+            None => "yxz"
         };
+        quirks::insert_tag(&mut map, &applied_quirks);
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
 
         samples.insert(0, SampleInfo { tag_map: Some(map), ..Default::default() });
 
+        // The readout time is computed from the clip metadata, so it's constant for the whole clip
+        if let (Some(rs), true) = (self.frame_readout_time, samples.len() > 1) {
+            let mut readout_times = Vec::new();
+            util::push_readout_time(&mut readout_times, 0.0, rs);
+            util::insert_readout_time_series(&mut samples, readout_times);
+        }
+
         Ok(samples)
     }
 
@@ -243,10 +255,39 @@ impl BlackmagicBraw {
         Err(ErrorKind::InvalidData.into())
     }
 
+    fn per_frame_tags(md: serde_json::Value, frame_rate: &mut Option<f64>) -> GroupedTagMap {
+        let mut map = GroupedTagMap::new();
+
+        if let Some(v) = md.get("sensor_rate").and_then(|v| v.as_array()) {
+            if v.len() == 2 {
+                *frame_rate = v[0].as_u64().zip(v[1].as_u64()).map(|(a, b)| a as f64 / b.max(1) as f64);
+            }
+        }
+        if let Some(v) = md.get("focal_length").and_then(|v| v.as_str()) {
+            let v = v.replace("mm", "");
+            if let Ok(v) = v.parse::<f32>() {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::FocalLength, "Focal length", f32, |v| format!("{v:.2} mm"), v, vec![]));
+            }
+        }
+        if let Some(v) = md.get("white_balance_kelvin").and_then(|v| v.as_u64()) {
+            util::insert_tag(&mut map, tag!(parsed GroupId::Colors, TagId::WhiteBalance, "White balance", u32, |v| format!("{v} K"), v as u32, vec![]));
+        }
+        if let Some(v) = md.get("white_balance_tint").and_then(|v| v.as_u64()) {
+            // Stored as u16, but the tint is signed
+            util::insert_tag(&mut map, tag!(parsed GroupId::Colors, TagId::Tint, "White balance tint", i16, |v| format!("{v}"), v as u16 as i16, vec![]));
+        }
+        if let Some(v) = md.get("iso").and_then(|v| v.as_u64()) {
+            util::insert_tag(&mut map, tag!(parsed GroupId::Exposure, TagId::ISOValue, "ISO value", u32, |v| format!("{v}"), v as u32, vec![]));
+        }
+
+        util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), md, vec![]));
+        map
+    }
+
     fn parse_per_frame_meta(data: &[u8]) -> Result<serde_json::Value> {
         if data.len() > 8 && &data[4..8] == b"bmdf" {
             let size = (&data[..8]).read_u32::<BigEndian>()? as usize;
-            let meta = &data[8..size];
+            let meta = data.get(8..size).ok_or(ErrorKind::InvalidData)?;
             let mut md = serde_json::Map::<String, serde_json::Value>::new();
             Self::iter_boxes(meta, false, |name, mut d, _| {
                 fn get_str<'a>(d: &'a [u8]) -> serde_json::Result<&'a str> {
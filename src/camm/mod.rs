@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 // Copyright © 2023 Adrian <adrian.eddy at gmail>
 
+pub mod writer;
+mod ricoh;
+
 use std::io::*;
 use std::sync::{ Arc, atomic::AtomicBool };
 
@@ -12,7 +15,8 @@ use memchr::memmem;
 #[derive(Default)]
 pub struct Camm {
     pub model: Option<String>,
-    frame_readout_time: Option<f64>
+    frame_readout_time: Option<f64>,
+    is_theta: bool,
 }
 
 // We could parse that too
@@ -20,7 +24,7 @@ pub struct Camm {
 
 impl Camm {
     pub fn camera_type(&self) -> String {
-        "CAMM".to_owned()
+        if self.is_theta { "Ricoh".to_owned() } else { "CAMM".to_owned() }
     }
     pub fn has_accurate_timestamps(&self) -> bool {
         false
@@ -38,7 +42,8 @@ impl Camm {
     pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
         for camm_pos in memmem::find_iter(buffer, b"camm") {
             if buffer.len() > 16 + camm_pos && &buffer[4..8] == b"ftyp" && &buffer[camm_pos-4-4-4-4..camm_pos-4-4-4] == b"stsd" {
-                return Some(Self::default());
+                let model = ricoh::detect_model(buffer);
+                return Some(Self { is_theta: model.is_some(), model, ..Default::default() });
             }
         }
         None
@@ -51,6 +56,7 @@ impl Camm {
         let mut pos = Vec::new();
         let mut quats = Vec::new();
         let mut gps = Vec::new();
+        let mut velocity = Vec::new();
 
         let mut samples = Vec::new();
 
@@ -125,7 +131,8 @@ impl Camm {
                                 lon: longitude,
                                 speed: 0.0,
                                 track: 0.0,
-                                altitude
+                                altitude,
+                                ..Default::default()
                             });
                         },
                         6 => { // gps
@@ -136,19 +143,26 @@ impl Camm {
                             let altitude            = d.read_f32::<LittleEndian>().ok()? as f64; // meters
                             let _horizontal_accuracy = d.read_f32::<LittleEndian>().ok()?; // meters
                             let _vertical_accuracy   = d.read_f32::<LittleEndian>().ok()?; // meters
-                            let _velocity_east       = d.read_f32::<LittleEndian>().ok()?; // meters/seconds
-                            let _velocity_north      = d.read_f32::<LittleEndian>().ok()?; // meters/seconds
-                            let _velocity_up         = d.read_f32::<LittleEndian>().ok()?; // meters/seconds
+                            let velocity_east       = d.read_f32::<LittleEndian>().ok()? as f64; // meters/seconds
+                            let velocity_north      = d.read_f32::<LittleEndian>().ok()? as f64; // meters/seconds
+                            let velocity_up         = d.read_f32::<LittleEndian>().ok()? as f64; // meters/seconds
                             let _speed_accuracy      = d.read_f32::<LittleEndian>().ok()?; // meters/seconds
 
+                            let horizontal_speed = (velocity_east * velocity_east + velocity_north * velocity_north).sqrt();
+                            let speed_3d = (horizontal_speed * horizontal_speed + velocity_up * velocity_up).sqrt();
+                            velocity.push(TimeVector3 { t: info.timestamp_ms / 1000.0, x: velocity_east, y: velocity_north, z: velocity_up });
+
                             gps.push(GpsData {
                                 is_acquired: gps_fix_type > 0,
                                 unix_timestamp: time_gps_epoch,
                                 lat: latitude,
                                 lon: longitude,
-                                speed: 0.0, // TODO
-                                track: 0.0, // TODO
-                                altitude
+                                speed: horizontal_speed * 3.6, // m/s to km/h
+                                track: velocity_east.atan2(velocity_north).to_degrees().rem_euclid(360.0),
+                                altitude,
+                                speed_3d: Some(speed_3d * 3.6),
+                                fix: Some(gps_fix_type as u8),
+                                ..Default::default()
                             });
                         },
                         7 => { // magnetic_field
@@ -159,7 +173,7 @@ impl Camm {
                             });
                         },
                         _ => {
-                            log::warn!("Unknown CAMM type: {typ}: {}", pretty_hex::pretty_hex(&data));
+                            crate::parse_warning!("Unknown CAMM type: {typ}: {}", pretty_hex::pretty_hex(&data));
                         }
                     }
                 });
@@ -172,8 +186,21 @@ impl Camm {
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data",  Vec_TimeVector3_f64, |v| format!("{:?}", v), magn, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Position3D,    TagId::Data, "3D position data",   Vec_TimeVector3_f64, |v| format!("{:?}", v), pos, vec![]));
+        if !quats.is_empty() {
+            util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+                source: "angle_axis",
+                component_order: "xyz",
+                handedness: "right",
+                negated: &["y", "z"],
+                ..Default::default()
+            });
+        }
         util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,    TagId::Data, "Quaternion data",    Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quats, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::GPS,           TagId::Data, "GPS data",           Vec_GpsData, |v| format!("{:?}", v), gps, vec![]));
+        if !velocity.is_empty() {
+            // East, north and up, from the type 6 (full GPS) packets
+            util::insert_tag(&mut map, tag!(parsed GroupId::GPS, TagId::Velocity, "GPS velocity (ENU, m/s)", Vec_TimeVector3_f64, |v| format!("{:?}", v), velocity, vec![]));
+        }
 
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "m/s²".into(),  Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "rad/s".into(), Vec::new()));
@@ -184,6 +211,12 @@ impl Camm {
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
 
+        if self.is_theta {
+            if let Err(e) = ricoh::insert_zenith_correction(stream, &mut map) {
+                crate::parse_warning!("Failed to read the zenith correction: {e:?}");
+            }
+        }
+
         samples.insert(0, SampleInfo { tag_map: Some(map), ..Default::default() });
 
         Ok(samples)
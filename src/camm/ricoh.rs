@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Ricoh THETA (V, Z1, X) write the IMU to a `camm` track, with the axes of the CAMM spec (Android sensor frame), with the upright body
+// as the "screen": x to the right and y up when looking at the front lens from behind, z towards the back lens.
+// The front lens is the center of the equirectangular frame, so the generic CAMM orientation applies.
+// The top/bottom (zenith) correction measured at the start of the recording is stored in the `RMKN` box in `udta`,
+// which is a TIFF with the Exif IFD, Ricoh maker notes and the THETA sub-IFD (0x4001):
+// - 0x0003: roll and pitch of the camera, in degrees (signed rationals)
+// - 0x0004: compass heading, in degrees (unsigned rational)
+
+use std::io::*;
+
+use crate::tags_impl::*;
+use crate::*;
+
+pub fn detect_model(buffer: &[u8]) -> Option<String> {
+    let model = util::find_between_with_offset(buffer, b"RICOH THETA", b'\0', -5)?;
+    Some(model).filter(|x| x.len() < 32 && x.bytes().all(|c| c.is_ascii_graphic() || c == b' '))
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    le: bool,
+}
+impl Tiff<'_> {
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.le { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.le { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+    // Position of the value of `tag` in the IFD at `offset`. Values up to 4 bytes are stored in the entry
+    fn find(&self, offset: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(offset)? as usize;
+        (0..count).map(|i| offset + 2 + i * 12).find(|&entry| self.u16(entry) == Some(tag)).and_then(|entry| {
+            let size = match self.u16(entry + 2)? { 3 => 2, 4 | 9 => 4, 5 | 10 | 12 => 8, _ => 1 };
+            if size * self.u32(entry + 4)? as usize <= 4 { Some(entry + 8) } else { Some(self.u32(entry + 8)? as usize) }
+        })
+    }
+    fn rational(&self, pos: usize, signed: bool) -> Option<f64> {
+        let (n, d) = (self.u32(pos)?, self.u32(pos + 4)?);
+        if d == 0 { return None; }
+        Some(if signed { n as i32 as f64 / d as i32 as f64 } else { n as f64 / d as f64 })
+    }
+}
+
+// (roll, pitch, heading) from the `RMKN` TIFF
+fn parse_rmkn(data: &[u8]) -> Option<(f64, f64, Option<f64>)> {
+    let tiff = Tiff { data, le: data.starts_with(b"II") };
+    let ifd0 = tiff.u32(4)? as usize;
+    let exif = tiff.u32(tiff.find(ifd0, 0x8769)?)? as usize;
+    let maker_note = tiff.find(exif, 0x927c)?;
+    // The maker notes start with the `Ricoh\0\0\0` header, the offsets are relative to the TIFF
+    if !data.get(maker_note..maker_note + 5)?.eq_ignore_ascii_case(b"ricoh") { return None; }
+    let theta = tiff.u32(tiff.find(maker_note + 8, 0x4001)?)? as usize;
+    let angles = tiff.find(theta, 0x0003)?;
+    let heading = tiff.find(theta, 0x0004).and_then(|x| tiff.rational(x, false));
+    Some((tiff.rational(angles, true)?, tiff.rational(angles + 8, true)?, heading))
+}
+
+pub fn insert_zenith_correction<T: Read + Seek>(stream: &mut T, map: &mut GroupedTagMap) -> Result<()> {
+    stream.seek(SeekFrom::Start(0))?;
+    while let Ok((typ, _offs, size, header_size)) = util::read_box(stream) {
+        if size == 0 || typ == 0 { break; }
+        let org_pos = stream.stream_position()?;
+        if typ == fourcc("moov") || typ == fourcc("udta") {
+            continue; // go inside these boxes
+        }
+        if typ == fourcc("RMKN") && size < 1024 * 1024 {
+            let mut buf = vec![0u8; size as usize - header_size as usize];
+            stream.read_exact(&mut buf)?;
+            if let Some((roll, pitch, heading)) = parse_rmkn(&buf) {
+                util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("ZenithRoll".into()),  "Zenith correction roll (deg)",  f64, |v| format!("{:.2}", v), roll,  vec![]));
+                util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("ZenithPitch".into()), "Zenith correction pitch (deg)", f64, |v| format!("{:.2}", v), pitch, vec![]));
+                if let Some(heading) = heading {
+                    util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("CompassHeading".into()), "Compass heading (deg)", f64, |v| format!("{:.2}", v), heading, vec![]));
+                }
+            }
+            break;
+        }
+        stream.seek(SeekFrom::Start(org_pos + size - header_size as u64))?;
+    }
+    Ok(())
+}
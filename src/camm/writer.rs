@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Encoding of telemetry into CAMM packets, to generate a `camm` track for MP4 files
+// https://developers.google.com/streetview/publish/camm-spec
+
+use byteorder::{ WriteBytesExt, LittleEndian, BigEndian };
+
+use crate::tags_impl::*;
+use crate::util::IMUData;
+
+#[derive(Debug, Clone)]
+pub enum CammPacket {
+    /// Angle-axis orientation in radians
+    AngleAxis([f32; 3]),
+    /// In nanoseconds
+    Exposure { pixel_exposure_time: i32, rolling_shutter_skew_time: i32 },
+    /// In rad/s
+    Gyro([f32; 3]),
+    /// In m/s²
+    Acceleration([f32; 3]),
+    Position([f32; 3]),
+    /// Latitude, longitude (degrees) and altitude (meters)
+    MinimalGps([f64; 3]),
+    Gps {
+        time_gps_epoch: f64,
+        gps_fix_type: i32,
+        latitude: f64,
+        longitude: f64,
+        altitude: f32,
+        horizontal_accuracy: f32,
+        vertical_accuracy: f32,
+        velocity_east: f32,
+        velocity_north: f32,
+        velocity_up: f32,
+        speed_accuracy: f32,
+    },
+    /// In μT
+    MagneticField([f32; 3]),
+}
+
+impl CammPacket {
+    pub fn packet_type(&self) -> u16 {
+        match self {
+            CammPacket::AngleAxis(_)     => 0,
+            CammPacket::Exposure { .. }  => 1,
+            CammPacket::Gyro(_)          => 2,
+            CammPacket::Acceleration(_)  => 3,
+            CammPacket::Position(_)      => 4,
+            CammPacket::MinimalGps(_)    => 5,
+            CammPacket::Gps { .. }       => 6,
+            CammPacket::MagneticField(_) => 7,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut d = Vec::with_capacity(64);
+        let _ = self.write(&mut d);
+        d
+    }
+
+    fn write(&self, d: &mut Vec<u8>) -> std::io::Result<()> {
+        d.write_u16::<LittleEndian>(0)?; // reserved
+        d.write_u16::<LittleEndian>(self.packet_type())?;
+        match self {
+            CammPacket::AngleAxis(v) | CammPacket::Gyro(v) | CammPacket::Acceleration(v) | CammPacket::Position(v) | CammPacket::MagneticField(v) => {
+                for x in v { d.write_f32::<LittleEndian>(*x)?; }
+            },
+            CammPacket::Exposure { pixel_exposure_time, rolling_shutter_skew_time } => {
+                d.write_i32::<LittleEndian>(*pixel_exposure_time)?;
+                d.write_i32::<LittleEndian>(*rolling_shutter_skew_time)?;
+            },
+            CammPacket::MinimalGps(v) => {
+                for x in v { d.write_f64::<LittleEndian>(*x)?; }
+            },
+            CammPacket::Gps { time_gps_epoch, gps_fix_type, latitude, longitude, altitude, horizontal_accuracy, vertical_accuracy, velocity_east, velocity_north, velocity_up, speed_accuracy } => {
+                d.write_f64::<LittleEndian>(*time_gps_epoch)?;
+                d.write_i32::<LittleEndian>(*gps_fix_type)?;
+                d.write_f64::<LittleEndian>(*latitude)?;
+                d.write_f64::<LittleEndian>(*longitude)?;
+                for x in [altitude, horizontal_accuracy, vertical_accuracy, velocity_east, velocity_north, velocity_up, speed_accuracy] {
+                    d.write_f32::<LittleEndian>(*x)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CammSample {
+    pub timestamp_ms: f64,
+    pub duration_ms: f64,
+    pub data: Vec<u8>,
+}
+
+/// Collects CAMM packets and builds the samples and sample tables of the `camm` track.
+/// Each packet is a separate sample in the track
+#[derive(Debug, Clone, Default)]
+pub struct CammWriter {
+    packets: Vec<(f64, CammPacket)>,
+}
+
+impl CammWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, timestamp_ms: f64, packet: CammPacket) {
+        self.packets.push((timestamp_ms, packet));
+    }
+
+    /// Adds the IMU data as returned by `util::normalized_imu` (deg/s, m/s², μT), converted to CAMM axes and units
+    pub fn add_imu(&mut self, imu: &[IMUData]) {
+        // Inverse of the orientation used when reading CAMM
+        let to_camm = |v: &[f64; 3], scale: f64| -> [f32; 3] { [(-v[1] * scale) as f32, (-v[0] * scale) as f32, (-v[2] * scale) as f32] };
+        for x in imu {
+            if let Some(g) = &x.gyro { self.add(x.timestamp_ms, CammPacket::Gyro(to_camm(g, std::f64::consts::PI / 180.0))); }
+            if let Some(a) = &x.accl { self.add(x.timestamp_ms, CammPacket::Acceleration(to_camm(a, 1.0))); }
+            if let Some(m) = &x.magn { self.add(x.timestamp_ms, CammPacket::MagneticField(to_camm(m, 1.0))); }
+        }
+    }
+
+    pub fn add_gps(&mut self, timestamp_ms: f64, gps: &GpsData) {
+        self.add(timestamp_ms, CammPacket::Gps {
+            time_gps_epoch: gps.unix_timestamp,
+            gps_fix_type: gps.fix.map(|x| x as i32).unwrap_or(if gps.is_acquired { 3 } else { 0 }),
+            latitude: gps.lat,
+            longitude: gps.lon,
+            altitude: gps.altitude as f32,
+            horizontal_accuracy: 0.0,
+            vertical_accuracy: 0.0,
+            velocity_east: (gps.speed / 3.6 * gps.track.to_radians().sin()) as f32,
+            velocity_north: (gps.speed / 3.6 * gps.track.to_radians().cos()) as f32,
+            velocity_up: 0.0,
+            speed_accuracy: 0.0,
+        });
+    }
+
+    /// Encoded samples sorted by timestamp. Duration of each sample lasts until the next one, the last one gets the duration of the previous sample
+    pub fn samples(&self) -> Vec<CammSample> {
+        let mut packets = self.packets.iter().collect::<Vec<_>>();
+        packets.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut ret: Vec<CammSample> = packets.into_iter().map(|(t, p)| CammSample { timestamp_ms: *t, duration_ms: 0.0, data: p.encode() }).collect();
+        let durations = ret.windows(2).map(|w| w[1].timestamp_ms - w[0].timestamp_ms).collect::<Vec<f64>>();
+        for (s, d) in ret.iter_mut().zip(durations.iter().chain(durations.last())) {
+            s.duration_ms = *d;
+        }
+        ret
+    }
+
+    /// `stsd` box with a single `camm` sample entry
+    pub fn stsd(&self) -> Vec<u8> {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&[0u8; 6]); // reserved
+        entry.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+        let entry = mp4_box(b"camm", &entry);
+
+        let mut stsd = vec![0u8; 4]; // version and flags
+        stsd.extend_from_slice(&1u32.to_be_bytes()); // entry_count
+        stsd.extend(entry);
+        mp4_box(b"stsd", &stsd)
+    }
+
+    /// `stts` box with the sample durations in `timescale` units
+    pub fn stts(&self, timescale: u32) -> Vec<u8> {
+        let mut entries: Vec<(u32, u32)> = Vec::new();
+        for s in self.samples() {
+            let delta = (s.duration_ms.max(0.0) * timescale as f64 / 1000.0).round() as u32;
+            match entries.last_mut() {
+                Some(last) if last.1 == delta => last.0 += 1,
+                _ => entries.push((1, delta))
+            }
+        }
+        let mut stts = vec![0u8; 4]; // version and flags
+        let _ = stts.write_u32::<BigEndian>(entries.len() as u32);
+        for (count, delta) in entries {
+            let _ = stts.write_u32::<BigEndian>(count);
+            let _ = stts.write_u32::<BigEndian>(delta);
+        }
+        mp4_box(b"stts", &stts)
+    }
+
+    /// `stsz` box with the size of each sample
+    pub fn stsz(&self) -> Vec<u8> {
+        let samples = self.samples();
+        let mut stsz = vec![0u8; 4]; // version and flags
+        let _ = stsz.write_u32::<BigEndian>(0); // sample_size, 0 means the sizes are in the table
+        let _ = stsz.write_u32::<BigEndian>(samples.len() as u32);
+        for s in samples {
+            let _ = stsz.write_u32::<BigEndian>(s.data.len() as u32);
+        }
+        mp4_box(b"stsz", &stsz)
+    }
+}
+
+fn mp4_box(typ: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len() + 8);
+    ret.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+    ret.extend_from_slice(typ);
+    ret.extend_from_slice(data);
+    ret
+}
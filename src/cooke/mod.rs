@@ -69,6 +69,7 @@ impl Cooke {
                     //if tsi == 1 { println!("{:?}, ts: {:.2}", timecode, timestamp[tsi] as f64 / 150000.0); }
                     match rtype {
                         Some("rt.header.lens.info") => {
+                            Self::insert_lens_identity(&mut map, &data);
                             util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Metadata, "Lens info", Json, |v| format!("{:?}", v), data, vec![]));
                         },
                         Some("rt.header.lens.shading") => {
@@ -215,6 +216,16 @@ impl Cooke {
         }
         Some(ret)
     }
+    /// Standard lens tags from the `rt.header.lens.info` record. Accepts a single record or a list of /i records
+    pub fn insert_lens_identity(map: &mut GroupedTagMap, data: &serde_json::Value) {
+        if let Some(arr) = data.as_array() {
+            for v in arr { Self::insert_lens_identity(map, v); }
+            return;
+        }
+        if data.get("RecordType").and_then(|x| x.as_str()) != Some("rt.header.lens.info") { return; }
+        let s = |key: &str| data.get(key).and_then(|x| x.as_str());
+        util::insert_lens_identity(map, s("SerialNumber"), s("Owner"), s("FirmwareVersion"));
+    }
     fn get_mtrx<const N: usize>(data: &serde_json::Value) -> Option<[[f64; N]; 3]> {
         let mut ret = [[0.0f64; N]; 3];
         for r in 1..=3 {
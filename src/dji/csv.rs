@@ -99,6 +99,15 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
     util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), magn, vec![]));
     util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()));
 
+    if !quat.is_empty() {
+        util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+            source: "quaternion",
+            component_order: "wxyz",
+            handedness: "right",
+            post_rotation: Some((0.5, -0.5, -0.5, 0.5)),
+            ..Default::default()
+        });
+    }
     util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,    TagId::Data, "Quaternion data",   Vec_TimeQuaternion_f64,  |v| format!("{:?}", v), quat, vec![]));
 
     Ok(vec![
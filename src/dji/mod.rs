@@ -13,6 +13,7 @@ use memchr::memmem;
 use prost::Message;
 
 mod csv;
+mod txt_log;
 
 #[derive(Default)]
 pub struct Dji {
@@ -28,7 +29,7 @@ impl Dji {
         true
     }
     pub fn possible_extensions() -> Vec<&'static str> {
-        vec!["mp4", "mov", "csv"]
+        vec!["mp4", "mov", "csv", "txt"]
     }
     pub fn frame_readout_time(&self) -> Option<f64> {
         self.frame_readout_time
@@ -48,14 +49,21 @@ impl Dji {
                 model: Some("CSV flight log".into()),
                 frame_readout_time: None
             })
+        } else if txt_log::detect(buffer) {
+            Some(Self {
+                model: Some("TXT flight log".into()),
+                frame_readout_time: None
+            })
         } else {
             None
         }
     }
 
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
-        if self.model.is_some() {
-            return csv::parse(stream, size);
+        match self.model.as_deref() {
+            Some("TXT flight log") => return txt_log::parse(stream, size),
+            Some(_) => return csv::parse(stream, size),
+            None => { }
         }
 
         let mut samples = Vec::new();
@@ -73,6 +81,8 @@ impl Dji {
         let mut prev_ts = 0.0;
         let mut prev_quat: Option<Quaternion<f64>> = None;
         let mut inv = false;
+        let mut readout_times = Vec::new();
+        let mut gaps = Vec::new();
 
         let ctx = util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
             if size > 0 {
@@ -99,7 +109,7 @@ impl Dji {
                         let v = serde_json::to_value(&clip).map_err(|_| Error::new(ErrorKind::Other, "Serialize error"));
                         if let Ok(vv) = v {
                             log::debug!("Metadata: {:?}", &vv);
-                            insert_tag(&mut tag_map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap(), vv, vec![]));
+                            insert_tag(&mut tag_map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), vv, vec![]));
                         }
                         if let Some(ref stream) = parsed.stream_meta {
                             if let Some(ref meta) = stream.video_stream_meta {
@@ -108,12 +118,14 @@ impl Dji {
                         }
                         if let Some(ref mut v) = self.frame_readout_time {
                             *v /= fps / sensor_fps;
+                            util::push_readout_time(&mut readout_times, info.timestamp_ms, *v);
                         }
                     }
 
                     let fps_ratio = fps / sensor_fps;
 
                     let mut quats = Vec::new();
+                    // The frame meta has no device temperature or battery level, so DJI clips have no `Temperature` group
                     if let Some(ref frame) = parsed.frame_meta {
                         let frame_ts = frame.frame_meta_header.as_ref().unwrap().frame_timestamp as i64;
                         if info.sample_index == 0 { first_timestamp = frame_ts; }
@@ -211,6 +223,15 @@ impl Dji {
 
                                 if info.sample_index == 0 { log::debug!("Quaternions: {:?}", &quats); }
                                 util::insert_tag(&mut tag_map, tag!(parsed GroupId::Quaternion, TagId::Data, "Quaternion data",  Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quats, vec![]));
+                                util::insert_quaternion_convention(&mut tag_map, &util::QuaternionConvention {
+                                    source: "quaternion",
+                                    component_order: "wxyz",
+                                    handedness: "right",
+                                    negated: &[],
+                                    pre_rotation: Some((0.0, 0.0, 1.0, 0.0)),
+                                    post_rotation: Some((0.5, -0.5, -0.5, 0.5)),
+                                    sign_unwrapped: true,
+                                });
                             }
                         }
                     }
@@ -221,12 +242,13 @@ impl Dji {
 
                     samples.push(info);
                 },
-                Err(e) => {
-                    log::warn!("Failed to parse protobuf: {:?}", e);
-                }
+                Err(e) => gaps.push(util::corrupted_sample(&info, &e))
             }
         }, cancel_flag)?;
 
+        util::insert_readout_time_series(&mut samples, readout_times);
+        util::insert_gap_markers(&mut samples, gaps);
+
         match (samples.first_mut(), focal_length, distortion_coeffs) {
             (Some(sample), Some(focal_length), Some(coeffs)) if coeffs.len() >= 4 => {
                 if let Some(tkhd) = ctx.tracks.iter().filter(|x| x.track_type == mp4parse::TrackType::Video).filter_map(|x| x.tkhd.as_ref()).next() {
@@ -234,7 +256,7 @@ impl Dji {
 
                     let profile = self.get_lens_profile(w, h, focal_length, &coeffs);
                     if let Some(ref mut tag_map) = sample.tag_map {
-                        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap(), profile, vec![]));
+                        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap_or_default(), profile, vec![]));
                     }
                 }
             },
@@ -249,34 +271,16 @@ impl Dji {
         let half_width = width as f64 / 2.0;
         let half_height = height as f64 / 2.0;
         let output_size = Self::get_output_size(width, height);
-        serde_json::json!({
-            "calibrated_by": "DJI",
-            "camera_brand": "DJI",
-            "camera_model": model,
-            "calib_dimension":  { "w": width, "h": height },
-            "orig_dimension":   { "w": width, "h": height },
-            "output_dimension": { "w": output_size.0, "h": output_size.1 },
-            "frame_readout_time": self.frame_readout_time,
-            "official": true,
-            "fisheye_params": {
-              "camera_matrix": [
+        LensProfile::new("DJI", &model, width, height)
+            .with_output_dimension(output_size.0, output_size.1)
+            .with_frame_readout_time(self.frame_readout_time)
+            .with_camera_matrix([
                 [ focal_length, 0.0, half_width ],
                 [ 0.0, focal_length, half_height ],
                 [ 0.0, 0.0, 1.0 ]
-              ],
-              "distortion_coeffs": coeffs
-            },
-            "sync_settings": {
-              "initial_offset": 0,
-              "initial_offset_inv": false,
-              "search_size": 0.5,
-              "max_sync_points": 5,
-              "every_nth_frame": 1,
-              "time_per_syncpoint": 0.6,
-              "do_autosync": false
-            },
-            "calibrator_version": "---"
-        })
+            ])
+            .with_distortion("opencv_fisheye", coeffs.iter().map(|x| *x as f64).collect(), false)
+            .to_json()
     }
 
     fn get_output_size(width: u32, height: u32) -> (u32, u32) {
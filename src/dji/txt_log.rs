@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Flight logs exported as TXT by the DJI apps. Most of the records are encrypted, but the frames with the flight state
+// are stored as plaintext JSON objects, one per line:
+// {"time":12.3,"osd":{"latitude":46.1,"longitude":14.5,"height":35.2,"pitch":-1.2,"roll":0.4,"yaw":87.1,"xSpeed":2.1,"ySpeed":0.3,"gpsNum":17},"gimbal":{"pitch":-30.0,"roll":0.0,"yaw":86.5}}
+// `time` is the flight time in seconds, angles are in degrees, `height` is in meters and speeds (north, east) in m/s.
+// Gimbal attitude is the camera orientation, so it's preferred over the aircraft attitude for the quaternions
+
+use std::io::*;
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+pub fn detect(buffer: &[u8]) -> bool {
+    memmem::find(buffer, b"\"osd\":{").is_some() && memmem::find(buffer, b"\"latitude\":").is_some()
+}
+
+pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
+    let mut gps = Vec::new();
+    let mut quat = Vec::new();
+
+    let mut first_timestamp = None;
+    let mut last_timestamp = 0.0;
+
+    for line in BufReader::new(stream).split(b'\n') {
+        let line = line?;
+        let Some(start) = memchr::memchr(b'{', &line) else { continue; };
+        let Ok(frame) = serde_json::from_slice::<serde_json::Value>(&line[start..]) else { continue; };
+        let Some(t) = frame.get("time").and_then(|x| x.as_f64()) else { continue; };
+        let get = |obj: &str, key: &str| frame.get(obj).and_then(|x| x.get(key)).and_then(|x| x.as_f64());
+
+        let t = t - *first_timestamp.get_or_insert(t);
+        last_timestamp = t;
+
+        if let (Some(lat), Some(lon)) = (get("osd", "latitude"), get("osd", "longitude")) {
+            let north = get("osd", "xSpeed").unwrap_or_default();
+            let east = get("osd", "ySpeed").unwrap_or_default();
+            gps.push(GpsData {
+                is_acquired: get("osd", "gpsNum").map(|x| x >= 4.0).unwrap_or(lat != 0.0 || lon != 0.0),
+                unix_timestamp: frame.get("timestamp").and_then(|x| x.as_f64()).map(|x| x / 1000.0).unwrap_or_default(),
+                lat,
+                lon,
+                speed: (north * north + east * east).sqrt() * 3.6, // m/s to km/h
+                track: east.atan2(north).to_degrees().rem_euclid(360.0),
+                altitude: get("osd", "height").unwrap_or_default(),
+                ..Default::default()
+            });
+        }
+
+        let source = if frame.get("gimbal").is_some() { "gimbal" } else { "osd" };
+        if let (Some(pitch), Some(roll), Some(yaw)) = (get(source, "pitch"), get(source, "roll"), get(source, "yaw")) {
+            quat.push(TimeQuaternion {
+                t: t * 1000.0,
+                v: processing::euler_to_quaternion(roll.to_radians(), pitch.to_radians(), yaw.to_radians())
+            });
+        }
+    }
+
+    if gps.is_empty() && quat.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "No plaintext frames in the flight log"));
+    }
+
+    let mut map = GroupedTagMap::new();
+
+    if !gps.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::GPS, TagId::Data, "GPS data", Vec_GpsData, |v| format!("{:?}", v), gps, vec![]));
+    }
+    if !quat.is_empty() {
+        util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+            source: "euler_zyx",
+            component_order: "wxyz",
+            handedness: "right",
+            ..Default::default()
+        });
+        util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion, TagId::Data, "Quaternion data", Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quat, vec![]));
+    }
+
+    Ok(vec![
+        SampleInfo { timestamp_ms: 0.0, duration_ms: last_timestamp * 1000.0, tag_map: Some(map), ..Default::default() }
+    ])
+}
@@ -53,107 +53,18 @@ impl EspLog {
         progress_cb: F,
         cancel_flag: Arc<AtomicBool>,
     ) -> Result<Vec<SampleInfo>> {
-        let mut buf = vec![0u8; 8000];
-
-        let mut gyro = Vec::new();
-        let mut accel = Vec::new();
+        let mut decoder = Decoder::new(false);
 
         // skip header
-        stream.read_exact(&mut buf[..7])?;
-
-        let mut accel_blk_size = 0;
-        let mut accel_range = 0i32;
-        let mut state = State::new();
-        let mut tmp_quats = vec![];
-        let mut rates: Vec<[i32; 3]> = vec![];
-        let mut accels: Vec<[i16; 3]> = vec![];
-        let mut cur_time = 0.0;
-        let mut orientation = "xyz".to_string();
+        stream.read_exact(&mut [0u8; 7])?;
+
         let mut res = || {
             while let Ok(byte) = stream.read_u8() {
                 if cancel_flag.load(Ordering::Relaxed) {
                     break;
                 }
-                match byte {
-                    0x01 => {
-                        // gyro setup
-                        if stream.read_u8()? != 0x01 {
-                            return Err(Error::new(ErrorKind::Other, "Unsupported algo revision"));
-                        }
-                        let blk_size = stream.read_u16::<LittleEndian>()?;
-                        tmp_quats.resize(blk_size as usize, [0, 0, 0]);
-                    }
-                    0x02 => {
-                        // delta time
-                        let dt = stream.read_u32::<LittleEndian>()?;
-
-                        // generate timestamps for gyro
-                        let scale = rates.len() as f64 / dt as f64 * 1e6 * 180.0
-                            / std::f64::consts::PI
-                            / ((1 << FIX_MULT) as f64);
-                        for (i, &q) in rates.iter().enumerate() {
-                            gyro.push(TimeVector3 {
-                                t: cur_time + dt as f64 * 1e-6 * (i as f64 / rates.len() as f64),
-                                x: q[0] as f64 * scale,
-                                y: q[1] as f64 * scale,
-                                z: q[2] as f64 * scale,
-                            });
-                        }
-                        rates.clear();
-                        // generate timestamps for accel
-                        let scale = 16.0 / 32767.0;
-                        for (i, &a) in accels.iter().enumerate() {
-                            accel.push(TimeVector3 {
-                                t: cur_time + dt as f64 * 1e-6 * (i as f64 / accels.len() as f64),
-                                x: a[0] as f64 * scale,
-                                y: a[1] as f64 * scale,
-                                z: a[2] as f64 * scale,
-                            });
-                        }
-                        accels.clear();
-                        cur_time += dt as f64 * 1e-6;
-                    }
-                    0x03 => {
-                        // gyro data
-                        let nread = stream.read(&mut buf)?;
-                        if let Some(res) = decompress_block(&state, &buf, &mut tmp_quats) {
-                            rates.extend_from_slice(&tmp_quats);
-                            state = res.new_state;
-                            stream
-                                .seek(SeekFrom::Current(res.bytes_eaten as i64 - nread as i64))?;
-                        } else {
-                            break;
-                        }
-                    }
-                    0x04 => {
-                        // accel setup
-                        accel_blk_size = stream.read_u8()? as usize;
-                        accel_range = 1 << stream.read_u8()?;
-                    }
-                    0x05 => {
-                        // accel data
-                        for _ in 0..accel_blk_size {
-                            accels.push([
-                                stream.read_i16::<LittleEndian>()?,
-                                stream.read_i16::<LittleEndian>()?,
-                                stream.read_i16::<LittleEndian>()?,
-                            ]);
-                        }
-                    }
-                    0x06 => {
-                        // time offset
-                        let ofs = stream.read_i32::<LittleEndian>()?;
-                        cur_time += ofs as f64 * 1e-6;
-                    }
-                    0x07 => {
-                        // imu orientation
-                        let mut buf = vec![0; 3];
-                        stream.read_exact(&mut buf)?;
-                        orientation = from_utf8(&buf).unwrap_or("xyz").to_string();
-                    }
-                    _ => {
-                        break;
-                    }
+                if !decoder.record(byte, stream)? {
+                    break;
                 }
                 progress_cb(stream.stream_position()? as f64 / size as f64);
             }
@@ -165,11 +76,11 @@ impl EspLog {
         // as a result of power failure and this is completely OK
         let res = res();
         if let Err(e) = res {
-            log::warn!("Unknown error during decode: {}", e);
+            crate::parse_warning!("Unknown error during decode: {}", e);
         }
 
         // If we could not decode any samples, then this is definitely an error
-        if gyro.len() == 0 {
+        if decoder.gyro.is_empty() {
             log::error!("No samples decoded");
             return Err(Error::new(
                 ErrorKind::Other,
@@ -177,8 +88,219 @@ impl EspLog {
             ));
         }
 
-        let first_ts = gyro.first().map(|x| x.t).unwrap_or(0.0);
-        let last_ts = gyro.last().map(|x| x.t).unwrap_or(0.0);
+        let first_ts = decoder.gyro.first().map(|x| x.t).unwrap_or(0.0);
+        let last_ts = decoder.gyro.last().map(|x| x.t).unwrap_or(0.0);
+
+        let map = decoder.tag_map();
+
+        Ok(vec![SampleInfo {
+            timestamp_ms: first_ts,
+            duration_ms: last_ts - first_ts,
+            tag_map: Some(map),
+            ..Default::default()
+        }])
+    }
+}
+
+fn read_vec3<T: Read>(stream: &mut T) -> Result<[i16; 3]> {
+    Ok([
+        stream.read_i16::<LittleEndian>()?,
+        stream.read_i16::<LittleEndian>()?,
+        stream.read_i16::<LittleEndian>()?,
+    ])
+}
+
+/// Incremental decoder of the log records after the header, shared by `parse` and the `live` module.
+/// Decoded readings are accumulated until `tag_map` takes them
+pub(crate) struct Decoder {
+    // In live mode a gyro block which can't be decompressed from a short read is reported as incomplete instead of corrupted
+    live: bool,
+    buf: Vec<u8>,
+    gyro: Vec<TimeVector3<f64>>,
+    accel: Vec<TimeVector3<f64>>,
+    magn: Vec<TimeVector3<f64>>,
+    pressure: Vec<TimeScalar<f64>>,
+    accel_blk_size: usize,
+    state: State,
+    tmp_quats: Vec<[i32; 3]>,
+    rates: Vec<[i32; 3]>,
+    accels: Vec<[i16; 3]>,
+    magns: Vec<[i16; 3]>,
+    pressures: Vec<u32>,
+    cur_time: f64,
+    orientation: String,
+}
+
+impl Decoder {
+    pub fn new(live: bool) -> Self {
+        Self {
+            live,
+            buf: vec![0u8; 8000],
+            gyro: Vec::new(),
+            accel: Vec::new(),
+            magn: Vec::new(),
+            pressure: Vec::new(),
+            accel_blk_size: 0,
+            state: State::new(),
+            tmp_quats: vec![],
+            rates: vec![],
+            accels: vec![],
+            magns: vec![],
+            pressures: vec![],
+            cur_time: 0.0,
+            orientation: "xyz".to_string(),
+        }
+    }
+
+    #[cfg(feature = "live")]
+    pub fn has_readings(&self) -> bool {
+        !self.gyro.is_empty() || !self.accel.is_empty() || !self.magn.is_empty() || !self.pressure.is_empty()
+    }
+
+    /// Decodes the record of type `byte`, returns false if it's unknown or its data is corrupted and decoding can't continue
+    pub fn record<T: Read + Seek>(&mut self, byte: u8, stream: &mut T) -> Result<bool> {
+        match byte {
+            0x01 => {
+                // gyro setup
+                if stream.read_u8()? != 0x01 {
+                    return Err(Error::new(ErrorKind::Other, "Unsupported algo revision"));
+                }
+                let blk_size = stream.read_u16::<LittleEndian>()?;
+                self.tmp_quats.resize(blk_size as usize, [0, 0, 0]);
+            }
+            0x02 => {
+                // delta time
+                let dt = stream.read_u32::<LittleEndian>()?;
+                let cur_time = self.cur_time;
+
+                // generate timestamps for gyro
+                let scale = self.rates.len() as f64 / dt as f64 * 1e6 * 180.0
+                    / std::f64::consts::PI
+                    / ((1 << FIX_MULT) as f64);
+                for (i, &q) in self.rates.iter().enumerate() {
+                    self.gyro.push(TimeVector3 {
+                        t: cur_time + dt as f64 * 1e-6 * (i as f64 / self.rates.len() as f64),
+                        x: q[0] as f64 * scale,
+                        y: q[1] as f64 * scale,
+                        z: q[2] as f64 * scale,
+                    });
+                }
+                self.rates.clear();
+                // generate timestamps for accel
+                let scale = 16.0 / 32767.0;
+                for (i, &a) in self.accels.iter().enumerate() {
+                    self.accel.push(TimeVector3 {
+                        t: cur_time + dt as f64 * 1e-6 * (i as f64 / self.accels.len() as f64),
+                        x: a[0] as f64 * scale,
+                        y: a[1] as f64 * scale,
+                        z: a[2] as f64 * scale,
+                    });
+                }
+                self.accels.clear();
+                // generate timestamps for magnetometer, 1/16 μT per LSB
+                for (i, &m) in self.magns.iter().enumerate() {
+                    self.magn.push(TimeVector3 {
+                        t: cur_time + dt as f64 * 1e-6 * (i as f64 / self.magns.len() as f64),
+                        x: m[0] as f64 / 16.0,
+                        y: m[1] as f64 / 16.0,
+                        z: m[2] as f64 / 16.0,
+                    });
+                }
+                self.magns.clear();
+                // generate timestamps for barometer
+                for (i, &p) in self.pressures.iter().enumerate() {
+                    self.pressure.push(TimeScalar {
+                        t: cur_time + dt as f64 * 1e-6 * (i as f64 / self.pressures.len() as f64),
+                        v: p as f64,
+                    });
+                }
+                self.pressures.clear();
+                self.cur_time += dt as f64 * 1e-6;
+            }
+            0x03 => {
+                // gyro data, the block is compressed so its length is known only after decompressing it
+                let mut nread = 0;
+                while nread < self.buf.len() {
+                    match stream.read(&mut self.buf[nread..])? {
+                        0 => break,
+                        n => nread += n
+                    }
+                }
+                // In live mode the rest of a short read may not be received yet, so a block which doesn't decompress from it is incomplete
+                let short_read = nread < self.buf.len();
+                // The block header is 6 bytes
+                let res = if nread >= 6 { decompress_block(&self.state, &self.buf[..nread], &mut self.tmp_quats) } else { None };
+                match res {
+                    Some(res) => {
+                        crate::util::report_checksum(true);
+                        self.rates.extend_from_slice(&self.tmp_quats);
+                        self.state = res.new_state;
+                        stream
+                            .seek(SeekFrom::Current(res.bytes_eaten as i64 - nread as i64))?;
+                    }
+                    None if self.live && short_read => return Err(ErrorKind::UnexpectedEof.into()),
+                    None => {
+                        // Blocks don't have a checksum, a block which fails to decompress is counted as corrupted
+                        crate::util::report_checksum(false);
+                        return Ok(false);
+                    }
+                }
+            }
+            0x04 => {
+                // accel setup
+                self.accel_blk_size = stream.read_u8()? as usize;
+                let _accel_range = 1i32 << stream.read_u8()?;
+            }
+            0x05 => {
+                // accel data, read whole before it's added, so a block cut off in live mode can be decoded again
+                let mut block = Vec::with_capacity(self.accel_blk_size);
+                for _ in 0..self.accel_blk_size {
+                    block.push(read_vec3(stream)?);
+                }
+                self.accels.extend(block);
+            }
+            0x06 => {
+                // time offset
+                let ofs = stream.read_i32::<LittleEndian>()?;
+                self.cur_time += ofs as f64 * 1e-6;
+            }
+            0x07 => {
+                // imu orientation
+                let mut buf = vec![0; 3];
+                stream.read_exact(&mut buf)?;
+                self.orientation = from_utf8(&buf).unwrap_or("xyz").to_string();
+            }
+            0x08 => {
+                // magnetometer data (v2): sample count followed by x/y/z i16 readings
+                let count = stream.read_u8()?;
+                let mut block = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    block.push(read_vec3(stream)?);
+                }
+                self.magns.extend(block);
+            }
+            0x09 => {
+                // barometer data (v2): sample count followed by u32 pressure readings in Pa
+                let count = stream.read_u8()?;
+                let mut block = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    block.push(stream.read_u32::<LittleEndian>()?);
+                }
+                self.pressures.extend(block);
+            }
+            _ => {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Takes the decoded readings
+    pub fn tag_map(&mut self) -> GroupedTagMap {
+        let accel = std::mem::take(&mut self.accel);
+        let gyro = std::mem::take(&mut self.gyro);
+        let magn = std::mem::take(&mut self.magn);
+        let pressure = std::mem::take(&mut self.pressure);
 
         let mut map = GroupedTagMap::new();
         util::insert_tag(
@@ -201,18 +323,48 @@ impl EspLog {
 
         util::insert_tag(
             &mut map,
-            tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), orientation.clone(), Vec::new()),
+            tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), self.orientation.clone(), Vec::new()),
         );
         util::insert_tag(
             &mut map,
-            tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), orientation.clone(), Vec::new()),
+            tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), self.orientation.clone(), Vec::new()),
         );
 
-        Ok(vec![SampleInfo {
-            timestamp_ms: first_ts,
-            duration_ms: last_ts - first_ts,
-            tag_map: Some(map),
-            ..Default::default()
-        }])
+        // Magnetometer and barometer blocks are only present in v2 logs
+        if !magn.is_empty() {
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Magnetometer, TagId::Data, "Magnetometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), magn, vec![]),
+            );
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Magnetometer, TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()),
+            );
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Magnetometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), self.orientation.clone(), Vec::new()),
+            );
+        }
+        if !pressure.is_empty() {
+            // International barometric formula, relative to the standard sea level pressure
+            let altitude = pressure.iter().map(|p| TimeScalar { t: p.t, v: 44330.0 * (1.0 - (p.v / 101325.0).powf(1.0 / 5.255)) }).collect::<Vec<_>>();
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Pressure, TagId::Data, "Barometric pressure", Vec_TimeScalar_f64, |v| format!("{:?}", v), pressure, vec![]),
+            );
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Pressure, TagId::Unit, "Pressure unit", String, |v| v.to_string(), "Pa".into(), Vec::new()),
+            );
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Altitude, TagId::Data, "Barometric altitude", Vec_TimeScalar_f64, |v| format!("{:?}", v), altitude, vec![]),
+            );
+            util::insert_tag(
+                &mut map,
+                tag!(parsed GroupId::Altitude, TagId::Unit, "Altitude unit", String, |v| v.to_string(), "m".into(), Vec::new()),
+            );
+        }
+        map
     }
 }
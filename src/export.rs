@@ -0,0 +1,368 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Export of all time series tags (Vec_Time*) to JSON lines or InfluxDB line protocol,
+// one record per reading, so the telemetry can be piped directly to Influx/Grafana or data-science tools.
+// Also extraction of the raw GPMF track from GoPro files, to share small telemetry-only files instead of the whole video,
+// the versioned JSON of all tags used by the Python and wasm bindings, and column-major buffers of the time series for numpy/Arrow.
+// Binary Betaflight blackbox logs of the IMU data are written by `bbl::write_bbl`
+
+use std::io::{ Read, Seek, Write };
+use std::collections::BTreeMap;
+use std::sync::{ Arc, atomic::AtomicBool };
+
+use crate::tags_impl::*;
+use crate::{ util, Input };
+
+pub mod bbl;
+#[cfg(feature = "arrow-export")]
+pub mod columnar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    JsonLines,
+    InfluxLineProtocol,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Only export these groups. All groups if `None`
+    pub groups: Option<Vec<GroupId>>,
+    /// Only export these fields (eg. `x`, `y`, `z`, `w`, `v`, `v0`...). All fields if `None`
+    pub fields: Option<Vec<String>>,
+    /// Added to every timestamp, eg. to convert clip-relative times to unix time
+    pub time_offset_s: f64,
+    /// Additional tags added to every record, next to `camera` and `model`
+    pub extra_tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+enum FieldValue {
+    Float(f64),
+    Int(i64),
+    Str(String),
+}
+
+const ARRAY_FIELDS: [&str; 8] = ["v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7"];
+
+type Record = (f64, Vec<(&'static str, FieldValue)>);
+
+fn time_series(value: &TagValue) -> Option<Vec<Record>> {
+    macro_rules! vec3 {
+        ($v:expr) => {
+            $v.get().iter().map(|x| (x.t as f64, vec![("x", FieldValue::Float(x.x as f64)), ("y", FieldValue::Float(x.y as f64)), ("z", FieldValue::Float(x.z as f64))])).collect()
+        };
+    }
+    macro_rules! array {
+        ($v:expr) => {
+            $v.get().iter().map(|x| (x.t, x.v.iter().enumerate().map(|(i, v)| (ARRAY_FIELDS[i], FieldValue::Float(*v))).collect())).collect()
+        };
+    }
+    Some(match value {
+        TagValue::Vec_TimeScalar_f64(v)  => v.get().iter().map(|x| (x.t, vec![("v", FieldValue::Float(x.v))])).collect(),
+        TagValue::Vec_TimeScalar_i64(v)  => v.get().iter().map(|x| (x.t, vec![("v", FieldValue::Int(x.v))])).collect(),
+        TagValue::Vec_TimeScalar_bool(v) => v.get().iter().map(|x| (x.t, vec![("v", FieldValue::Int(x.v as i64))])).collect(),
+        TagValue::Vec_TimeScalar_Json(v) => v.get().iter().map(|x| (x.t, vec![("v", match &x.v {
+            serde_json::Value::Number(n) if n.is_i64() => FieldValue::Int(n.as_i64().unwrap_or_default()),
+            serde_json::Value::Number(n) => FieldValue::Float(n.as_f64().unwrap_or_default()),
+            serde_json::Value::String(s) => FieldValue::Str(s.clone()),
+            v => FieldValue::Str(v.to_string())
+        })])).collect(),
+        TagValue::Vec_TimeVector3_i8(v)     => vec3!(v),
+        TagValue::Vec_TimeVector3_u8(v)     => vec3!(v),
+        TagValue::Vec_TimeVector3_i16(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_u16(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_i32(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_u32(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_f32(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_f64(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_i64(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_u64(v)    => vec3!(v),
+        TagValue::Vec_TimeVector3_i64f64(v) => vec3!(v),
+        // Quaternion timestamps are in milliseconds
+        TagValue::Vec_TimeQuaternion_f64(v) => v.get().iter().map(|x| (x.t / 1000.0, vec![("w", FieldValue::Float(x.v.w)), ("x", FieldValue::Float(x.v.x)), ("y", FieldValue::Float(x.v.y)), ("z", FieldValue::Float(x.v.z))])).collect(),
+        TagValue::Vec_TimeArray2_f64(v)     => array!(v),
+        TagValue::Vec_TimeArray4_f64(v)     => array!(v),
+        TagValue::Vec_TimeArray8_f64(v)     => array!(v),
+        _ => return None
+    })
+}
+
+fn escape_influx(s: &str, chars: &[char]) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || chars.contains(&c) { ret.push('\\'); }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Writes all time series from `input` to `writer`. Returns the number of written records
+pub fn export_time_series<W: Write>(input: &Input, options: &ExportOptions, writer: &mut W) -> std::io::Result<usize> {
+    let samples = match input.samples.as_ref() {
+        Some(x) => x,
+        None => return Ok(0)
+    };
+
+    let mut tags = BTreeMap::new();
+    tags.insert("camera".to_owned(), input.camera_type());
+    if let Some(model) = input.camera_model() {
+        tags.insert("model".to_owned(), model.clone());
+    }
+    tags.extend(options.extra_tags.clone());
+
+    let mut count = 0;
+    for info in samples {
+        let Some(grouped_tag_map) = info.tag_map.as_ref() else { continue; };
+        for (group, map) in grouped_tag_map {
+            if let Some(groups) = &options.groups {
+                if !groups.contains(group) { continue; }
+            }
+            for (tag_id, desc) in map {
+                let Some(records) = time_series(&desc.value) else { continue; };
+                for (t, fields) in records {
+                    let fields = fields.into_iter()
+                        .filter(|(name, _)| options.fields.as_ref().map(|f| f.iter().any(|x| x == *name)).unwrap_or(true))
+                        .filter(|(_, v)| !matches!(v, FieldValue::Float(v) if !v.is_finite()))
+                        .collect::<Vec<_>>();
+                    if fields.is_empty() { continue; }
+                    let t = t + options.time_offset_s;
+
+                    match options.format {
+                        ExportFormat::JsonLines => {
+                            let mut obj = serde_json::Map::new();
+                            obj.insert("t".into(), t.into());
+                            obj.insert("group".into(), group.to_string().into());
+                            obj.insert("tag".into(), tag_id.to_string().into());
+                            for (k, v) in &tags {
+                                obj.insert(k.clone(), v.clone().into());
+                            }
+                            for (name, v) in fields {
+                                obj.insert(name.into(), match v {
+                                    FieldValue::Float(v) => v.into(),
+                                    FieldValue::Int(v)   => v.into(),
+                                    FieldValue::Str(v)   => v.into(),
+                                });
+                            }
+                            writeln!(writer, "{}", serde_json::Value::Object(obj))?;
+                        },
+                        ExportFormat::InfluxLineProtocol => {
+                            // measurement,tag=value,... field=value,... timestamp_ns
+                            let mut line = escape_influx(&group.to_string(), &[',', ' ']);
+                            line.push_str(&format!(",tag={}", escape_influx(&tag_id.to_string(), &[',', '=', ' '])));
+                            for (k, v) in &tags {
+                                if v.is_empty() { continue; }
+                                line.push_str(&format!(",{}={}", escape_influx(k, &[',', '=', ' ']), escape_influx(v, &[',', '=', ' '])));
+                            }
+                            line.push(' ');
+                            let fields = fields.into_iter().map(|(name, v)| match v {
+                                FieldValue::Float(v) => format!("{name}={v}"),
+                                FieldValue::Int(v)   => format!("{name}={v}i"),
+                                FieldValue::Str(v)   => format!("{name}=\"{}\"", escape_influx(&v, &['"'])),
+                            }).collect::<Vec<String>>();
+                            line.push_str(&fields.join(","));
+                            writeln!(writer, "{line} {}", (t * 1_000_000_000.0).round() as i64)?;
+                        }
+                    }
+                    count += 1;
+                }
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Time series of a single tag in column-major layout, for the bindings: `t` and every column are contiguous `f64` buffers
+/// of the same length, so they can be wrapped as numpy or Arrow arrays without creating an object per reading
+#[derive(Debug, Clone)]
+pub struct ColumnBuffers {
+    pub group: GroupId,
+    pub tag: TagId,
+    /// Names of the columns, eg. `x`, `y`, `z`, or `w`, `x`, `y`, `z` for quaternions
+    pub fields: Vec<&'static str>,
+    /// Timestamps in seconds, also for the quaternions
+    pub t: Vec<f64>,
+    /// One buffer per field. Values which aren't numbers are NaN
+    pub columns: Vec<Vec<f64>>,
+}
+impl ColumnBuffers {
+    pub fn len(&self) -> usize {
+        self.t.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.t.is_empty()
+    }
+    pub fn column(&self, field: &str) -> Option<&[f64]> {
+        let i = self.fields.iter().position(|x| *x == field)?;
+        Some(&self.columns[i])
+    }
+}
+
+/// All time series of `input` (or only of `groups`) as column buffers, with the readings of all samples concatenated
+pub fn column_buffers(input: &Input, groups: Option<&[GroupId]>) -> Vec<ColumnBuffers> {
+    let mut ret = BTreeMap::<(GroupId, TagId), ColumnBuffers>::new();
+    for grouped_tag_map in input.samples.iter().flatten().filter_map(|x| x.tag_map.as_ref()) {
+        for (group, map) in grouped_tag_map {
+            if groups.map(|g| !g.contains(group)).unwrap_or_default() { continue; }
+            for (tag_id, desc) in map {
+                let Some(records) = time_series(&desc.value) else { continue; };
+                if records.is_empty() { continue; }
+                let time_scale = if matches!(desc.value, TagValue::Vec_TimeQuaternion_f64(_)) { 0.001 } else { 1.0 };
+
+                let buffers = ret.entry((group.clone(), tag_id.clone())).or_insert_with(|| {
+                    let fields = records[0].1.iter().map(|x| x.0).collect::<Vec<_>>();
+                    ColumnBuffers { group: group.clone(), tag: tag_id.clone(), columns: vec![Vec::new(); fields.len()], fields, t: Vec::new() }
+                });
+                buffers.t.reserve(records.len());
+                for col in buffers.columns.iter_mut() {
+                    col.reserve(records.len());
+                }
+                for (t, fields) in records {
+                    buffers.t.push(t * time_scale);
+                    for (col, (_, v)) in buffers.columns.iter_mut().zip(fields) {
+                        col.push(match v {
+                            FieldValue::Float(v) => v,
+                            FieldValue::Int(v) => v as f64,
+                            FieldValue::Str(_) => f64::NAN,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    ret.into_values().collect()
+}
+
+/// Version of the JSON produced by `telemetry_json` and `write_telemetry_json`.
+/// Any change of the structure has to bump it and add an entry to `TELEMETRY_SCHEMA_CHANGELOG`, the build fails if they don't match
+pub const TELEMETRY_SCHEMA_VERSION: u32 = 1;
+
+/// (version, description of the change), oldest first
+pub const TELEMETRY_SCHEMA_CHANGELOG: &[(u32, &str)] = &[
+    (1, "Versioned object: { schema_version, camera, model, samples: [{ <group>: { <tag>: value } }] }. Optional columnar time series"),
+];
+const _: () = assert!(TELEMETRY_SCHEMA_CHANGELOG[TELEMETRY_SCHEMA_CHANGELOG.len() - 1].0 == TELEMETRY_SCHEMA_VERSION, "Add the new schema version to TELEMETRY_SCHEMA_CHANGELOG");
+
+#[derive(Debug, Clone, Default)]
+pub struct TelemetryJsonOptions {
+    /// Values formatted with units etc. (`TagDescription::format`) instead of the raw values
+    pub human_readable: bool,
+    /// Arrays of objects with the same keys (eg. `[{t, x, y, z}, ...]`) are stored as an object of arrays (`{t: [...], x: [...], ...}`),
+    /// which is much faster to consume from JS. Ignored with `human_readable`
+    pub columnar: bool,
+    /// Keys of all objects sorted (`util::canonical_json`), so the output of the same file is byte-exact between versions and platforms
+    pub sort_keys: bool,
+}
+
+// [{a: 1, b: 2}, {a: 3, b: 4}] -> {a: [1, 3], b: [2, 4]}, if all items are objects with the same keys
+fn to_columnar(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::Array(items) => {
+            let keys = match items.first() {
+                Some(Value::Object(first)) => first.keys().cloned().collect::<Vec<String>>(),
+                _ => return Value::Array(items)
+            };
+            let same_keys = items.iter().all(|x| matches!(x, Value::Object(o) if o.len() == keys.len() && keys.iter().all(|k| o.contains_key(k))));
+            if !same_keys { return Value::Array(items); }
+
+            let mut columns = keys.into_iter().map(|k| (k, Vec::with_capacity(items.len()))).collect::<Vec<(String, Vec<Value>)>>();
+            for item in items {
+                if let Value::Object(mut o) = item {
+                    for (k, column) in columns.iter_mut() {
+                        column.push(o.remove(k.as_str()).unwrap_or_default());
+                    }
+                }
+            }
+            Value::Object(columns.into_iter().map(|(k, v)| (k, Value::Array(v))).collect())
+        },
+        Value::Object(o) => Value::Object(o.into_iter().map(|(k, v)| (k, to_columnar(v))).collect()),
+        v => v
+    }
+}
+
+fn sample_json(map: &GroupedTagMap, options: &TelemetryJsonOptions) -> serde_json::Value {
+    let mut groups = serde_json::Map::new();
+    for (group, tags) in map {
+        let mut group_map = serde_json::Map::new();
+        for (tag_id, desc) in tags {
+            let value = if options.human_readable {
+                serde_json::Value::String(desc.format().unwrap_or_else(|e| format!("<{e}>")))
+            } else {
+                let v = serde_json::to_value(&desc.value).unwrap_or_default();
+                if options.columnar { to_columnar(v) } else { v }
+            };
+            let value = if options.sort_keys { util::canonical_json(&value) } else { value };
+            group_map.insert(tag_id.to_string(), value);
+        }
+        groups.insert(group.to_string(), serde_json::Value::Object(group_map));
+    }
+    serde_json::Value::Object(groups)
+}
+
+fn telemetry_header(input: &Input) -> serde_json::Map<String, serde_json::Value> {
+    let mut obj = serde_json::Map::new();
+    obj.insert("schema_version".into(), TELEMETRY_SCHEMA_VERSION.into());
+    obj.insert("camera".into(), input.camera_type().into());
+    obj.insert("model".into(), input.camera_model().cloned().into());
+    obj
+}
+
+/// All tags of `input` as a versioned JSON object, see `TELEMETRY_SCHEMA_CHANGELOG` for the structure
+pub fn telemetry_json(input: &Input, options: &TelemetryJsonOptions) -> serde_json::Value {
+    let mut obj = telemetry_header(input);
+    let samples = input.samples.iter().flatten()
+        .filter_map(|x| x.tag_map.as_ref())
+        .map(|x| sample_json(x, options))
+        .collect::<Vec<_>>();
+    obj.insert("samples".into(), samples.into());
+    serde_json::Value::Object(obj)
+}
+
+/// Same as `telemetry_json`, but written to `writer` one sample at a time, so the whole document is never held in memory.
+/// Returns the number of written samples
+pub fn write_telemetry_json<W: Write>(input: &Input, options: &TelemetryJsonOptions, writer: &mut W) -> std::io::Result<usize> {
+    let header = serde_json::Value::Object(telemetry_header(input)).to_string();
+    // Reopen the header object to append the samples array
+    write!(writer, "{},\"samples\":[", header.strip_suffix('}').unwrap_or(&header))?;
+    let mut count = 0;
+    for map in input.samples.iter().flatten().filter_map(|x| x.tag_map.as_ref()) {
+        if count > 0 { writer.write_all(b",")?; }
+        serde_json::to_writer(&mut *writer, &sample_json(map, options))?;
+        count += 1;
+    }
+    writer.write_all(b"]}")?;
+    Ok(count)
+}
+
+/// Copies the GPMF track of a GoPro MP4 to `writer` as a raw `.gpmf` file, which can be loaded back with `Input::from_stream`.
+/// The GPMF from the `udta` box (camera model, settings) is written first, followed by the payloads of the track, byte-exact.
+/// Returns the number of written bytes
+pub fn extract_gpmf<T: Read + Seek, W: Write>(stream: &mut T, size: usize, writer: &mut W, cancel_flag: Arc<AtomicBool>) -> std::io::Result<usize> {
+    let mut written = 0;
+
+    let buffer = util::read_beginning_and_end(stream, size, 4*1024*1024)?;
+    if let Some(pos) = memchr::memmem::find(&buffer, b"GPMFDEVC").filter(|x| *x >= 4) {
+        let len = u32::from_be_bytes([buffer[pos - 4], buffer[pos - 3], buffer[pos - 2], buffer[pos - 1]]) as usize;
+        if let Some(udta) = buffer.get(pos + 4..pos - 4 + len) {
+            writer.write_all(udta)?;
+            written += udta.len();
+        }
+    }
+
+    let mut result = Ok(());
+    util::get_metadata_track_samples(stream, size, true, |_info, data: &[u8], _file_position, _video_md| {
+        if result.is_ok() && data.len() > 8 && &data[0..4] == b"DEVC" {
+            result = writer.write_all(data);
+            written += data.len();
+        }
+    }, cancel_flag)?;
+    result?;
+
+    if written == 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "No GPMF data found"));
+    }
+    Ok(written)
+}
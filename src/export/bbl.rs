@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Binary Betaflight blackbox log (.bbl) with the normalized gyroscope and accelerometer, for tools which don't read the CSV export.
+// Every frame is an intra frame with the loop iteration and time as unsigned and the IMU values as signed variable-byte integers,
+// so no predictor state is needed. Axes and scales are the same as in the `gyro2bb` CSV: deg/s for the gyroscope and `acc_1G` units for the accelerometer
+
+use std::io::{ Result, Write };
+
+use crate::util;
+use crate::Input;
+
+const ACC_1G: f64 = 2048.0;
+
+pub(crate) fn write_unsigned_vb(out: &mut Vec<u8>, mut v: u32) {
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+pub(crate) fn write_signed_vb(out: &mut Vec<u8>, v: i32) {
+    write_unsigned_vb(out, ((v << 1) ^ (v >> 31)) as u32); // ZigZag
+}
+
+/// Writes the IMU data of `input` as a blackbox log, `orientation` is passed to `util::normalized_imu`.
+/// Returns the number of written frames
+pub fn write_bbl<W: Write>(input: &Input, orientation: Option<String>, writer: &mut W) -> Result<usize> {
+    let imu_data = util::normalized_imu(input, orientation)?;
+    let imu_data = imu_data.iter().filter(|x| x.gyro.is_some() || x.accl.is_some()).collect::<Vec<_>>();
+
+    let looptime = match (imu_data.first(), imu_data.last()) {
+        (Some(first), Some(last)) if imu_data.len() > 1 => ((last.timestamp_ms - first.timestamp_ms) * 1000.0 / (imu_data.len() - 1) as f64).round().max(1.0),
+        _ => 1000.0
+    };
+    let craft_name = format!("{} {}", input.camera_type(), input.camera_model().map(|x| x.as_str()).unwrap_or_default());
+
+    let header = [
+        "Product:Blackbox flight data recorder by Nicholas Sherlock".to_owned(),
+        "Data version:2".to_owned(),
+        "I interval:1".to_owned(),
+        "P interval:1/1".to_owned(),
+        "Field I name:loopIteration,time,gyroADC[0],gyroADC[1],gyroADC[2],accSmooth[0],accSmooth[1],accSmooth[2]".to_owned(),
+        "Field I signed:0,0,1,1,1,1,1,1".to_owned(),
+        "Field I predictor:0,0,0,0,0,0,0,0".to_owned(),
+        "Field I encoding:1,1,0,0,0,0,0,0".to_owned(),
+        "Field P predictor:6,2,1,1,1,1,1,1".to_owned(),
+        "Field P encoding:9,0,0,0,0,0,0,0".to_owned(),
+        "Firmware type:Cleanflight".to_owned(),
+        format!("Firmware revision:telemetry-parser {}", env!("CARGO_PKG_VERSION")),
+        format!("Craft name:{}", craft_name.trim()),
+        format!("looptime:{looptime:.0}"),
+        "gyro_scale:0x3f800000".to_owned(),
+        format!("acc_1G:{ACC_1G:.0}"),
+    ];
+    let mut out = Vec::with_capacity(64 * 1024);
+    for line in header {
+        out.extend_from_slice(format!("H {line}\n").as_bytes());
+    }
+
+    let first_timestamp = imu_data.first().map(|x| x.timestamp_ms).unwrap_or_default();
+    for (i, v) in imu_data.iter().enumerate() {
+        let gyro = v.gyro.unwrap_or_default();
+        let accl = v.accl.unwrap_or_default();
+        out.push(b'I');
+        write_unsigned_vb(&mut out, i as u32);
+        write_unsigned_vb(&mut out, ((v.timestamp_ms - first_timestamp) * 1000.0).round().max(0.0) as u32);
+        for value in [-gyro[2], gyro[1], gyro[0], -accl[2] * ACC_1G, accl[1] * ACC_1G, accl[0] * ACC_1G] {
+            write_signed_vb(&mut out, value.round() as i32);
+        }
+        if out.len() > 60 * 1024 {
+            writer.write_all(&out)?;
+            out.clear();
+        }
+    }
+    out.extend_from_slice(b"E\xffEnd of log\0");
+    writer.write_all(&out)?;
+
+    Ok(imu_data.len())
+}
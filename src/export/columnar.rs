@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Arrow record batches of the time series, enabled with the `arrow-export` feature, and Parquet files with `parquet-export`.
+// Each time series tag is a separate batch with a `t` column (seconds) followed by the fields (`x`, `y`, `z`...) as Float64.
+// The schema metadata has the `group` and `tag` names, and the value columns have the `unit` of the group in the field metadata
+
+use std::collections::{ BTreeMap, HashMap };
+use std::sync::Arc;
+use arrow::array::{ ArrayRef, Float64Array };
+use arrow::datatypes::{ DataType, Field, Schema };
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::tags_impl::*;
+use crate::Input;
+
+// Unit of each group, from the first sample which has it
+fn units(input: &Input) -> BTreeMap<GroupId, String> {
+    let mut ret = BTreeMap::new();
+    for grouped_tag_map in input.samples.iter().flatten().filter_map(|x| x.tag_map.as_ref()) {
+        for (group, map) in grouped_tag_map {
+            if let Some(unit) = map.get_t(TagId::Unit) as Option<&String> {
+                ret.entry(group.clone()).or_insert_with(|| unit.clone());
+            }
+        }
+    }
+    ret
+}
+
+/// One record batch per time series tag of `input` (or only of `groups`)
+pub fn record_batches(input: &Input, groups: Option<&[GroupId]>) -> Result<Vec<RecordBatch>, ArrowError> {
+    let units = units(input);
+    super::column_buffers(input, groups).into_iter().map(|buffers| {
+        let unit = units.get(&buffers.group);
+        let metadata = HashMap::from([
+            ("group".to_owned(), buffers.group.to_string()),
+            ("tag".to_owned(), buffers.tag.to_string()),
+        ]);
+        let mut fields = vec![Field::new("t", DataType::Float64, false).with_metadata(HashMap::from([("unit".to_owned(), "s".to_owned())]))];
+        for name in &buffers.fields {
+            let field = Field::new(*name, DataType::Float64, true);
+            fields.push(match unit {
+                Some(unit) => field.with_metadata(HashMap::from([("unit".to_owned(), unit.clone())])),
+                None => field
+            });
+        }
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(Float64Array::from(buffers.t))];
+        columns.extend(buffers.columns.into_iter().map(|x| Arc::new(Float64Array::from(x)) as ArrayRef));
+
+        RecordBatch::try_new(Arc::new(Schema::new_with_metadata(fields, metadata)), columns)
+    }).collect()
+}
+
+/// Writes `batch` to `writer` as a Parquet file. The group, tag and units are kept in the Arrow schema stored in the file
+#[cfg(feature = "parquet-export")]
+pub fn write_parquet<W: std::io::Write + Send>(batch: &RecordBatch, writer: W) -> Result<(), parquet::errors::ParquetError> {
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
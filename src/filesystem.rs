@@ -4,6 +4,10 @@
 use std::io::Read;
 use std::sync::OnceLock;
 
+#[cfg(feature = "http")]
+pub mod http;
+pub mod spool;
+
 #[cfg(target_os = "android")]
 mod base {
     pub type FilesystemBase = jni::JavaVM;
@@ -56,6 +60,11 @@ pub fn get_folder(path: &str) -> String {
 pub fn file_with_extension(path: &str, ext: &str) -> Option<String> {
     if let Some(pos) = path.rfind('.') {
         let new_path = if ext.is_empty() { path[..pos].to_owned() } else { format!("{}.{}", &path[..pos], ext) };
+        #[cfg(feature = "http")]
+        if http::is_url(path) {
+            return if http::HttpFile::exists(&new_path) { Some(new_path) } else { None };
+        }
+        #[cfg(not(feature = "no-fs"))]
         if std::path::Path::new(&new_path).exists() {
             return Some(new_path);
         }
@@ -76,18 +85,44 @@ pub fn list_folder(path: &str) -> Vec<(String, String)> {
     if let Some(funcs) = FILESYSTEM_FUNCTIONS.get() {
         return (funcs.list_folder)(path);
     }
-    let mut files = Vec::new();
-    if let Ok(entries) = std::fs::read_dir(path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                if entry.metadata().as_ref().map(|x| x.is_file() || x.is_dir()).unwrap_or_default() {
-                    files.push((entry.file_name().to_string_lossy().to_string(), entry.path().to_string_lossy().to_string()));
+    std_fs::list_folder(path)
+}
+
+// With the `no-fs` feature (eg. for WebAssembly in the browser), only the functions from `set_filesystem_functions` can access the files,
+// so sidecar files and other parts of the recording aren't looked up
+#[cfg(not(feature = "no-fs"))]
+mod std_fs {
+    use super::*;
+    pub fn list_folder(path: &str) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(path) {
+            for entry in entries {
+                if let Ok(entry) = entry {
+                    if entry.metadata().as_ref().map(|x| x.is_file() || x.is_dir()).unwrap_or_default() {
+                        files.push((entry.file_name().to_string_lossy().to_string(), entry.path().to_string_lossy().to_string()));
+                    }
                 }
             }
         }
+        files
+    }
+    pub fn open_file<'a>(path: &str) -> std::io::Result<FileWrapper<'a>> {
+        let file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len() as usize;
+        Ok(FileWrapper { file: Box::new(file), size })
     }
-    files
 }
+#[cfg(feature = "no-fs")]
+mod std_fs {
+    use super::*;
+    pub fn list_folder(_path: &str) -> Vec<(String, String)> {
+        Vec::new()
+    }
+    pub fn open_file<'a>(path: &str) -> std::io::Result<FileWrapper<'a>> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("Filesystem access is disabled, can't open {path}")))
+    }
+}
+
 pub trait ReadSeek: std::io::Read + std::io::Seek {}
 impl<T: std::io::Read + std::io::Seek> ReadSeek for T {}
 
@@ -99,9 +134,13 @@ pub fn open_file<'a>(_base: &'a FilesystemBase, path: &str) -> std::io::Result<F
     if let Some(funcs) = FILESYSTEM_FUNCTIONS.get() {
         return (funcs.open_file)(_base, path);
     }
-    let file = std::fs::File::open(path)?;
-    let size = file.metadata()?.len() as usize;
-    Ok(FileWrapper { file: Box::new(file), size })
+    #[cfg(feature = "http")]
+    if http::is_url(path) {
+        let file = http::HttpFile::open(path)?;
+        let size = file.size() as usize;
+        return Ok(FileWrapper { file: Box::new(file), size });
+    }
+    std_fs::open_file(path)
 }
 
 pub fn get_extension(path: &str) -> String {
@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Read + Seek over HTTP(S) range requests, so files in cloud storage can be parsed without downloading them.
+// Parsers read the header and footer first and then only the metadata tracks, so usually only a small part of the file is transferred.
+// Small reads are served from a cached block, to avoid a request for every box header
+
+use std::io::*;
+
+const BLOCK_SIZE: u64 = 1024 * 1024;
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+fn to_io_error(e: ureq::Error) -> Error {
+    match e {
+        ureq::Error::Status(404, _) => Error::new(ErrorKind::NotFound, e.to_string()),
+        ureq::Error::Status(401 | 403, _) => Error::new(ErrorKind::PermissionDenied, e.to_string()),
+        e => Error::new(ErrorKind::Other, e.to_string())
+    }
+}
+
+pub struct HttpFile {
+    agent: ureq::Agent,
+    url: String,
+    size: u64,
+    position: u64,
+    block: Vec<u8>,
+    block_start: u64,
+}
+
+impl HttpFile {
+    pub fn open(url: &str) -> Result<Self> {
+        let agent = ureq::AgentBuilder::new().build();
+        let size = Self::content_length(&agent, url)?;
+        Ok(Self { agent, url: url.to_owned(), size, position: 0, block: Vec::new(), block_start: 0 })
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the file exists on the server, used for the sidecar files
+    pub fn exists(url: &str) -> bool {
+        ureq::head(url).call().is_ok()
+    }
+
+    // Some servers don't report the length on HEAD, in that case it's taken from the `Content-Range` of a single byte request
+    fn content_length(agent: &ureq::Agent, url: &str) -> Result<u64> {
+        let head = agent.head(url).call().map_err(to_io_error)?;
+        if head.header("Accept-Ranges") != Some("none") {
+            if let Some(len) = head.header("Content-Length").and_then(|x| x.parse::<u64>().ok()) {
+                return Ok(len);
+            }
+        }
+        let resp = agent.get(url).set("Range", "bytes=0-0").call().map_err(to_io_error)?;
+        if resp.status() != 206 {
+            return Err(Error::new(ErrorKind::Unsupported, "Server doesn't support range requests"));
+        }
+        resp.header("Content-Range")
+            .and_then(|x| x.rsplit('/').next())
+            .and_then(|x| x.parse::<u64>().ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Missing file size in Content-Range"))
+    }
+
+    fn fetch(&self, start: u64, len: u64, out: &mut Vec<u8>) -> Result<()> {
+        let end = (start + len).min(self.size);
+        out.clear();
+        if end <= start { return Ok(()); }
+
+        let resp = self.agent.get(&self.url).set("Range", &format!("bytes={}-{}", start, end - 1)).call().map_err(to_io_error)?;
+        if resp.status() != 206 {
+            return Err(Error::new(ErrorKind::Unsupported, "Server doesn't support range requests"));
+        }
+        resp.into_reader().take(end - start).read_to_end(out)?;
+        if out.len() as u64 != end - start {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!("Expected {} bytes from the server, got {}", end - start, out.len())));
+        }
+        Ok(())
+    }
+}
+
+impl Read for HttpFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.position >= self.size || buf.is_empty() { return Ok(0); }
+
+        // Large reads go directly to the server
+        if buf.len() as u64 >= BLOCK_SIZE {
+            let mut data = Vec::with_capacity(buf.len());
+            self.fetch(self.position, buf.len() as u64, &mut data)?;
+            buf[..data.len()].copy_from_slice(&data);
+            self.position += data.len() as u64;
+            return Ok(data.len());
+        }
+
+        let in_block = self.position >= self.block_start && self.position < self.block_start + self.block.len() as u64;
+        if !in_block {
+            let mut block = std::mem::take(&mut self.block);
+            self.fetch(self.position, BLOCK_SIZE, &mut block)?;
+            self.block = block;
+            self.block_start = self.position;
+        }
+        let offset = (self.position - self.block_start) as usize;
+        let n = buf.len().min(self.block.len() - offset);
+        buf[..n].copy_from_slice(&self.block[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::End(x) => self.size as i64 + x,
+            SeekFrom::Current(x) => self.position as i64 + x,
+        };
+        if new_pos < 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Seek before the start of the file"));
+        }
+        self.position = new_pos as u64;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Read + Seek over a non-seekable source (pipe, stdin, network stream), for `Input::from_reader`.
+// Parsers need the size of the input and read the header and footer for the detection, so the source is read completely first.
+// It's kept in memory up to the limit, larger inputs are written to a temporary file, which is deleted on drop.
+// With the `no-fs` feature there are no temporary files and the limit is ignored
+
+use std::io::*;
+use std::path::PathBuf;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    File(std::fs::File, PathBuf),
+}
+
+pub struct SpooledReader {
+    spool: Spool,
+    size: usize,
+}
+
+fn temp_path() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("telemetry-parser-{}-{}.spool", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)))
+}
+
+impl SpooledReader {
+    pub fn new<R: Read>(mut reader: R, memory_limit: usize) -> Result<Self> {
+        let memory_limit = if cfg!(feature = "no-fs") { usize::MAX } else { memory_limit };
+        let mut data = Vec::new();
+        (&mut reader).take((memory_limit as u64).saturating_add(1)).read_to_end(&mut data)?;
+        if data.len() <= memory_limit {
+            let size = data.len();
+            return Ok(Self { spool: Spool::Memory(Cursor::new(data)), size });
+        }
+
+        let path = temp_path();
+        let file = std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        // Created before writing, so the file is deleted also when the copy fails
+        let mut ret = Self { spool: Spool::File(file, path), size: 0 };
+        if let Spool::File(ref mut file, _) = ret.spool {
+            file.write_all(&data)?;
+            let copied = std::io::copy(&mut reader, file)?;
+            file.seek(SeekFrom::Start(0))?;
+            ret.size = data.len() + copied as usize;
+        }
+        Ok(ret)
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    pub fn is_in_memory(&self) -> bool {
+        matches!(self.spool, Spool::Memory(_))
+    }
+}
+
+impl Read for SpooledReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.spool {
+            Spool::Memory(x) => x.read(buf),
+            Spool::File(x, _) => x.read(buf),
+        }
+    }
+}
+
+impl Seek for SpooledReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match &mut self.spool {
+            Spool::Memory(x) => x.seek(pos),
+            Spool::File(x, _) => x.seek(pos),
+        }
+    }
+}
+
+impl Drop for SpooledReader {
+    fn drop(&mut self) {
+        // The file has to be closed before it can be removed on Windows
+        if let Spool::File(file, path) = std::mem::replace(&mut self.spool, Spool::Memory(Cursor::new(Vec::new()))) {
+            drop(file);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
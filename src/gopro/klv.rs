@@ -162,7 +162,8 @@ impl KLV {
             b"WBAL" | b"ISOE" | b"SHUT" |
             b"MWET" | b"IORI" | b"CORI" |
             b"AALP" | b"WNDM" | b"UNIF" |
-            b"WRGB" | b"GPS5" | b"FASC" => TagId::Data,
+            b"WRGB" | b"GPS5" | b"FASC" |
+            b"GPS9" | b"MSKP" | b"LSKP" => TagId::Data,
 
             b"SIUN" | b"UNIT" => TagId::Unit,
             b"MTRX" => TagId::Matrix,
@@ -188,6 +189,9 @@ impl KLV {
             b"IORI" => GroupId::ImageOrientation,
             b"SHUT" => GroupId::Exposure,
             b"GPS5" => GroupId::GPS,
+            b"GPS9" => GroupId::Custom("GPS9".into()), // HERO11+, decoded and moved to `GPS` in `GoPro::process_map`
+            b"MSKP" => GroupId::Custom("MainVideoFrameSkip".into()),
+            b"LSKP" => GroupId::Custom("LowResVideoFrameSkip".into()),
             b"FASC" => GroupId::Custom("FovAdaptationScore".into()),
             b"MWET" => GroupId::Custom("MicrophoneWet".into()),
             b"AALP" => GroupId::Custom("AGCAudioLevel".into()),
@@ -19,6 +19,9 @@ pub struct GoPro {
     frame_readout_time: Option<f64>,
     has_cori: bool,
     is_raw_gpmf: bool,
+    chapter_paths: Vec<String>,
+    lrv_path: Option<String>,
+    timewarp_rate: Option<String>,
 }
 
 impl GoPro {
@@ -29,7 +32,7 @@ impl GoPro {
         self.has_cori
     }
     pub fn possible_extensions() -> Vec<&'static str> {
-        vec!["mp4", "mov", "360", "gpmf"]
+        vec!["mp4", "mov", "360", "gpmf", "lrv"]
     }
     pub fn frame_readout_time(&self) -> Option<f64> {
         self.frame_readout_time
@@ -38,7 +41,88 @@ impl GoPro {
         v
     }
 
-    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], filepath: P) -> Option<Self> {
+        let mut ret = Self::detect_buffer(buffer);
+        if let Some(obj) = &mut ret {
+            if !obj.is_raw_gpmf {
+                let path = filepath.as_ref().to_str().unwrap_or_default();
+                if util::is_chapter_merging_enabled() {
+                    obj.chapter_paths = Self::detect_chapters(path);
+                }
+                obj.lrv_path = Self::detect_lrv(path);
+            }
+        }
+        ret
+    }
+
+    // Recordings are split into ~4 GB chapters: GX010001.MP4, GX020001.MP4, ... (GOPR0001.MP4, GP010001.MP4, ... on older cameras)
+    // Returns paths of the following chapters if `path` is the first one
+    fn detect_chapters(path: &str) -> Vec<String> {
+        let mut ret = Vec::new();
+        let filename = filesystem::get_filename(path);
+        let Some((stem, ext)) = filename.split_once('.') else { return ret; };
+        if stem.len() != 8 || !stem.is_ascii() || !stem[4..].bytes().all(|x| x.is_ascii_digit()) { return ret; }
+
+        let (prefix, first_chapter) = if stem.starts_with("GOPR") {
+            ("GP", 0)
+        } else if stem.starts_with('G') && &stem[2..4] == "01" {
+            (&stem[0..2], 1)
+        } else {
+            return ret;
+        };
+
+        let files = filesystem::list_folder(&filesystem::get_folder(path));
+        for chapter in (first_chapter + 1)..100 {
+            let name = format!("{prefix}{chapter:02}{}.{ext}", &stem[4..]);
+            match files.iter().find(|(n, _)| n == &name) {
+                Some((_, p)) => ret.push(p.clone()),
+                None => break
+            }
+        }
+        ret
+    }
+
+    // Low resolution proxy of GX010001.MP4 is GL010001.LRV
+    fn detect_lrv(path: &str) -> Option<String> {
+        let filename = filesystem::get_filename(path);
+        let (stem, _) = filename.split_once('.')?;
+        if stem.len() != 8 || !stem.is_ascii() || !stem.starts_with('G') || stem.starts_with("GL") { return None; }
+        let name = format!("GL{}.LRV", &stem[2..]);
+        filesystem::list_folder(&filesystem::get_folder(path)).into_iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)).map(|(_, p)| p)
+    }
+
+    fn has_gps(samples: &[SampleInfo]) -> bool {
+        samples.iter().any(|x| x.tag_map.as_ref().map(|m| m.contains_key(&GroupId::GPS)).unwrap_or_default())
+    }
+
+    // Some firmwares write the GPS stream only to the LRV file. Copy it to the samples of the main file with the closest timestamp
+    fn merge_lrv_gps(&self, samples: &mut [SampleInfo], cancel_flag: Arc<AtomicBool>) {
+        let Some(path) = &self.lrv_path else { return; };
+        let fs = filesystem::get_base();
+        let mut f = match filesystem::open_file(&fs, path) {
+            Ok(f) => f,
+            Err(e) => { crate::parse_warning!("Failed to open {path}: {e:?}"); return; }
+        };
+        let mut lrv_samples = Vec::new();
+        if let Err(e) = self.parse_track(&mut f.file, f.size, &mut lrv_samples, &mut Vec::new(), |_| (), cancel_flag) {
+            crate::parse_warning!("Failed to parse {path}: {e:?}");
+            return;
+        }
+        for lrv in lrv_samples {
+            let Some(lrv_map) = lrv.tag_map else { continue; };
+            let Some(target) = samples.iter_mut().filter(|x| x.tag_map.is_some()).min_by(|a, b| {
+                (a.timestamp_ms - lrv.timestamp_ms).abs().total_cmp(&(b.timestamp_ms - lrv.timestamp_ms).abs())
+            }) else { break; };
+            let map = target.tag_map.as_mut().unwrap();
+            for (g, v) in lrv_map {
+                if g == GroupId::GPS {
+                    map.entry(g).or_insert_with(TagMap::new).extend(v);
+                }
+            }
+        }
+    }
+
+    fn detect_buffer(buffer: &[u8]) -> Option<Self> {
         let mut ret = None;
 
         if buffer.len() > 8 && &buffer[0..4] == b"DEVC" {
@@ -77,6 +161,7 @@ impl GoPro {
                 }
                 obj.extra_gpmf = Some(map);
             }
+            obj.detect_lens_readout_times(&gpmf_box[8+8..]);
             ret = Some(obj);
         } else if memmem::find(buffer, b"GoPro MET").is_some() {
             ret = Some(Self::default());
@@ -94,16 +179,78 @@ impl GoPro {
                 }
             }
         }
+        if let Some(obj) = &mut ret {
+            let hilights = Self::parse_hilights(buffer);
+            if !hilights.is_empty() {
+                util::insert_markers(obj.extra_gpmf.get_or_insert_with(GroupedTagMap::new), hilights);
+            }
+            let map = obj.extra_gpmf.get_or_insert_with(GroupedTagMap::new);
+            Self::insert_serial_number(map, buffer);
+            // TimeWarp speed (`2X`, `5X`, ..., `AUTO`), only present in TimeWarp clips
+            obj.timewarp_rate = map.values().find_map(|v| v.get_t(TagId::Unknown(0x52415445/*RATE*/)) as Option<&String>).cloned();
+            if map.is_empty() {
+                obj.extra_gpmf = None;
+            }
+        }
         ret
     }
 
+    // HERO8 and newer store the serial number as `CASN` in the `udta` GPMF. Older cameras have only the `CAME` box in `udta`,
+    // which is a 16-byte hash of the serial number, so it's exposed as `SerialNumberHash`, to tell the cameras apart
+    fn insert_serial_number(map: &mut GroupedTagMap, buffer: &[u8]) {
+        let casn = map.values().find_map(|v| v.get_t(TagId::Unknown(0x4341534E/*CASN*/)) as Option<&String>).cloned();
+        if casn.is_some() {
+            util::insert_camera_serial(map, casn.as_deref());
+            return;
+        }
+        let came = memmem::find_iter(buffer, b"CAME")
+            .filter(|&pos| pos >= 4 && buffer[pos - 4..pos] == 24u32.to_be_bytes()) // Box with 16 bytes of data
+            .find_map(|pos| buffer.get(pos + 4..pos + 20));
+        if let Some(hash) = came.filter(|x| x.iter().any(|b| *b != 0)) {
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("SerialNumberHash".into()), "Serial number hash", String, |v| v.clone(), util::to_hex(hash).replace(' ', ""), vec![]));
+        }
+    }
+
+    // HiLight tags added during the recording (button, voice command or the app), in milliseconds from the start of the clip.
+    // HERO5 - HERO7 store them in the `HMMT` box of `udta`, newer cameras in `HLMT` with one `MANL` KLV per tag in the `udta` GPMF
+    fn parse_hilights(buffer: &[u8]) -> Vec<MarkerData> {
+        let mut times = Vec::new();
+        if let Some(pos) = memmem::find(buffer, b"HMMT") {
+            let mut d = buffer.get(pos + 4..).unwrap_or_default();
+            let count = d.read_u32::<BigEndian>().unwrap_or_default();
+            for _ in 0..count.min(100) {
+                let Ok(ms) = d.read_u32::<BigEndian>() else { break; };
+                times.push(ms);
+            }
+        } else if let Some(pos) = memmem::find(buffer, b"HLMT") {
+            let hlmt = buffer.get(pos..pos + 8)
+                .map(|x| x[5] as usize * u16::from_be_bytes([x[6], x[7]]) as usize)
+                .and_then(|len| buffer.get(pos + 8..pos + 8 + len))
+                .unwrap_or_default();
+            for p in memmem::find_iter(hlmt, b"MANL") {
+                let Some(klv) = hlmt.get(p..p + 12) else { break; };
+                if klv[4] != b'L' || klv[5] != 4 { continue; }
+                times.push(u32::from_be_bytes([klv[8], klv[9], klv[10], klv[11]]));
+            }
+        }
+        times.into_iter().filter(|x| *x > 0).enumerate().map(|(i, ms)| MarkerData {
+            t: ms as f64 / 1000.0,
+            kind: "HiLight".into(),
+            label: format!("HiLight {}", i + 1),
+            payload: serde_json::Value::Null,
+        }).collect()
+    }
+
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         let mut samples = Vec::new();
         if let Some(extra) = &self.extra_gpmf {
-            samples.push(SampleInfo { tag_map: Some(extra.clone()), ..Default::default() });
+            let mut extra = extra.clone();
+            Self::insert_digital_lens(&mut extra);
+            samples.push(SampleInfo { tag_map: Some(extra), ..Default::default() });
         }
 
         let mut fps = None;
+        let mut gaps = Vec::new();
 
         if self.is_raw_gpmf {
             let mut data = Vec::with_capacity(size);
@@ -120,23 +267,64 @@ impl GoPro {
                 }
             }
         } else {
-            let ctx = util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
-                if size > 0 {
-                    progress_cb(file_position as f64 / size as f64);
+            let num_files = 1 + self.chapter_paths.len();
+            fps = self.parse_track(stream, size, &mut samples, &mut gaps, |p| progress_cb(p / num_files as f64), cancel_flag.clone())?;
+
+            if !self.chapter_paths.is_empty() {
+                let mut chapters = vec![MarkerData { t: 0.0, kind: "Chapter".into(), label: "Chapter 1".into(), payload: serde_json::json!({ "chapter": 1 }) }];
+                let fs = filesystem::get_base();
+                for (i, path) in self.chapter_paths.iter().enumerate() {
+                    if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
+                    let mut f = match filesystem::open_file(&fs, path) {
+                        Ok(f) => f,
+                        Err(e) => { crate::parse_warning!("Failed to open chapter {path}: {e:?}"); break; }
+                    };
+                    let time_offset_ms = samples.last().map(|x| x.timestamp_ms + x.duration_ms).unwrap_or_default();
+                    let prev_timestamp_us = samples.iter().rev().find_map(|x| Self::get_timestamp(x, &GroupId::Gyroscope));
+                    let first = samples.len();
+                    let first_gap = gaps.len();
+                    self.parse_track(&mut f.file, f.size, &mut samples, &mut gaps, |p| progress_cb((i + 1) as f64 / num_files as f64 + p / num_files as f64), cancel_flag.clone())?;
+
+                    // GPMF timestamps continue across chapters, if they don't, this file belongs to a different recording
+                    let next_timestamp_us = samples[first..].iter().find_map(|x| Self::get_timestamp(x, &GroupId::Gyroscope));
+                    if let (Some(prev), Some(next)) = (prev_timestamp_us, next_timestamp_us) {
+                        if next < prev {
+                            crate::parse_warning!("Chapter {path} is not continuous with the previous one, stopping.");
+                            samples.truncate(first);
+                            gaps.truncate(first_gap);
+                            break;
+                        }
+                    }
+                    for s in &mut samples[first..] {
+                        s.timestamp_ms += time_offset_ms;
+                    }
+                    for gap in &mut gaps[first_gap..] {
+                        gap.t += time_offset_ms / 1000.0;
+                    }
+                    chapters.push(MarkerData {
+                        t: time_offset_ms / 1000.0,
+                        kind: "Chapter".into(),
+                        label: format!("Chapter {}", i + 2),
+                        payload: serde_json::json!({ "chapter": i + 2, "file": filesystem::get_filename(path) })
+                    });
                 }
-                if Self::detect_metadata(data) {
-                    if let Ok(mut map) = GoPro::parse_metadata(&data[8..], GroupId::Default, false) {
-                        self.process_map(&mut map);
-                        info.tag_map = Some(map);
-                        samples.push(info);
+                if chapters.len() > 1 {
+                    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+                        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+                    }
+                    if let Some(map) = samples[0].tag_map.as_mut() {
+                        util::insert_markers(map, chapters);
                     }
                 }
-            }, cancel_flag)?;
-            if !ctx.tracks.is_empty() {
-                fps = util::get_fps_from_track(&ctx.tracks[0]);
             }
         }
+        if self.lrv_path.is_some() && !Self::has_gps(&samples) {
+            self.merge_lrv_gps(&mut samples, cancel_flag.clone());
+        }
+        self.process_time_bases(&mut samples);
         self.process_samples(&mut samples, fps);
+        Self::collect_sensor_temperature(&mut samples);
+        util::insert_gap_markers(&mut samples, gaps);
 
         if self.model.as_ref().map(|x| x.contains("HERO5")).unwrap_or_default() {
             if samples.is_empty() {
@@ -170,6 +358,7 @@ impl GoPro {
                                         0x63 => util::insert_tag(first_map, tag!(parsed GroupId::Default, TagId::Unknown(0x56464f56), "VFOV", String, |v| v.clone(), "S".into(), vec![])),
                                         _ => log::debug!("Unknown lens byte {}", util::to_hex(&buf)),
                                     }
+                                    Self::insert_digital_lens(first_map);
                                 }
                             }
                             stream.seek(SeekFrom::Start(org_pos + size - header_size as u64))?;
@@ -182,7 +371,88 @@ impl GoPro {
         Ok(samples)
     }
 
-    fn detect_metadata(data: &[u8]) -> bool {
+    // IMU streams have the sensor temperature (`TMPC`, °C) once per payload. GPMF doesn't have the battery level
+    fn collect_sensor_temperature(samples: &mut Vec<SampleInfo>) {
+        let temperature = samples.iter().filter_map(|info| {
+            let map = info.tag_map.as_ref()?;
+            let v = [GroupId::Gyroscope, GroupId::Accelerometer].iter().find_map(|g| map.get(g)?.get_t(TagId::Temperature) as Option<&f32>)?;
+            Some(TimeScalar { t: info.timestamp_ms / 1000.0, v: *v as f64 })
+        }).collect::<Vec<_>>();
+        util::insert_temperature(samples, temperature);
+    }
+
+    fn parse_track<T: Read + Seek, F: Fn(f64)>(&self, stream: &mut T, size: usize, samples: &mut Vec<SampleInfo>, gaps: &mut Vec<MarkerData>, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Option<f64>> {
+        let first = samples.len();
+        let ctx = util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+            if size > 0 {
+                progress_cb(file_position as f64 / size as f64);
+            }
+            if Self::detect_metadata(data) {
+                match GoPro::parse_metadata(&data[8..], GroupId::Default, false) {
+                    Ok(mut map) => {
+                        self.process_map(&mut map);
+                        info.tag_map = Some(map);
+                        samples.push(info);
+                    }
+                    Err(e) => gaps.push(util::corrupted_sample(&info, &e))
+                }
+            }
+        }, cancel_flag.clone());
+        match ctx {
+            Ok(ctx) => Ok(ctx.tracks.first().and_then(util::get_fps_from_track)),
+            Err(e) if util::is_salvage_allowed() && samples.len() == first => {
+                crate::parse_warning!("Failed to read the metadata track ({e:?}), scanning the file for GPMF packets");
+                self.salvage_track(stream, size, samples, &cancel_flag)?;
+                Ok(None)
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    // GPMF payloads are written to `mdat` every ~1 second, as a `DEVC` container with the size in the KLV header
+    fn salvage_track<T: Read + Seek>(&self, stream: &mut T, size: usize, samples: &mut Vec<SampleInfo>, cancel_flag: &Arc<AtomicBool>) -> Result<()> {
+        const DURATION_MS: f64 = 1001.0;
+        let mut index = 0;
+        util::salvage_chunks(stream, size, b"DEVC", 1024*1024, cancel_flag, |file_position, chunk| {
+            if chunk.len() < 8 || chunk[4] != 0 { return; }
+            let len = chunk[5] as usize * (&chunk[6..8]).read_u16::<BigEndian>().unwrap_or_default() as usize;
+            if len == 0 || len > chunk.len() - 8 { return; }
+            if let Ok(mut map) = GoPro::parse_metadata(&chunk[8..8 + len], GroupId::Default, false) {
+                if map.is_empty() { return; }
+                self.process_map(&mut map);
+                samples.push(SampleInfo {
+                    sample_index: index,
+                    timestamp_ms: index as f64 * DURATION_MS,
+                    duration_ms: DURATION_MS,
+                    file_position,
+                    sample_size: len + 8,
+                    tag_map: Some(map),
+                    ..Default::default()
+                });
+                index += 1;
+            }
+        })
+    }
+
+    // GoPro MAX has two sensors with different readout times and writes one SROT per lens in the `udta` GPMF.
+    // `parse_metadata` keeps only the last one, so scan the raw KLVs and expose all of them.
+    fn detect_lens_readout_times(&mut self, gpmf: &[u8]) {
+        let times = memmem::find_iter(gpmf, b"SROT").filter_map(|pos| {
+            let klv = gpmf.get(pos..pos + 12)?;
+            if klv[4] != b'f' || klv[5] != 4 { return None; }
+            Some((&klv[8..]).read_f32::<BigEndian>().ok()? as f64)
+        }).collect::<Vec<f64>>();
+
+        if times.len() < 2 { return; }
+
+        self.frame_readout_time = Some(times[0]);
+        if let Some(map) = self.extra_gpmf.as_mut() {
+            util::insert_tag(map, tag!(parsed GroupId::Imager, TagId::FrameReadoutTime, "Frame readout time", f64, |v| format!("{:.4} ms", v), times[0], vec![]));
+            util::insert_tag(map, tag!(parsed GroupId::Imager, TagId::Custom("LensReadoutTimes".into()), "Frame readout time per lens (front, back)", Vec_f64, |v| format!("{:?}", v), times, vec![]));
+        }
+    }
+
+    pub(crate) fn detect_metadata(data: &[u8]) -> bool {
         data.len() > 8 && &data[0..4] == b"DEVC"
     }
 
@@ -241,7 +511,7 @@ impl GoPro {
         Ok(map)
     }
 
-    fn process_map(&self, tag_map: &mut GroupedTagMap) {
+    pub(crate) fn process_map(&self, tag_map: &mut GroupedTagMap) {
         for (g, v) in tag_map.iter_mut() {
             // If we have ORIN and ORIO but not MTRX, construct MTRX from ORIN and ORIO and insert to the map
             if v.contains_key(&TagId::OrientationIn) && v.contains_key(&TagId::OrientationOut) && !v.contains_key(&TagId::Matrix) {
@@ -271,7 +541,76 @@ impl GoPro {
                     v.insert(TagId::Orientation, crate::tag!(parsed g.clone(), TagId::Orientation, "IMUO", String, |v| v.to_string(), o, Vec::new()));
                 }
             }
+
+            if g == &GroupId::Custom("GPS9".into()) {
+                if let Some(gps) = Self::gps9_to_gps_data(v) {
+                    // Speeds as separate series, with the GPS time (unix timestamp in seconds) like the positions
+                    let speed_2d = gps.iter().map(|x| TimeScalar { t: x.unix_timestamp, v: x.speed }).collect::<Vec<_>>();
+                    let speed_3d = gps.iter().filter_map(|x| Some(TimeScalar { t: x.unix_timestamp, v: x.speed_3d? })).collect::<Vec<_>>();
+                    v.insert(TagId::Custom("Speed2D".into()), crate::tag!(parsed g.clone(), TagId::Custom("Speed2D".into()), "2D speed (km/h)", Vec_TimeScalar_f64, |v| format!("{:?}", v), speed_2d, Vec::new()));
+                    v.insert(TagId::Custom("Speed3D".into()), crate::tag!(parsed g.clone(), TagId::Custom("Speed3D".into()), "3D speed (km/h)", Vec_TimeScalar_f64, |v| format!("{:?}", v), speed_3d, Vec::new()));
+                    v.insert(TagId::Data, crate::tag!(parsed g.clone(), TagId::Data, "GPS data", Vec_GpsData, |v| format!("{:?}", v), gps, Vec::new()));
+                }
+            }
         }
+
+        // GPS9 has the time, DOP and fix of every position, so it replaces GPS5 when both are present. HERO13 writes only GPS9
+        let gps9 = GroupId::Custom("GPS9".into());
+        if tag_map.get(&gps9).and_then(|v| v.get(&TagId::Data)).is_some_and(|x| matches!(x.value, TagValue::Vec_GpsData(_))) {
+            let mut v = tag_map.remove(&gps9).unwrap();
+            for tag in v.values_mut() {
+                tag.group = GroupId::GPS;
+            }
+            tag_map.insert(GroupId::GPS, v);
+        }
+        Self::insert_digital_lens(tag_map);
+    }
+
+    // `VFOV` letter of the lens mode as the shared `DigitalLens` tag
+    fn insert_digital_lens(tag_map: &mut GroupedTagMap) {
+        let lens = tag_map.values().find_map(|v| v.get_t(TagId::Unknown(0x56464f56/*VFOV*/)) as Option<&String>).map(|x| match x.trim() {
+            "W" => DigitalLens::Wide,
+            "S" => DigitalLens::SuperView,
+            "H" => DigitalLens::HyperView,
+            "L" => DigitalLens::Linear,
+            "M" => DigitalLens::Medium,
+            "N" => DigitalLens::Narrow,
+            x => DigitalLens::Other(x.to_owned())
+        });
+        if let Some(lens) = lens {
+            util::insert_tag(tag_map, tag!(parsed GroupId::Default, TagId::DigitalLens, "Digital lens", DigitalLens, |v| v.as_str().to_owned(), lens, vec![]));
+        }
+    }
+
+    // GPS9: lat, lon, alt, 2D speed, 3D speed, days since 2000, seconds since midnight, DOP, fix
+    fn gps9_to_gps_data(map: &TagMap) -> Option<Vec<GpsData>> {
+        let scale = match &map.get(&TagId::Scale)?.value {
+            TagValue::Vec_i32(v) => v.get().iter().map(|x| *x as f64).collect::<Vec<f64>>(),
+            TagValue::Vec_u32(v) => v.get().iter().map(|x| *x as f64).collect::<Vec<f64>>(),
+            _ => vec![1.0; 9]
+        };
+        let rows = match &map.get(&TagId::Data)?.value {
+            TagValue::Vec_Vec_Scalar(v) => v.get().clone(),
+            TagValue::Vec_Scalar(v) => vec![v.get().clone()],
+            _ => { return None; }
+        };
+        let value = |row: &[Scalar], i: usize| -> Option<f64> {
+            Some(row.get(i)?.as_f64()? / scale.get(i).copied().filter(|x| *x != 0.0).unwrap_or(1.0))
+        };
+        rows.iter().map(|row| {
+            Some(GpsData {
+                is_acquired:    value(row, 8)? >= 2.0, // 2D or 3D fix
+                unix_timestamp: 946684800.0 + value(row, 5)? * 86400.0 + value(row, 6)?,
+                lat:            value(row, 0)?,
+                lon:            value(row, 1)?,
+                altitude:       value(row, 2)?,
+                speed:          value(row, 3)? * 3.6, // m/s to km/h
+                track:          0.0,
+                speed_3d:       value(row, 4).map(|x| x * 3.6),
+                dop:            value(row, 7),
+                fix:            value(row, 8).map(|x| x as u8),
+            })
+        }).collect()
     }
 
     fn get_timestamp(info: &util::SampleInfo, group_id: &GroupId) -> Option<i64> {
@@ -290,12 +629,112 @@ impl GoPro {
         None
     }
 
+    // Samples of a GPMF block are distributed evenly between its STMP and the STMP of the same stream in the next block.
+    // The last block uses the increment of the previous one. Returns the timestamps in µs, relative to `start_timestamp_us`,
+    // which is initialized with the first block seen
+    fn block_timestamps(samples: &[SampleInfo], i: usize, group: &GroupId, count: usize, start_timestamp_us: &mut Option<i64>, prev_increment: &mut i64) -> Vec<i64> {
+        let timestamp_us = Self::get_timestamp(&samples[i], group).unwrap_or_default();
+        let start = *start_timestamp_us.get_or_insert(timestamp_us);
+        let next_timestamp_us = samples.get(i + 1).and_then(|x| Self::get_timestamp(x, group));
+        let increment = next_timestamp_us.map(|x| (x - timestamp_us) / (count as i64).max(1)).unwrap_or(*prev_increment);
+        *prev_increment = increment;
+        (0..count as i64).map(|k| timestamp_us - start + k * increment).collect()
+    }
+
+    // Every value stream other than the IMU (SHUT, ISOE, WBAL etc.) gets the timestamp of each of its values as `SampleTimestamps`,
+    // in seconds from the first STMP in the file
+    fn stream_timestamps(samples: &mut [SampleInfo]) {
+        const SKIP: &[GroupId] = &[GroupId::Default, GroupId::Gyroscope, GroupId::Accelerometer, GroupId::Magnetometer, GroupId::CameraOrientation, GroupId::ImageOrientation, GroupId::GravityVector, GroupId::Quaternion, GroupId::GPS];
+        let mut start_timestamp_us = samples.iter()
+            .filter_map(|x| x.tag_map.as_ref())
+            .flat_map(|x| x.values())
+            .filter_map(|x| (x.get_t(TagId::TimestampUs) as Option<&u64>).map(|x| *x as i64))
+            .min();
+        let mut increments = std::collections::BTreeMap::<GroupId, i64>::new();
+        for i in 0..samples.len() {
+            let Some(map) = samples[i].tag_map.as_ref() else { continue; };
+            let mut result = Vec::new();
+            for (group, tags) in map.iter() {
+                if SKIP.contains(group) || !tags.contains_key(&TagId::TimestampUs) || tags.contains_key(&TagId::SampleTimestamps) { continue; }
+                let Some(data) = tags.get(&TagId::Data) else { continue; };
+                let Some(count) = data.value.len() else { continue; };
+                let prev_increment = increments.entry(group.clone()).or_default();
+                let timestamps = Self::block_timestamps(samples, i, group, count, &mut start_timestamp_us, prev_increment);
+                result.push((group.clone(), timestamps.into_iter().map(|x| x as f64 / 1_000_000.0).collect::<Vec<f64>>()));
+            }
+            if let Some(map) = samples[i].tag_map.as_mut() {
+                for (group, timestamps) in result {
+                    util::insert_tag(map, tag!(parsed group, TagId::SampleTimestamps, "Timestamps of the values (s)", Vec_f64, |v| format!("{:?}", v), timestamps, vec![]));
+                }
+            }
+        }
+    }
+
+    // TimeWarp and slow motion clips are played at a different speed than they were captured, but the GPMF timestamps (STMP) are in the capture time.
+    // Each payload has the media time of its video frames and the capture time of its first gyro reading, so they are the anchors of the mapping.
+    // The speed of TimeWarp can change during the clip (`AUTO`, or slowed down with a button), so the mapping is piecewise linear.
+    // The mapping is exposed as `Default/CaptureTime` (capture seconds at media seconds), and with `InputOptions::map_to_media_time`
+    // the STMP are converted to the media time, so all timestamps derived from them follow the playback
+    fn process_time_bases(&self, samples: &mut Vec<SampleInfo>) {
+        let anchors = samples.iter()
+            .filter_map(|x| Some((Self::get_timestamp(x, &GroupId::Gyroscope)?, x.timestamp_ms)))
+            .collect::<Vec<(i64, f64)>>();
+        let (Some(first), Some(last)) = (anchors.first().copied(), anchors.last().copied()) else { return; };
+        let media_span_ms = last.1 - first.1;
+        if anchors.len() < 2 || media_span_ms <= 0.0 { return; }
+
+        // Capture seconds per second of playback
+        let scale = (last.0 - first.0) as f64 / 1000.0 / media_span_ms;
+        let mode = if self.timewarp_rate.is_some() || scale > 1.1 {
+            "TimeWarp"
+        } else if scale < 0.9 {
+            "SlowMotion"
+        } else {
+            return;
+        };
+        let to_media = util::is_media_time_enabled();
+
+        if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+            samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+        }
+        if let Some(map) = samples[0].tag_map.as_mut() {
+            let capture_time = anchors.iter().map(|(us, ms)| TimeScalar { t: (ms - first.1) / 1000.0, v: (us - first.0) as f64 / 1_000_000.0 }).collect::<Vec<_>>();
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("RecordingMode".into()), "Recording mode", String, |v| v.clone(), mode.into(), vec![]));
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("CaptureTimeScale".into()), "Capture time per playback time", f64, |v| format!("{:.3}", v), scale, vec![]));
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("CaptureTime".into()), "Capture time (s) at media time (s)", Vec_TimeScalar_f64, |v| format!("{:?}", v), capture_time, vec![]));
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("TimeBase".into()), "Time base of the timestamps", String, |v| v.clone(), (if to_media { "media" } else { "capture" }).into(), vec![]));
+        }
+        if !to_media { return; }
+
+        // Capture µs to media µs, in the range of the original STMP. Outside of the anchors, the nearest segment is extended
+        let map_us = |us: i64| -> i64 {
+            let i = anchors.partition_point(|x| x.0 <= us).clamp(1, anchors.len() - 1);
+            let (a, b) = (anchors[i - 1], anchors[i]);
+            let slope = if b.0 > a.0 { (b.1 - a.1) * 1000.0 / (b.0 - a.0) as f64 } else { 1.0 / scale };
+            first.0 + ((a.1 - first.1) * 1000.0 + (us - a.0) as f64 * slope).round() as i64
+        };
+        for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+            for tags in map.values_mut() {
+                if let Some(TagValue::u64(v)) = tags.get_mut(&TagId::TimestampUs).map(|x| &mut x.value) {
+                    let t = v.get_mut();
+                    *t = map_us(*t as i64).max(0) as u64;
+                }
+                if let Some(TagValue::u32(v)) = tags.get_mut(&TagId::Unknown(0x5449434b /*TICK*/)).map(|x| &mut x.value) {
+                    let t = v.get_mut();
+                    *t = (map_us(*t as i64 * 1000) / 1000).max(0) as u32;
+                }
+            }
+        }
+    }
+
     fn process_samples(&mut self, samples: &mut Vec<SampleInfo>, fps: Option<f64>) {
         // Normalize quaternions
         let mut prev_increment = 0;
         let mut start_timestamp_us = None;
         let mut global_ts_cori: f64 = 0.0;
         let mut global_ts_iori: f64 = 0.0;
+        let mut global_ts_grav: f64 = 0.0;
+        let mut prev_increment_grav = 0;
         let global_increment = fps.map(|x| 1000.0 / x);
         for i in 0..samples.len() {
             let info = &samples[i];
@@ -305,23 +744,36 @@ impl GoPro {
 
             let mut cori = Vec::new();
             let mut iori = Vec::new();
+            let mut grav = Vec::new();
             for (group, map) in grouped_tag_map.iter() {
-                if group == &GroupId::CameraOrientation || group == &GroupId::ImageOrientation {
+                // GRAV is written at the same rate as CORI, so the timestamps are calculated the same way.
+                // It's a unit vector of the gravity direction in the camera frame, with the raw axes of the stream (`IMU orientation` doesn't apply).
+                // Unlike the accelerometer readings, it's not affected by the camera acceleration, so it can be used for horizon leveling directly
+                if group == &GroupId::GravityVector {
                     let scale = *(map.get_t(TagId::Scale) as Option<&i16>).unwrap_or(&32767) as f64;
-                    let mut timestamp_us = *(map.get_t(TagId::TimestampUs) as Option<&u64>).unwrap_or(&0) as i64;
-                    // let start_count = *(map.get_t(TagId::Count) as Option<&u32>).unwrap_or(&0);
-                    let next_timestamp_us = samples.get(i + 1).map(|x | Self::get_timestamp(x, &group)).unwrap_or(None);
-                    if start_timestamp_us.is_none() {
-                        start_timestamp_us = Some(timestamp_us);
+                    if let Some(arr) = map.get_t(TagId::Data) as Option<&Vec<Vector3<i16>>> {
+                        let timestamps = Self::block_timestamps(samples, i, group, arr.len(), &mut start_timestamp_us, &mut prev_increment_grav);
+                        for (v, mut ts) in arr.iter().zip(timestamps) {
+                            if let Some(global_inc) = global_increment {
+                                ts = (global_ts_grav * 1000.0).round() as i64;
+                                global_ts_grav += global_inc;
+                            }
+                            let (x, y, z) = (v.x as f64 / scale, v.y as f64 / scale, v.z as f64 / scale);
+                            let len = (x * x + y * y + z * z).sqrt();
+                            if len > 0.0 {
+                                grav.push(TimeVector3 { t: ts as f64 / 1_000_000.0, x: x / len, y: y / len, z: z / len });
+                            }
+                        }
                     }
+                    continue;
+                }
+                if group == &GroupId::CameraOrientation || group == &GroupId::ImageOrientation {
+                    let scale = *(map.get_t(TagId::Scale) as Option<&i16>).unwrap_or(&32767) as f64;
                     // TODO https://github.com/gopro/gpmf-parser/blob/master/GPMF_utils.c
                     if let Some(arr) = map.get_t(TagId::Data) as Option<&Vec<Quaternion<i16>>> {
                         self.has_cori = true;
-                        let sample_count = arr.len() as i64;
-                        let increment = next_timestamp_us.map(|x| ((x - timestamp_us) / sample_count)).unwrap_or(prev_increment);
-                        prev_increment = increment;
-                        for v in arr.iter() {
-                            let mut ts = timestamp_us - start_timestamp_us.unwrap();
+                        let timestamps = Self::block_timestamps(samples, i, group, arr.len(), &mut start_timestamp_us, &mut prev_increment);
+                        for (v, mut ts) in arr.iter().zip(timestamps) {
                             if let Some(global_inc) = global_increment {
                                 if group == &GroupId::CameraOrientation {
                                     ts = (global_ts_cori * 1000.0).round() as i64;
@@ -341,7 +793,6 @@ impl GoPro {
                                     z: v.z as f64 / scale
                                 }
                             ));
-                            timestamp_us += increment;
                         }
                     }
                 }
@@ -355,8 +806,21 @@ impl GoPro {
 
                 let grouped_tag_map = samples[i].tag_map.as_mut().unwrap();
                 util::insert_tag(grouped_tag_map, tag!(parsed GroupId::Quaternion, TagId::Data, "Quaternion data",  Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quat, vec![]));
+                // Stored as CORI and IORI, the result is their product
+                util::insert_quaternion_convention(grouped_tag_map, &util::QuaternionConvention {
+                    source: "cori*iori",
+                    component_order: "wxyz",
+                    handedness: "right",
+                    negated: &["x"],
+                    ..Default::default()
+                });
+            }
+            if !grav.is_empty() {
+                let grouped_tag_map = samples[i].tag_map.as_mut().unwrap();
+                util::insert_tag(grouped_tag_map, tag!(parsed GroupId::GravityVector, TagId::Data, "Gravity vector", Vec_TimeVector3_f64, |v| format!("{:?}", v), grav, vec![]));
             }
         }
+        Self::stream_timestamps(samples);
     }
     pub fn get_avg_sample_duration(samples: &Vec<SampleInfo>, group_id: &GroupId) -> Option<f64> {
         let mut total_duration_ms = 0.0;
@@ -422,3 +886,86 @@ impl GoPro {
         }).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn klv(key: &[u8; 4], data_type: u8, size: u8, repeat: u16, data: &[u8]) -> Vec<u8> {
+        let mut ret = key.to_vec();
+        ret.push(data_type);
+        ret.push(size);
+        ret.extend(repeat.to_be_bytes());
+        ret.extend(data);
+        while ret.len() % 4 != 0 { ret.push(0); }
+        ret
+    }
+    fn container(key: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+        let data = children.concat();
+        let mut ret = key.to_vec();
+        ret.extend([0, 4]);
+        ret.extend(((data.len() / 4) as u16).to_be_bytes());
+        ret.extend(data);
+        ret
+    }
+    fn be<T: Copy, const N: usize>(v: &[T], f: fn(T) -> [u8; N]) -> Vec<u8> {
+        v.iter().flat_map(|x| f(*x)).collect()
+    }
+    fn process(devc: &[u8]) -> GroupedTagMap {
+        let mut map = GoPro::parse_metadata(devc, GroupId::Default, false).unwrap();
+        GoPro { model: Some("HERO13 Black".into()), ..Default::default() }.process_map(&mut map);
+        map
+    }
+
+    // GYRO stream laid out like in HERO12/13 files, with ORIN/ORIO instead of MTRX
+    #[test]
+    fn imu_scale_and_orientation() {
+        let devc = container(b"DEVC", &[container(b"STRM", &[
+            klv(b"STMP", b'J', 8, 1, &1000u64.to_be_bytes()),
+            klv(b"SIUN", b'c', 5, 1, b"rad/s"),
+            klv(b"SCAL", b's', 2, 1, &10i16.to_be_bytes()),
+            klv(b"ORIN", b'c', 3, 1, b"ZXY"),
+            klv(b"ORIO", b'c', 3, 1, b"YxZ"),
+            klv(b"GYRO", b's', 6, 2, &be(&[100i16, 200, 300, -10, 0, 10], i16::to_be_bytes)),
+        ])]);
+        let map = process(&devc);
+        let gyro = map.get(&GroupId::Gyroscope).unwrap();
+
+        let orientation = gyro.get_t(TagId::Orientation) as Option<&String>;
+        assert_eq!(orientation.map(|x| x.as_str()), Some("ZyX"));
+        let raw2unit = *(gyro.get_t(TagId::Scale) as Option<&i16>).unwrap() as f64;
+        let data = (gyro.get_t(TagId::Data) as Option<&Vec<Vector3<i16>>>).unwrap();
+        let v = data[0].clone().into_scaled(&raw2unit, &1.0).orient(orientation.unwrap().as_bytes());
+        assert_eq!((v.x, v.y, v.z), (30.0, -20.0, 10.0));
+        assert_eq!(data.len(), 2);
+    }
+
+    #[test]
+    fn gps9_replaces_gps5() {
+        // lat, lon, alt, 2D speed, 3D speed, days since 2000, ms since midnight, DOP, fix
+        let mut row = be(&[521234567i32, 49876543, 102345, 1500, 1600, 8000, 3600000], i32::to_be_bytes);
+        row.extend(be(&[150u16, 3], u16::to_be_bytes));
+        let devc = container(b"DEVC", &[
+            container(b"STRM", &[
+                klv(b"SCAL", b'l', 4, 5, &be(&[10000000i32, 10000000, 1000, 1000, 100], i32::to_be_bytes)),
+                klv(b"GPS5", b'l', 20, 1, &be(&[521234567i32, 49876543, 102345, 1500, 160], i32::to_be_bytes)),
+            ]),
+            container(b"STRM", &[
+                klv(b"SCAL", b'l', 4, 9, &be(&[10000000i32, 10000000, 1000, 1000, 1000, 1, 1000, 100, 1], i32::to_be_bytes)),
+                klv(b"TYPE", b'c', 9, 1, b"lllllllSS"),
+                klv(b"GPS9", b'?', 32, 1, &row),
+            ]),
+        ]);
+        let map = process(&devc);
+        assert!(!map.contains_key(&GroupId::Custom("GPS9".into())));
+        let gps = map.get(&GroupId::GPS).unwrap();
+        let data = (gps.get_t(TagId::Data) as Option<&Vec<GpsData>>).unwrap();
+        assert_eq!(data.len(), 1);
+        assert!((data[0].lat - 52.1234567).abs() < 1e-9);
+        assert!((data[0].lon - 4.9876543).abs() < 1e-9);
+        assert!((data[0].speed - 1.5 * 3.6).abs() < 1e-9);
+        assert_eq!(data[0].unix_timestamp, 946684800.0 + 8000.0 * 86400.0 + 3600.0);
+        assert_eq!((data[0].dop, data[0].fix, data[0].is_acquired), (Some(1.5), Some(3), true));
+        assert!(gps.values().all(|x| x.group == GroupId::GPS));
+    }
+}
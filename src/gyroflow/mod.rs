@@ -9,7 +9,7 @@ use std::path::Path;
 use crate::tags_impl::*;
 use crate::*;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Gyroflow {
     pub model: Option<String>,
     vendor: String,
@@ -17,6 +17,35 @@ pub struct Gyroflow {
 }
 
 // .gcsv format as described here: https://docs.gyroflow.xyz/app/technical-details/gcsv-format
+// Version 2 names the columns in the `t,...` row, so they can be in any order and any of the sensors can be missing:
+// gx,gy,gz (gyroscope), ax,ay,az (accelerometer), mx,my,mz (magnetometer), qw,qx,qy,qz (quaternion) and temp (IMU temperature, stored in `Temperature/Data`).
+// Units different from the v1 defaults can be declared with `unit,<column>,<unit>` rows in the header,
+// and `event,<t>,<name>` rows anywhere in the data are stored in the `Markers` group
+
+// Column indices of the sensor values
+struct Columns {
+    gyro: Option<[usize; 3]>,
+    accl: Option<[usize; 3]>,
+    magn: Option<[usize; 3]>,
+    quat: Option<[usize; 4]>,
+    temp: Option<usize>,
+}
+impl Columns {
+    fn v1() -> Self {
+        Self { gyro: Some([1, 2, 3]), accl: Some([4, 5, 6]), magn: Some([7, 8, 9]), quat: None, temp: None }
+    }
+    fn from_header(row: &csv::StringRecord) -> Self {
+        let index = |name: &str| row.iter().position(|x| x == name);
+        let vec3 = |names: [&str; 3]| -> Option<[usize; 3]> { Some([index(names[0])?, index(names[1])?, index(names[2])?]) };
+        Self {
+            gyro: vec3(["gx", "gy", "gz"]),
+            accl: vec3(["ax", "ay", "az"]),
+            magn: vec3(["mx", "my", "mz"]),
+            quat: index("qw").zip(vec3(["qx", "qy", "qz"])).map(|(w, v)| [w, v[0], v[1], v[2]]),
+            temp: index("temp").or_else(|| index("temperature")),
+        }
+    }
+}
 
 impl Gyroflow {
     pub fn camera_type(&self) -> String {
@@ -82,13 +111,18 @@ impl Gyroflow {
         None
     }
 
-    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, _size: usize, _progress_cb: F, _cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
 
         let mut header = BTreeMap::new();
 
         let mut gyro = Vec::new();
         let mut accl = Vec::new();
         let mut magn = Vec::new();
+        let mut quat = Vec::new();
+        let mut temp = Vec::new();
+        let mut markers = Vec::new();
+        let mut units = BTreeMap::<String, String>::new();
+        let mut columns = Columns::v1();
 
         let mut csv = csv::ReaderBuilder::new()
             .has_headers(false)
@@ -100,20 +134,36 @@ impl Gyroflow {
 
         let mut time_scale = 0.001; // default to millisecond
 
-        for row in csv.records() {
-            let row = match row {
-                Ok(row) => row,
+        let mut row = csv::StringRecord::new();
+        loop {
+            match csv.read_record(&mut row) {
+                Ok(true) => { },
+                Ok(false) => { break; },
                 Err(_) => { continue; }
-            };
+            }
+            if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
 
             if row.len() == 1 {
                 continue; // first line
             } else if row.len() == 2 && !passed_header {
                 header.insert(row[0].to_owned(), row[1].to_owned());
                 continue;
+            } else if row.len() == 3 && !passed_header && &row[0] == "unit" {
+                units.insert(row[1].to_owned(), row[2].to_owned());
+                continue;
             } else if &row[0] == "t" || &row[0] == "time" {
                 passed_header = true;
                 time_scale =  header.remove("tscale").unwrap_or("0.001".to_owned()).parse::<f64>().unwrap();
+                let version = header.get("version").and_then(|x| x.parse::<f64>().ok()).unwrap_or(1.0);
+                if version >= 2.0 {
+                    columns = Columns::from_header(&row);
+                }
+                continue;
+            } else if row.len() >= 3 && &row[0] == "event" {
+                match row[1].parse::<f64>() {
+                    Ok(t) => markers.push(MarkerData { t: t * time_scale, kind: "Marker".into(), label: row[2].to_owned(), payload: serde_json::Value::Null }),
+                    Err(e) => log::error!("Failed to parse event time: {row:?} - {e:?}")
+                }
                 continue;
             }
 
@@ -121,34 +171,31 @@ impl Gyroflow {
                 Ok(time) => time * time_scale,
                 Err(e) => { log::error!("Failed to parse time: {row:?} - {e:?}"); continue; }
             };
-            if row.len() >= 4 {
-                gyro.push(TimeVector3 {
-                    t: time,
-                    x: row[1].parse::<f64>().unwrap_or_default(),
-                    y: row[2].parse::<f64>().unwrap_or_default(),
-                    z: row[3].parse::<f64>().unwrap_or_default(),
-                });
-            }
-            if row.len() >= 7 {
-                accl.push(TimeVector3 {
-                    t: time,
-                    x: row[4].parse::<f64>().unwrap_or_default(),
-                    y: row[5].parse::<f64>().unwrap_or_default(),
-                    z: row[6].parse::<f64>().unwrap_or_default()
-                });
+            let value = |i: usize| row[i].parse::<f64>().unwrap_or_default();
+            let vec3 = |i: Option<[usize; 3]>| -> Option<TimeVector3<f64>> {
+                let i = i.filter(|i| i.iter().all(|x| *x < row.len()))?;
+                Some(TimeVector3 { t: time, x: value(i[0]), y: value(i[1]), z: value(i[2]) })
+            };
+            if let Some(v) = vec3(columns.gyro) { gyro.push(v); }
+            if let Some(v) = vec3(columns.accl) { accl.push(v); }
+            if let Some(v) = vec3(columns.magn) { magn.push(v); }
+            if let Some(i) = columns.quat.filter(|i| i.iter().all(|x| *x < row.len())) {
+                quat.push(TimeQuaternion { t: time * 1000.0, v: Quaternion { w: value(i[0]), x: value(i[1]), y: value(i[2]), z: value(i[3]) } });
             }
-            if row.len() >= 10 {
-                magn.push(TimeVector3 {
-                    t: time,
-                    x: row[7].parse::<f64>().unwrap_or_default(),
-                    y: row[8].parse::<f64>().unwrap_or_default(),
-                    z: row[9].parse::<f64>().unwrap_or_default()
-                });
+            if let Some(i) = columns.temp.filter(|x| *x < row.len()) {
+                temp.push(TimeScalar { t: time, v: value(i) });
             }
         }
+        let unit = |column: &str| units.get(column).map(|x| x.as_str());
+        let accl_unit = match unit("ax") { Some("m/s²" | "m/s2") => "m/s²", _ => "g" };
         let accl_scale = 1.0 / header.remove("ascale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
-        let gyro_scale = 1.0 / header.remove("gscale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap() * std::f64::consts::PI / 180.0;
-        let mag_scale = 100.0 / header.remove("mscale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap(); // Gauss to microtesla
+        let gyro_scale = 1.0 / header.remove("gscale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
+        let gyro_scale = if unit("gx") == Some("deg/s") { gyro_scale } else { gyro_scale * std::f64::consts::PI / 180.0 }; // rad/s by default
+        let mag_scale = 1.0 / header.remove("mscale").unwrap_or("1.0".to_owned()).parse::<f64>().unwrap();
+        let mag_scale = if matches!(unit("mx"), Some("uT" | "μT")) { mag_scale } else { mag_scale * 100.0 }; // Gauss to microtesla
+        if matches!(unit("temp").or(unit("temperature")), Some("F" | "°F")) {
+            temp.iter_mut().for_each(|x| x.v = (x.v - 32.0) * 5.0 / 9.0);
+        }
         let imu_orientation = header.remove("orientation").unwrap_or("xzY".to_owned()); // default
 
         let mut map = GroupedTagMap::new();
@@ -165,7 +212,12 @@ impl Gyroflow {
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data",  Vec_TimeVector3_f64, |v| format!("{:?}", v), magn, vec![]));
 
-        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(),  Vec::new()));
+        if !quat.is_empty() {
+            util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion, TagId::Data, "Quaternion data", Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quat, vec![]));
+        }
+        util::insert_markers(&mut map, markers);
+
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), accl_unit.into(),  Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit",  String, |v| v.to_string(), "μT".into(), Vec::new()));
 
@@ -177,8 +229,10 @@ impl Gyroflow {
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.to_string(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.to_string(), Vec::new()));
 
-        Ok(vec![
+        let mut samples = vec![
             SampleInfo { tag_map: Some(map), ..Default::default() }
-        ])
+        ];
+        util::insert_temperature(&mut samples, temp);
+        Ok(samples)
     }
 }
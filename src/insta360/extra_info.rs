@@ -461,4 +461,22 @@ pub fn parse_offset(data: &str) -> std::io::Result<serde_json::Value> {
                                              .map(|v| v.parse::<f64>().map_err(|_| ErrorKind::InvalidData.into()))
                                              .collect();
     Ok(vec?.into())
-}
\ No newline at end of file
+}
+
+impl From<extra_metadata::ImageFovType> for crate::tags_impl::DigitalLens {
+    fn from(fov: extra_metadata::ImageFovType) -> Self {
+        use extra_metadata::ImageFovType::*;
+        use crate::tags_impl::DigitalLens;
+        match fov {
+            FovTypeWide                 => DigitalLens::Wide,
+            FovTypeLinear               => DigitalLens::Linear,
+            FovTypeUltrawide            => DigitalLens::UltraWide,
+            FovTypeNarrow               => DigitalLens::Narrow,
+            FovTypeLinearHorizon        => DigitalLens::LinearHorizonLeveling,
+            FovTypeSuper                => DigitalLens::SuperView,
+            FovTypeMaxView              => DigitalLens::MaxView,
+            FovTypeUnknown | FovTypeNum => DigitalLens::Unknown,
+            x => DigitalLens::Other(format!("{:?}", x).trim_start_matches("FovType").to_owned())
+        }
+    }
+}
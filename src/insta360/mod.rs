@@ -17,6 +17,40 @@ pub const MAGIC: &[u8] = b"8db42d694ccc418790edff439fe026bf";
 
 use crate::util::*;
 
+/// Lens setup decoded from `offset_v3`. The distortion is always the unified omnidirectional model (`xi`) with the Brown-Conrady polynomial,
+/// which is the `insta360` model of the lens profiles, but the undistortion of the 360 cameras works on both fisheye halves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LensKind {
+    /// Single fisheye lens (GO, ONE R/RS, Ace)
+    Fisheye,
+    /// Single lens with `xi` = 0, so only the polynomial distortion applies
+    Rectilinear,
+    /// Two back-to-back fisheye lenses of the 360 cameras (ONE X, X2, X3, X4), the offsets describe the front lens
+    DualFisheye,
+}
+impl LensKind {
+    fn new(model: &str, num: f64, xi: f64) -> Self {
+        // "Insta360 ONE X2", "Insta360 X3", "Insta360 OneRS 1-Inch 360"; the "Insta360" prefix itself doesn't count
+        let name = model.trim_start_matches("Insta360").replace(' ', "").to_ascii_uppercase();
+        let name = name.strip_prefix("ONE").unwrap_or(&name);
+        let is_360 = name.starts_with('X') || name.contains("360");
+        if num >= 2.0 || is_360 {
+            Self::DualFisheye
+        } else if xi == 0.0 {
+            Self::Rectilinear
+        } else {
+            Self::Fisheye
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fisheye     => "Fisheye",
+            Self::Rectilinear => "Rectilinear",
+            Self::DualFisheye => "DualFisheye",
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Insta360 {
     pub model: Option<String>,
@@ -55,7 +89,7 @@ impl Insta360 {
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         let mut tag_map = self.parse_file(stream, size, progress_cb, cancel_flag)?;
         self.process_map(&mut tag_map);
-        Ok(vec![SampleInfo { tag_map: Some(tag_map), ..std::default::Default::default() }])
+        Ok(util::split_samples_by_time(vec![SampleInfo { tag_map: Some(tag_map), ..std::default::Default::default() }], 1000.0))
     }
 
     fn parse_file<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<GroupedTagMap> {
@@ -141,8 +175,13 @@ impl Insta360 {
             });
         }
 
+        let serial = try_block!(String, {
+            tag_map.query::<serde_json::Value>(GroupId::Default, TagId::Metadata).ok()?.as_object()?.get("serial_number")?.as_str()?.to_owned()
+        });
+        util::insert_camera_serial(tag_map, serial.as_deref());
+
         let has_offset_v3 = crate::try_block!(bool, {
-            (tag_map.get(&GroupId::Default)?.get_t(TagId::Metadata) as Option<&serde_json::Value>)?.as_object()?.get("offset_v3")?.as_array()?.len() >= 20
+            tag_map.query::<serde_json::Value>(GroupId::Default, TagId::Metadata).ok()?.as_object()?.get("offset_v3")?.as_array()?.len() >= 20
         }).unwrap_or_default();
         log::debug!("Has offset_v3: {has_offset_v3}");
 
@@ -166,6 +205,13 @@ impl Insta360 {
             }
         };
 
+        let firmware = try_block!(String, {
+            tag_map.query::<serde_json::Value>(GroupId::Default, TagId::Metadata).ok()?.as_object()?.get("fw_version")?.as_str()?.to_owned()
+        });
+        let fw_quirks = crate::quirks::find(&self.camera_type(), self.model.as_deref(), firmware.as_deref());
+        let imu_orientation = crate::quirks::imu_orientation(&fw_quirks).unwrap_or(imu_orientation);
+        crate::quirks::insert_tag(tag_map, &fw_quirks);
+
         if let Some(x) = tag_map.get_mut(&GroupId::Gyroscope) {
             x.insert(Orientation, tag!(parsed Gyroscope,     Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.to_string(), Vec::new()));
         }
@@ -174,7 +220,7 @@ impl Insta360 {
         }
 
         crate::try_block!({
-            let md = (tag_map.get(&GroupId::Default)?.get_t(TagId::Metadata) as Option<&serde_json::Value>)?.as_object()?;
+            let md = tag_map.query::<serde_json::Value>(GroupId::Default, TagId::Metadata).ok()?.as_object()?;
             match (md.get("dimension").and_then(|x| x.as_object()), md.get("window_crop_info").and_then(|x| x.as_object()), md.get("offset_v3").and_then(|x| x.as_array())) {
                 (Some(dim), Some(crop_info), Some(offset_v3)) if offset_v3.len() >= 20 => {
                     let (w, h) = (dim.get("x")?.as_i64()? as u32, dim.get("y")?.as_i64()? as u32);
@@ -192,29 +238,29 @@ impl Insta360 {
         {
             let fft = self.first_frame_timestamp.unwrap_or_default() / 1000.0;
             let gyro_timestamp = self.gyro_timestamp.unwrap_or_default() / 1000.0;
+            let is_raw_gyro = self.is_raw_gyro;
             let mut update_timestamps = |group: &GroupId| {
                 if let Some(g) = tag_map.get_mut(group) {
                     if let Some(g) = g.get_mut(&TagId::Data) {
                         match &mut g.value {
                             // Gyro/accel
                             TagValue::Vec_TimeVector3_f64(g) => {
-                                for x in g.get_mut() {
+                                g.modify(move |v| for x in v.iter_mut() {
                                     x.t -= fft;
-                                    if self.is_raw_gyro {
+                                    if is_raw_gyro {
                                         x.t /= 1000.0;
                                     }
                                     x.t -= gyro_timestamp;
-                                }
+                                });
                             },
                             // Exposure
                             TagValue::Vec_TimeScalar_f64(g) => {
-                                let _ = g.get(); // make sure it's parsed
-                                for x in g.get_mut() {
+                                g.modify(move |v| for x in v.iter_mut() {
                                     x.t -= fft;
-                                    if self.is_raw_gyro {
+                                    if is_raw_gyro {
                                         x.t /= 1000.0;
                                     }
-                                }
+                                });
                             },
                             _ => { }
                         }
@@ -231,11 +277,15 @@ impl Insta360 {
         let model = self.model.clone().unwrap_or_default().replace("Insta360 ", "");
 
         // offset_v3: num_xi_fx_fy_cx_cy_yaw_pitch_roll_tx_ty_tz_k1_k2_k3_p1_p2_width_height_lensType_flag
+        // Older firmware doesn't write the flag
 
-        let (_num, xi, fx, fy, cx, cy, yaw, pitch, roll, _tx, _ty, _tz, k1, k2, k3, p1, p2, lens_width, lens_height, _lens_type, _flag) =
+        let (num, xi, fx, fy, cx, cy, yaw, pitch, roll, _tx, _ty, _tz, k1, k2, k3, p1, p2, lens_width, lens_height) =
             (offset_v3[0], offset_v3[1], offset_v3[2], offset_v3[3], offset_v3[4], offset_v3[5], offset_v3[6], offset_v3[7],
             offset_v3[8], offset_v3[9], offset_v3[10], offset_v3[11], offset_v3[12], offset_v3[13], offset_v3[14], offset_v3[15],
-            offset_v3[16], offset_v3[17], offset_v3[18], offset_v3[19], offset_v3[20]);
+            offset_v3[16], offset_v3[17], offset_v3[18]);
+        let lens_type = offset_v3.get(19).map(|x| *x as u32);
+        let flag = offset_v3.get(20).map(|x| *x as u32);
+        let kind = LensKind::new(self.model.as_deref().unwrap_or_default(), num, xi);
 
         let c_ratio = (
             size.0 as f64 / lens_width,
@@ -248,38 +298,30 @@ impl Insta360 {
 
         let output_size = Self::get_output_size(size.0, size.1);
 
-        let profile = serde_json::json!({
-            "calibrated_by": "Insta360",
-            "camera_brand": "Insta360",
-            "camera_model": model,
-            "calib_dimension": { "w": size.0, "h": size.1 },
-            "orig_dimension":  { "w": size.0, "h": size.1 },
-            "output_dimension": { "w": output_size.0, "h": output_size.1 },
-            "frame_readout_time": self.frame_readout_time,
-            "official": true,
-            "asymmetrical": true,
-            "fisheye_params": {
-              "camera_matrix": [
+        let profile = LensProfile::new("Insta360", &model, size.0, size.1)
+            .with_output_dimension(output_size.0, output_size.1)
+            .with_frame_readout_time(self.frame_readout_time)
+            .with_camera_matrix([
                 [ fx / f_ratio.0,   0.0,              cx * c_ratio.0 ],
                 [ 0.0,              fy / f_ratio.1,   cy * c_ratio.1 ],
                 [ 0.0,              0.0,              1.0 ]
-              ],
-              "distortion_coeffs": [k1, k2, k3, p1, p2, xi]
-            },
-            "distortion_model": "insta360",
-            "sync_settings": {
-              "initial_offset": 0,
-              "initial_offset_inv": false,
-              "search_size": 0.3,
-              "max_sync_points": 5,
-              "every_nth_frame": 1,
-              "time_per_syncpoint": 0.5,
-              "do_autosync": false
-            },
-            "calibrator_version": "---"
+            ])
+            .with_distortion("insta360", vec![k1, k2, k3, p1, p2, xi], true)
+            .with_sync_settings(SyncSettings { search_size: 0.3, time_per_syncpoint: 0.5, ..Default::default() })
+            .to_json();
+
+        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap_or_default(), profile, vec![]));
+
+        let distortion = serde_json::json!({
+            "model": "insta360",
+            "kind": kind.as_str(),
+            "lens_count": num as u32,
+            "lens_type": lens_type,
+            "flag": flag,
+            "xi": xi, "k1": k1, "k2": k2, "k3": k3, "p1": p1, "p2": p2,
         });
-
-        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap(), profile, vec![]));
+        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Distortion, "Lens distortion", Json, |v| serde_json::to_string(v).unwrap_or_default(), distortion, vec![]));
+        insert_tag(tag_map, tag!(parsed GroupId::Lens, TagId::Custom("LensKind".into()), "Lens kind", String, |v| v.clone(), kind.as_str().into(), vec![]));
 
         if pitch.abs() > 0.0 || roll.abs() > 0.0 || yaw.abs() > 0.0 {
             const DEG2RAD: f64 = std::f64::consts::PI / 180.0;
@@ -294,7 +336,7 @@ impl Insta360 {
                 [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
                 [-sp,     cp * sr,                cp * cr],
             ];
-            let rotate = |vec: &mut TimeVector3<f64>| {
+            let rotate = move |vec: &mut TimeVector3<f64>| {
                 let mut rotated = [0.0f64; 3];
                 for i in 0..3 {
                     rotated[i] += mat[i][0] * vec.x;
@@ -310,9 +352,7 @@ impl Insta360 {
                 if let Some(x) = tag_map.get_mut(&group) {
                     if let Some(xx) = x.get_mut(&TagId::Data) {
                         if let TagValue::Vec_TimeVector3_f64(arr) = &mut xx.value {
-                            for v in arr.get_mut().iter_mut() {
-                                rotate(v);
-                            }
+                            arr.modify(move |arr| arr.iter_mut().for_each(rotate));
                         }
                     }
                 }
@@ -329,3 +369,18 @@ impl Insta360 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lens_kind() {
+        assert_eq!(LensKind::new("Insta360 GO 3",    1.0, 1.2), LensKind::Fisheye);
+        assert_eq!(LensKind::new("Insta360 OneRS",   1.0, 0.9), LensKind::Fisheye);
+        assert_eq!(LensKind::new("Insta360 Ace Pro", 1.0, 0.0), LensKind::Rectilinear);
+        assert_eq!(LensKind::new("Insta360 ONE X2",  1.0, 1.0), LensKind::DualFisheye);
+        assert_eq!(LensKind::new("Insta360 X3",      1.0, 1.0), LensKind::DualFisheye);
+        assert_eq!(LensKind::new("Insta360 GO 3",    2.0, 1.2), LensKind::DualFisheye);
+    }
+}
@@ -100,6 +100,10 @@ impl super::Insta360 {
                     self.gyro_range = Some(gyro_info.gyro_range as f64);
                     self.acc_range  = Some(gyro_info.acc_range as f64);
                 }
+                match extra_info::extra_metadata::ImageFovType::try_from(info.fov_type) {
+                    Ok(extra_info::extra_metadata::ImageFovType::FovTypeUnknown) | Err(_) => { },
+                    Ok(fov) => insert_tag(&mut map, tag!(parsed Default, TagId::DigitalLens, "Digital lens", DigitalLens, |v| v.as_str().to_owned(), fov.into(), vec![])),
+                }
                 let mut v = serde_json::to_value(&info).map_err(|_| Error::new(ErrorKind::Other, "Serialize error"));
                 if let Ok(vv) = &mut v {
                     if let Some(obj) = vv.as_object_mut() {
@@ -118,51 +122,18 @@ impl super::Insta360 {
                     }
                 }
                 if let Ok(vv) = v {
-                    insert_tag(&mut map, tag!(parsed Default, TagId::Metadata, "Extra metadata", Json, |v| serde_json::to_string(v).unwrap(), vv, data));
+                    insert_tag(&mut map, tag!(parsed Default, TagId::Metadata, "Extra metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), vv, data));
                 }
             },
-            RecordType::Thumbnail => { // video frame in h264
-                insert_tag(&mut map, tag!(parsed Default, File("thumbnail.h264".into()), "Thumbnail", Vec_u8, |v| format!("{} bytes", v.len()), data.to_vec(), vec![]));
+            RecordType::Thumbnail => { // video frame in h264, or JPEG in photos
+                let name = if data.starts_with(&[0xFF, 0xD8, 0xFF]) { "thumbnail.jpg" } else { "thumbnail.h264" };
+                insert_tag(&mut map, tag!(parsed Default, File(name.into()), "Thumbnail", Vec_u8, |v| format!("{} bytes", v.len()), data.to_vec(), vec![]));
             },
-            RecordType::ThumbnailExt => { // video frame in h264
-                insert_tag(&mut map, tag!(parsed Default, File("thumbnail-ext.h264".into()), "ThumbnailExt", Vec_u8, |v| format!("{} bytes", v.len()), data.to_vec(), vec![]));
+            RecordType::ThumbnailExt => { // video frame in h264, or JPEG in photos
+                let name = if data.starts_with(&[0xFF, 0xD8, 0xFF]) { "thumbnail-ext.jpg" } else { "thumbnail-ext.h264" };
+                insert_tag(&mut map, tag!(parsed Default, File(name.into()), "ThumbnailExt", Vec_u8, |v| format!("{} bytes", v.len()), data.to_vec(), vec![]));
             },
             RecordType::Gyro => {
-                let item_size = if self.is_raw_gyro { 8+6*2 } else { 8+6*8 };
-
-                let mut acc_vec  = Vec::with_capacity(len as usize / item_size);
-                let mut gyro_vec = Vec::with_capacity(len as usize / item_size);
-                while d.position() < len as u64 {
-                    let timestamp = d.read_u64::<LittleEndian>()? as f64 / 1000.0;
-                    if !self.is_raw_gyro {
-                        acc_vec.push(TimeVector3 {
-                            t: timestamp,
-                            x: d.read_f64::<LittleEndian>()?,
-                            y: d.read_f64::<LittleEndian>()?,
-                            z: d.read_f64::<LittleEndian>()?,
-                        });
-                        gyro_vec.push(TimeVector3 {
-                            t: timestamp,
-                            x: d.read_f64::<LittleEndian>()?,
-                            y: d.read_f64::<LittleEndian>()?,
-                            z: d.read_f64::<LittleEndian>()?,
-                        });
-                    } else {
-                        acc_vec.push(TimeVector3 {
-                            t: timestamp,
-                            x: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                            y: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                            z: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                        });
-                        gyro_vec.push(TimeVector3 {
-                            t: timestamp,
-                            x: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                            y: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                            z: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
-                        });
-                    }
-                }
-
                 if self.is_raw_gyro {
                     let gyro_scale = 32768.0 / self.gyro_range.unwrap_or(2000.0); // 2000 dps
                     let accl_scale = 32768.0 / self.acc_range.unwrap_or(16.0); // ± 16g
@@ -174,10 +145,21 @@ impl super::Insta360 {
                     insert_tag(&mut map, tag!(parsed Gyroscope,     Unit, "Gyroscope unit",     String, |v| v.to_string(), "rad/s".into(), Vec::new()));
                 }
 
-                insert_tag(&mut map, tag!(parsed Accelerometer, Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), acc_vec, vec![]));
-                insert_tag(&mut map, tag!(parsed Gyroscope,     Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro_vec, vec![]));
+                // Gyro records can be several MB, so they are decoded only when accessed
+                let raw = self.is_raw_gyro;
+                insert_tag(&mut map, tag!(Accelerometer, Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), if raw { Self::parse_imu::<true, false> } else { Self::parse_imu::<false, false> }, data));
+                insert_tag(&mut map, tag!(Gyroscope,     Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), if raw { Self::parse_imu::<true, true>  } else { Self::parse_imu::<false, true>  }, data));
 
                 insert_tag(&mut map, tag!(parsed Accelerometer, Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(),  Vec::new()));
+
+                // Full-scale range in the unit of the data, for the saturation detection
+                if let Some(r) = self.gyro_range {
+                    let r = if raw { r } else { r.to_radians() };
+                    insert_tag(&mut map, tag!(parsed Gyroscope,     FullScale, "Gyroscope full-scale range",     f64, |v| format!("±{:.2}", v), r, vec![]));
+                }
+                if let Some(r) = self.acc_range {
+                    insert_tag(&mut map, tag!(parsed Accelerometer, FullScale, "Accelerometer full-scale range", f64, |v| format!("±{} g", v), r, vec![]));
+                }
             },
             RecordType::Exposure | RecordType::ExposureSecondary => {
                 insert_tag(&mut map, tag!(Exposure, Data, "Shutter speed", Vec_TimeScalar_f64, |v| format!("{:?}", v), |d| {
@@ -226,7 +208,8 @@ impl super::Insta360 {
                             lon,
                             speed,
                             track,
-                            altitude
+                            altitude,
+                            ..Default::default()
                         });
                     }
                     Ok(gps)
@@ -303,9 +286,40 @@ impl super::Insta360 {
             RecordType::TBox | // Unknown format
             RecordType::Quaternions | // Unknown format
             _ => {
-                log::warn!("Unknown Insta360 record: {}, size: {}, format: {}, {}", id, data.len(), format, pretty_hex::pretty_hex(&&data[0..data.len().min(256)]));
+                crate::parse_warning!("Unknown Insta360 record: {}, size: {}, format: {}, {}", id, data.len(), format, pretty_hex::pretty_hex(&&data[0..data.len().min(256)]));
             }
         }
         Ok(map)
     }
+
+    // Each item is a timestamp followed by accelerometer and gyroscope readings, either as f64 or as raw u16 (`RAW`)
+    fn parse_imu<const RAW: bool, const GYRO: bool>(d: &mut Cursor<&[u8]>) -> Result<Vec<TimeVector3<f64>>> {
+        let len = d.get_ref().len() as u64;
+        let item_size = if RAW { 8+6*2 } else { 8+6*8 };
+        let mut ret = Vec::with_capacity(len as usize / item_size);
+        while d.position() < len {
+            let t = d.read_u64::<LittleEndian>()? as f64 / 1000.0;
+            let read = |d: &mut Cursor<&[u8]>| -> Result<TimeVector3<f64>> {
+                if RAW {
+                    Ok(TimeVector3 {
+                        t,
+                        x: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
+                        y: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
+                        z: d.read_u16::<LittleEndian>()? as f64 - 32768.0,
+                    })
+                } else {
+                    Ok(TimeVector3 {
+                        t,
+                        x: d.read_f64::<LittleEndian>()?,
+                        y: d.read_f64::<LittleEndian>()?,
+                        z: d.read_f64::<LittleEndian>()?,
+                    })
+                }
+            };
+            let accl = read(d)?;
+            let gyro = read(d)?;
+            ret.push(if GYRO { gyro } else { accl });
+        }
+        Ok(ret)
+    }
 }
@@ -57,12 +57,13 @@ impl KanDao {
         let mut first_timestamp = None;
 
         let mut current_device_id = None;
+        let mut ranges = None;
 
         let mut map = GroupedTagMap::new();
 
         macro_rules! add_device {
             ($num:tt) => {
-                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceID".into()), "Device ID", u32, |v| format!("{:?}", v), $num, vec![]));
+                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceID".into()), "Device ID", String, |v| v.to_string(), $num.to_string(), vec![]));
 
                 util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl.clone(), vec![]));
                 util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro.clone(), vec![]));
@@ -72,6 +73,11 @@ impl KanDao {
                 util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
                 util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit",  String, |v| v.to_string(), "μT"   .into(), Vec::new()));
 
+                if let Some((gyro_range, acc_range)) = ranges.take() {
+                    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::FullScale, "Gyroscope full-scale range",     f64, |v| format!("±{} deg/s", v), gyro_range, Vec::new()));
+                    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::FullScale, "Accelerometer full-scale range", f64, |v| format!("±{} g", v),     acc_range,  Vec::new()));
+                }
+
                 let imu_orientation = "xYz";
                 util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
                 util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
@@ -125,6 +131,7 @@ impl KanDao {
 
                 let gyro_scale = 32768.0 / gyro_range; // 1000 dps
                 let accl_scale = 32768.0 / acc_range; // ± 2g
+                ranges = Some((gyro_range, acc_range));
 
                 let sample_num = stream.read_u32::<LittleEndian>()?;
                 for _ in 0..sample_num {
@@ -184,6 +191,38 @@ impl KanDao {
             add_device!(num);
         }
 
-        Ok(ret)
+        Self::estimate_clock_offsets(&mut ret);
+
+        Ok(util::split_samples_by_time(ret, 1000.0))
+    }
+
+    // Each device has its own clock, so the offset of each device to the first one is found by correlating the gyroscope magnitudes.
+    // Only the magnitude is compared, because the devices are mounted in different orientations
+    fn estimate_clock_offsets(samples: &mut [SampleInfo]) {
+        let magnitude = |info: &SampleInfo, max_len: usize| -> Vec<TimeVector3<f64>> {
+            let Some(Ok(gyro)) = info.tag_map.as_ref().map(|x| x.query::<Vec<TimeVector3<f64>>>(GroupId::Gyroscope, TagId::Data)) else { return Vec::new(); };
+            let step = (gyro.len() / max_len.max(1)).max(1);
+            gyro.iter().step_by(step).map(|v| TimeVector3 { t: v.t, x: (v.x * v.x + v.y * v.y + v.z * v.z).sqrt(), y: 0.0, z: 0.0 }).collect()
+        };
+        if samples.len() < 2 { return; }
+        let reference = magnitude(&samples[0], usize::MAX);
+        if reference.is_empty() { return; }
+
+        let apply = util::is_device_clock_alignment_enabled();
+        let options = crate::sync::SyncOptions::default();
+        for info in samples.iter_mut().skip(1) {
+            let Some(result) = crate::sync::estimate_offset(&reference, &magnitude(info, 20000), &options) else { continue; };
+            let Some(map) = info.tag_map.as_mut() else { continue; };
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::TimeOffset,                    "Clock offset to the first device", f64, |v| format!("{:.3} ms", v), result.offset_ms, vec![]));
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("SyncQuality".into()), "Gyro magnitude correlation",        f64, |v| format!("{:.3}", v), result.correlation, vec![]));
+            if apply {
+                let offset_ms = result.offset_ms;
+                let f: util::TimeMapFn = Arc::new(move |t| t + offset_ms);
+                for group in [GroupId::Gyroscope, GroupId::Accelerometer, GroupId::Magnetometer] {
+                    if let Some(m) = map.get_mut(&group) { util::map_timestamps(m, &f); }
+                }
+                info.timestamp_ms += offset_ms;
+            }
+        }
     }
 }
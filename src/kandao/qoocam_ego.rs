@@ -4,7 +4,7 @@
 use std::io::*;
 use memchr::memmem;
 use std::sync::{ Arc, atomic::AtomicBool };
-use std::collections::HashMap;
+use std::collections::{ BTreeMap, HashMap };
 
 use crate::tags_impl::*;
 use crate::*;
@@ -21,6 +21,15 @@ enum Value {
     Json(serde_json::Value),
 }
 
+// Factory calibration from the `kdfp` box
+#[derive(Default)]
+struct FactoryCalibration {
+    camera_matrix: Option<[[f64; 3]; 3]>,
+    distortion: Option<Vec<f64>>,
+    size: (Option<u32>, Option<u32>),
+    imu_matrix: Option<[[f64; 3]; 3]>,
+}
+
 impl QoocamEgo {
     pub fn camera_type(&self) -> String {
         "KanDao".to_owned()
@@ -45,11 +54,14 @@ impl QoocamEgo {
         if memmem::find(buffer, b"QooCam 3 Ultra").is_some() {
             return Some(Self { model: Some("QooCam 3 Ultra".into()) });
         }
+        if memmem::find(buffer, b"kdfp").is_some() && memmem::find(buffer, b"kfix").is_some() {
+            return Some(Self { model: None });
+        }
         None
     }
 
-    fn parse_data<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<HashMap<String, Value>> {
-        let mut map = HashMap::new();
+    fn parse_data<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<BTreeMap<String, Value>> {
+        let mut map = BTreeMap::new();
         let count = stream.read_u32::<LittleEndian>()?;
         for _ in 0..count {
             let mut name = [0u8; 32];
@@ -128,14 +140,63 @@ impl QoocamEgo {
         Ok(map)
     }
 
-    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, _size: usize, _progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+    fn doubles(v: &Value) -> Option<Vec<f64>> {
+        match v {
+            Value::Json(serde_json::Value::Array(arr)) => arr.iter().map(|x| x.as_f64()).collect(),
+            Value::Json(v) => Some(vec![v.as_f64()?]),
+            _ => None
+        }
+    }
+
+    // The `kdfp` box uses the same item layout as `kfix`, but the item names differ between the camera generations,
+    // so the values are recognized by their name and the number of elements. Items with other names are ignored.
+    // The IMU matrix maps the IMU axes to the camera axes (camera = M * imu)
+    fn parse_factory_calibration(md: &BTreeMap<String, Value>) -> FactoryCalibration {
+        let mut ret = FactoryCalibration::default();
+        for (name, v) in md {
+            let Some(v) = Self::doubles(v) else { continue; };
+            let upper = name.to_ascii_uppercase();
+            let matrix = || -> Option<[[f64; 3]; 3]> {
+                if v.len() != 9 { return None; }
+                Some([[v[0], v[1], v[2]], [v[3], v[4], v[5]], [v[6], v[7], v[8]]])
+            };
+            if upper.contains("IMU") {
+                if ret.imu_matrix.is_none() { ret.imu_matrix = matrix(); }
+            } else if upper.contains("DIST") || upper.contains("COEF") {
+                if ret.distortion.is_none() && (4..=8).contains(&v.len()) { ret.distortion = Some(v); }
+            } else if upper.contains("WIDTH") && v.len() == 1 {
+                ret.size.0 = Some(v[0] as u32);
+            } else if upper.contains("HEIGHT") && v.len() == 1 {
+                ret.size.1 = Some(v[0] as u32);
+            } else if upper.contains("INTRINSIC") || (upper.contains("CAM") && upper.contains("MAT")) || upper == "K" {
+                if ret.camera_matrix.is_none() { ret.camera_matrix = matrix(); }
+            }
+        }
+        ret
+    }
+
+    // Axis permutation with signs, if the matrix is one
+    fn matrix_to_orientation(m: &[[f64; 3]; 3]) -> Option<String> {
+        let mut ret = String::new();
+        for row in m {
+            let (i, v) = row.iter().enumerate().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+            if (v.abs() - 1.0).abs() > 1e-3 || row.iter().map(|x| x.abs()).sum::<f64>() - v.abs() > 1e-3 { return None; }
+            let axis = b"XYZ"[i] as char;
+            if ret.contains(axis) || ret.contains(axis.to_ascii_lowercase()) { return None; }
+            ret.push(if *v < 0.0 { axis.to_ascii_lowercase() } else { axis });
+        }
+        Some(ret)
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, file_size: usize, _progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         let mut gyro = Vec::new();
         let mut accl = Vec::new();
         let mut exp = Vec::new();
         let mut first_timestamp = None;
         let mut last_timestamp = None;
-        let mut metadata = HashMap::new();
+        let mut metadata = BTreeMap::new();
         let mut rear_lens = false;
+        let mut calibration = None;
 
         let mut map = GroupedTagMap::new();
 
@@ -158,6 +219,12 @@ impl QoocamEgo {
                     }
                 }
             }
+            if typ == fourcc("kdfp") { // Factory calibration
+                let md = Self::parse_data(stream, size as usize - header_size as usize)?;
+                calibration = Some(Self::parse_factory_calibration(&md));
+                let md = md.into_iter().filter_map(|(k, v)| match v { Value::Json(v) => Some((k, v)), _ => None }).collect::<serde_json::Map<_, _>>();
+                metadata.insert("kdfp".into(), serde_json::Value::Object(md));
+            }
             if typ == fourcc("kvar") { // Variable metadata
                 let md = Self::parse_data(stream, size as usize - header_size as usize)?;
                 //println!("Variable metadata: {:#?}", md);
@@ -233,6 +300,50 @@ impl QoocamEgo {
             tag!(parsed GroupId::Default, TagId::Metadata, "Extra metadata", Json, |v| format!("{:?}", v), serde_json::to_value(metadata).map_err(|_| Error::new(ErrorKind::Other, "Serialize error"))?, vec![])
         );
 
+        let mut imu_orientation = match self.model.as_deref() {
+            Some("QooCam 3 Ultra") => if rear_lens { "yxz" } else { "yXZ" },
+            _ => "XYZ"
+        }.to_owned();
+
+        if let Some(calib) = calibration {
+            if let (Some(camera_matrix), Some(coeffs)) = (calib.camera_matrix, calib.distortion) {
+                let (width, height) = match calib.size {
+                    (Some(w), Some(h)) => (w, h),
+                    _ => {
+                        stream.seek(SeekFrom::Start(0))?;
+                        util::get_video_metadata(stream, file_size).map(|md| (md.width as u32, md.height as u32)).unwrap_or_default()
+                    }
+                };
+                if width > 0 && height > 0 {
+                    let model = self.model.as_deref().unwrap_or("QooCam");
+                    let profile = LensProfile::new("KanDao", model, width, height)
+                        .with_camera_matrix(camera_matrix)
+                        .with_distortion("opencv_fisheye", coeffs, false)
+                        .to_json();
+                    util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap_or_default(), profile, vec![]));
+                }
+            }
+            if let Some(m) = calib.imu_matrix {
+                let rows = m.iter().map(|x| x.to_vec()).collect::<Vec<_>>();
+                util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Matrix, "IMU mounting matrix", Vec_Vec_f64, |v| format!("{:?}", v), rows.clone(), vec![]));
+                util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Matrix, "IMU mounting matrix", Vec_Vec_f64, |v| format!("{:?}", v), rows, vec![]));
+
+                // The factory matrix replaces the model default. If it's not a simple axis swap, the samples are rotated directly
+                match Self::matrix_to_orientation(&m) {
+                    Some(o) => imu_orientation = o,
+                    None => {
+                        for v in gyro.iter_mut().chain(accl.iter_mut()) {
+                            let (x, y, z) = (v.x, v.y, v.z);
+                            v.x = m[0][0] * x + m[0][1] * y + m[0][2] * z;
+                            v.y = m[1][0] * x + m[1][1] * y + m[1][2] * z;
+                            v.z = m[2][0] * x + m[2][1] * y + m[2][2] * z;
+                        }
+                        imu_orientation = "XYZ".into();
+                    }
+                }
+            }
+        }
+
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
         util::insert_tag(&mut map, tag!(parsed GroupId::Exposure,      TagId::Data, "Exposure data",      Vec_TimeScalar_f64,  |v| format!("{:?}", v), exp, vec![]));
@@ -240,15 +351,11 @@ impl QoocamEgo {
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "rad/s".into(), Vec::new()));
 
-        let imu_orientation = match self.model.as_deref() {
-            Some("QooCam 3 Ultra") => if rear_lens { "yxz" } else { "yXZ" },
-            _ => "XYZ"
-        };
-        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
-        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.clone(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation, Vec::new()));
 
-        Ok(vec![
+        Ok(util::split_samples_by_time(vec![
             SampleInfo { timestamp_ms: 0.0, duration_ms: last_timestamp.unwrap_or_default() - first_timestamp.unwrap_or_default(), tag_map: Some(map), ..Default::default() }
-        ])
+        ], 1000.0))
     }
 }
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 // Copyright © 2021-2022 Adrian <adrian.eddy at gmail>
 
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
 mod sony;
 mod gopro;
 mod gyroflow;
@@ -11,23 +13,180 @@ mod witmotion;
 mod dji;
 mod phone_apps;
 mod ardupilot;
+mod ulog;
 mod blackmagic;
 mod red;
 mod vuze;
 mod kandao;
-mod camm;
+pub mod camm;
 mod esplog;
 mod cooke;
 mod senseflow;
 mod freefly;
+mod panasonic;
+mod subtitles;
+mod arri;
+mod monitor_recorder;
 
 pub mod tags_impl;
 pub mod util;
 pub mod filesystem;
+pub mod quirks;
+pub mod export;
+pub mod processing;
+pub mod sync;
+#[cfg(feature = "bench-data")]
+pub mod bench_data;
+#[cfg(feature = "live")]
+pub mod live;
 
 use std::io::*;
 use std::sync::{ Arc, atomic::AtomicBool };
 use util::*;
+use tags_impl::*;
+
+/// Options of `Input::from_stream_with_options`. New options can be added in any release,
+/// so create it with `InputOptions::default()` or one of the presets and the `with_*` methods
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct InputOptions {
+    /// Decimate the `Data` tag of each group to at most this many readings in total, keeping the extrema.
+    /// Useful for previews, where full-resolution data is not needed.
+    pub max_samples_per_group: Option<usize>,
+    /// Only detect the format and collect the tags, without decoding the heavy data vectors.
+    /// Lazily parsed tags (gyro, accelerometer, quaternions etc.) stay undecoded until they are accessed,
+    /// so getting the camera model and checking which groups are present is much faster.
+    /// Decimation is not applied in this mode, because it would force parsing of all data.
+    pub probe_only: bool,
+    /// Verify checksums of packets and frames in formats which have them (WitMotion, SenseFlow, Blackbox, EspLog).
+    /// The result is added to the first sample as `Default/ChecksumStats` tag.
+    pub validate_checksums: bool,
+    /// With `validate_checksums`, reject the file if the ratio of corrupted packets is above this value (0.0 - 1.0)
+    pub max_corruption_ratio: Option<f64>,
+    /// If the `moov` box is missing or broken (eg. file recovered after a crash), scan the `mdat` for metadata packets directly.
+    /// Timestamps are then reconstructed from the order of the packets, so they are only approximate.
+    /// Supported for GoPro (GPMF) and Sony (RTMD)
+    pub allow_salvage: bool,
+    /// What to do when a parser inserts a `Custom` or `Unknown` tag which already exists in the group.
+    /// With `KeepBoth`, conflicting values are stored under ids namespaced with the format name, see `util::duplicate_tags`
+    pub tag_merge_policy: TagMergePolicy,
+    /// Column mapping for CSV logs which aren't supported natively. See `util::CsvSchema`
+    pub csv_schema: Option<CsvSchema>,
+    /// In logs with multiple IMUs (KanDao), shift the timestamps of each device by its estimated clock offset,
+    /// so all devices share the timeline of the first one. The offsets are always exposed as `Default/TimeOffset` tags
+    pub align_device_clocks: bool,
+    /// Keep only these groups in the parsed samples, eg. to drop GPS and lens data when only the IMU is needed
+    pub groups: Option<Vec<GroupId>>,
+    /// Remove the location and identification (GPS, serial numbers, owner and network names) from the parsed samples, see `Input::anonymize`
+    pub anonymize: bool,
+    /// In MP4/MOV files with more than one metadata track (eg. an extra track added by another app), read only the track with this index.
+    /// The candidates are listed by `util::metadata_tracks`. By default the first track recognized by the parser is used
+    pub metadata_track_index: Option<usize>,
+    /// Parse the file as this format (name of the `SupportedFormats` variant, case-insensitive, eg. `WitMotion` or `Camm`),
+    /// regardless of the extension and also when the detection fails. Useful for misnamed files.
+    /// If the data doesn't match the format, parsing fails with an error instead of trying the other formats
+    pub force_format: Option<String>,
+    /// In clips played at a different speed than they were captured (GoPro TimeWarp and slow motion), convert the timestamps
+    /// of the telemetry from the capture time to the playback time of the video, so overlays don't drift.
+    /// The mode and the mapping between the time bases are always exposed in the `Default` group (`RecordingMode`, `CaptureTime`, `TimeBase`)
+    pub map_to_media_time: bool,
+    /// Pixel pitch of camera models which aren't known to the parsers, or to correct the built-in values.
+    /// Checked before the file in the `TELEMETRY_PARSER_SENSOR_DB` environment variable, see `util::sensor_database_pixel_pitch` for the format
+    pub sensor_database: Option<serde_json::Value>,
+    /// The file is still being recorded: parse it as far as there's complete data and remember where it stopped,
+    /// so `Input::resume` can continue when the file gets longer. Supported for Sony MXF (RTMD).
+    /// The file may not have the footer yet, so the detection can need `force_format` too
+    pub growing_file: bool,
+    /// Formats are detected in the order of `SupportedFormats` and by default only the first matching one is parsed.
+    /// With this option, all matching formats are parsed (eg. GPMF in a file which also has a `camm` track) and the groups
+    /// which the first format doesn't have are added from the others. `Input::sources` tells which format contributed which groups
+    pub parse_all_formats: bool,
+    /// When the first chapter of a recording split by the camera is opened (GoPro GX01xxxx.MP4, GX02xxxx.MP4, ...), parse the following chapters too
+    /// and merge them into one continuous timeline, with the chapter boundaries as `Chapter` markers. By default only the opened file is parsed
+    pub merge_chapters: bool,
+}
+
+impl InputOptions {
+    /// Fast detection of the format and the available tags, see `probe_only`
+    pub fn probe() -> Self {
+        Self { probe_only: true, ..Default::default() }
+    }
+    /// Only the IMU groups (gyroscope, accelerometer, magnetometer and quaternions)
+    pub fn gyro_only() -> Self {
+        Self { groups: Some(vec![GroupId::Gyroscope, GroupId::Accelerometer, GroupId::Magnetometer, GroupId::Quaternion]), ..Default::default() }
+    }
+    /// Everything that can be read from the file: checksums are validated, broken files are salvaged
+    /// and conflicting tags are kept
+    pub fn full() -> Self {
+        Self { validate_checksums: true, allow_salvage: true, tag_merge_policy: TagMergePolicy::KeepBoth, ..Default::default() }
+    }
+
+    pub fn with_max_samples_per_group(mut self, max: usize) -> Self {
+        self.max_samples_per_group = Some(max);
+        self
+    }
+    pub fn with_probe_only(mut self, probe_only: bool) -> Self {
+        self.probe_only = probe_only;
+        self
+    }
+    pub fn with_checksum_validation(mut self, validate: bool, max_corruption_ratio: Option<f64>) -> Self {
+        self.validate_checksums = validate;
+        self.max_corruption_ratio = max_corruption_ratio;
+        self
+    }
+    pub fn with_salvage(mut self, allow: bool) -> Self {
+        self.allow_salvage = allow;
+        self
+    }
+    pub fn with_tag_merge_policy(mut self, policy: TagMergePolicy) -> Self {
+        self.tag_merge_policy = policy;
+        self
+    }
+    pub fn with_csv_schema(mut self, schema: CsvSchema) -> Self {
+        self.csv_schema = Some(schema);
+        self
+    }
+    pub fn with_device_clock_alignment(mut self, align: bool) -> Self {
+        self.align_device_clocks = align;
+        self
+    }
+    pub fn with_groups(mut self, groups: Vec<GroupId>) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+    pub fn with_anonymization(mut self, anonymize: bool) -> Self {
+        self.anonymize = anonymize;
+        self
+    }
+    pub fn with_metadata_track_index(mut self, track_index: usize) -> Self {
+        self.metadata_track_index = Some(track_index);
+        self
+    }
+    pub fn with_forced_format(mut self, format: &str) -> Self {
+        self.force_format = Some(format.to_owned());
+        self
+    }
+    pub fn with_media_time(mut self, map: bool) -> Self {
+        self.map_to_media_time = map;
+        self
+    }
+    pub fn with_sensor_database(mut self, db: serde_json::Value) -> Self {
+        self.sensor_database = Some(db);
+        self
+    }
+    pub fn with_growing_file(mut self, growing: bool) -> Self {
+        self.growing_file = growing;
+        self
+    }
+    pub fn with_all_formats(mut self, all: bool) -> Self {
+        self.parse_all_formats = all;
+        self
+    }
+    pub fn with_chapter_merging(mut self, merge: bool) -> Self {
+        self.merge_chapters = merge;
+        self
+    }
+}
 
 macro_rules! impl_formats {
     ($($name:ident => $class:ty,)*) => {
@@ -36,10 +195,27 @@ macro_rules! impl_formats {
         }
         pub struct Input {
             inner: SupportedFormats,
-            pub samples: Option<Vec<SampleInfo>>
+            /// Ordered by `timestamp_ms`, see `util::sort_samples`
+            pub samples: Option<Vec<SampleInfo>>,
+            stats: Vec<util::TrackStats>,
+            warnings: Vec<util::ParseWarning>,
+            // Source of the IMU groups, if they were merged from another input
+            imu_inner: Option<Box<SupportedFormats>>,
+            sources: Vec<util::FormatSource>
         }
         impl Input {
             pub fn from_stream<T: Read + Seek, P: AsRef<std::path::Path>, F: Fn(f64)>(stream: &mut T, size: usize, filepath: P, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Input> {
+                Self::from_stream_with_options(stream, size, filepath, progress_cb, cancel_flag, InputOptions::default())
+            }
+            /// Parses a non-seekable stream, eg. a pipe or stdin. The data is spooled to memory, or to a temporary file after `memory_limit` bytes
+            /// (see `filesystem::spool::DEFAULT_MEMORY_LIMIT`). Meant for the logs which are read from the start anyway (gcsv, bbl, esplog, witmotion etc.),
+            /// video containers work too, but they have to be spooled completely before the header at the end can be read
+            pub fn from_reader<R: Read, P: AsRef<std::path::Path>, F: Fn(f64)>(reader: R, memory_limit: usize, filepath: P, progress_cb: F, cancel_flag: Arc<AtomicBool>, options: InputOptions) -> Result<Input> {
+                let mut spool = filesystem::spool::SpooledReader::new(reader, memory_limit)?;
+                let size = spool.size();
+                Self::from_stream_with_options(&mut spool, size, filepath, progress_cb, cancel_flag, options)
+            }
+            pub fn from_stream_with_options<T: Read + Seek, P: AsRef<std::path::Path>, F: Fn(f64)>(stream: &mut T, size: usize, filepath: P, progress_cb: F, cancel_flag: Arc<AtomicBool>, options: InputOptions) -> Result<Input> {
                 let read_mb = if size as u64 > 30u64*1024*1024*1024 { // If file is greater than 30 GB, read 30 MB header/footer
                     30
                 } else if size as u64 > 5u64*1024*1024*1024 { // If file is greater than 5 GB, read 10 MB header/footer
@@ -47,35 +223,119 @@ macro_rules! impl_formats {
                 } else {
                     4
                 };
+                let ctx = util::ParseContext::new(&options);
                 let buf = util::read_beginning_and_end(stream, size, read_mb*1024*1024)?;
                 if buf.is_empty() {
                     return Err(Error::new(ErrorKind::Other, "File is empty or there was an error trying to load it."));
                 }
                 let ext = filepath.as_ref().extension().map(|x| x.to_ascii_lowercase().to_string_lossy().to_owned().to_string());
+                let format_names = [$(stringify!($name)),*];
+                if let Some(format) = options.force_format.as_deref() {
+                    if !format_names.iter().any(|x| x.eq_ignore_ascii_case(format)) {
+                        return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown format `{format}`, supported formats: {}", format_names.join(", "))));
+                    }
+                }
+                let mut parsed_formats: Option<Input> = None;
                 {$(
+                    // `Some(true)` for the forced format, `Some(false)` for all the others
+                    let forced = options.force_format.as_deref().map(|x| x.eq_ignore_ascii_case(stringify!($name)));
                     let exts = <$class>::possible_extensions();
-                    let mut check = true;
-                    if !exts.is_empty() {
+                    let mut check = forced.unwrap_or(true);
+                    if !exts.is_empty() && forced.is_none() {
                         if let Some(ref ext) = ext {
                             if !exts.contains(&ext.as_str()) { check = false; }
                         }
                     }
                     if check {
-                        if let Some(mut x) = <$class>::detect(&buf, &filepath) {
-                            return Ok(Input {
-                                samples: x.parse(stream, size, progress_cb, cancel_flag).ok(),
-                                inner: SupportedFormats::$name(x)
-                            });
+                        // Detection is still done for the forced format, because some parsers get their variant from it (eg. Blackbox CSV or binary)
+                        let detected = <$class>::detect(&buf, &filepath).or_else(|| forced.filter(|x| *x).map(|_| <$class>::default()));
+                        if let Some(mut x) = detected {
+                            ctx.begin_format(&options, &x.camera_type());
+                            if parsed_formats.is_some() {
+                                stream.seek(SeekFrom::Start(0))?;
+                            }
+                            let parsed = x.parse(stream, size, &progress_cb, cancel_flag.clone());
+                            let parsed = match parsed {
+                                Ok(samples) if forced == Some(true) && !samples.iter().any(|x| x.tag_map.as_ref().is_some_and(|m| !m.is_empty())) => {
+                                    Err(Error::new(ErrorKind::InvalidData, "no telemetry was found"))
+                                },
+                                x => x
+                            };
+                            if let (Err(e), Some(true)) = (&parsed, forced) {
+                                return Err(Error::new(ErrorKind::InvalidData, format!("File doesn't match the forced format {}: {e}", stringify!($name))));
+                            }
+                            let mut samples = parsed.ok();
+                            if let Some(samples) = samples.as_mut() {
+                                util::sort_samples(&mut samples[..]);
+                                util::apply_sensor_database(samples, options.sensor_database.as_ref(), &x.camera_type(), x.model.as_deref());
+                                if !options.probe_only {
+                                    util::insert_start_offsets(samples, x.has_accurate_timestamps());
+                                }
+                            }
+                            if let Some(stats) = util::end_checksum_validation() {
+                                let ratio = stats.corruption_ratio();
+                                if let Some(max) = options.max_corruption_ratio {
+                                    if ratio > max {
+                                        return Err(Error::new(ErrorKind::InvalidData, format!("File is corrupted: {} of {} packets have invalid checksum", stats.invalid, stats.valid + stats.invalid)));
+                                    }
+                                }
+                                if let Some(samples) = samples.as_mut() {
+                                    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+                                        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+                                    }
+                                    if let Some(map) = samples[0].tag_map.as_mut() {
+                                        util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("ChecksumStats".into()), "Checksum validation", Json, |v| serde_json::to_string(v).unwrap_or_default(), {
+                                            serde_json::json!({ "valid": stats.valid, "invalid": stats.invalid, "corruption_ratio": ratio })
+                                        }, vec![]));
+                                    }
+                                }
+                            }
+                            if let (Some(samples), false) = (samples.as_mut(), options.probe_only) {
+                                util::collect_white_balance(samples, &x.camera_type());
+                            }
+                            if let (Some(samples), Some(groups)) = (samples.as_mut(), options.groups.as_ref()) {
+                                for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+                                    map.retain(|g, _| groups.contains(g));
+                                }
+                            }
+                            if let (Some(samples), Some(max), false) = (samples.as_mut(), options.max_samples_per_group, options.probe_only) {
+                                util::decimate_samples(samples, max);
+                            }
+                            if let (Some(samples), true) = (samples.as_mut(), options.anonymize) {
+                                for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+                                    util::anonymize_tag_map(map);
+                                }
+                            }
+                            let sources = vec![util::FormatSource::new(stringify!($name), samples.iter().flatten())];
+                            let input = Input {
+                                samples,
+                                inner: SupportedFormats::$name(x),
+                                stats: util::take_track_stats(),
+                                warnings: util::take_parse_warnings(),
+                                imu_inner: None,
+                                sources
+                            };
+                            if !options.parse_all_formats {
+                                return Ok(input);
+                            }
+                            match parsed_formats.as_mut() {
+                                Some(first) => first.add_format(input),
+                                None => parsed_formats = Some(input)
+                            }
                         }
                     }
                 )*}
+                drop(ctx);
+                if let Some(input) = parsed_formats {
+                    return Ok(input);
+                }
                 // If nothing was detected, check if there's a file with the same name but different extension
-                if ext.as_deref() == Some("mp4") || ext.as_deref() == Some("mov") {
+                if options.force_format.is_none() && (ext.as_deref() == Some("mp4") || ext.as_deref() == Some("mov")) {
                     let fs = filesystem::get_base();
-                    for try_ext in ["gcsv", "bbl", "bfl", "csv", "GCSV", "BBL", "BFL", "CSV"] {
+                    for try_ext in ["gcsv", "bbl", "bfl", "csv", "xml", "GCSV", "BBL", "BFL", "CSV", "XML", "poses.json", "POSES.JSON"] {
                         if let Some(gyro_path) = filepath.as_ref().to_str().and_then(|x| filesystem::file_with_extension(x, try_ext)) {
                             if let Ok(mut f) = filesystem::open_file(&fs, &gyro_path) {
-                                return Self::from_stream(&mut f.file, f.size, &gyro_path, progress_cb, cancel_flag);
+                                return Self::from_stream_with_options(&mut f.file, f.size, &gyro_path, progress_cb, cancel_flag, options);
                             }
                         }
                     }
@@ -93,7 +353,7 @@ macro_rules! impl_formats {
                 }
             }
             pub fn normalize_imu_orientation(&self, v: String) -> String {
-                match &self.inner {
+                match self.imu_inner.as_deref().unwrap_or(&self.inner) {
                     $(SupportedFormats::$name(_) => <$class>::normalize_imu_orientation(v),)*
                 }
             }
@@ -103,14 +363,231 @@ macro_rules! impl_formats {
                 }
             }
             pub fn has_accurate_timestamps(&self) -> bool {
-                match &self.inner {
+                match self.imu_inner.as_deref().unwrap_or(&self.inner) {
                     $(SupportedFormats::$name(x) => x.has_accurate_timestamps(),)*
                 }
             }
+            /// Combines two inputs, eg. a video file (frame timing, lens data) with a log of an external IMU (gcsv, bbl).
+            /// Groups listed in `policy.secondary_groups` are taken from `secondary`, all other groups from `primary`.
+            /// Timestamps of `secondary` are shifted by `policy.time_offset_ms` and then remapped with `policy.time_map`
+            pub fn merge(primary: Input, secondary: Input, policy: &MergePolicy) -> Input {
+                let mut samples = primary.samples.unwrap_or_default();
+                for info in samples.iter_mut() {
+                    if let Some(map) = info.tag_map.as_mut() {
+                        map.retain(|group, _| !policy.secondary_groups.contains(group));
+                    }
+                }
+
+                let offset = policy.time_offset_ms;
+                let time_map = policy.time_map.clone();
+                let f: util::TimeMapFn = Arc::new(move |t| match &time_map {
+                    Some(m) => m(t + offset),
+                    None => t + offset
+                });
+                for mut info in secondary.samples.unwrap_or_default() {
+                    let Some(mut map) = info.tag_map.take() else { continue; };
+                    map.retain(|group, _| policy.secondary_groups.contains(group));
+                    if map.is_empty() { continue; }
+                    for tags in map.values_mut() {
+                        // The readings are moved to the time of the primary video, the offset is computed again below
+                        tags.remove(&TagId::StartOffset);
+                        util::map_timestamps(tags, &f);
+                    }
+                    let end = f(info.timestamp_ms + info.duration_ms);
+                    info.timestamp_ms = f(info.timestamp_ms);
+                    info.duration_ms = end - info.timestamp_ms;
+                    info.tag_map = Some(map);
+                    samples.push(info);
+                }
+                util::sort_samples(&mut samples[..]);
+                util::insert_start_offsets(&mut samples, true);
+
+                Input {
+                    inner: primary.inner,
+                    samples: Some(samples),
+                    stats: primary.stats,
+                    warnings: primary.warnings.into_iter().chain(secondary.warnings).collect(),
+                    imu_inner: Some(secondary.imu_inner.unwrap_or_else(|| Box::new(secondary.inner))),
+                    sources: primary.sources.into_iter().map(|mut x| { x.groups.retain(|g| !policy.secondary_groups.contains(g)); x })
+                        .chain(secondary.sources.into_iter().map(|mut x| { x.groups.retain(|g| policy.secondary_groups.contains(g)); x }))
+                        .filter(|x| !x.groups.is_empty())
+                        .collect()
+                }
+            }
+            // Adds the groups of `other` which aren't in `self` yet, see `InputOptions::parse_all_formats`
+            fn add_format(&mut self, other: Input) {
+                let existing = self.samples.iter().flatten().filter_map(|x| x.tag_map.as_ref()).flat_map(|x| x.keys().cloned()).collect::<std::collections::BTreeSet<GroupId>>();
+                let mut added = std::collections::BTreeSet::new();
+                let samples = self.samples.get_or_insert_with(Vec::new);
+                for mut info in other.samples.unwrap_or_default() {
+                    let Some(map) = info.tag_map.as_mut() else { continue; };
+                    map.retain(|group, _| !existing.contains(group));
+                    if map.is_empty() { continue; }
+                    added.extend(map.keys().cloned());
+                    samples.push(info);
+                }
+                util::sort_samples(&mut samples[..]);
+                self.stats.extend(other.stats);
+                self.warnings.extend(other.warnings);
+                for mut source in other.sources {
+                    source.groups.retain(|g| added.contains(g));
+                    if !source.groups.is_empty() {
+                        self.sources.push(source);
+                    }
+                }
+            }
+            /// Splits a log which was recorded continuously across several clips (external IMU logger, gcsv or blackbox) into one input per clip.
+            /// `clips` are the start and the duration of each clip in milliseconds, in the time of the log. The log is parsed only once,
+            /// each input gets the readings in the range of its clip, with the timestamps relative to the clip start
+            pub fn split_clips(&self, clips: &[(f64, f64)]) -> Result<Vec<Input>> {
+                let mut ret = Vec::with_capacity(clips.len());
+                for &(start_ms, duration_ms) in clips {
+                    let inner = match &self.inner {
+                        SupportedFormats::Gyroflow(x) => SupportedFormats::Gyroflow(x.clone()),
+                        SupportedFormats::BlackBox(x) => SupportedFormats::BlackBox(x.clone()),
+                        _ => return Err(Error::new(ErrorKind::Unsupported, "Only gcsv and blackbox logs can be split into clips"))
+                    };
+                    let end_ms = start_ms + duration_ms;
+                    let mut samples = Vec::new();
+                    for info in self.samples.iter().flatten() {
+                        // Metadata of the whole log is at 0 ms without duration, it's kept in every clip
+                        let is_header = info.timestamp_ms == 0.0 && info.duration_ms == 0.0;
+                        if !is_header && (info.timestamp_ms >= end_ms || info.timestamp_ms + info.duration_ms < start_ms) { continue; }
+                        let mut info = info.clone();
+                        if let Some(map) = info.tag_map.as_mut() {
+                            for tags in map.values_mut() {
+                                tags.remove(&TagId::StartOffset);
+                                util::slice_timestamps(tags, start_ms, end_ms);
+                            }
+                        }
+                        if !is_header {
+                            let end = (info.timestamp_ms + info.duration_ms).min(end_ms);
+                            info.timestamp_ms = info.timestamp_ms.max(start_ms) - start_ms;
+                            info.duration_ms = end - start_ms - info.timestamp_ms;
+                        }
+                        samples.push(info);
+                    }
+                    util::insert_start_offsets(&mut samples, true);
+
+                    ret.push(Input {
+                        inner,
+                        samples: Some(samples),
+                        stats: self.stats.clone(),
+                        warnings: self.warnings.clone(),
+                        imu_inner: None,
+                        sources: self.sources.clone()
+                    });
+                }
+                Ok(ret)
+            }
+            /// Continues the parsing of a file which is still being recorded (see `InputOptions::growing_file`), after it got longer.
+            /// `size` is the current size of the file. The new samples are added to `samples`, returns how many there were
+            pub fn resume<T: Read + Seek>(&mut self, stream: &mut T, size: usize) -> Result<usize> {
+                let SupportedFormats::Sony(x) = &mut self.inner else {
+                    return Err(Error::new(ErrorKind::Unsupported, "Resuming is supported only for Sony MXF files"));
+                };
+                let _ctx = util::ParseContext::new(&InputOptions { growing_file: true, ..Default::default() });
+                let _ = util::take_parse_warnings();
+                let new_samples = x.resume(stream, size)?;
+                let count = new_samples.len();
+                let accurate_timestamps = x.has_accurate_timestamps();
+
+                let samples = self.samples.get_or_insert_with(Vec::new);
+                samples.extend(new_samples);
+                util::sort_samples(&mut samples[..]);
+                util::insert_start_offsets(samples, accurate_timestamps);
+                self.warnings.extend(util::take_parse_warnings());
+                Ok(count)
+            }
+            /// The samples in the order of `util::sort_samples`, for the samples modified after parsing
+            pub fn sorted_samples(&self) -> Vec<&SampleInfo> {
+                let mut ret = self.samples.iter().flatten().collect::<Vec<_>>();
+                util::sort_samples(&mut ret[..]);
+                ret
+            }
+            /// Best available preview image embedded in the file (Insta360 photos, RED), as JPEG data.
+            /// If there are multiple, the largest one is returned
+            pub fn thumbnail(&self) -> Option<Vec<u8>> {
+                self.samples.iter().flatten()
+                    .filter_map(|x| x.tag_map.as_ref()?.get(&GroupId::Default))
+                    .flat_map(|map| map.iter())
+                    .filter_map(|(id, desc)| match (id, &desc.value) {
+                        (TagId::File(_), TagValue::Vec_u8(v)) if v.get().starts_with(&[0xFF, 0xD8, 0xFF]) => Some(v.get()),
+                        _ => None
+                    })
+                    .max_by_key(|x| x.len())
+                    .cloned()
+            }
+            /// Which format contributed which groups, in the order of detection. There's more than one with `InputOptions::parse_all_formats`
+            /// or after `merge`
+            pub fn sources(&self) -> &[util::FormatSource] {
+                &self.sources
+            }
+            /// Size and rate of the telemetry tracks read from the file. Useful to detect truncated tracks or unusually sparse data.
+            /// Empty for formats which don't store the telemetry in a track
+            pub fn stats(&self) -> &[util::TrackStats] {
+                &self.stats
+            }
+            /// Warnings reported while parsing the file (unknown tags, skipped samples, checksum failures etc.)
+            pub fn warnings(&self) -> &[util::ParseWarning] {
+                &self.warnings
+            }
+            /// Decodes all tags of `group` and returns them, together with the index of the sample they belong to.
+            /// Other groups are left untouched, so different threads can load different groups of the same `Arc<Input>` concurrently
+            pub fn load_group(&self, group: GroupId) -> Vec<(usize, &TagMap)> {
+                let mut ret = Vec::new();
+                for (i, sample) in self.samples.iter().flatten().enumerate() {
+                    if let Some(map) = sample.tag_map.as_ref().and_then(|x| x.get(&group)) {
+                        for desc in map.values() {
+                            desc.value.ensure_parsed();
+                        }
+                        ret.push((i, map));
+                    }
+                }
+                ret
+            }
+            /// Removes the GPS groups, serial numbers, owner names and network names from all samples, so the telemetry can be shared
+            /// without the location and identification of the camera. See `util::anonymize_tag_map`
+            pub fn anonymize(&mut self) {
+                for map in self.samples.iter_mut().flatten().filter_map(|x| x.tag_map.as_mut()) {
+                    util::anonymize_tag_map(map);
+                }
+            }
+            /// Image geometry (crop area, sensor size, pixel pitch, readout time) valid at `samples[sample_index]`.
+            /// Falls back to `frame_readout_time()` if the readout time isn't in the tags
+            pub fn imager_geometry(&self, sample_index: usize) -> util::ImagerGeometry {
+                let mut ret = util::imager_geometry(self.samples.as_deref().unwrap_or_default(), sample_index);
+                if ret.frame_readout_time.is_none() {
+                    ret.frame_readout_time = self.frame_readout_time();
+                }
+                ret
+            }
+            /// Whether the file can be stabilized, with the reasons, eg. for file-open dialogs,
+            /// see `util::suitability_report`
+            pub fn suitability_report(&self) -> util::SuitabilityReport {
+                util::suitability_report(self)
+            }
+
+            /// Markers (HiLights, sync beeps, camera triggers, ...) of the whole file, ordered by time
+            pub fn markers(&self) -> Vec<MarkerData> {
+                util::get_markers(self.samples.as_deref().unwrap_or_default())
+            }
+
+            /// Start of the recording in local time and UTC with the time zone offset, see `util::recording_start`
+            pub fn recording_start(&self) -> Option<util::RecordingStart> {
+                util::recording_start(self.samples.as_deref().unwrap_or_default())
+            }
         }
     };
 }
 
+// Tags are decoded lazily and thread-safely, so `Input` can be shared between threads in an `Arc`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() { }
+    assert_send_sync::<Input>();
+};
+
+// Detection priority: formats are tried in this order, so the more specific ones are first (eg. GoPro before the generic CAMM track)
 impl_formats! {
     GoPro     => gopro::GoPro,
     Sony      => sony::Sony,
@@ -124,6 +601,7 @@ impl_formats! {
     WitMotion => witmotion::WitMotion,
     PhoneApps => phone_apps::PhoneApps,
     ArduPilot => ardupilot::ArduPilot,
+    ULog      => ulog::ULog,
     Vuze      => vuze::Vuze,
     KanDao    => kandao::KanDao,
     QoocamEgo => kandao::QoocamEgo,
@@ -132,4 +610,8 @@ impl_formats! {
     Cooke     => cooke::Cooke,
     SenseFlow => senseflow::SenseFlow,
     Freefly   => freefly::Freefly,
+    Panasonic => panasonic::Panasonic,
+    SubtitleTelemetry => subtitles::SubtitleTelemetry,
+    Arri      => arri::Arri,
+    MonitorRecorder => monitor_recorder::MonitorRecorder,
 }
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Real-time decoding of the telemetry streamed by IMU loggers (WitMotion binary protocol, EspLog), eg. from a serial port.
+// Serial ports are opened like files (`/dev/ttyUSB0`, `\\.\COM3`), with the baud rate set up by the system or the application.
+// Bytes are buffered until a whole packet is available and decoded with the same decoders as the files,
+// so every batch of readings has the same tags as a parsed file. Timestamps continue from the previous batches
+
+use std::io::*;
+use std::sync::{ Arc, atomic::{ AtomicBool, Ordering } };
+#[cfg(not(feature = "single-thread"))]
+use std::sync::mpsc;
+
+use crate::util::{ self, SampleInfo, ParseWarning };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveFormat {
+    WitMotion,
+    EspLog,
+}
+
+enum Decoder {
+    WitMotion(crate::witmotion::binary::Decoder),
+    EspLog(crate::esplog::Decoder),
+}
+
+// Format is unknown until this many bytes were received without a match
+const DETECT_LIMIT: usize = 4096;
+
+pub struct LiveParser {
+    decoder: Option<Decoder>,
+    buffer: Vec<u8>,
+    sample_index: u64,
+    corrupted: bool,
+    warnings: Vec<ParseWarning>,
+}
+
+impl LiveParser {
+    /// `format` can be `None` to detect it from the first received bytes
+    pub fn new(format: Option<LiveFormat>) -> Self {
+        let mut ret = Self { decoder: None, buffer: Vec::new(), sample_index: 0, corrupted: false, warnings: Vec::new() };
+        if let Some(format) = format {
+            ret.set_format(format);
+        }
+        ret
+    }
+
+    pub fn format(&self) -> Option<LiveFormat> {
+        match self.decoder {
+            Some(Decoder::WitMotion(_)) => Some(LiveFormat::WitMotion),
+            Some(Decoder::EspLog(_))    => Some(LiveFormat::EspLog),
+            None => None
+        }
+    }
+
+    fn set_format(&mut self, format: LiveFormat) {
+        self.decoder = Some(match format {
+            LiveFormat::WitMotion => Decoder::WitMotion(crate::witmotion::binary::Decoder::new()),
+            LiveFormat::EspLog    => Decoder::EspLog(crate::esplog::Decoder::new(true)),
+        });
+    }
+
+    // EspLog streams start with the header, WitMotion packets start with 0x55 and the stream can start in the middle of one
+    fn detect(&mut self) -> Result<bool> {
+        if self.buffer.starts_with(b"EspLog0") {
+            self.set_format(LiveFormat::EspLog);
+            self.buffer.drain(..7);
+            return Ok(true);
+        }
+        let packet_start = (0..self.buffer.len().saturating_sub(11)).find(|&i| {
+            self.buffer[i] == 0x55 && matches!(self.buffer[i + 1], 0x50..=0x54 | 0x59) && self.buffer[i + 11] == 0x55
+        });
+        if let Some(i) = packet_start {
+            self.set_format(LiveFormat::WitMotion);
+            self.buffer.drain(..i);
+            return Ok(true);
+        }
+        if self.buffer.len() > DETECT_LIMIT {
+            return Err(Error::new(ErrorKind::InvalidData, "Unknown format of the live stream"));
+        }
+        Ok(false)
+    }
+
+    /// Adds the received bytes and returns the readings decoded from them, if there are any complete packets
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<SampleInfo>> {
+        if self.corrupted {
+            return Err(Error::new(ErrorKind::InvalidData, "Live stream is corrupted, decoding can't continue"));
+        }
+        self.buffer.extend_from_slice(data);
+        if self.decoder.is_none() && !self.detect()? {
+            return Ok(None);
+        }
+        let Some(decoder) = self.decoder.as_mut() else { return Ok(None); };
+
+        let mut cursor = Cursor::new(&self.buffer[..]);
+        loop {
+            let mut pos = cursor.position();
+            let res = match decoder {
+                Decoder::WitMotion(d) => {
+                    // Skip to the next packet after a corrupted one
+                    match memchr::memchr(0x55, &self.buffer[pos as usize..]) {
+                        Some(skip) => { pos += skip as u64; cursor.set_position(pos); },
+                        None => { pos = self.buffer.len() as u64; cursor.set_position(pos); break; }
+                    }
+                    d.packet(&mut cursor).map(|_| true)
+                },
+                Decoder::EspLog(d) => match cursor.read_u8() {
+                    Ok(byte) => d.record(byte, &mut cursor),
+                    Err(e) => Err(e)
+                }
+            };
+            match res {
+                Ok(true) => { },
+                Ok(false) => { self.corrupted = true; break; },
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { cursor.set_position(pos); break; },
+                Err(e) => return Err(e)
+            }
+        }
+        let consumed = cursor.position() as usize;
+        self.buffer.drain(..consumed);
+        self.warnings.extend(util::take_parse_warnings());
+
+        let has_readings = match decoder {
+            Decoder::WitMotion(d) => d.has_readings(),
+            Decoder::EspLog(d) => d.has_readings(),
+        };
+        if !has_readings {
+            return Ok(None);
+        }
+        let tag_map = match decoder {
+            Decoder::WitMotion(d) => d.tag_map(),
+            Decoder::EspLog(d) => d.tag_map(),
+        };
+        self.sample_index += 1;
+        Ok(Some(SampleInfo { sample_index: self.sample_index - 1, tag_map: Some(tag_map), ..Default::default() }))
+    }
+
+    /// Warnings reported since the last call (unknown packets, invalid data)
+    pub fn take_warnings(&mut self) -> Vec<ParseWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+/// Reads `reader` until the end of the stream or until `cancel_flag` is set, and calls `callback` with the readings of every read.
+/// Returns the warnings reported while decoding, see `LiveParser::take_warnings`
+pub fn run<R: Read, F: FnMut(SampleInfo)>(mut reader: R, format: Option<LiveFormat>, cancel_flag: Arc<AtomicBool>, mut callback: F) -> Result<Vec<ParseWarning>> {
+    let mut parser = LiveParser::new(format);
+    let mut buf = vec![0u8; 4096];
+    while !cancel_flag.load(Ordering::Relaxed) {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            // Serial ports return these when there's no data within their timeout
+            Err(e) if matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::TimedOut) => continue,
+            #[cfg(not(feature = "single-thread"))]
+            Err(e) if e.kind() == ErrorKind::WouldBlock => { std::thread::sleep(std::time::Duration::from_millis(1)); continue; },
+            Err(e) => return Err(e)
+        };
+        if let Some(sample) = parser.push(&buf[..n])? {
+            callback(sample);
+        }
+    }
+    Ok(parser.take_warnings())
+}
+
+/// Same as `run`, in a background thread. The channel is closed when the stream ends, the thread returns the result of `run`.
+/// Not available with the `single-thread` feature, where `LiveParser::push` should be called by the event loop of the application
+#[cfg(not(feature = "single-thread"))]
+pub fn spawn<R: Read + Send + 'static>(reader: R, format: Option<LiveFormat>, cancel_flag: Arc<AtomicBool>) -> (mpsc::Receiver<SampleInfo>, std::thread::JoinHandle<Result<Vec<ParseWarning>>>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = std::thread::spawn(move || {
+        run(reader, format, cancel_flag, |sample| { let _ = tx.send(sample); })
+    });
+    (rx, handle)
+}
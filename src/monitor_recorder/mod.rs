@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// MP4 files recorded by Hollyland and Accsoon wireless monitors. The recorder adds an auxiliary data track to the video, named after the vendor in its `hdlr`,
+// which carries the timecode of the source camera and passes through the metadata received over HDMI/SDI from compatible cameras.
+// Passthrough payloads which are GPMF (GoPro) or RTMD (Sony) are decoded by the existing parsers,
+// text samples with the timecode are stored as `Default/Timecode`
+
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+#[derive(Default)]
+pub struct MonitorRecorder {
+    pub model: Option<String>,
+    source: Option<&'static str>,
+}
+
+impl MonitorRecorder {
+    pub fn camera_type(&self) -> String {
+        match self.source {
+            Some(source) => format!("{} ({source} passthrough)", self.vendor()),
+            None => self.vendor().to_owned()
+        }
+    }
+    pub fn has_accurate_timestamps(&self) -> bool {
+        false
+    }
+    pub fn possible_extensions() -> Vec<&'static str> {
+        vec!["mp4", "mov"]
+    }
+    pub fn frame_readout_time(&self) -> Option<f64> {
+        None
+    }
+    pub fn normalize_imu_orientation(v: String) -> String {
+        v
+    }
+
+    fn vendor(&self) -> &'static str {
+        match self.model.as_deref() {
+            Some(x) if x.starts_with("Accsoon") => "Accsoon",
+            _ => "Hollyland"
+        }
+    }
+
+    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+        // The auxiliary track has the vendor name in its handler name, the name alone can be anywhere in a file (eg. in a camera's lens metadata)
+        let vendor = memmem::find_iter(buffer, b"hdlr").find_map(|pos| {
+            let name = buffer.get(pos + 28..)?;
+            let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len()).min(64)];
+            if name.starts_with(b"Hollyland") {
+                Some("Hollyland")
+            } else if name.starts_with(b"Accsoon") || name.starts_with(b"ACCSOON") {
+                Some("Accsoon")
+            } else {
+                None
+            }
+        })?;
+        // The recorder writes the model after the vendor name in the `udta` encoder string, eg. "Hollyland Mars M1"
+        let model = util::find_between(buffer, vendor.as_bytes(), b'\0')
+            .map(|x| format!("{vendor}{}", x.trim_end()))
+            .filter(|x| x.len() < 64 && x.chars().all(|c| c.is_ascii_graphic() || c == ' '))
+            .unwrap_or_else(|| vendor.to_owned());
+
+        Some(Self { model: Some(model), source: None })
+    }
+
+    // HH:MM:SS:FF, or HH:MM:SS;FF for drop frame
+    fn parse_timecode(data: &[u8]) -> Option<String> {
+        let text = std::str::from_utf8(data).ok()?.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        let b = text.as_bytes();
+        let is_tc = b.len() == 11 && b[2] == b':' && b[5] == b':' && (b[8] == b':' || b[8] == b';')
+            && [0, 1, 3, 4, 6, 7, 9, 10].iter().all(|&i| b[i].is_ascii_digit());
+        if is_tc { Some(text.to_owned()) } else { None }
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        let mut samples = Vec::new();
+        let mut gpmf = false;
+        let mut rtmd = false;
+
+        let gopro = gopro::GoPro::default();
+        let mut on_sample = |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+            if size > 0 {
+                progress_cb(file_position as f64 / size as f64);
+            }
+            let map = if gopro::GoPro::detect_metadata(data) {
+                gopro::GoPro::parse_metadata(&data[8..], GroupId::Default, false).ok().map(|mut map| {
+                    gopro.process_map(&mut map);
+                    gpmf = true;
+                    map
+                })
+            } else if sony::Sony::detect_metadata(data) {
+                sony::Sony::parse_metadata(&data[0x1C..]).ok().map(|map| {
+                    rtmd = true;
+                    map
+                })
+            } else {
+                Self::parse_timecode(data).map(|tc| {
+                    let mut map = GroupedTagMap::new();
+                    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("Timecode".into()), "Source camera timecode", String, |v| v.clone(), tc, vec![]));
+                    map
+                })
+            };
+            if let Some(map) = map {
+                info.tag_map = Some(map);
+                samples.push(info);
+            }
+        };
+        util::get_metadata_track_samples(stream, size, false, &mut on_sample, cancel_flag.clone())?;
+        stream.seek(SeekFrom::Start(0))?;
+        util::get_other_track_samples(stream, size, false, &mut on_sample, cancel_flag)?;
+
+        if rtmd {
+            let mut parser = sony::Sony::default();
+            parser.process_map(&mut samples);
+            // Store the orientation in the common convention, like the Sony parser does when it's reported
+            for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+                for group in [GroupId::Gyroscope, GroupId::Accelerometer] {
+                    let Ok(orientation) = map.query::<String>(group.clone(), TagId::Orientation).cloned() else { continue; };
+                    if orientation.len() != 3 { continue; }
+                    util::insert_tag(map, tag!(parsed group, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), sony::Sony::normalize_imu_orientation(orientation), Vec::new()));
+                }
+            }
+        }
+        self.source = match (gpmf, rtmd) {
+            (true, _) => Some("GoPro"),
+            (_, true) => Some("Sony"),
+            _ => None
+        };
+
+        samples.sort_by(|a, b| a.timestamp_ms.total_cmp(&b.timestamp_ms));
+
+        Ok(samples)
+    }
+}
@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+use std::path::Path;
+
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+#[derive(Default)]
+pub struct Panasonic {
+    pub model: Option<String>,
+    xml: Option<Vec<u8>>,
+    is_xml: bool,
+}
+
+// Lumix cameras write a clip XML (eg. `P1000001.XML`) next to the MOV file
+// and a `PANA` box with camera info in MOV/MP4 `udta`. Only the camera, lens and exposure info is read, there's no IMU data
+
+impl Panasonic {
+    pub fn camera_type(&self) -> String {
+        "Panasonic".to_owned()
+    }
+    pub fn has_accurate_timestamps(&self) -> bool {
+        false
+    }
+    pub fn possible_extensions() -> Vec<&'static str> {
+        vec!["mov", "mp4", "xml"]
+    }
+    pub fn frame_readout_time(&self) -> Option<f64> {
+        None
+    }
+    pub fn normalize_imu_orientation(v: String) -> String {
+        v
+    }
+
+    pub fn detect<P: AsRef<Path>>(buffer: &[u8], filepath: P) -> Option<Self> {
+        if Self::is_clip_xml(buffer) {
+            return Some(Self {
+                model: Self::xml_value(buffer, "ModelName"),
+                xml: Some(buffer.to_vec()),
+                is_xml: true
+            });
+        }
+
+        if let Some(p1) = memmem::find(buffer, b"PANA") {
+            let pana = &buffer[p1..(p1 + 1024).min(buffer.len())];
+            if memmem::find(pana, b"Panasonic").is_some() || memmem::find(pana, b"LUMIX").is_some() {
+                let mut obj = Self {
+                    model: util::find_between_with_offset(pana, b"DC-", b'\0', -3)
+                        .or_else(|| util::find_between_with_offset(pana, b"DMC-", b'\0', -4)),
+                    ..Default::default()
+                };
+                let path = filepath.as_ref().to_str().unwrap_or_default();
+                for ext in ["XML", "xml"] {
+                    if let Some(xml) = filesystem::file_with_extension(path, ext).and_then(|x| filesystem::read_file(&x).ok()) {
+                        if Self::is_clip_xml(&xml) {
+                            if let Some(model) = Self::xml_value(&xml, "ModelName") {
+                                obj.model = Some(model);
+                            }
+                            obj.xml = Some(xml);
+                            break;
+                        }
+                    }
+                }
+                return Some(obj);
+            }
+        }
+        None
+    }
+
+    fn is_clip_xml(buffer: &[u8]) -> bool {
+        buffer.starts_with(b"<?xml") && memmem::find(buffer, b"<Manufacturer>Panasonic</Manufacturer>").is_some()
+    }
+
+    fn xml_value(buffer: &[u8], name: &str) -> Option<String> {
+        let open = format!("<{name}>");
+        let v = util::find_between(buffer, open.as_bytes(), b'<')?;
+        let v = v.trim();
+        if v.is_empty() { None } else { Some(v.to_owned()) }
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        let mut map = GroupedTagMap::new();
+
+        if let Some(xml) = &self.xml {
+            let mut md = serde_json::Map::new();
+            for key in ["Manufacturer", "ModelName", "SerialNo.", "Firmware", "StartTimecode", "Duration", "EditUnit", "CaptureFps", "VideoFormat", "Codec", "LensName", "LensSerialNo.", "FocalLength", "ColorSpace", "Gamma", "WhiteBalance", "ISO"] {
+                if let Some(v) = Self::xml_value(xml, key) {
+                    md.insert(key.trim_end_matches('.').to_owned(), serde_json::Value::String(v));
+                }
+            }
+
+            if let Some(v) = md.get("LensName").and_then(|x| x.as_str()) {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Name, "Lens name", String, |v| v.clone(), v.into(), vec![]));
+            }
+            util::insert_lens_identity(&mut map, md.get("LensSerialNo").and_then(|x| x.as_str()), None, None);
+            if let Some(v) = md.get("FocalLength").and_then(|x| x.as_str()).and_then(|x| x.replace("mm", "").trim().parse::<f32>().ok()) {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::FocalLength, "Focal length", f32, |v| format!("{v:.2} mm"), v, vec![]));
+            }
+            if let Some(v) = md.get("CaptureFps").and_then(|x| x.as_str()).and_then(|x| x.trim_end_matches('p').parse::<f64>().ok()) {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::FrameRate, "Frame rate", f64, |v| format!("{:?}", v), v, vec![]));
+            }
+            if let Some(v) = md.get("ISO").and_then(|x| x.as_str()).and_then(|x| x.parse::<u32>().ok()) {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Exposure, TagId::ISOValue, "ISO value", u32, |v| format!("{v}"), v, vec![]));
+            }
+
+            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::Value::Object(md), vec![]));
+        }
+
+        let mut samples = vec![SampleInfo { tag_map: Some(map), ..Default::default() }];
+
+        if !self.is_xml {
+            // Newer bodies (GH6, GH7, S5II) write a metadata track, but the layout of its samples is not documented,
+            // so they are only exposed as raw `Default/Data` blobs. No gyro is decoded from them
+            let _ = util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+                if size > 0 {
+                    progress_cb(file_position as f64 / size as f64);
+                }
+                let mut map = GroupedTagMap::new();
+                util::insert_tag(&mut map, tag!(GroupId::Default, TagId::Data, "Raw metadata sample", data));
+                info.tag_map = Some(map);
+                samples.push(info);
+            }, cancel_flag);
+        }
+
+        Ok(samples)
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// iPhone videos from apps which record the CoreMotion data as QuickTime timed metadata (`mebx` tracks).
+// The sample description has a `keys` box with one child per item, where the box type is the local key id (u32)
+// and its `keyd` box has the namespace (`mdta`) followed by the key name, eg. `com.apple.quicktime.motion.rotation-rate`.
+// Each sample is a list of items: size (u32), local key id (u32) and the value. Recognized keys, by the last part of the name:
+// - `attitude`: quaternion as x, y, z, w (CMQuaternion order)
+// - `rotation-rate`: x, y, z in rad/s
+// - `gravity` and `user-acceleration`: x, y, z in g, the accelerometer is their sum, like the raw reading would be
+// - `ISO6709`: location string, eg. `+37.3349-122.0090+030.000/`
+// Vectors are big-endian f32 or f64, depending on the item size. The readings use the timestamps of the metadata track samples
+
+use std::collections::BTreeMap;
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+use byteorder::{ ReadBytesExt, BigEndian };
+
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+#[derive(Clone, Copy)]
+enum Key {
+    Attitude,
+    RotationRate,
+    Gravity,
+    UserAcceleration,
+    Location,
+}
+
+impl Key {
+    fn from_name(name: &str) -> Option<Self> {
+        let last = name.rsplit('.').next()?.to_ascii_lowercase().replace(['-', '_'], "");
+        match last.as_str() {
+            "attitude"         => Some(Self::Attitude),
+            "rotationrate"     => Some(Self::RotationRate),
+            "gravity"          => Some(Self::Gravity),
+            "useracceleration" => Some(Self::UserAcceleration),
+            "iso6709"          => Some(Self::Location),
+            _ => None
+        }
+    }
+}
+
+pub fn detect(buffer: &[u8]) -> bool {
+    memmem::find(buffer, b"mebx").is_some() && (memmem::find(buffer, b"rotation-rate").is_some() || memmem::find(buffer, b"rotationRate").is_some())
+}
+
+// Recognized keys of the `mebx` tracks, by track index and local key id
+fn read_keys(moov: &[u8]) -> BTreeMap<usize, BTreeMap<u32, Key>> {
+    let mut ret = BTreeMap::new();
+    for (track_index, (_, trak)) in util::mp4_children(moov).into_iter().filter(|x| x.0 == b"trak").enumerate() {
+        let Some(stsd) = util::mp4_find(trak, &[b"mdia", b"minf", b"stbl", b"stsd"]) else { continue; };
+        // Version and flags (4), entry count (4), then the entry: size (4), type (4), reserved (6) and data reference index (2)
+        if stsd.get(12..16) != Some(&b"mebx"[..]) { continue; }
+        let entry_size = stsd.get(8..).and_then(|mut x| x.read_u32::<BigEndian>().ok()).unwrap_or_default() as usize;
+        let Some(keys) = stsd.get(24..8 + entry_size).and_then(|x| util::mp4_find(x, &[b"keys"])) else { continue; };
+
+        let mut track_keys = BTreeMap::new();
+        for (id, item) in util::mp4_children(keys) {
+            let Some(keyd) = util::mp4_find(item, &[b"keyd"]) else { continue; };
+            let name = String::from_utf8_lossy(keyd.get(4..).unwrap_or_default()).into_owned();
+            if let Some(key) = Key::from_name(name.trim_end_matches('\0')) {
+                track_keys.insert(u32::from_be_bytes([id[0], id[1], id[2], id[3]]), key);
+            }
+        }
+        if !track_keys.is_empty() {
+            ret.insert(track_index, track_keys);
+        }
+    }
+    ret
+}
+
+// `n` big-endian f32 or f64 values
+fn read_floats(mut value: &[u8], n: usize) -> Option<Vec<f64>> {
+    if value.len() == n * 8 {
+        (0..n).map(|_| value.read_f64::<BigEndian>().ok()).collect()
+    } else if value.len() == n * 4 {
+        (0..n).map(|_| value.read_f32::<BigEndian>().ok().map(|x| x as f64)).collect()
+    } else {
+        None
+    }
+}
+
+// Latitude, longitude and optional altitude, each starting with a sign, eg. `+37.3349-122.0090+030.000/`
+fn parse_iso6709(value: &[u8]) -> Option<(f64, f64, f64)> {
+    let s = std::str::from_utf8(value).ok()?.trim_matches('\0').split('/').next()?;
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in s.char_indices().skip(1) {
+        if c == '+' || c == '-' {
+            parts.push(&s[start..i]);
+            start = i;
+        }
+    }
+    parts.push(&s[start..]);
+    let lat = parts.first()?.parse::<f64>().ok()?;
+    let lon = parts.get(1)?.parse::<f64>().ok()?;
+    let alt = parts.get(2).and_then(|x| x.parse::<f64>().ok()).unwrap_or_default();
+    Some((lat, lon, alt))
+}
+
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+    let keys = read_keys(&util::read_moov(stream, size)?);
+    if keys.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "No CoreMotion metadata track found"));
+    }
+
+    let mut gyro = Vec::new();
+    let mut accl = Vec::new();
+    let mut grav = Vec::new();
+    let mut quat = Vec::new();
+    let mut gps = Vec::new();
+
+    util::get_metadata_track_samples(stream, size, false, |info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+        if size > 0 {
+            progress_cb(file_position as f64 / size as f64);
+        }
+        let Some(keys) = keys.get(&info.track_index) else { return; };
+        let t = info.timestamp_ms / 1000.0;
+        let mut gravity = None;
+        let mut user_acceleration = None;
+
+        let mut d = data;
+        while d.len() >= 8 {
+            let len = u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize;
+            let id = u32::from_be_bytes([d[4], d[5], d[6], d[7]]);
+            if len < 8 || len > d.len() { break; }
+            let value = &d[8..len];
+            d = &d[len..];
+
+            match keys.get(&id) {
+                Some(Key::Attitude) => if let Some(v) = read_floats(value, 4) {
+                    quat.push(TimeQuaternion { t: info.timestamp_ms, v: Quaternion { w: v[3], x: v[0], y: v[1], z: v[2] } });
+                },
+                Some(Key::RotationRate) => if let Some(v) = read_floats(value, 3) {
+                    gyro.push(TimeVector3 { t, x: v[0], y: v[1], z: v[2] });
+                },
+                Some(Key::Gravity) => if let Some(v) = read_floats(value, 3) {
+                    grav.push(TimeVector3 { t, x: v[0], y: v[1], z: v[2] });
+                    gravity = Some(v);
+                },
+                Some(Key::UserAcceleration) => user_acceleration = read_floats(value, 3),
+                Some(Key::Location) => if let Some((lat, lon, altitude)) = parse_iso6709(value) {
+                    gps.push(GpsData { is_acquired: true, unix_timestamp: t, lat, lon, speed: 0.0, track: 0.0, altitude, ..Default::default() });
+                },
+                None => { }
+            }
+        }
+        if let Some(a) = user_acceleration {
+            let g = gravity.unwrap_or_else(|| vec![0.0; 3]);
+            accl.push(TimeVector3 { t, x: a[0] + g[0], y: a[1] + g[1], z: a[2] + g[2] });
+        }
+    }, cancel_flag)?;
+
+    let mut map = GroupedTagMap::new();
+
+    if !gyro.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "rad/s".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), "XYZ".into(), Vec::new()));
+    }
+    if !accl.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), "XYZ".into(), Vec::new()));
+    }
+    if !grav.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::GravityVector, TagId::Data, "Gravity vector",     Vec_TimeVector3_f64, |v| format!("{:?}", v), grav, vec![]));
+    }
+    if !quat.is_empty() {
+        util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+            source: "quaternion",
+            component_order: "xyzw",
+            handedness: "right",
+            ..Default::default()
+        });
+        util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,    TagId::Data, "Quaternion data",    Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quat, vec![]));
+    }
+    if !gps.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::GPS,           TagId::Data, "GPS data",           Vec_GpsData, |v| format!("{:?}", v), gps, vec![]));
+    }
+
+    Ok(vec![
+        SampleInfo { tag_map: Some(map), ..Default::default() }
+    ])
+}
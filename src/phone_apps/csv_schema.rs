@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// CSV logs of any logger, with the columns described by a user-supplied `util::CsvSchema`
+
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+
+use crate::tags_impl::*;
+use crate::*;
+use crate::util::CsvSchema;
+
+pub fn load_sidecar(path: &str) -> Option<CsvSchema> {
+    let ext = filesystem::get_extension(path);
+    if ext != "csv" && ext != "txt" { return None; }
+
+    if let Some(p) = filesystem::file_with_extension(path, "schema.json") {
+        return serde_json::from_slice(&filesystem::read_file(&p).ok()?).map_err(|e| crate::parse_warning!("Invalid CSV schema {p}: {e}")).ok();
+    }
+    if let Some(p) = filesystem::file_with_extension(path, "schema.yaml") {
+        return serde_yaml::from_slice(&filesystem::read_file(&p).ok()?).map_err(|e| crate::parse_warning!("Invalid CSV schema {p}: {e}")).ok();
+    }
+    #[cfg(feature = "toml-schema")]
+    if let Some(p) = filesystem::file_with_extension(path, "schema.toml") {
+        let data = filesystem::read_file(&p).ok()?;
+        return toml::from_str(&String::from_utf8_lossy(&data)).map_err(|e| crate::parse_warning!("Invalid CSV schema {p}: {e}")).ok();
+    }
+    None
+}
+
+fn columns(schema: &CsvSchema) -> Vec<&String> {
+    let mut ret = vec![&schema.time_column];
+    for c in [&schema.gyro, &schema.accl, &schema.magn].into_iter().flatten() {
+        ret.extend(c.iter());
+    }
+    ret
+}
+
+pub fn detect(buffer: &[u8], schema: &CsvSchema) -> bool {
+    if schema.gyro.is_none() && schema.accl.is_none() && schema.magn.is_none() { return false; }
+    let Some(header) = buffer.split(|x| *x == b'\n').nth(schema.skip_lines) else { return false; };
+    let header = String::from_utf8_lossy(header);
+    let header = header.split(schema.delimiter).map(|x| x.trim().trim_matches('"')).collect::<Vec<&str>>();
+    columns(schema).iter().all(|c| header.contains(&c.as_str()))
+}
+
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, schema: &CsvSchema, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+    let mut stream = BufReader::new(stream);
+    for _ in 0..schema.skip_lines {
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+    }
+
+    let mut csv = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(schema.delimiter as u8)
+        .trim(csv::Trim::All)
+        .from_reader(stream);
+
+    let h = csv.headers()?.clone();
+    let col = |name: &str| h.iter().position(|x| x == name);
+    let cols3 = |c: &Option<[String; 3]>| -> Option<[usize; 3]> {
+        let c = c.as_ref()?;
+        Some([col(&c[0])?, col(&c[1])?, col(&c[2])?])
+    };
+    let time_col = col(&schema.time_column).ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("Missing column {}", schema.time_column)))?;
+    let gyro_cols = cols3(&schema.gyro);
+    let accl_cols = cols3(&schema.accl);
+    let magn_cols = cols3(&schema.magn);
+
+    let mut gyro = Vec::new();
+    let mut accl = Vec::new();
+    let mut magn = Vec::new();
+
+    let mut first_timestamp = None;
+    let mut last_timestamp = 0.0;
+
+    let mut row = csv::StringRecord::new();
+    while csv.read_record(&mut row)? {
+        if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
+        let Some(ts) = row.get(time_col).and_then(|x| x.parse::<f64>().ok()) else { continue; };
+        let t = (ts - *first_timestamp.get_or_insert(ts)) * schema.time_scale;
+        last_timestamp = t;
+
+        let read = |c: [usize; 3]| -> Option<TimeVector3<f64>> {
+            Some(TimeVector3 {
+                t,
+                x: row.get(c[0])?.parse::<f64>().ok()?,
+                y: row.get(c[1])?.parse::<f64>().ok()?,
+                z: row.get(c[2])?.parse::<f64>().ok()?
+            })
+        };
+        if let Some(v) = gyro_cols.and_then(read) { gyro.push(v); }
+        if let Some(v) = accl_cols.and_then(read) { accl.push(v); }
+        if let Some(v) = magn_cols.and_then(read) { magn.push(v); }
+    }
+
+    let mut map = GroupedTagMap::new();
+
+    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "CSV schema", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::to_value(schema).unwrap_or_default(), vec![]));
+
+    if !gyro.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data,        "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit,        "Gyroscope unit",     String, |v| v.to_string(), schema.gyro_unit.clone(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation",    String, |v| v.to_string(), schema.orientation.clone(), Vec::new()));
+    }
+    if !accl.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data,        "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit,        "Accelerometer unit", String, |v| v.to_string(), schema.accl_unit.clone(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation",    String, |v| v.to_string(), schema.orientation.clone(), Vec::new()));
+    }
+    if !magn.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data,        "Magnetometer data",  Vec_TimeVector3_f64, |v| format!("{:?}", v), magn, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit,        "Magnetometer unit",  String, |v| v.to_string(), schema.magn_unit.clone(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Orientation, "IMU orientation",    String, |v| v.to_string(), schema.orientation.clone(), Vec::new()));
+    }
+
+    Ok(vec![
+        SampleInfo { timestamp_ms: 0.0, duration_ms: last_timestamp * 1000.0, tag_map: Some(map), ..Default::default() }
+    ])
+}
@@ -2,7 +2,7 @@
 // Copyright © 2021 Adrian <adrian.eddy at gmail>
 
 use std::io::*;
-use byteorder::ReadBytesExt;
+use std::sync::{ Arc, atomic::AtomicBool };
 
 use crate::tags_impl::*;
 use crate::*;
@@ -20,35 +20,57 @@ pub fn detect(buffer: &[u8]) -> bool {
     memmem::find(hdr, b"Yaw").is_some()
 }
 
-pub fn parse<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleInfo>> {
+// Replaces all repeating whitespace with a single space, without reading the whole file to memory
+struct SqueezeWhitespace<R: BufRead> {
+    inner: R,
+    prev: u8,
+}
+impl<R: BufRead> Read for SqueezeWhitespace<R> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        if out.is_empty() { return Ok(0); }
+        let mut n = 0;
+        while n == 0 {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() { break; }
+            let mut consumed = 0;
+            for &chr in buf {
+                if n == out.len() { break; }
+                consumed += 1;
+                if !(self.prev.is_ascii_whitespace() && chr.is_ascii_whitespace()) || chr == b'\n' {
+                    out[n] = chr;
+                    n += 1;
+                    self.prev = chr;
+                }
+            }
+            self.inner.consume(consumed);
+        }
+        Ok(n)
+    }
+}
+
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
     let mut gyro = Vec::new();
     let mut accl = Vec::new();
 
     let mut last_timestamp = 0.0;
     let mut first_timestamp = 0.0;
 
-    // Replace all repeating whitespace with a single space
-    let mut buffer = Vec::with_capacity(size);
-    let mut prev_chr = '\0';
-    while (stream.stream_position()? as usize) < size {
-        let chr = stream.read_u8()? as char;
-        if !(prev_chr.is_ascii_whitespace() && chr.is_ascii_whitespace()) || chr == '\n' {
-            buffer.push(chr as u8);
-            prev_chr = chr;
-        }
-    }
-    let d = Cursor::new(&buffer[..]);
+    let mut reader = BufReader::new(SqueezeWhitespace { inner: BufReader::new(stream), prev: 0 });
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let delimiter = if header.contains("Time Xg Yg Zg") { b' ' } else { b',' };
 
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .trim(csv::Trim::All)
-        .delimiter(if memmem::find(&buffer, b"Time Xg Yg Zg").is_some() { b' ' } else { b',' })
+        .delimiter(delimiter)
         .flexible(true)
-        .from_reader(d);
+        .from_reader(Cursor::new(header.into_bytes()).chain(reader));
 
     let h = csv.headers()?.clone();
-    for row in csv.records() {
-        let row = row?;
+    let mut row = csv::StringRecord::new();
+    while csv.read_record(&mut row)? {
+        if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
         let map = util::create_csv_map_hdr(&row, &h);
 
         let mut ts = map.get("Time").unwrap_or(&"0.0").parse::<f64>().unwrap_or(0.0);
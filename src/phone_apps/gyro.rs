@@ -2,6 +2,7 @@
 // Copyright © 2021 Adrian <adrian.eddy at gmail>
 
 use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
 
 use crate::tags_impl::*;
 use crate::*;
@@ -11,7 +12,7 @@ pub fn detect(buffer: &[u8]) -> bool {
     memmem::find(buffer, b"Time, Rotation Rate (X), Rotation Rate (Y), Rotation Rate (Z)").is_some()
 }
 
-pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
     let mut gyro = Vec::new();
 
     let mut last_timestamp = 0.0;
@@ -23,8 +24,9 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
         .from_reader(stream);
 
     let h = csv.headers()?.clone();
-    for row in csv.records() {
-        let row = row?;
+    let mut row = csv::StringRecord::new();
+    while csv.read_record(&mut row)? {
+        if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
         let map = util::create_csv_map_hdr(&row, &h);
 
         let mut ts = map.get("Time").unwrap_or(&"0.0").parse::<f64>().unwrap_or(0.0); // seconds since UNIX epoch
@@ -8,6 +8,9 @@ mod sensor_logger_android;
 mod sensor_record;
 mod opencamera_sensors;
 mod filmit;
+mod csv_schema;
+mod core_motion;
+mod vio_poses;
 
 use std::io::*;
 use std::sync::{ Arc, atomic::AtomicBool };
@@ -17,7 +20,8 @@ use crate::*;
 #[derive(Default)]
 pub struct PhoneApps {
     pub model: Option<String>,
-    path: String
+    path: String,
+    schema: Option<util::CsvSchema>
 }
 
 impl PhoneApps {
@@ -41,26 +45,39 @@ impl PhoneApps {
         let path = filepath.as_ref().to_str().unwrap_or_default().to_owned();
         // let filename = filesystem::get_filename(&filepath);
 
-        if sensor_logger        ::detect(&buffer)        { return Some(Self { model: Some("Sensor Logger"        .to_owned()), path }); }
-        if gf_recorder          ::detect(&buffer)        { return Some(Self { model: Some("GF Recorder"          .to_owned()), path }); }
-        if gyro                 ::detect(&buffer)        { return Some(Self { model: Some("Gyro"                 .to_owned()), path }); }
-        if sensor_logger_android::detect(&buffer)        { return Some(Self { model: Some("Sensor Logger Android".to_owned()), path }); }
-        if sensor_record        ::detect(&buffer)        { return Some(Self { model: Some("Sensor Record"        .to_owned()), path }); }
-        if opencamera_sensors   ::detect(&buffer, &path) { return Some(Self { model: Some("OpenCamera Sensors"   .to_owned()), path }); }
-        if filmit               ::detect(&buffer)        { return Some(Self { model: Some("Film it"              .to_owned()), path }); }
+        if let Some(schema) = util::csv_schema().or_else(|| csv_schema::load_sidecar(&path)) {
+            if csv_schema::detect(buffer, &schema) {
+                return Some(Self { model: Some(schema.model.clone().unwrap_or("Custom CSV".into())), path, schema: Some(schema) });
+            }
+        }
+
+        if sensor_logger        ::detect(&buffer)        { return Some(Self { model: Some("Sensor Logger"        .to_owned()), path, schema: None }); }
+        if gf_recorder          ::detect(&buffer)        { return Some(Self { model: Some("GF Recorder"          .to_owned()), path, schema: None }); }
+        if gyro                 ::detect(&buffer)        { return Some(Self { model: Some("Gyro"                 .to_owned()), path, schema: None }); }
+        if sensor_logger_android::detect(&buffer)        { return Some(Self { model: Some("Sensor Logger Android".to_owned()), path, schema: None }); }
+        if sensor_record        ::detect(&buffer)        { return Some(Self { model: Some("Sensor Record"        .to_owned()), path, schema: None }); }
+        if opencamera_sensors   ::detect(&buffer, &path) { return Some(Self { model: Some("OpenCamera Sensors"   .to_owned()), path, schema: None }); }
+        if filmit               ::detect(&buffer)        { return Some(Self { model: Some("Film it"              .to_owned()), path, schema: None }); }
+        if core_motion          ::detect(buffer)         { return Some(Self { model: Some("iOS CoreMotion"       .to_owned()), path, schema: None }); }
+        if vio_poses            ::detect(buffer, &path)  { return Some(Self { model: Some("VIO poses"            .to_owned()), path, schema: None }); }
 
         None
     }
 
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        if let Some(schema) = &self.schema {
+            return csv_schema::parse(stream, size, schema, progress_cb, cancel_flag);
+        }
         match self.model.as_deref() {
-            Some("Sensor Logger")           => sensor_logger        ::parse(stream, size),
-            Some("GF Recorder")             => gf_recorder          ::parse(stream, size),
-            Some("Gyro")                    => gyro                 ::parse(stream, size),
-            Some("Sensor Logger Android")   => sensor_logger_android::parse(stream, size, &self.path),
-            Some("Sensor Record")           => sensor_record        ::parse(stream, size),
-            Some("OpenCamera Sensors")      => opencamera_sensors   ::parse(stream, size, &self.path),
+            Some("Sensor Logger")           => sensor_logger        ::parse(stream, size, progress_cb, cancel_flag),
+            Some("GF Recorder")             => gf_recorder          ::parse(stream, size, progress_cb, cancel_flag),
+            Some("Gyro")                    => gyro                 ::parse(stream, size, progress_cb, cancel_flag),
+            Some("Sensor Logger Android")   => sensor_logger_android::parse(stream, size, &self.path, progress_cb, cancel_flag),
+            Some("Sensor Record")           => sensor_record        ::parse(stream, size, progress_cb, cancel_flag),
+            Some("OpenCamera Sensors")      => opencamera_sensors   ::parse(stream, size, &self.path, progress_cb, cancel_flag),
             Some("Film it")                 => filmit               ::parse(stream, size, progress_cb, cancel_flag),
+            Some("iOS CoreMotion")          => core_motion          ::parse(stream, size, progress_cb, cancel_flag),
+            Some("VIO poses")               => vio_poses            ::parse(stream, size),
             _ => {
                 Err(ErrorKind::InvalidInput.into())
             }
@@ -2,6 +2,7 @@
 // Copyright © 2022 Adrian <adrian.eddy at gmail>
 
 use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
 use crate::tags_impl::*;
 use crate::*;
 
@@ -63,7 +64,7 @@ pub fn detect(_buffer: &[u8], filepath: &str) -> bool {
     !get_possible_paths(filepath).is_empty()
 }
 
-pub fn parse<T: Read + Seek>(_stream: &mut T, _size: usize, filepath: &str) -> Result<Vec<SampleInfo>> {
+pub fn parse<T: Read + Seek, F: Fn(f64)>(_stream: &mut T, _size: usize, filepath: &str, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
     let fs = filesystem::get_base();
     let paths = get_possible_paths(filepath);
 
@@ -75,8 +76,10 @@ pub fn parse<T: Read + Seek>(_stream: &mut T, _size: usize, filepath: &str) -> R
     let mut first_timestamp = 0.0;
 
     for path in paths {
+        if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
         let filename = filesystem::get_filename(&path);
         let mut file = filesystem::open_file(&fs, &path)?;
+        let size = file.size;
 
         let mut csv = csv::ReaderBuilder::new()
             .has_headers(false)
@@ -84,8 +87,9 @@ pub fn parse<T: Read + Seek>(_stream: &mut T, _size: usize, filepath: &str) -> R
             .delimiter(b',')
             .from_reader(&mut file.file);
 
-        for row in csv.records() {
-            let row = row?;
+        let mut row = csv::StringRecord::new();
+        while csv.read_record(&mut row)? {
+            if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
 
             if row.len() != 4 {
                 continue;
@@ -2,6 +2,7 @@
 // Copyright © 2021 Gro2mi
 
 use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
 
 use crate::tags_impl::*;
 use crate::*;
@@ -11,7 +12,7 @@ pub fn detect(buffer: &[u8]) -> bool {
     memmem::find(buffer, b"time,seconds_elapsed").is_some()
 }
 
-pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize, path: &str) -> Result<Vec<SampleInfo>> {
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, path: &str, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
     let mut gyro = Vec::new();
     let mut accl = Vec::new();
     let mut magn = Vec::new();
@@ -19,7 +20,7 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize, path: &str) -> Result
     let mut last_timestamp = 0.0;
     let mut first_timestamp = 0.0;
 
-    let mut read_from_stream = |filename: &str, stream: &mut dyn Read| -> Result<()> {
+    let mut read_from_stream = |filename: &str, stream: &mut dyn Read, size: usize| -> Result<()> {
         let mut csv = csv::ReaderBuilder::new()
             .has_headers(true)
             .trim(csv::Trim::All)
@@ -36,8 +37,9 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize, path: &str) -> Result
             magn.clear();
         }
 
-        for row in csv.records() {
-            let row = row?;
+        let mut row = csv::StringRecord::new();
+        while csv.read_record(&mut row)? {
+            if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
             let map = util::create_csv_map_hdr(&row, &h);
 
             let mut ts = map.get("time").unwrap_or(&"0.0").parse::<f64>().unwrap_or(0.0); // seconds since UNIX epoch
@@ -81,15 +83,16 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize, path: &str) -> Result
     };
 
     let filename = filesystem::get_filename(&path);
-    read_from_stream(&filename, stream)?;
+    read_from_stream(&filename, stream, size)?;
 
     let fs = filesystem::get_base();
     let other_filenames = [ "Accelerometer.csv", "Gyroscope.csv", "Magnetometer.csv", "AccelerometerUncalibrated.csv", "GyroscopeUncalibrated.csv", "MagnetometerUncalibrated.csv" ];
     for x in filesystem::list_folder(&filesystem::get_folder(path)) {
         if filename == x.0 { continue; }
+        if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
         if other_filenames.contains(&x.0.as_str()) {
             if let Ok(mut buffer) = filesystem::open_file(&fs, &x.1) {
-                read_from_stream(&x.0, &mut buffer.file)?;
+                read_from_stream(&x.0, &mut buffer.file, buffer.size)?;
             }
         }
     }
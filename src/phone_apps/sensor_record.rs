@@ -2,6 +2,7 @@
 // Copyright © 2021 Gro2mi
 
 use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
 
 use crate::tags_impl::*;
 use crate::*;
@@ -11,7 +12,7 @@ pub fn detect(buffer: &[u8]) -> bool {
     memmem::find(buffer, b"Timestamp,Milliseconds,X,Y,Z").is_some()
 }
 
-pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
+pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
     let mut gyro = Vec::new();
     let mut accl = Vec::new();
     let mut magn = Vec::new();
@@ -25,8 +26,9 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
         .from_reader(stream);
 
     let h = csv.headers()?.clone();
-    for row in csv.records() {
-        let row = row?;
+    let mut row = csv::StringRecord::new();
+    while csv.read_record(&mut row)? {
+        if util::row_progress(csv.position().line(), csv.position().byte(), size, &progress_cb, &cancel_flag) { break; }
         let map = util::create_csv_map_hdr(&row, &h);
 
         let mut ts = map.get("Milliseconds").unwrap_or(&"0.0").parse::<f64>().unwrap_or(0.0); // seconds since UNIX epoch
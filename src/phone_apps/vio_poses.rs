@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Camera poses from visual-inertial odometry (ARCore, Qualcomm VIO), exported as JSON by AR capture rigs, usually next to the video.
+// The keys are generic, so only files named `*.poses.json` are detected (eg. `VID_0001.poses.json` for `VID_0001.mp4`).
+// The root is an array of poses, or an object with the array in `poses`, `frames` or `data`. Each pose has:
+// - timestamp: `timestamp_ns`, `timestamp_us`, `timestamp_ms`, `timestamp_s`, or `timestamp`/`time`/`t` with the unit guessed from the pose rate
+// - rotation: `rotation`, `orientation` or `quaternion` as [x, y, z, w] (order of ARCore `Pose.getRotationQuaternion`) or {"x", "y", "z", "w"}, or flat `qx`, `qy`, `qz`, `qw`
+// - translation: `translation` or `position` as [x, y, z] or {"x", "y", "z"} in metres, or flat `tx`, `ty`, `tz`
+// Both can also be nested in a `pose` object. The values are stored as they are, in the world frame of the tracking session (right-handed, Y up in ARCore)
+
+use std::io::*;
+use serde_json::Value;
+
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+pub fn detect(buffer: &[u8], path: &str) -> bool {
+    if !path.to_ascii_lowercase().ends_with(".poses.json") { return false; }
+    let has = |keys: &[&[u8]]| keys.iter().any(|k| memmem::find(buffer, k).is_some());
+    let first = buffer.iter().find(|x| !x.is_ascii_whitespace());
+    matches!(first, Some(b'{' | b'['))
+        && has(&[b"\"timestamp", b"\"time\"", b"\"t\""])
+        && has(&[b"\"rotation\"", b"\"orientation\"", b"\"quaternion\"", b"\"qw\""])
+        && has(&[b"\"translation\"", b"\"position\"", b"\"tx\""])
+}
+
+// Components of `[a, b, c]` arrays or `{"a": .., "b": .., "c": ..}` objects
+fn components<const N: usize>(v: &Value, names: [&str; N]) -> Option<[f64; N]> {
+    let mut ret = [0.0; N];
+    for (i, (r, name)) in ret.iter_mut().zip(names).enumerate() {
+        *r = match v {
+            Value::Array(arr) => arr.get(i)?.as_f64()?,
+            _ => v.get(name)?.as_f64()?
+        };
+    }
+    Some(ret)
+}
+
+fn find<'a>(v: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|k| v.get(*k))
+}
+
+pub fn parse<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleInfo>> {
+    let mut data = Vec::with_capacity(size);
+    stream.read_to_end(&mut data)?;
+    let root: Value = serde_json::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let poses = match &root {
+        Value::Array(x) => Some(x),
+        _ => find(&root, &["poses", "frames", "data"]).and_then(|x| x.as_array())
+    }.ok_or_else(|| Error::new(ErrorKind::InvalidData, "No poses found"))?;
+
+    // Raw timestamps, `unit` is their scale to seconds if it's known from the key
+    let mut timestamps = Vec::with_capacity(poses.len());
+    let mut unit = None;
+    let mut quats = Vec::with_capacity(poses.len());
+    let mut positions = Vec::with_capacity(poses.len());
+    for pose in poses {
+        let p = pose.get("pose").unwrap_or(pose);
+        let ts = [("timestamp_ns", 1e-9), ("timestamp_us", 1e-6), ("timestamp_ms", 1e-3), ("timestamp_s", 1.0)].iter()
+            .find_map(|(k, scale)| Some((pose.get(*k)?.as_f64()?, Some(*scale))))
+            .or_else(|| Some((find(pose, &["timestamp", "time", "t"])?.as_f64()?, None)));
+        let Some((ts, scale)) = ts else { continue; };
+        unit = unit.or(scale);
+
+        let rotation = find(p, &["rotation", "orientation", "quaternion"]).and_then(|x| components(x, ["x", "y", "z", "w"]))
+            .or_else(|| components(p, ["qx", "qy", "qz", "qw"]));
+        let translation = find(p, &["translation", "position"]).and_then(|x| components(x, ["x", "y", "z"]))
+            .or_else(|| components(p, ["tx", "ty", "tz"]));
+        if rotation.is_none() && translation.is_none() { continue; }
+
+        timestamps.push(ts);
+        quats.push(rotation);
+        positions.push(translation);
+    }
+    if timestamps.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "No poses found"));
+    }
+
+    // Poses are expected between 2 Hz and 1 kHz, so the ranges of the median interval in each unit don't overlap
+    let unit = unit.unwrap_or_else(|| {
+        let mut deltas = timestamps.windows(2).map(|x| (x[1] - x[0]).abs()).filter(|x| *x > 0.0).collect::<Vec<f64>>();
+        deltas.sort_by(|a, b| a.total_cmp(b));
+        match deltas.get(deltas.len() / 2).copied().unwrap_or_default() {
+            x if x > 7.5e5 => 1e-9,
+            x if x > 750.0 => 1e-6,
+            x if x > 0.75  => 1e-3,
+            _ => 1.0
+        }
+    });
+    let first_timestamp = timestamps[0];
+
+    let mut quat_data = Vec::new();
+    let mut pos_data = Vec::new();
+    for ((ts, q), p) in timestamps.iter().zip(quats).zip(positions) {
+        let t = (ts - first_timestamp) * unit;
+        if let Some([x, y, z, w]) = q {
+            quat_data.push(TimeQuaternion { t: t * 1000.0, v: Quaternion { w, x, y, z } });
+        }
+        if let Some([x, y, z]) = p {
+            pos_data.push(TimeVector3 { t, x, y, z });
+        }
+    }
+
+    let mut map = GroupedTagMap::new();
+    if !quat_data.is_empty() {
+        util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+            source: "quaternion",
+            component_order: "xyzw",
+            handedness: "right",
+            ..Default::default()
+        });
+        util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion, TagId::Data, "Quaternion data", Vec_TimeQuaternion_f64, |v| format!("{:?}", v), quat_data, vec![]));
+    }
+    if !pos_data.is_empty() {
+        util::insert_tag(&mut map, tag!(parsed GroupId::Position3D, TagId::Data, "3D position data", Vec_TimeVector3_f64, |v| format!("{:?}", v), pos_data, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Position3D, TagId::Unit, "3D position unit", String, |v| v.to_string(), "m".into(), Vec::new()));
+    }
+
+    Ok(vec![
+        SampleInfo { tag_map: Some(map), ..Default::default() }
+    ])
+}
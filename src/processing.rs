@@ -0,0 +1,372 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Post-processing utilities for the parsed IMU data: bias estimation, quaternion conversions and synthetic gyroscope
+
+use crate::tags_impl::*;
+use crate::{ tag, util, util::SampleInfo, Input };
+
+#[derive(Debug, Clone)]
+pub struct BiasEstimationOptions {
+    /// Length of the analyzed windows. Windows where the camera didn't move are used for the estimation
+    pub window_ms: f64,
+    /// Maximum standard deviation of the gyroscope readings (deg/s) in a static window
+    pub max_gyro_std: f64,
+    /// Maximum standard deviation of the accelerometer readings (m/s²) in a static window
+    pub max_accl_std: f64,
+    /// Maximum difference between the accelerometer magnitude and 1 g (m/s²) in a static window
+    pub max_gravity_error: f64,
+}
+impl Default for BiasEstimationOptions {
+    fn default() -> Self {
+        Self {
+            window_ms: 500.0,
+            max_gyro_std: 0.5,
+            max_accl_std: 0.2,
+            max_gravity_error: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BiasEstimate {
+    /// Mean gyroscope reading during the static periods, in deg/s
+    pub gyro_bias: [f64; 3],
+    /// Unit vector of the gravity direction during the static periods. `None` if there's no accelerometer data
+    pub gravity: Option<[f64; 3]>,
+    /// Detected static periods, as (start, end) in milliseconds
+    pub static_periods: Vec<(f64, f64)>,
+}
+
+#[derive(Default)]
+struct Stats {
+    count: usize,
+    sum: [f64; 3],
+    sum_sq: [f64; 3],
+}
+impl Stats {
+    fn add(&mut self, v: &[f64; 3]) {
+        self.count += 1;
+        for ((sum, sum_sq), x) in self.sum.iter_mut().zip(self.sum_sq.iter_mut()).zip(v) {
+            *sum += x;
+            *sum_sq += x * x;
+        }
+    }
+    fn merge(&mut self, other: &Stats) {
+        self.count += other.count;
+        for (a, b) in self.sum.iter_mut().zip(other.sum) { *a += b; }
+        for (a, b) in self.sum_sq.iter_mut().zip(other.sum_sq) { *a += b; }
+    }
+    fn mean(&self) -> [f64; 3] {
+        let n = self.count.max(1) as f64;
+        [self.sum[0] / n, self.sum[1] / n, self.sum[2] / n]
+    }
+    fn max_std(&self) -> f64 {
+        let n = self.count.max(1) as f64;
+        let mean = self.mean();
+        (0..3).map(|i| (self.sum_sq[i] / n - mean[i] * mean[i]).max(0.0).sqrt()).fold(0.0, f64::max)
+    }
+}
+
+/// Detects the static periods in the IMU data and estimates the gyroscope bias and gravity direction from them.
+/// The results are stored in the first sample as `Gyroscope/Bias` and `Accelerometer/GravityDirection` tags.
+/// Returns `None` if there's no gyroscope data or the camera never stays still
+pub fn estimate_bias(input: &mut Input, options: &BiasEstimationOptions) -> Option<BiasEstimate> {
+    let imu = util::normalized_imu(input, None).ok()?;
+    if imu.is_empty() || options.window_ms <= 0.0 { return None; }
+
+    let mut estimate = BiasEstimate::default();
+    let mut gyro_total = Stats::default();
+    let mut accl_total = Stats::default();
+
+    for window in imu.chunk_by(|a, b| (a.timestamp_ms / options.window_ms).floor() == (b.timestamp_ms / options.window_ms).floor()) {
+        let mut gyro = Stats::default();
+        let mut accl = Stats::default();
+        for x in window {
+            if let Some(g) = &x.gyro { gyro.add(g); }
+            if let Some(a) = &x.accl { accl.add(a); }
+        }
+        // Too few readings to tell whether the camera was still
+        if gyro.count < 10 || gyro.max_std() > options.max_gyro_std { continue; }
+        if accl.count > 0 {
+            let a = accl.mean();
+            let magnitude = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+            if accl.max_std() > options.max_accl_std || (magnitude - 9.80665).abs() > options.max_gravity_error { continue; }
+        }
+
+        let start = window.first().map(|x| x.timestamp_ms).unwrap_or_default();
+        let end = window.last().map(|x| x.timestamp_ms).unwrap_or_default();
+        match estimate.static_periods.last_mut() {
+            Some(last) if (start - last.1) <= options.window_ms => last.1 = end,
+            _ => estimate.static_periods.push((start, end))
+        }
+
+        gyro_total.merge(&gyro);
+        accl_total.merge(&accl);
+    }
+    if gyro_total.count == 0 { return None; }
+
+    estimate.gyro_bias = gyro_total.mean();
+    if accl_total.count > 0 {
+        let a = accl_total.mean();
+        let magnitude = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+        if magnitude > 0.0 {
+            estimate.gravity = Some([a[0] / magnitude, a[1] / magnitude, a[2] / magnitude]);
+        }
+    }
+
+    let samples = input.samples.get_or_insert_with(Vec::new);
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        let b = estimate.gyro_bias;
+        util::insert_tag(map, tag!(parsed GroupId::Gyroscope, TagId::Bias, "Estimated gyroscope bias", f64x3, |v| format!("{:.4} {:.4} {:.4} deg/s", v.0, v.1, v.2), (b[0], b[1], b[2]), vec![]));
+        if let Some(g) = estimate.gravity {
+            util::insert_tag(map, tag!(parsed GroupId::Accelerometer, TagId::Custom("GravityDirection".into()), "Estimated gravity direction", f64x3, |v| format!("{:.4} {:.4} {:.4}", v.0, v.1, v.2), (g[0], g[1], g[2]), vec![]));
+        }
+    }
+
+    Some(estimate)
+}
+
+/// Converts a unit quaternion to Euler angles (roll, pitch, yaw) in radians, using the aerospace (ZYX) sequence
+pub fn quaternion_to_euler(q: &Quaternion<f64>) -> [f64; 3] {
+    let roll = (2.0 * (q.w * q.x + q.y * q.z)).atan2(1.0 - 2.0 * (q.x * q.x + q.y * q.y));
+    let pitch = (2.0 * (q.w * q.y - q.z * q.x)).clamp(-1.0, 1.0).asin();
+    let yaw = (2.0 * (q.w * q.z + q.x * q.y)).atan2(1.0 - 2.0 * (q.y * q.y + q.z * q.z));
+    [roll, pitch, yaw]
+}
+
+/// Inverse of `quaternion_to_euler`: unit quaternion from roll, pitch and yaw in radians (aerospace ZYX sequence)
+pub fn euler_to_quaternion(roll: f64, pitch: f64, yaw: f64) -> Quaternion<f64> {
+    let (sr, cr) = (roll / 2.0).sin_cos();
+    let (sp, cp) = (pitch / 2.0).sin_cos();
+    let (sy, cy) = (yaw / 2.0).sin_cos();
+    Quaternion {
+        w: cr * cp * cy + sr * sp * sy,
+        x: sr * cp * cy - cr * sp * sy,
+        y: cr * sp * cy + sr * cp * sy,
+        z: cr * cp * sy - sr * sp * cy,
+    }
+}
+
+/// Angular velocity (rad/s) in the body frame, which rotates `a` to `b` in `dt` seconds
+pub fn angular_velocity(a: &Quaternion<f64>, b: &Quaternion<f64>, dt: f64) -> [f64; 3] {
+    let conj = Quaternion { w: a.w, x: -a.x, y: -a.y, z: -a.z };
+    let mut d = conj * b.clone();
+    // Take the shortest path
+    if d.w < 0.0 { d = -d; }
+    let sin_half = (d.x * d.x + d.y * d.y + d.z * d.z).sqrt();
+    if sin_half < 1e-12 || dt <= 0.0 { return [0.0; 3]; }
+    let angle = 2.0 * sin_half.atan2(d.w);
+    let k = angle / (sin_half * dt);
+    [d.x * k, d.y * k, d.z * k]
+}
+
+// Angular velocity (rad/s) between each pair of quaternions, at the midpoint of the pair.
+// Quaternion timestamps are in milliseconds, the result is in seconds like the other time series
+fn quaternion_rates(quats: &[TimeQuaternion<f64>]) -> Vec<TimeVector3<f64>> {
+    quats.windows(2).map(|w| {
+        let v = angular_velocity(&w[0].v, &w[1].v, (w[1].t - w[0].t) / 1000.0);
+        TimeVector3 { t: (w[0].t + w[1].t) / 2.0 / 1000.0, x: v[0], y: v[1], z: v[2] }
+    }).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct DerivedGyroOptions {
+    /// Number of readings averaged by the centered moving average. 1 disables smoothing
+    pub smoothing_window: usize,
+    /// Replace the gyroscope data if the file already has it
+    pub overwrite: bool,
+}
+impl Default for DerivedGyroOptions {
+    fn default() -> Self {
+        Self {
+            smoothing_window: 1,
+            overwrite: false,
+        }
+    }
+}
+
+/// Differentiates the `Quaternion/Data` series into a synthetic gyroscope (rad/s), for sources which don't store the raw gyro.
+/// The result is stored in the first sample as `Gyroscope/Data`, with `Gyroscope/DerivedFrom` set to `Quaternion`.
+/// Returns `false` if there are no quaternions or gyroscope data already exists
+pub fn derive_gyro_from_quaternions(input: &mut Input, options: &DerivedGyroOptions) -> bool {
+    let Some(samples) = input.samples.as_mut() else { return false; };
+
+    let has_gyro = samples.iter().any(|x| x.tag_map.as_ref().and_then(|m| m.get(&GroupId::Gyroscope)).map(|g| g.contains_key(&TagId::Data)).unwrap_or_default());
+    if has_gyro && !options.overwrite { return false; }
+
+    let mut quats = Vec::new();
+    for info in samples.iter() {
+        if let Some(Ok(q)) = info.tag_map.as_ref().map(|m| m.query::<Vec<TimeQuaternion<f64>>>(GroupId::Quaternion, TagId::Data)) {
+            quats.extend_from_slice(q);
+        }
+    }
+    if quats.len() < 2 { return false; }
+
+    let mut gyro = quaternion_rates(&quats);
+
+    if options.smoothing_window > 1 {
+        let half = options.smoothing_window / 2;
+        gyro = (0..gyro.len()).map(|i| {
+            let window = &gyro[i.saturating_sub(half)..(i + half + 1).min(gyro.len())];
+            let n = window.len() as f64;
+            TimeVector3 {
+                t: gyro[i].t,
+                x: window.iter().map(|v| v.x).sum::<f64>() / n,
+                y: window.iter().map(|v| v.y).sum::<f64>() / n,
+                z: window.iter().map(|v| v.z).sum::<f64>() / n,
+            }
+        }).collect();
+    }
+
+    if has_gyro {
+        for info in samples.iter_mut() {
+            if let Some(g) = info.tag_map.as_mut().and_then(|m| m.get_mut(&GroupId::Gyroscope)) {
+                g.remove(&TagId::Data);
+            }
+        }
+    }
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        util::insert_tag(map, tag!(parsed GroupId::Gyroscope, TagId::Data,        "Gyroscope data (derived from quaternions)", Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
+        util::insert_tag(map, tag!(parsed GroupId::Gyroscope, TagId::Unit,        "Gyroscope unit",      String, |v| v.to_string(), "rad/s".into(), vec![]));
+        util::insert_tag(map, tag!(parsed GroupId::Gyroscope, TagId::Orientation, "IMU orientation",     String, |v| v.to_string(), "XYZ".into(), vec![]));
+        util::insert_tag(map, tag!(parsed GroupId::Gyroscope, TagId::Custom("DerivedFrom".into()), "Source of derived data", String, |v| v.to_string(), "Quaternion".into(), vec![]));
+    }
+    true
+}
+
+#[derive(Debug, Clone)]
+pub struct SaturationOptions {
+    /// Readings above this fraction of the full-scale range are counted as saturated
+    pub threshold: f64,
+    /// Without a known full-scale range, the peak value is treated as the clipping level only if it's held for this many consecutive readings
+    pub min_plateau: usize,
+    /// Saturated readings closer than this (in milliseconds) are reported as a single event
+    pub merge_gap_ms: f64,
+}
+impl Default for SaturationOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.99,
+            min_plateau: 3,
+            merge_gap_ms: 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SaturationReport {
+    pub group: GroupId,
+    /// Level above which the readings of each axis are saturated, in the unit of the data. `None` if the axis isn't clipped
+    pub levels: [Option<f64>; 3],
+    /// Percentage of saturated readings of each axis
+    pub percentage: [f64; 3],
+    /// Periods where any axis was saturated, as (start, end) in milliseconds
+    pub events: Vec<(f64, f64)>,
+}
+
+/// Detects the readings at the sensor's full-scale range in `Gyroscope/Data` and `Accelerometer/Data`.
+/// The range is taken from the `FullScale` tag if the format stores it, otherwise clipping is recognized by the peak value being held for several readings.
+/// The per-axis percentage is stored in the first sample as `Saturation` tag of each group and every saturation period is added to `Events/Data`
+pub fn detect_saturation(input: &mut Input, options: &SaturationOptions) -> Vec<SaturationReport> {
+    let Some(samples) = input.samples.as_mut() else { return Vec::new(); };
+
+    let mut reports = Vec::new();
+    for group in [GroupId::Gyroscope, GroupId::Accelerometer] {
+        let mut data = Vec::new();
+        let mut scale = 1.0;
+        let mut full_scale = None;
+        for info in samples.iter() {
+            let Some(map) = info.tag_map.as_ref() else { continue; };
+            if let Ok(v) = map.query::<f64>(group.clone(), TagId::Scale) { scale = *v; }
+            if let Ok(v) = map.query::<f32>(group.clone(), TagId::Scale) { scale = *v as f64; }
+            if let Ok(v) = map.query::<f64>(group.clone(), TagId::FullScale) { full_scale = Some(*v); }
+            if let Ok(d) = map.query::<Vec<TimeVector3<f64>>>(group.clone(), TagId::Data) {
+                let s = if scale != 0.0 { scale } else { 1.0 };
+                data.extend(d.iter().map(|v| (v.t * 1000.0, [v.x / s, v.y / s, v.z / s])));
+            }
+        }
+        if data.is_empty() { continue; }
+
+        let level = |axis: usize| -> Option<f64> {
+            if let Some(fs) = full_scale { return Some(fs.abs() * options.threshold); }
+            let peak = data.iter().map(|x| x.1[axis].abs()).fold(0.0, f64::max);
+            if peak <= 0.0 { return None; }
+            let longest = data.chunk_by(|a, b| (a.1[axis].abs() == peak) == (b.1[axis].abs() == peak))
+                .filter(|x| x[0].1[axis].abs() == peak)
+                .map(|x| x.len())
+                .max().unwrap_or_default();
+            (longest >= options.min_plateau.max(2)).then_some(peak)
+        };
+        let levels = [level(0), level(1), level(2)];
+
+        let mut counts = [0usize; 3];
+        let mut events: Vec<(f64, f64)> = Vec::new();
+        for (t, v) in &data {
+            let mut saturated = false;
+            for ((count, level), x) in counts.iter_mut().zip(&levels).zip(v) {
+                if level.is_some_and(|l| x.abs() >= l) {
+                    *count += 1;
+                    saturated = true;
+                }
+            }
+            if saturated {
+                match events.last_mut() {
+                    Some(last) if t - last.1 <= options.merge_gap_ms => last.1 = *t,
+                    _ => events.push((*t, *t))
+                }
+            }
+        }
+        let n = data.len() as f64;
+        reports.push(SaturationReport { group, levels, percentage: counts.map(|x| x as f64 * 100.0 / n), events });
+    }
+    if reports.is_empty() { return reports; }
+
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        let mut events = Vec::new();
+        for r in &reports {
+            let p = r.percentage;
+            util::insert_tag(map, tag!(parsed r.group.clone(), TagId::Custom("Saturation".into()), "Saturated readings per axis", f64x3, |v| format!("{:.2}% {:.2}% {:.2}%", v.0, v.1, v.2), (p[0], p[1], p[2]), vec![]));
+            events.extend(r.events.iter().map(|(start, end)| CameraEvent {
+                t: start / 1000.0,
+                severity: EventSeverity::Warning,
+                code: format!("{}Saturation", r.group),
+                text: format!("{} saturated for {:.0} ms", r.group, end - start),
+            }));
+        }
+        util::insert_events(map, events);
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quaternion_rates_of_constant_rotation() {
+        // 1 rad/s around z, sampled every 10 ms
+        let quats = (0..100).map(|i| {
+            let t = i as f64 * 10.0;
+            let half = t / 1000.0 / 2.0;
+            TimeQuaternion { t, v: Quaternion { w: half.cos(), x: 0.0, y: 0.0, z: half.sin() } }
+        }).collect::<Vec<_>>();
+
+        let rates = quaternion_rates(&quats);
+        assert_eq!(rates.len(), 99);
+        for (i, r) in rates.iter().enumerate() {
+            assert!((r.t - (i as f64 * 0.01 + 0.005)).abs() < 1e-9, "timestamp {} at {i}", r.t);
+            assert!((r.z - 1.0).abs() < 1e-6, "rate {} at {i}", r.z);
+            assert!(r.x.abs() < 1e-9 && r.y.abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Central registry of model- and firmware-specific workarounds.
+// Parsers look up the quirks for the detected camera and apply them, and the active ones are listed in the
+// `Default/Quirks` tag, so it's visible in the output which corrections were made.
+
+use crate::tags_impl::*;
+use crate::tag;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quirk {
+    /// Reported frame readout time is two times larger than the real value
+    HalvedReadoutTime,
+    /// IMU orientation differs from the default for this camera type
+    ImuOrientation(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub struct FirmwareQuirk {
+    pub camera_type: &'static str,
+    /// `None` matches all models
+    pub model: Option<&'static str>,
+    /// `None` matches all firmware versions, otherwise the version has to match exactly
+    pub firmware: Option<&'static str>,
+    pub quirk: Quirk,
+    pub description: &'static str,
+}
+
+impl FirmwareQuirk {
+    pub fn matches(&self, camera_type: &str, model: Option<&str>, firmware: Option<&str>) -> bool {
+        if self.camera_type != camera_type { return false; }
+        if let Some(m) = self.model {
+            if model != Some(m) { return false; }
+        }
+        if let Some(fw) = self.firmware {
+            match firmware {
+                Some(v) if v == fw => { },
+                _ => return false
+            }
+        }
+        true
+    }
+}
+
+pub const QUIRKS: &[FirmwareQuirk] = &[
+    FirmwareQuirk { camera_type: "Blackmagic", model: None, firmware: Some("7.9"), quirk: Quirk::HalvedReadoutTime, description: "Frame readout time is reported two times too large" },
+    FirmwareQuirk { camera_type: "Blackmagic", model: Some("Micro Studio Camera 4K G2"), firmware: Some("8.4"), quirk: Quirk::ImuOrientation("yXZ"), description: "IMU orientation changed in firmware 8.4" },
+    // Used only when the file doesn't contain the IMU orientation tag
+    FirmwareQuirk { camera_type: "Sony", model: Some("DSC-RX0M2"),   firmware: None, quirk: Quirk::ImuOrientation("xZY"), description: "IMU orientation is not stored in the file" },
+    FirmwareQuirk { camera_type: "Sony", model: Some("DSC-RX100M7"), firmware: None, quirk: Quirk::ImuOrientation("Yzx"), description: "IMU orientation is not stored in the file" },
+    FirmwareQuirk { camera_type: "Sony", model: Some("ZV-1"),        firmware: None, quirk: Quirk::ImuOrientation("xzy"), description: "IMU orientation is not stored in the file" },
+];
+
+/// Returns all quirks registered for the given camera
+pub fn find(camera_type: &str, model: Option<&str>, firmware: Option<&str>) -> Vec<&'static FirmwareQuirk> {
+    QUIRKS.iter().filter(|x| x.matches(camera_type, model, firmware)).collect()
+}
+
+/// Returns the IMU orientation override, if any of the quirks changes it
+pub fn imu_orientation(quirks: &[&'static FirmwareQuirk]) -> Option<&'static str> {
+    quirks.iter().find_map(|x| match x.quirk { Quirk::ImuOrientation(o) => Some(o), _ => None })
+}
+
+pub fn has(quirks: &[&'static FirmwareQuirk], quirk: Quirk) -> bool {
+    quirks.iter().any(|x| x.quirk == quirk)
+}
+
+/// Lists the quirks which were applied by the parser in the `Default/Quirks` tag
+pub fn insert_tag(map: &mut GroupedTagMap, applied: &[&'static FirmwareQuirk]) {
+    if applied.is_empty() { return; }
+    let list = applied.iter().map(|x| match (x.model, x.firmware) {
+        (Some(m), Some(fw)) => format!("{} {} (firmware {}): {}", x.camera_type, m, fw, x.description),
+        (Some(m), None)     => format!("{} {}: {}", x.camera_type, m, x.description),
+        (None, Some(fw))    => format!("{} (firmware {}): {}", x.camera_type, fw, x.description),
+        (None, None)        => format!("{}: {}", x.camera_type, x.description),
+    }).collect::<Vec<String>>();
+    crate::util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("Quirks".into()), "Active firmware quirks", Vec_String, |v| v.join("\n"), list, vec![]));
+}
@@ -3,12 +3,14 @@
 
 use std::io::*;
 use std::sync::{ Arc, atomic::AtomicBool };
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use crate::tags_impl::*;
 use crate::*;
 use byteorder::{ ReadBytesExt, BigEndian };
 
+mod rdx;
+
 #[derive(Default)]
 pub struct RedR3d {
     pub model: Option<String>,
@@ -77,7 +79,7 @@ impl RedR3d {
 
                 let files = filesystem::list_folder(&filesystem::get_folder(path));
                 if files.is_empty() {
-                    log::warn!("Failed to read directory of file {path}");
+                    crate::parse_warning!("Failed to read directory of file {path}");
                 }
                 for x in files.into_iter() {
                     let fname = x.0;
@@ -96,9 +98,7 @@ impl RedR3d {
     }
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, _stream: &mut T, _size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         let fs = filesystem::get_base();
-        let mut gyro = Vec::new();
-        let mut accl = Vec::new();
-        let mut first_timestamp = None;
+        let mut rdx_payloads = Vec::new();
         let mut map = GroupedTagMap::new();
         let mut samples = Vec::new();
 
@@ -106,14 +106,20 @@ impl RedR3d {
         let mut data4096 = vec![0u8; 4096];
 
         let mut csv = String::new();
-        let mut rmd = HashMap::<String, String>::new();
+        let mut rmd = BTreeMap::<String, String>::new();
+        let mut rmd_gyro = Vec::new();
+        let mut rmd_accl = Vec::new();
+        let mut thumbnail_checked = false;
 
         let total_count = all_parts.len() as f64;
 
         for (i, path) in all_parts.into_iter().enumerate() {
             let ext = filesystem::get_extension(path.as_str());
             if ext == "rmd" {
-                rmd.extend(Self::parse_rmd(&path));
+                let (fields, g, a) = Self::parse_rmd(&path);
+                rmd.extend(fields);
+                rmd_gyro.extend(g);
+                rmd_accl.extend(a);
                 continue;
             }
 
@@ -133,30 +139,15 @@ impl RedR3d {
                     stream.seek(SeekFrom::Current(-8))?;
                     stream.read_exact(&mut data)?;
                     if data.len() > 4096 && (size as usize) <= data.len() {
-                        let mut data = &data[4096..size as usize];
-
-                        crate::try_block!({
-                            if &name == b"RDX\x01" {
-                                csv.push_str(std::str::from_utf8(data).ok()?);
-                            } else {
-                                while let Ok(timestamp) = data.read_u64::<BigEndian>() {
-                                    if first_timestamp.is_none() {
-                                        first_timestamp = Some(timestamp);
-                                    }
-                                    let t = (timestamp - first_timestamp.unwrap()) as f64 / 1000000.0;
-                                    accl.push(TimeVector3 { t,
-                                        x: -data.read_i16::<BigEndian>().ok()? as f64 / 100.0,
-                                        y: -data.read_i16::<BigEndian>().ok()? as f64 / 100.0,
-                                        z: -data.read_i16::<BigEndian>().ok()? as f64 / 100.0
-                                    });
-                                    gyro.push(TimeVector3 { t,
-                                        x: data.read_i16::<BigEndian>().ok()? as f64 / 10.0,
-                                        y: data.read_i16::<BigEndian>().ok()? as f64 / 10.0,
-                                        z: data.read_i16::<BigEndian>().ok()? as f64 / 10.0
-                                    });
-                                }
+                        if &name == b"RDX\x01" {
+                            if let Ok(text) = std::str::from_utf8(&data[4096..size as usize]) {
+                                csv.push_str(text);
                             }
-                        });
+                        } else {
+                            data.truncate(size as usize);
+                            data.drain(..4096);
+                            rdx_payloads.push(data);
+                        }
                     }
                 } else if &name == b"RED2" {
                     let mut data = Vec::with_capacity(aligned_size);
@@ -174,7 +165,19 @@ impl RedR3d {
                 } else if &name == b"RDI\x01" {
                     if aligned_size >= 4096 {
                         stream.read_exact(&mut data4096)?;
-                        stream.seek(SeekFrom::Current(aligned_size as i64 - 8 - 4096))?;
+                        let remaining = aligned_size as i64 - 8 - 4096;
+                        if !thumbnail_checked && remaining > 0 {
+                            // The preview image, if present, follows the metadata of the first frame
+                            thumbnail_checked = true;
+                            let mut data = data4096.clone();
+                            data.resize(4096 + remaining as usize, 0);
+                            stream.read_exact(&mut data[4096..])?;
+                            if let Some(jpeg) = util::find_jpeg(&data) {
+                                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::File("thumbnail.jpg".into()), "Thumbnail", Vec_u8, |v| format!("{} bytes", v.len()), jpeg.to_vec(), vec![]));
+                            }
+                        } else {
+                            stream.seek(SeekFrom::Current(remaining))?;
+                        }
                         if let Ok(size) = (&data4096[86..]).read_u16::<BigEndian>() {
                             let mut per_frame_map = GroupedTagMap::new();
                             let _ = self.parse_meta(&data4096[88..88 + size as usize], &mut per_frame_map);
@@ -203,10 +206,11 @@ impl RedR3d {
             if let Some(v) = rmd.get("lens") {
                 util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Name, "Lens name", String, |v| v.clone(), v.into(), vec![]));
             }
+            Self::insert_audio_sync(&mut map, &rmd);
             crate::try_block!({
                 if let TagValue::Json(ref mut md) = map.get_mut(&GroupId::Default)?.get_mut(&TagId::Metadata)?.value {
                     if let Some(md) = md.get_mut().as_object_mut() {
-                        for (k, v) in rmd.drain() {
+                        for (k, v) in std::mem::take(&mut rmd) {
                             if k == "fittype" {
                                 if v.starts_with("Fit Width ") || v.starts_with("Fit Height ") {
                                     if let Ok(num) = v.replace("Fit Width ", "").replace("Fit Height ", "").replace("x", "").parse::<f64>() {
@@ -226,6 +230,17 @@ impl RedR3d {
             });
         }
 
+        let (accl, gyro) = rdx::to_readings(&rdx::decode(&rdx_payloads));
+        drop(rdx_payloads);
+
+        // Newer firmware can write the motion data to the RMD sidecar as well
+        let mut gyro = Self::merge_motion(gyro, rmd_gyro, "gyroscope");
+        let mut accl = Self::merge_motion(accl, rmd_accl, "accelerometer");
+        let start = gyro.first().into_iter().chain(accl.first()).map(|x| x.t).reduce(f64::min).unwrap_or_default();
+        for v in gyro.iter_mut().chain(accl.iter_mut()) {
+            v.t -= start;
+        }
+
         // Try to get the sync data, if no async data present
         if accl.is_empty() && gyro.is_empty() && !samples.is_empty() {
             let mut timestamp = 0.0;
@@ -388,6 +403,10 @@ impl RedR3d {
             if let Some(v) = md.get("lens_name").and_then(|v| v.as_str()) {
                 util::insert_tag(map, tag!(parsed GroupId::Lens, TagId::Name, "Lens name", String, |v| v.clone(), v.into(), vec![]));
             }
+            if let Some(v) = md.get("lens_cooke_i_static") {
+                crate::cooke::Cooke::insert_lens_identity(map, v);
+            }
+            util::insert_lens_identity(map, md.get("lens_serial_number").and_then(|v| v.as_str()), md.get("lens_owner").and_then(|v| v.as_str()), None);
 
             let pixel_pitch = match self.model.as_deref() {
                 Some("KOMODO 6K")       => Some((4400, 4400)),
@@ -401,14 +420,97 @@ impl RedR3d {
                 util::insert_tag(map, tag!(parsed GroupId::Imager, TagId::PixelPitch, "Pixel pitch", u32x2, |v| format!("{v:?}"), pp, vec![]));
             }
 
-            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap(), serde_json::Value::Object(md), vec![]));
+            let local = Self::parse_date_time(md.get("local_date"), md.get("local_time"));
+            let utc   = Self::parse_date_time(md.get("gmt_date"),   md.get("gmt_time"));
+            util::insert_recording_start(map, local, utc, None);
+
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::Value::Object(md), vec![]));
         }
         Ok(())
     }
 
-    fn parse_rmd(file: &str) -> HashMap<String, String> {
-        let mut rmd = HashMap::<String, String>::new();
+    // Dates and times are stored as digits, eg. `20240131` and `153012`, separators are skipped if there are any
+    fn parse_date_time(date: Option<&serde_json::Value>, time: Option<&serde_json::Value>) -> Option<chrono::NaiveDateTime> {
+        let digits = |v: Option<&serde_json::Value>| -> Option<String> { Some(v?.as_str()?.chars().filter(|c| c.is_ascii_digit()).collect()) };
+        let date = chrono::NaiveDate::parse_from_str(&digits(date)?, "%Y%m%d").ok()?;
+        let time = chrono::NaiveTime::parse_from_str(digits(time)?.get(..6)?, "%H%M%S").ok()?;
+        Some(date.and_time(time))
+    }
+
+    // Timecode of the external audio recorder and its sound roll, for syncing dual-system sound.
+    // The timecode is stored in `Timecode/AudioTimecode`, and both are added as an event at the start of the clip
+    fn insert_audio_sync(map: &mut GroupedTagMap, rmd: &BTreeMap<String, String>) {
+        let audio_tc = rmd.get("audio_timecode").filter(|x| !x.is_empty());
+        let sound_roll = rmd.get("sound_roll").filter(|x| !x.is_empty());
+        if let Some(tc) = audio_tc {
+            util::insert_tag(map, tag!(parsed GroupId::Timecode, TagId::Custom("AudioTimecode".into()), "Audio timecode", String, |v| v.clone(), tc.clone(), vec![]));
+        }
+        if let Some(roll) = sound_roll {
+            util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("SoundRoll".into()), "Sound roll", String, |v| v.clone(), roll.clone(), vec![]));
+        }
+        let text = match (audio_tc, sound_roll) {
+            (Some(tc), Some(roll)) => format!("Audio timecode {tc}, sound roll {roll}"),
+            (Some(tc), None) => format!("Audio timecode {tc}"),
+            (None, Some(roll)) => format!("Sound roll {roll}"),
+            (None, None) => return
+        };
+        util::insert_events(map, vec![CameraEvent { t: 0.0, severity: EventSeverity::Info, code: "AudioSync".into(), text }]);
+    }
+
+    // RDX and RMD readings are timestamped with the same camera clock, so both are merged on one timeline, which starts at the first reading of either.
+    // Readings with the same timestamp in both are taken from the RDX data. If the timestamps don't overlap at all, they can't be aligned,
+    // so only the source with the higher rate is used and the other one is dropped with a warning
+    fn merge_motion(rdx: Vec<TimeVector3<f64>>, rmd: Vec<TimeVector3<f64>>, name: &str) -> Vec<TimeVector3<f64>> {
+        let range = |v: &[TimeVector3<f64>]| (v.first().map(|x| x.t).unwrap_or_default(), v.last().map(|x| x.t).unwrap_or_default());
+        if rdx.is_empty() { return rmd; }
+        if rmd.is_empty() { return rdx; }
+        let ((rdx_start, rdx_end), (rmd_start, rmd_end)) = (range(&rdx), range(&rmd));
+        if rdx_start > rmd_end || rmd_start > rdx_end {
+            let use_rmd = Self::sample_rate(&rmd) > Self::sample_rate(&rdx);
+            crate::parse_warning!("RED {name} data in RMD and RDX have unrelated timestamps, only the {} data is used", if use_rmd { "RMD" } else { "RDX" });
+            return if use_rmd { rmd } else { rdx };
+        }
+        let mut ret = rdx;
+        ret.extend(rmd);
+        ret.sort_by(|a, b| a.t.total_cmp(&b.t)); // Stable, so the RDX reading is first when both have the same timestamp
+        ret.dedup_by(|b, a| (b.t - a.t).abs() < 1e-6);
+        ret
+    }
+
+    fn sample_rate(v: &[TimeVector3<f64>]) -> f64 {
+        match (v.first(), v.last()) {
+            (Some(first), Some(last)) if last.t > first.t => (v.len() - 1) as f64 / (last.t - first.t),
+            _ => 0.0
+        }
+    }
+
+    // Motion arrays are stored as `t x y z` quadruples, with timestamp in microseconds,
+    // gyroscope in deg/s and accelerometer in m/s², in the same axes as the RDX data
+    fn parse_rmd_motion(contents: &[u8], name: &str, negate: bool) -> Vec<TimeVector3<f64>> {
+        let mut ret = Vec::new();
+        if let Some(v) = util::find_between(contents, format!("<{} type=\"float_array\" value=\"", name).as_bytes(), b'"') {
+            let values = v.split(|c: char| c == ',' || c == ';' || c.is_ascii_whitespace()).filter_map(|x| x.parse::<f64>().ok()).collect::<Vec<f64>>();
+            let sign = if negate { -1.0 } else { 1.0 };
+            for x in values.chunks_exact(4) {
+                ret.push(TimeVector3 {
+                    t: x[0] / 1000000.0,
+                    x: x[1] * sign,
+                    y: x[2] * sign,
+                    z: x[3] * sign
+                });
+            }
+        }
+        ret
+    }
+
+    fn parse_rmd(file: &str) -> (BTreeMap<String, String>, Vec<TimeVector3<f64>>, Vec<TimeVector3<f64>>) {
+        let mut rmd = BTreeMap::<String, String>::new();
+        let mut gyro = Vec::new();
+        let mut accl = Vec::new();
         if let Ok(contents) = filesystem::read_file(file) {
+            gyro = Self::parse_rmd_motion(&contents, "gyro_data", false);
+            accl = Self::parse_rmd_motion(&contents, "accel_data", true);
+
             let mut find = |name: &str, typ| {
                 if let Some(v) = util::find_between(&contents, format!("<{} type=\"{}\" value=\"", name, typ).as_bytes(), b'"') {
                     if !v.is_empty() {
@@ -436,6 +538,8 @@ impl RedR3d {
             find("lens", "string");
             find("scene", "string");
             find("shot", "string");
+            find("sound_roll", "string");
+            find("audio_timecode", "string");
             find("label", "string");
             find("video_slate_position", "int");
             find("poster_frame", "int");
@@ -451,6 +555,6 @@ impl RedR3d {
             }
         }
 
-        rmd
+        (rmd, gyro, accl)
     }
 }
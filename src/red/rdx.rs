@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Decoding of the `RDX\x02` motion payloads. Each record is a big-endian u64 timestamp in microseconds and 6 i16 values:
+// accelerometer in 1/100 m/s² (negated) and gyroscope in 1/10 deg/s.
+// Payloads are collected while the file is read and decoded together at the end, split into chunks decoded in parallel (unless the `single-thread` feature is enabled).
+// The records are decoded into one preallocated array, each thread into its own part of it. With the `simd` feature (nightly Rust) each record is scaled in one vector operation
+
+use crate::tags_impl::*;
+
+const RECORD_SIZE: usize = 20;
+const DIVISORS: [f64; 6] = [-100.0, -100.0, -100.0, 10.0, 10.0, 10.0];
+
+// Less than this many bytes are decoded in the current thread
+#[cfg(not(feature = "single-thread"))]
+const PARALLEL_THRESHOLD: usize = 1024 * 1024;
+
+#[cfg(feature = "simd")]
+fn scale(raw: &[u8]) -> [f64; 6] {
+    use std::simd::{ prelude::*, ToBytes };
+    let mut bytes = [0u8; 16];
+    bytes[..12].copy_from_slice(&raw[..12]);
+    let mut divisors = [1.0; 8];
+    divisors[..6].copy_from_slice(&DIVISORS);
+    let v = i16x8::from_be_bytes(u8x16::from_array(bytes)).cast::<f64>() / f64x8::from_array(divisors);
+    let v = v.to_array();
+    [v[0], v[1], v[2], v[3], v[4], v[5]]
+}
+#[cfg(not(feature = "simd"))]
+fn scale(raw: &[u8]) -> [f64; 6] {
+    std::array::from_fn(|i| i16::from_be_bytes([raw[i * 2], raw[i * 2 + 1]]) as f64 / DIVISORS[i])
+}
+
+fn records<'a>(payloads: &'a [&'a [u8]]) -> impl Iterator<Item = (u64, [f64; 6])> + 'a {
+    payloads.iter().flat_map(|payload| payload.chunks_exact(RECORD_SIZE)).map(|record| {
+        let (ts, values) = record.split_at(8);
+        (u64::from_be_bytes(ts.try_into().unwrap()), scale(values))
+    })
+}
+
+fn record_count(payloads: &[&[u8]]) -> usize {
+    payloads.iter().map(|x| x.len() / RECORD_SIZE).sum()
+}
+
+fn decode_into(payloads: &[&[u8]], out: &mut [(u64, [f64; 6])]) {
+    for (o, record) in out.iter_mut().zip(records(payloads)) {
+        *o = record;
+    }
+}
+
+/// Decodes the payloads (without the 4096-byte header) to the (timestamp in µs, accelerometer x y z, gyroscope x y z) records, in order
+pub fn decode(payloads: &[Vec<u8>]) -> Vec<(u64, [f64; 6])> {
+    let payloads = payloads.iter().map(|x| x.as_slice()).collect::<Vec<_>>();
+    let mut ret = vec![(0, [0.0; 6]); record_count(&payloads)];
+
+    #[cfg(not(feature = "single-thread"))]
+    {
+        let threads = std::thread::available_parallelism().map(|x| x.get()).unwrap_or(1).min(payloads.len());
+        if ret.len() * RECORD_SIZE >= PARALLEL_THRESHOLD && threads > 1 {
+            decode_parallel(&payloads, threads, &mut ret);
+            return ret;
+        }
+    }
+    decode_into(&payloads, &mut ret);
+    ret
+}
+
+// Each thread decodes its chunk of payloads into its own part of `out`, with the parser options of the calling thread,
+// and what it reported is merged back in the order of the chunks. A panic in any of them is propagated
+#[cfg(not(feature = "single-thread"))]
+fn decode_parallel(payloads: &[&[u8]], threads: usize, out: &mut [(u64, [f64; 6])]) {
+    let chunk_size = payloads.len().div_ceil(threads);
+    let ctx = crate::util::WorkerContext::capture();
+    std::thread::scope(|s| {
+        let mut rest = out;
+        let handles = payloads.chunks(chunk_size).map(|chunk| {
+            let (part, tail) = std::mem::take(&mut rest).split_at_mut(record_count(chunk));
+            rest = tail;
+            let ctx = &ctx;
+            s.spawn(move || ctx.run(|| decode_into(chunk, part)).1)
+        }).collect::<Vec<_>>();
+        for handle in handles {
+            match handle.join() {
+                Ok(report) => report.merge(),
+                Err(e) => std::panic::resume_unwind(e)
+            }
+        }
+    });
+}
+
+/// Splits the records into the accelerometer and gyroscope readings, with the record timestamps in seconds
+pub fn to_readings(records: &[(u64, [f64; 6])]) -> (Vec<TimeVector3<f64>>, Vec<TimeVector3<f64>>) {
+    let mut accl = Vec::with_capacity(records.len());
+    let mut gyro = Vec::with_capacity(records.len());
+    for (ts, v) in records {
+        let t = *ts as f64 / 1000000.0;
+        accl.push(TimeVector3 { t, x: v[0], y: v[1], z: v[2] });
+        gyro.push(TimeVector3 { t, x: v[3], y: v[4], z: v[5] });
+    }
+    (accl, gyro)
+}
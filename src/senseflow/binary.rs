@@ -5,20 +5,37 @@ use crate::*;
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use std::io::*;
 
-pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
-    let mut stream = std::io::BufReader::new(stream);
+// Every recording session starts with a 512-byte header (brand, sensor settings and the start time), followed by the packets.
+// Logs can contain several sessions, each is returned as a separate sample with its index in `Default/SessionIndex`
+// and the timestamps relative to the start of the session. The sample timestamp is the start of the session relative to the first one,
+// according to the start times in the headers, or the end of the previous session if the clock isn't set
+
+const HEADER_SIZE: usize = 512;
 
-    let mut gyro = Vec::new();
-    let mut accl = Vec::new();
-    let mut angl = Vec::new();
-    let mut magn = Vec::new();
-    let mut quat = Vec::new();
+struct Header {
+    imu_orientation: String,
+    created_at: chrono::NaiveDateTime,
+    accl_timeoffset: i32,
+    accl_range: f64,
+    gyro_timeoffset: i32,
+    gyro_range: f64,
+    magn_timeoffset: i32,
+    timestamp_step: f64,
+    metadata: serde_json::Value,
+}
 
-    let mut last_timestamp;
+#[derive(Default)]
+struct Session {
+    gyro: Vec<TimeVector3<f64>>,
+    accl: Vec<TimeVector3<f64>>,
+    angl: Vec<TimeVector3<f64>>,
+    magn: Vec<TimeVector3<i64, f64>>,
+    quat: Vec<TimeQuaternion<f64>>,
+    last_timestamp: f64,
+}
 
-    let mut buf = vec![0u8; 512];
-    stream.read_exact(&mut buf[0..512])?;
-    let mut d = std::io::Cursor::new(&buf);
+fn parse_header(buf: &[u8]) -> Result<Header> {
+    let mut d = std::io::Cursor::new(buf);
 
     let brand = unsafe { std::str::from_utf8_unchecked(&buf[0..12]) };
     let version = unsafe { std::str::from_utf8_unchecked(&buf[12..16]) };
@@ -38,11 +55,9 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
         .and_then(|x| x.and_hms_milli_opt(h, m, s, ms))
         .unwrap_or_default();
 
-    let first_timestamp = 0f64;
-
     d.seek(SeekFrom::Start(76))?;
     let _init_quat = TimeQuaternion {
-        t: (first_timestamp * 1000.0) as f64,
+        t: 0.0,
         v: Quaternion {
             w: d.read_f32::<LittleEndian>()? as f64,
             x: d.read_f32::<LittleEndian>()? as f64,
@@ -69,7 +84,6 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
     let magn_range = (d.read_u32::<LittleEndian>()? as f64) / 1000.0;
 
     let timestamp_step = 1.0f64 / (log_freq as f64);
-    last_timestamp = first_timestamp - timestamp_step;
 
     let metadata = serde_json::json!({
        "brand": brand,
@@ -92,6 +106,27 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
        "init quat": _init_quat,
     });
 
+    Ok(Header {
+        imu_orientation: imu_orientation.to_owned(),
+        created_at,
+        accl_timeoffset,
+        accl_range,
+        gyro_timeoffset,
+        gyro_range,
+        magn_timeoffset,
+        timestamp_step,
+        metadata,
+    })
+}
+
+pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
+    let mut stream = std::io::BufReader::new(stream);
+
+    let mut buf = vec![0u8; HEADER_SIZE];
+    stream.read_exact(&mut buf)?;
+    let header = parse_header(&buf)?;
+    let mut sessions = vec![(Session { last_timestamp: -header.timestamp_step, ..Default::default() }, header)];
+
     // acc gyro mag quad angle temp -- --
     let sensor_length = [6, 6, 6, 8, 12, 2, 0, 0];
     let mut sensor_valid = [0u8; 8];
@@ -108,29 +143,31 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
                 data_valid >>= 1;
             }
 
+            let Some((session, header)) = sessions.last_mut() else { break; };
             if let Ok(mut d) = checksum(&mut stream, data_length) {
-                last_timestamp += timestamp_step;
+                session.last_timestamp += header.timestamp_step;
+                let last_timestamp = session.last_timestamp;
                 if sensor_valid[0] == 1 {
-                    accl.push(TimeVector3 {
-                        t: (last_timestamp as f64) + (accl_timeoffset as f64) / 1000.0,
-                        x: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * accl_range,
-                        y: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * accl_range,
-                        z: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * accl_range,
+                    session.accl.push(TimeVector3 {
+                        t: (last_timestamp as f64) + (header.accl_timeoffset as f64) / 1000.0,
+                        x: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.accl_range,
+                        y: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.accl_range,
+                        z: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.accl_range,
                     });
                 }
 
                 if sensor_valid[1] == 1 {
-                    gyro.push(TimeVector3 {
-                        t: (last_timestamp as f64) + (gyro_timeoffset as f64) / 1000.0,
-                        x: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * gyro_range,
-                        y: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * gyro_range,
-                        z: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * gyro_range,
+                    session.gyro.push(TimeVector3 {
+                        t: (last_timestamp as f64) + (header.gyro_timeoffset as f64) / 1000.0,
+                        x: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.gyro_range,
+                        y: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.gyro_range,
+                        z: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * header.gyro_range,
                     });
                 }
 
                 if sensor_valid[2] == 1 {
-                    magn.push(TimeVector3 {
-                        t: (last_timestamp as f64) + (magn_timeoffset as f64) / 1000.0,
+                    session.magn.push(TimeVector3 {
+                        t: (last_timestamp as f64) + (header.magn_timeoffset as f64) / 1000.0,
                         x: d.read_i16::<LittleEndian>()? as i64,
                         y: d.read_i16::<LittleEndian>()? as i64,
                         z: d.read_i16::<LittleEndian>()? as i64,
@@ -138,7 +175,7 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
                 }
 
                 if sensor_valid[3] == 1 {
-                    quat.push(TimeQuaternion {
+                    session.quat.push(TimeQuaternion {
                         t: (last_timestamp * 1000.0) as f64,
                         v: util::multiply_quats(
                             (
@@ -153,7 +190,7 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
                 }
 
                 if sensor_valid[4] == 1 {
-                    angl.push(TimeVector3 {
+                    session.angl.push(TimeVector3 {
                         t: last_timestamp as f64,
                         x: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * 180.0, // Roll
                         y: ((d.read_i16::<LittleEndian>()? as f64) / 32768.0) * 180.0, // Pitch
@@ -161,9 +198,41 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
                     });
                 }
             }
+        } else if tag == u16::from_be_bytes([b'S', b'e']) {
+            // Header of the next session, written after the last packet of the previous one
+            buf[..2].copy_from_slice(b"Se");
+            if stream.read_exact(&mut buf[2..]).is_err() { break; }
+            if buf.starts_with(b"SenseFlow") {
+                let header = parse_header(&buf)?;
+                sessions.push((Session { last_timestamp: -header.timestamp_step, ..Default::default() }, header));
+            } else {
+                stream.seek_relative(2 - HEADER_SIZE as i64)?;
+            }
         }
     }
 
+    let first_start = sessions[0].1.created_at;
+    let mut prev_end_ms = 0.0;
+    let mut samples = Vec::with_capacity(sessions.len());
+    for (index, (session, header)) in sessions.into_iter().enumerate() {
+        let timestamp_ms = ((header.created_at - first_start).num_milliseconds() as f64).max(prev_end_ms);
+        let duration_ms = session.last_timestamp.max(0.0) * 1000.0;
+        prev_end_ms = timestamp_ms + duration_ms;
+        samples.push(SampleInfo {
+            sample_index: index as u64,
+            timestamp_ms,
+            duration_ms,
+            tag_map: Some(session_map(session, header, index)),
+            ..Default::default()
+        });
+    }
+    Ok(samples)
+}
+
+fn session_map(session: Session, header: Header, index: usize) -> GroupedTagMap {
+    let Session { gyro, accl, angl, magn, quat, .. } = session;
+    let imu_orientation = header.imu_orientation;
+
     let mut map = GroupedTagMap::new();
 
     util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}",  v), accl, vec![]),);
@@ -172,8 +241,8 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
     util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()),);
     util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()),);
     
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()),);
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()),);
+    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.clone(), Vec::new()),);
+    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation, Vec::new()),);
     
     util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_i64f64, |v| format!("{:?}", v), magn, vec![]),);
     util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()),);
@@ -182,14 +251,13 @@ pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleI
     util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()),        TagId::Unit, "Angle unit", String, |v| v.to_string(), "deg".into(),  Vec::new()),);
     
     util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,   TagId::Data, "Quaternion data",   Vec_TimeQuaternion_f64,  |v| format!("{:?}", v), quat, vec![]),);
-    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap(), metadata, vec![]),);
-    
-    Ok(vec![SampleInfo {
-        timestamp_ms: first_timestamp as f64,
-        duration_ms: (last_timestamp - first_timestamp) as f64,
-        tag_map: Some(map),
-        ..Default::default()
-    }])
+    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), header.metadata, vec![]),);
+    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("SessionIndex".into()), "Recording session index", u32, |v| v.to_string(), index as u32, vec![]),);
+    if header.created_at.and_utc().timestamp() > 0 {
+        util::insert_recording_start(&mut map, Some(header.created_at), None, None);
+    }
+
+    map
 }
 
 fn checksum<T: Read + Seek>(stream: &mut T, item_size: u64) -> Result<Cursor<Vec<u8>>> {
@@ -198,6 +266,7 @@ fn checksum<T: Read + Seek>(stream: &mut T, item_size: u64) -> Result<Cursor<Vec
     let sum = stream.read_u8()?;
     let init: u8 = 0;
     let calculated_sum = buf.iter().fold(init, |sum, &x| sum.wrapping_add(x));
+    crate::util::report_checksum(calculated_sum == sum);
 
     if calculated_sum == sum {
         Ok(Cursor::new(buf))
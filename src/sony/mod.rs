@@ -20,6 +20,16 @@ use memchr::memmem;
 pub struct Sony {
     pub model: Option<String>,
     frame_readout_time: Option<f64>,
+    serial: Option<String>,
+    xml_gps: Option<GpsData>,
+    xml_events: Vec<CameraEvent>,
+    #[cfg(feature="sony-xml")]
+    sidecar: Option<xml_metadata::Metadata>,
+    // Where the parsing of a MXF file which is still being recorded ended, see `resume`
+    mxf_state: Option<mxf::ParseState>,
+    // Carried over between the calls of `process_map`, when the file is processed in parts
+    imu_description: std::collections::BTreeMap<GroupId, TagMap>,
+    recording_start_found: bool,
 }
 impl Sony {
     pub fn camera_type(&self) -> String {
@@ -49,53 +59,380 @@ impl Sony {
     }
 
     pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+        #[cfg(feature="sony-xml")]
+        let sidecar = xml_metadata::find_sidecar(_filepath.as_ref().to_str().unwrap_or_default())
+            .and_then(|path| filesystem::read_file(&path).ok())
+            .map(|data| (xml_metadata::parse(&data), data))
+            .filter(|(md, _)| md.manufacturer == "Sony");
+
         if let Some(p1) = memmem::find(buffer, b"manufacturer=\"Sony\"") {
             return Some(Self {
                 model: util::find_between(&buffer[p1..(p1+1024).min(buffer.len())], b"modelName=\"", b'"'),
-                frame_readout_time: None
+                frame_readout_time: None,
+                serial: util::find_between(&buffer[p1..(p1+1024).min(buffer.len())], b"serialNo=\"", b'"'),
+                xml_gps: Self::parse_xml_gps(buffer),
+                xml_events: Self::parse_xml_events(buffer),
+                #[cfg(feature="sony-xml")]
+                sidecar: sidecar.map(|(md, _)| md),
+                ..Default::default()
+            });
+        }
+        // MP4 without the embedded XML, but with the non-realtime metadata file from the card structure next to it
+        #[cfg(feature="sony-xml")]
+        if let Some((md, data)) = sidecar {
+            return Some(Self {
+                model: Some(md.model.clone()).filter(|x| !x.is_empty()),
+                frame_readout_time: None,
+                serial: md.serial.clone(),
+                xml_gps: Self::parse_xml_gps(&data),
+                xml_events: Self::parse_xml_events(&data),
+                sidecar: Some(md),
+                ..Default::default()
             });
         }
         None
     }
 
+    // Fill in what's missing in the file from the non-realtime metadata XML
+    #[cfg(feature="sony-xml")]
+    fn merge_sidecar(&mut self, samples: &mut Vec<SampleInfo>, md: xml_metadata::Metadata) {
+        if self.model.is_none() && !md.model.is_empty() {
+            self.model = Some(md.model.clone());
+        }
+        if self.serial.is_none() {
+            self.serial = md.serial.clone();
+        }
+        let has_tag = |group: &GroupId, ids: &[TagId]| samples.iter().any(|x| x.tag_map.as_ref().and_then(|m| m.get(group)).map(|g| ids.iter().any(|id| g.contains_key(id))).unwrap_or_default());
+        let lens = md.lens.filter(|_| !has_tag(&GroupId::Lens, &[TagId::Name, TagId::LensAttributes]));
+        let timecode = md.start_timecode.filter(|_| !has_tag(&GroupId::Default, &[TagId::Custom("StartTimecode".into())]));
+        if lens.is_none() && timecode.is_none() { return; }
+
+        if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+            samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+        }
+        if let Some(map) = samples[0].tag_map.as_mut() {
+            if let Some(lens) = lens {
+                util::insert_tag(map, tag!(parsed GroupId::Lens, TagId::Name, "Lens name", String, |v| v.clone(), lens, vec![]));
+            }
+            if let Some(tc) = timecode {
+                util::insert_tag(map, tag!(parsed GroupId::Default, TagId::Custom("StartTimecode".into()), "Start timecode", String, |v| v.clone(), tc, vec![]));
+            }
+        }
+    }
+
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         let mut header = [0u8; 4];
         stream.read_exact(&mut header)?;
         stream.seek(SeekFrom::Start(0))?;
 
+        let mut gaps = Vec::new();
         let mut samples = if header == [0x06, 0x0E, 0x2B, 0x34] { // MXF header
-            mxf::parse(stream, size, progress_cb, cancel_flag, None)?
+            if util::is_growing_file() {
+                let mut state = mxf::ParseState::default();
+                let mut samples = mxf::parse_growing(stream, size, progress_cb, cancel_flag, &mut state)?;
+                mxf::insert_events(&mut samples, std::mem::take(&mut state.events));
+                self.mxf_state = Some(state);
+                samples
+            } else {
+                mxf::parse(stream, size, progress_cb, cancel_flag, None)?
+            }
         } else {
             let mut samples = Vec::new();
-            util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+            let res = util::get_metadata_track_samples(stream, size, true, |mut info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
                 if size > 0 {
                     progress_cb(file_position as f64 / size as f64);
                 }
                 if Self::detect_metadata(data) {
-                    if let Ok(map) = Self::parse_metadata(&data[0x1C..]) {
-                        info.tag_map = Some(map);
-                        samples.push(info);
+                    match Self::parse_metadata(&data[0x1C..]) {
+                        Ok(map) => {
+                            info.tag_map = Some(map);
+                            samples.push(info);
+                        }
+                        Err(e) => gaps.push(util::corrupted_sample(&info, &e))
                     }
                 }
-            }, cancel_flag)?;
+            }, cancel_flag.clone());
+            match res {
+                Ok(_) => { },
+                Err(e) if util::is_salvage_allowed() && samples.is_empty() => {
+                    crate::parse_warning!("Failed to read the metadata track ({e:?}), scanning the file for RTMD packets");
+                    Self::salvage_rtmd(stream, size, &mut samples, &cancel_flag)?;
+                },
+                Err(e) => return Err(e)
+            }
             samples
         };
 
         self.process_map(&mut samples);
+        Self::collect_stabilizer_data(&mut samples);
+
+        #[cfg(feature="sony-xml")]
+        if let Some(md) = self.sidecar.take() {
+            self.merge_sidecar(&mut samples, md);
+        }
+
+        // Serial number of the body is only in the XML (`Device` element)
+        if let Some(serial) = self.serial.take() {
+            if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+                samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+            }
+            if let Some(map) = samples[0].tag_map.as_mut() {
+                util::insert_camera_serial(map, Some(&serial));
+            }
+        }
+
+        // GPS from the XML is only a single position for the whole clip, use it only if there are no per-frame GPS packets
+        if let Some(gps) = self.xml_gps.take() {
+            if !samples.iter().any(|x| x.tag_map.as_ref().map(|x| x.contains_key(&GroupId::GPS)).unwrap_or_default()) {
+                let mut map = GroupedTagMap::new();
+                util::insert_tag(&mut map, tag!(parsed GroupId::GPS, TagId::Data, "GPS data", Vec_GpsData, |v| format!("{:?}", v), vec![gps], vec![]));
+                samples.insert(0, SampleInfo { tag_map: Some(map), ..Default::default() });
+            }
+        }
+
+        util::insert_gap_markers(&mut samples, gaps);
+
+        if !self.xml_events.is_empty() {
+            let mut map = GroupedTagMap::new();
+            util::insert_tag(&mut map, tag!(parsed GroupId::Events, TagId::Data, "Camera events", Vec_CameraEvent, |v| format!("{:?}", v), std::mem::take(&mut self.xml_events), vec![]));
+            samples.insert(0, SampleInfo { tag_map: Some(map), ..Default::default() });
+        }
+
+        Ok(samples)
+    }
+
+    /// Continues the parsing of a MXF file which is still being recorded, from where the previous call stopped. `size` is the current size of the file.
+    /// Returns only the new samples. Available if the file was parsed with `InputOptions::growing_file`
+    pub fn resume<T: Read + Seek>(&mut self, stream: &mut T, size: usize) -> Result<Vec<SampleInfo>> {
+        let state = self.mxf_state.as_mut().ok_or_else(|| Error::new(ErrorKind::Unsupported, "Only MXF files parsed as growing files can be resumed"))?;
+        let mut samples = mxf::parse_growing(stream, size, |_| (), Arc::new(AtomicBool::new(false)), state)?;
+        let events = std::mem::take(&mut state.events);
+        mxf::insert_events(&mut samples, events);
 
+        self.process_map(&mut samples);
+        Self::collect_stabilizer_data(&mut samples);
         Ok(samples)
     }
 
-    fn process_map(&mut self, samples: &mut Vec<SampleInfo>) {
+    // RTMD packets have only a weak magic (header length), so a match is accepted only if it decodes to at least two known groups.
+    // There's one packet per video frame
+    fn salvage_rtmd<T: Read + Seek>(stream: &mut T, size: usize, samples: &mut Vec<SampleInfo>, cancel_flag: &Arc<AtomicBool>) -> Result<()> {
+        let mut duration_ms = 1000.0 / 25.0;
+        util::salvage_chunks(stream, size, &[0x00, 0x1C], 64*1024, cancel_flag, |file_position, chunk| {
+            if !Self::detect_metadata(chunk) { return; }
+            if let Ok(map) = Self::parse_metadata(&chunk[0x1C..]) {
+                let known_groups = [GroupId::Imager, GroupId::Lens, GroupId::Exposure, GroupId::Gyroscope, GroupId::Accelerometer];
+                if known_groups.iter().filter(|x| map.contains_key(x)).count() < 2 { return; }
+                if let Some(fr) = map.get(&GroupId::Default).and_then(|x| x.get(&TagId::FrameRate)) {
+                    match &fr.value {
+                        TagValue::f32(v) if *v.get() > 0.0 => duration_ms = 1000.0 / *v.get() as f64,
+                        TagValue::f64(v) if *v.get() > 0.0 => duration_ms = 1000.0 / *v.get(),
+                        _ => { }
+                    }
+                }
+                let index = samples.len() as u64;
+                samples.push(SampleInfo {
+                    sample_index: index,
+                    timestamp_ms: index as f64 * duration_ms,
+                    duration_ms,
+                    file_position,
+                    tag_map: Some(map),
+                    ..Default::default()
+                });
+            }
+        })
+    }
+
+    // The XAVC XML (NonRealTimeMeta) lists timecode changes in `LtcChangeTable` and shot marks in `KlvPacketTable`, both indexed by frame number
+    fn parse_xml_events(buffer: &[u8]) -> Vec<CameraEvent> {
+        let mut ret = Vec::new();
+        let attr = |el: &[u8], name: &str| -> Option<String> { util::find_between(el, format!(" {name}=\"").as_bytes(), b'"') };
+        let elements = |name: &str| -> Vec<&[u8]> {
+            memmem::find_iter(buffer, format!("<{name} ").as_bytes()).map(|pos| {
+                let end = memchr::memchr(b'>', &buffer[pos..]).map(|x| pos + x).unwrap_or(buffer.len());
+                &buffer[pos..end]
+            }).collect()
+        };
+
+        let fps = elements("VideoFrame").first().and_then(|x| attr(x, "formatFps"))
+            .and_then(|x| x.trim_end_matches(['p', 'i']).parse::<f64>().ok())
+            .or_else(|| elements("LtcChangeTable").first().and_then(|x| attr(x, "tcFps")).and_then(|x| x.parse::<f64>().ok()))
+            .filter(|x| *x > 0.0);
+        let Some(fps) = fps else { return ret; };
+        let time = |el: &[u8]| -> Option<f64> { Some(attr(el, "frameCount")?.parse::<f64>().ok()? / fps) };
+
+        for el in elements("LtcChange") {
+            let Some(t) = time(el) else { continue; };
+            let tc = attr(el, "value").unwrap_or_default();
+            match attr(el, "status").as_deref() {
+                Some("increment") if t == 0.0 => ret.push(CameraEvent { t, severity: EventSeverity::Info, code: "RecordStart".into(), text: format!("Timecode {tc}") }),
+                Some("increment") => ret.push(CameraEvent { t, severity: EventSeverity::Warning, code: "TimecodeDiscontinuity".into(), text: format!("Timecode jumped to {tc}") }),
+                Some("end") => ret.push(CameraEvent { t, severity: EventSeverity::Info, code: "RecordStop".into(), text: format!("Timecode {tc}") }),
+                _ => { }
+            }
+        }
+        for el in elements("KlvPacket") {
+            let Some(t) = time(el) else { continue; };
+            if attr(el, "key").as_deref() == Some("060E2B34010101050301020A02000000") {
+                ret.push(CameraEvent { t, severity: EventSeverity::Info, code: "ShotMark".into(), text: String::new() });
+            }
+        }
+        ret.sort_by(|a, b| a.t.total_cmp(&b.t));
+        ret
+    }
+
+    fn gps_unix_timestamp(date: &str, time: (f64, f64, f64)) -> Option<f64> {
+        let date = chrono::NaiveDate::parse_from_str(date.trim().trim_end_matches('\0'), "%Y:%m:%d").ok()?;
+        let seconds = time.0 * 3600.0 + time.1 * 60.0 + time.2;
+        Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp() as f64 + seconds)
+    }
+
+    fn gps_speed_kmh(speed: f64, speed_ref: u8) -> f64 {
+        match speed_ref {
+            b'M' => speed * 1.609344,
+            b'N' => speed * 1.852,
+            _ => speed
+        }
+    }
+
+    // The first byte of the capture timestamp (0xe304) is the time zone of the camera clock: bits 0-4 are the offset in 30 minute steps,
+    // bit 5 is set for negative offsets and bit 6 during daylight saving time, which is already included in the offset.
+    // Eg. 0x02 for GMT+1, 0x44 for GMT+2 (CEST) and 0x2A for GMT-5. The date and time after it is the local time
+    fn recording_start_from_rtmd(map: &TagMap) -> Option<(chrono::NaiveDateTime, i32)> {
+        let desc = map.get(&TagId::CaptureTimestamp)?;
+        let TagValue::u64(v) = &desc.value else { return None; };
+        let tz = *v.raw_data.first()?;
+        let offset = (tz & 0x1F) as i32 * 30 * if tz & 0x20 != 0 { -1 } else { 1 };
+        let local = chrono::DateTime::from_timestamp(*v.get() as i64, 0)?.naive_utc();
+        Some((local, offset))
+    }
+
+    fn gps_from_rtmd(map: &TagMap) -> Option<GpsData> {
+        let dms = |id: &str| -> Option<f64> {
+            let v = map.get_t(TagId::Custom(id.into())) as Option<&(f64, f64, f64)>;
+            v.map(|v| v.0 + v.1 / 60.0 + v.2 / 3600.0)
+        };
+        let reference = |id: &str| -> u8 { (map.get_t(TagId::Custom(id.into())) as Option<&u8>).copied().unwrap_or_default() };
+
+        let mut lat = dms("GPSLatitude")?;
+        let mut lon = dms("GPSLongitude")?;
+        if reference("GPSLatitudeRef")  == b'S' { lat = -lat; }
+        if reference("GPSLongitudeRef") == b'W' { lon = -lon; }
+        let mut altitude = (map.get_t(TagId::Custom("GPSAltitude".into())) as Option<&f64>).copied().unwrap_or_default();
+        if reference("GPSAltitudeRef") == 1 { altitude = -altitude; }
+
+        let unix_timestamp = crate::try_block!(f64, {
+            let date = map.get_t(TagId::Custom("GPSDateStamp".into())) as Option<&String>;
+            let time = map.get_t(TagId::Custom("GPSTimeStamp".into())) as Option<&(f64, f64, f64)>;
+            Self::gps_unix_timestamp(date?, *time?)?
+        }).unwrap_or_default();
+
+        Some(GpsData {
+            is_acquired: reference("GPSStatus") != b'V',
+            unix_timestamp,
+            lat,
+            lon,
+            speed: Self::gps_speed_kmh((map.get_t(TagId::Custom("GPSSpeed".into())) as Option<&f64>).copied().unwrap_or_default(), reference("GPSSpeedRef")),
+            track: (map.get_t(TagId::Custom("GPSTrack".into())) as Option<&f64>).copied().unwrap_or_default(),
+            altitude,
+            ..Default::default()
+        })
+    }
+
+    // XAVC non-realtime metadata: <Group name="ExifGPS"><Item name="Latitude" value="35:39:50.123"/>...</Group>
+    fn parse_xml_gps(buffer: &[u8]) -> Option<GpsData> {
+        let start = memmem::find(buffer, b"<Group name=\"ExifGPS\"")?;
+        let end = memmem::find(&buffer[start..], b"</Group>").map(|x| start + x).unwrap_or(buffer.len());
+        let group = &buffer[start..end];
+
+        let item = |name: &str| -> Option<String> {
+            let pos = memmem::find(group, format!("name=\"{name}\"").as_bytes())?;
+            let item_end = memchr::memchr(b'>', &group[pos..]).map(|x| pos + x).unwrap_or(group.len());
+            util::find_between(&group[pos..item_end], b"value=\"", b'"')
+        };
+        let triplet = |name: &str| -> Option<(f64, f64, f64)> {
+            let v = item(name)?.split(':').map(|x| x.trim().parse::<f64>().ok()).collect::<Option<Vec<f64>>>()?;
+            Some((*v.first()?, *v.get(1).unwrap_or(&0.0), *v.get(2).unwrap_or(&0.0)))
+        };
+        let reference = |name: &str| -> u8 { item(name).and_then(|x| x.bytes().next()).unwrap_or_default() };
+
+        let lat = triplet("Latitude")?;
+        let lon = triplet("Longitude")?;
+        let mut lat = lat.0 + lat.1 / 60.0 + lat.2 / 3600.0;
+        let mut lon = lon.0 + lon.1 / 60.0 + lon.2 / 3600.0;
+        if reference("LatitudeRef")  == b'S' { lat = -lat; }
+        if reference("LongitudeRef") == b'W' { lon = -lon; }
+        let mut altitude = item("Altitude").and_then(|x| x.parse::<f64>().ok()).unwrap_or_default();
+        if reference("AltitudeRef") == b'1' { altitude = -altitude; }
+
+        let unix_timestamp = crate::try_block!(f64, {
+            Self::gps_unix_timestamp(&item("DateStamp")?, triplet("TimeStamp")?)?
+        }).unwrap_or_default();
+
+        Some(GpsData {
+            is_acquired: reference("Status") != b'V',
+            unix_timestamp,
+            lat,
+            lon,
+            speed: Self::gps_speed_kmh(item("Speed").and_then(|x| x.parse::<f64>().ok()).unwrap_or_default(), reference("SpeedRef")),
+            track: item("Track").and_then(|x| x.parse::<f64>().ok()).unwrap_or_default(),
+            altitude,
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn process_map(&mut self, samples: &mut Vec<SampleInfo>) {
+        let mut readout_times = Vec::new();
+        let model_quirks = quirks::find(&self.camera_type(), self.model.as_deref(), None);
+        let mut orientation_quirk_used = false;
         for sample in samples.iter_mut() {
             if let Some(ref mut map) = sample.tag_map {
+                if let Some(gps) = map.get(&GroupId::GPS).and_then(Self::gps_from_rtmd) {
+                    util::insert_tag(map, tag!(parsed GroupId::GPS, TagId::Data, "GPS data", Vec_GpsData, |v| format!("{:?}", v), vec![gps], vec![]));
+                }
+                if !self.recording_start_found {
+                    if let Some((local, offset)) = map.get(&GroupId::Default).and_then(Self::recording_start_from_rtmd) {
+                        util::insert_recording_start(map, Some(local), None, Some(offset));
+                        self.recording_start_found = true;
+                    }
+                }
                 if map.contains_key(&GroupId::Accelerometer) {
                     util::insert_tag(map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
                 }
 
+                for group in [GroupId::Gyroscope, GroupId::Accelerometer] {
+                    let Some(imu) = map.get_mut(&group) else { continue; };
+                    let unit_flag = if group == GroupId::Gyroscope { 0xe438 } else { 0xe448 };
+
+                    // Newer bodies (a7 IV, FX3 etc.) don't write the IMU description tags in every sample,
+                    // so remember the last ones and carry them over to the samples which only contain the data
+                    let description = self.imu_description.entry(group.clone()).or_default();
+                    for id in [TagId::Frequency, TagId::TimeOffset, TagId::Scale, TagId::Orientation, TagId::Unknown(unit_flag)] {
+                        if let Some(v) = imu.get(&id) {
+                            description.insert(id, v.clone());
+                        } else if let Some(v) = description.get(&id) {
+                            imu.insert(id, v.clone());
+                        }
+                    }
+
+                    if group == GroupId::Gyroscope {
+                        if let Some(is_radians) = imu.get_t(TagId::Unknown(0xe438)) as Option<&bool> {
+                            let unit = if *is_radians { "rad/s" } else { "deg/s" };
+                            imu.insert(TagId::Unit, tag!(parsed GroupId::Gyroscope, TagId::Unit, "Gyroscope unit", String, |v| v.to_string(), unit.into(), Vec::new()));
+                        }
+                    }
+                    if !imu.contains_key(&TagId::Orientation) {
+                        if let Some(o) = quirks::imu_orientation(&model_quirks) {
+                            imu.insert(TagId::Orientation, tag!(parsed group.clone(), TagId::Orientation, "IMU orientation", String, |v| v.to_string(), o.into(), Vec::new()));
+                            orientation_quirk_used = true;
+                        }
+                    }
+                }
+
                 if let Some(imager) = map.get_mut(&GroupId::Imager) {
                     if let Some(v) = imager.get_t(TagId::FrameReadoutTime) as Option<&f64> {
                         self.frame_readout_time = Some(*v);
+                        util::push_readout_time(&mut readout_times, sample.timestamp_ms, *v);
                     }
 
                     let mut crop_scale = 1.0;
@@ -133,13 +470,63 @@ impl Sony {
                 }
             }
         }
+        util::insert_readout_time_series(samples, readout_times);
+
+        if orientation_quirk_used {
+            if let Some(map) = samples.first_mut().and_then(|x| x.tag_map.as_mut()) {
+                let applied = model_quirks.into_iter().filter(|x| matches!(x.quirk, quirks::Quirk::ImuOrientation(_))).collect::<Vec<_>>();
+                quirks::insert_tag(map, &applied);
+            }
+        }
+    }
+
+    // Per-frame stabilizer state and the IBIS/OSS shift tables merged into time series in the first sample,
+    // so it's easy to tell whether the footage was already stabilized in camera.
+    // Time offsets in the tables are assumed to be in microseconds from the frame start, like the IMU offsets
+    fn collect_stabilizer_data(samples: &mut Vec<SampleInfo>) {
+        let mut enabled = Vec::new();
+        let mut shifts: std::collections::BTreeMap<GroupId, Vec<TimeVector3<f64>>> = std::collections::BTreeMap::new();
+        for info in samples.iter() {
+            let Some(map) = info.tag_map.as_ref() else { continue; };
+            let t = info.timestamp_ms / 1000.0;
+            if let Ok(v) = map.query::<bool>(GroupId::Default, TagId::ImageStabilizer) {
+                enabled.push(TimeScalar { t, v: *v });
+            }
+            for group in [GroupId::IBIS, GroupId::LensOSS] {
+                if let Ok(table) = map.query::<Vec<TimeVector3<i32>>>(group.clone(), TagId::Data) {
+                    // Empty OSS table is stored as a single entry of -1
+                    let table = table.iter().filter(|x| !(x.t == -1 && x.x == -1 && x.y == -1 && x.z == -1));
+                    shifts.entry(group).or_default().extend(table.map(|x| TimeVector3 {
+                        t: t + x.t as f64 / 1_000_000.0,
+                        x: x.x as f64,
+                        y: x.y as f64,
+                        z: x.z as f64
+                    }));
+                }
+            }
+        }
+        shifts.retain(|_, v| !v.is_empty());
+        if enabled.is_empty() && shifts.is_empty() { return; }
+
+        if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+            samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+        }
+        if let Some(map) = samples[0].tag_map.as_mut() {
+            if !enabled.is_empty() {
+                util::insert_tag(map, tag!(parsed GroupId::IBIS, TagId::Enabled, "Image stabilizer enabled", Vec_TimeScalar_bool, |v| format!("{:?}", v), enabled, vec![]));
+            }
+            for (group, v) in shifts {
+                let desc = if group == GroupId::IBIS { "IBIS shift" } else { "Lens OSS shift" };
+                util::insert_tag(map, tag!(parsed group, TagId::StabilizerShift, desc, Vec_TimeVector3_f64, |v| format!("{:?}", v), v, vec![]));
+            }
+        }
     }
 
-    fn detect_metadata(data: &[u8]) -> bool {
+    pub(crate) fn detect_metadata(data: &[u8]) -> bool {
         data.len() > 0x1C && data[0..2] == [0x00, 0x1C]
     }
 
-    fn parse_metadata(data: &[u8]) -> Result<GroupedTagMap> {
+    pub(crate) fn parse_metadata(data: &[u8]) -> Result<GroupedTagMap> {
         let mut slice = Cursor::new(data);
         let datalen = data.len() as usize;
         let mut map = GroupedTagMap::new();
@@ -161,7 +548,7 @@ impl Sony {
             let len = slice.read_u16::<BigEndian>()? as usize;
             let pos = slice.position() as usize;
             if pos + len > datalen {
-                log::warn!("Invalid tag: {:02x}, len: {}, Available: {}", tag, len, datalen - pos);
+                crate::parse_warning!("Invalid tag: {:02x}, len: {}, Available: {}", tag, len, datalen - pos);
                 // log::warn!("{}", crate::util::to_hex(&data[pos-4..]));
                 break;
             }
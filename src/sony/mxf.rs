@@ -9,17 +9,67 @@ use byteorder::{ ReadBytesExt, BigEndian };
 use crate::*;
 use crate::tags_impl::*;
 
+// Where the parsing of a file which is still being written ended, so it can continue from there when the file is longer
+pub struct ParseState {
+    /// Start of the first KLV packet which wasn't complete yet, or the end of the file
+    pub offset: u64,
+    frame_rate: f64,
+    index: u64,
+    timecode: Option<[u8; 4]>,
+    prev_timecode: Option<(String, i64)>,
+    pub events: Vec<CameraEvent>,
+}
+impl Default for ParseState {
+    fn default() -> Self {
+        Self { offset: 0, frame_rate: 25.0, index: 0, timecode: None, prev_timecode: None, events: Vec::new() }
+    }
+}
+
 pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>, metadata_only: Option<&mut util::VideoMetadata>) -> Result<Vec<SampleInfo>> {
+    let mut state = ParseState::default();
+    let mut samples = parse_packets(stream, size, progress_cb, cancel_flag, metadata_only, &mut state, false)?;
+    insert_events(&mut samples, state.events);
+    Ok(samples)
+}
+
+pub(crate) fn insert_events(samples: &mut Vec<SampleInfo>, events: Vec<CameraEvent>) {
+    if !events.is_empty() {
+        let mut map = GroupedTagMap::new();
+        util::insert_tag(&mut map, tag!(parsed GroupId::Events, TagId::Data, "Timecode discontinuities", Vec_CameraEvent, |v| format!("{:?}", v), events, vec![]));
+        samples.insert(0, SampleInfo { tag_map: Some(map), ..Default::default() });
+    }
+}
+
+/// Parses a file which is still being written, from `state.offset` up to the last complete packet.
+/// `size` is the current size of the file. The timecode discontinuities found in this part are in `state.events`
+pub fn parse_growing<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>, state: &mut ParseState) -> Result<Vec<SampleInfo>> {
+    parse_packets(stream, size, progress_cb, cancel_flag, None, state, true)
+}
+
+// With `growing`, the parsing stops at the first incomplete or unknown packet (not written yet), instead of failing or skipping it
+fn parse_packets<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>, metadata_only: Option<&mut util::VideoMetadata>, state: &mut ParseState, growing: bool) -> Result<Vec<SampleInfo>> {
     let mut stream = std::io::BufReader::with_capacity(128*1024, stream);
+    if growing {
+        stream.seek(SeekFrom::Start(state.offset))?;
+    }
     let mut samples = Vec::new();
 
-    let mut frame_rate = 25.0;
-
-    let mut index = 0;
+    // The state is updated only when the parsing succeeded, so it can be retried after an error
+    let mut offset = state.offset;
+    let mut frame_rate = state.frame_rate;
+    let mut index = state.index;
+    let mut timecode = state.timecode;
+    let mut prev_timecode = state.prev_timecode.clone();
+    let mut events = Vec::new();
     let mut id = [0u8; 16];
-    while let Ok(_) = stream.read_exact(&mut id) {
+    loop {
+        if growing {
+            offset = stream.stream_position()?;
+        }
+        if stream.read_exact(&mut id).is_err() { break; }
         if &id[0..4] != &[0x06, 0x0e, 0x2b, 0x34] {
-            log::warn!("Unknown ID {} at 0x{:08x}", util::to_hex(&id), stream.stream_position()? - 16);
+            if growing { break; }
+            crate::parse_warning!(at stream.stream_position()? - 16; "Unknown ID {}", util::to_hex(&id));
             while let Ok(byte) = stream.read_u8() {
                 if byte == 0x06 {
                     let mut id2 = [0u8; 3];
@@ -34,7 +84,12 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_c
             continue;
         }
 
-        let length = read_ber(&mut stream)?;
+        let length = match read_ber(&mut stream) {
+            Ok(x) => x,
+            Err(_) if growing => break,
+            Err(e) => return Err(e)
+        };
+        if growing && stream.stream_position()? + length as u64 > size as u64 { break; }
 
         if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
         if size > 0 {
@@ -43,10 +98,7 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_c
 
         // log::debug!("{}: {}", util::to_hex(&id), length);
 
-        if id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0D, 0x01, 0x01, 0x01, 0x01, 0x01, 0x28, 0x00] || // CDCIDescriptor
-           id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x51, 0x00] || // MPEGPictureEssenceDescriptor
-           id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x5f, 0x00] || // VC1VideoDescriptor
-           id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0D, 0x01, 0x01, 0x01, 0x01, 0x01, 0x29, 0x00] { // RGBAPictureEssenceDescriptor
+        if is_picture_descriptor(&id) {
             let mut data = vec![0; length];
             stream.read_exact(&mut data)?;
             if let Ok(data) = parse_set(&data) {
@@ -67,11 +119,12 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_c
         }
 
         if id == [0x06, 0x0e, 0x2b, 0x34, 0x01, 0x02, 0x01, 0x01, 0x0d, 0x01, 0x03, 0x01, 0x17, 0x01, 0x02, 0x01] { // Metadata, Ancillary, SMPTE ST 436
+            let file_position = stream.stream_position()?;
             let mut data = vec![0; length];
             stream.read_exact(&mut data)?;
             let data = parse_ancillary(&data)?;
 
-            if let Ok(map) = super::Sony::parse_metadata(&data) {
+            if let Ok(mut map) = super::Sony::parse_metadata(&data) {
                 if let Some(group) = map.get(&GroupId::Default) {
                     if let Some(val) = group.get(&TagId::FrameRate) {
                         match &val.value {
@@ -83,26 +136,91 @@ pub fn parse<T: Read + Seek, F: Fn(f64)>(stream: &mut T, size: usize, progress_c
                 }
                 let duration_ms = 1000.0 / frame_rate;
 
+                if let Some(tc) = timecode.take() {
+                    let (text, frame) = decode_timecode(&tc, frame_rate);
+                    if let Some((prev_text, prev_frame)) = &prev_timecode {
+                        if frame != prev_frame + 1 {
+                            events.push(CameraEvent {
+                                t: index as f64 * duration_ms / 1000.0,
+                                severity: EventSeverity::Warning,
+                                code: "TimecodeDiscontinuity".into(),
+                                text: format!("Timecode jumped from {prev_text} to {text} at frame {index}"),
+                            });
+                        }
+                    }
+                    util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("Timecode".into()), "Timecode", String, |v| v.clone(), text.clone(), vec![]));
+                    prev_timecode = Some((text, frame));
+                }
+                // Frame index which is continuous even if the timecode isn't
+                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("FrameIndex".into()), "Continuous frame index", u64, |v| v.to_string(), index, vec![]));
+
                 // log::debug!("Index: {}, Duration: {}, Frame rate: {}, Timestamp: {}", index, duration_ms, frame_rate, index as f64 * duration_ms);
 
                 samples.push(SampleInfo {
                     sample_index: index,
                     duration_ms,
                     timestamp_ms: index as f64 * duration_ms,
+                    file_position,
+                    sample_size: length,
                     tag_map: Some(map),
                     ..Default::default()
                 });
                 index += 1;
             }
+        } else if id[..14] == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x05, 0x01, 0x01, 0x0d, 0x01, 0x03, 0x01, 0x04, 0x01] { // System metadata pack, SMPTE 331M
+            let mut data = vec![0; length];
+            stream.read_exact(&mut data)?;
+            timecode = parse_system_item_timecode(&data);
         } else {
             stream.seek(SeekFrom::Current(length as i64))?;
         }
     }
 
+    *state = ParseState { offset, frame_rate, index, timecode, prev_timecode, events };
+
     Ok(samples)
 }
 
-fn read_ber<T: Read + Seek>(stream: &mut T) -> Result<usize> {
+// Returns the SMPTE 12M timecode of the content package, from the user date/time stamp or the creation date/time stamp
+fn parse_system_item_timecode(data: &[u8]) -> Option<[u8; 4]> {
+    // bitmap (1), rate (1), type (1), channel handle (2), continuity count (2), label (16), creation stamp (17), user stamp (17)
+    if data.len() < 57 { return None; }
+    let bitmap = data[0];
+    for (present, offset) in [(bitmap & 0x10 != 0, 40), (bitmap & 0x20 != 0, 23)] {
+        if present && data[offset] == 0x81 { // SMPTE 12M timecode
+            return data[offset + 1..offset + 5].try_into().ok();
+        }
+    }
+    None
+}
+
+// Returns the timecode as HH:MM:SS:FF (;FF for drop frame) and the frame number counted from 00:00:00:00
+fn decode_timecode(tc: &[u8; 4], frame_rate: f64) -> (String, i64) {
+    let bcd = |b: u8| ((b >> 4) * 10 + (b & 0x0f)) as i64;
+    let frames  = bcd(tc[0] & 0x3f);
+    let seconds = bcd(tc[1] & 0x7f);
+    let minutes = bcd(tc[2] & 0x7f);
+    let hours   = bcd(tc[3] & 0x3f);
+    let drop_frame = tc[0] & 0x40 != 0;
+
+    let nominal_fps = (frame_rate.round() as i64).max(1);
+    let total_minutes = hours * 60 + minutes;
+    let mut frame = (total_minutes * 60 + seconds) * nominal_fps + frames;
+    if drop_frame {
+        // Drop frame skips 2 frames (at 30 fps) every minute except every tenth minute
+        frame -= nominal_fps / 15 * (total_minutes - total_minutes / 10);
+    }
+    (format!("{:02}:{:02}:{:02}{}{:02}", hours, minutes, seconds, if drop_frame { ';' } else { ':' }, frames), frame)
+}
+
+pub(crate) fn is_picture_descriptor(id: &[u8; 16]) -> bool {
+    *id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0D, 0x01, 0x01, 0x01, 0x01, 0x01, 0x28, 0x00] || // CDCIDescriptor
+    *id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x51, 0x00] || // MPEGPictureEssenceDescriptor
+    *id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0d, 0x01, 0x01, 0x01, 0x01, 0x01, 0x5f, 0x00] || // VC1VideoDescriptor
+    *id == [0x06, 0x0e, 0x2b, 0x34, 0x02, 0x53, 0x01, 0x01, 0x0D, 0x01, 0x01, 0x01, 0x01, 0x01, 0x29, 0x00]    // RGBAPictureEssenceDescriptor
+}
+
+pub(crate) fn read_ber<T: Read + Seek>(stream: &mut T) -> Result<usize> {
     let mut size = stream.read_u8()? as usize;
 
     if size & 0x80 != 0 {
@@ -116,7 +234,7 @@ fn read_ber<T: Read + Seek>(stream: &mut T) -> Result<usize> {
     Ok(size)
 }
 
-fn parse_ancillary(buffer: &[u8]) -> Result<Vec<u8>> {
+pub(crate) fn parse_ancillary(buffer: &[u8]) -> Result<Vec<u8>> {
     let mut slice = Cursor::new(&buffer);
 
     let count = slice.read_u16::<BigEndian>()?; // number of lines
@@ -153,7 +271,7 @@ fn parse_ancillary(buffer: &[u8]) -> Result<Vec<u8>> {
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
-enum MxfMetaTag {
+pub(crate) enum MxfMetaTag {
     Duration,
     RoundedTimecodeBase,
     StartTimecode,
@@ -174,7 +292,7 @@ enum MxfMetaTag {
     ColorRange
 }
 
-fn parse_set(buffer: &[u8]) -> Result<BTreeMap<MxfMetaTag, serde_json::Value>> {
+pub(crate) fn parse_set(buffer: &[u8]) -> Result<BTreeMap<MxfMetaTag, serde_json::Value>> {
     let mut slice = Cursor::new(&buffer);
     let mut map = BTreeMap::<MxfMetaTag, serde_json::Value>::new();
 
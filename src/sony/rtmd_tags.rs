@@ -349,6 +349,13 @@ pub fn get_tag(tag: u16, tag_data: &[u8]) -> TagDescription {
         0xe301 => tag!(Exposure, TagId::Custom("ISOSensitivity".into()), "ISO Sensitivity", u32, "{}", |d| d.read_u32::<BigEndian>(), tag_data),
         0xe302 => tag!(Default,  TagId::Custom("GainSettingType".into()), "Gain Setting Type", String, "{}", |d| Ok(String::from(if d.read_u8()? == 0 { "dB" } else { "ISO" })), tag_data),
         0xe303 => tag!(Default,  TagId::Custom("LightingPreset".into()), "Lighting Preset", u8, "{}", |d| d.read_u8(), tag_data),
+        // VENICE and BURANO
+        0xe305 => tag!(Imager,   AnamorphicSqueeze, "Anamorphic squeeze ratio", f64, "{:.2}x", |d| read_rational(d), tag_data),
+        0xe306 => tag!(Exposure, TagId::Custom("BaseISOMode".into()), "Dual base ISO mode", u8, |v| { match v {
+            0 => "Low base".into(),
+            1 => "High base".into(),
+            _ => format!("{}", v)
+        } }, |d| d.read_u8(), tag_data),
         0x8119 => tag!(Exposure, TagId::Custom("ISOValue4".into()), "ISO value", u32, "{}", |d| d.read_u32::<BigEndian>(), tag_data),
         0x811e => tag!(Exposure, TagId::Custom("ISOValue5".into()), "ISO value", u32, "{}", |d| d.read_u32::<BigEndian>(), tag_data),
         0xe304 => tag!(Default, CaptureTimestamp, "Capture timestamp", u64, |&v| chrono::TimeZone::timestamp_opt(&chrono::Utc, v as i64, 0).single().map(|x| x.to_string()).unwrap_or_default(), |x| {
@@ -734,6 +741,7 @@ pub fn get_tag(tag: u16, tag_data: &[u8]) -> TagDescription {
             b'M' => "Magnetic direction".into(),
             _ => format!("{}", *v as char)
         }, |d| d.read_u8(), tag_data),
+        0x850f => tag!(GPS, TagId::Custom("GPSTrack".into()), "GPS track", f64, "{}", |d| read_rational(d), tag_data),
 
         0x8512 => tag!(GPS, TagId::Custom("GPSMapDatum".into()),  "GPS map datum",  String, |v| v.to_string(), |d| read_utf8(d), tag_data),
         0x851d => tag!(GPS, TagId::Custom("GPSDateStamp".into()), "GPS date stamp", String, |v| v.to_string(), |d| read_utf8(d), tag_data), // TODO: Exif::ExifDate
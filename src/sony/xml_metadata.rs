@@ -18,10 +18,31 @@ pub fn parse_from_file(stream: &mut std::fs::File) -> std::io::Result<Metadata>
     Err(std::io::ErrorKind::NotFound.into())
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct Metadata {
     pub manufacturer: String,
     pub model: String,
     pub frame_count: usize,
+    pub lens: Option<String>,
+    pub serial: Option<String>,
+    /// HH:MM:SS:FF
+    pub start_timecode: Option<String>,
+}
+
+// LTC value is stored as hex BCD in the `ffssmmhh` order, with the flag bits in the upper bits of each field
+fn decode_ltc(v: &str) -> Option<String> {
+    if v.len() != 8 { return None; }
+    let b = (0..4).map(|i| u8::from_str_radix(&v[i * 2..i * 2 + 2], 16).ok()).collect::<Option<Vec<u8>>>()?;
+    let bcd = |x: u8| (x >> 4) * 10 + (x & 0x0f);
+    Some(format!("{:02}:{:02}:{:02}:{:02}", bcd(b[3] & 0x3f), bcd(b[2] & 0x7f), bcd(b[1] & 0x7f), bcd(b[0] & 0x3f)))
+}
+
+/// Finds the non-realtime metadata file next to the clip: `C0001.MP4` -> `C0001M01.XML`, used in the `M4ROOT` and `XDROOT` card structures
+pub fn find_sidecar(path: &str) -> Option<String> {
+    let filename = crate::filesystem::get_filename(path);
+    let (stem, _) = filename.rsplit_once('.')?;
+    let name = format!("{stem}M01.XML");
+    crate::filesystem::list_folder(&crate::filesystem::get_folder(path)).into_iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)).map(|(_, p)| p)
 }
 
 pub fn parse(data: &[u8]) -> Metadata {
@@ -31,6 +52,9 @@ pub fn parse(data: &[u8]) -> Metadata {
     let mut frame_count = 0usize;
     let mut model = String::new();
     let mut manufacturer = String::new();
+    let mut lens = None;
+    let mut serial = None;
+    let mut start_timecode = None;
 
     loop {
         match reader.read_event_into(&mut buf) {
@@ -38,18 +62,34 @@ pub fn parse(data: &[u8]) -> Metadata {
                 if e.name().as_ref() == b"Duration" || e.name().as_ref() == b"Device" {
                     for ox in e.attributes() {
                         if let Ok(x) = ox {
-                            if x.key.as_ref() == b"value"        { frame_count = String::from_utf8_lossy(&x.value).parse::<usize>().unwrap(); }
+                            if x.key.as_ref() == b"value"        { frame_count = String::from_utf8_lossy(&x.value).parse::<usize>().unwrap_or_default(); }
                             if x.key.as_ref() == b"modelName"    { model = String::from_utf8_lossy(&x.value).into(); }
                             if x.key.as_ref() == b"manufacturer" { manufacturer = String::from_utf8_lossy(&x.value).into(); }
+                            if x.key.as_ref() == b"serialNo"     { serial = Some(String::from_utf8_lossy(&x.value).into()); }
                         }
                     }
                 }
+                if e.name().as_ref() == b"Lens" {
+                    for x in e.attributes().flatten() {
+                        if x.key.as_ref() == b"modelName" { lens = Some(String::from_utf8_lossy(&x.value).into()); }
+                    }
+                }
+                if e.name().as_ref() == b"LtcChange" && start_timecode.is_none() {
+                    let attrs = e.attributes().flatten().map(|x| (x.key.as_ref().to_vec(), String::from_utf8_lossy(&x.value).to_string())).collect::<Vec<_>>();
+                    let attr = |k: &[u8]| attrs.iter().find(|x| x.0 == k).map(|x| x.1.clone());
+                    if attr(b"frameCount").as_deref() == Some("0") {
+                        start_timecode = attr(b"value").and_then(|v| decode_ltc(&v));
+                    }
+                }
             },
             Ok(Event::Eof) => break, // exits the loop when reaching end of file
-            Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+            Err(e) => {
+                log::error!("XML error at position {}: {:?}", reader.buffer_position(), e);
+                break;
+            },
             _ => (), // There are several other `Event`s we do not consider here
         }
         buf.clear();
     }
-    Metadata { manufacturer, model, frame_count }
+    Metadata { manufacturer, model, frame_count, lens, serial, start_timecode }
 }
@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Telemetry embedded as a text subtitle track in MP4/MOV files, eg. DJI goggles recordings with the OSD data:
+// signal:4 ch:5 flightTime:186 uavBat:15.2V glsBat:15.6V uavBatCells:4 glsBatCells:4 delay:28ms bitrate:25.4Mbps rcSignal:0
+// Every numeric `key:value` pair is converted to a time series in the `OSD` group, the full text is kept in `OSD/Data`
+
+use std::io::*;
+use std::sync::{ Arc, atomic::AtomicBool };
+use std::collections::BTreeMap;
+
+use crate::tags_impl::*;
+use crate::*;
+use memchr::memmem;
+
+#[derive(Default)]
+pub struct SubtitleTelemetry {
+    pub model: Option<String>,
+}
+
+impl SubtitleTelemetry {
+    pub fn camera_type(&self) -> String {
+        "Subtitle telemetry".to_owned()
+    }
+    pub fn has_accurate_timestamps(&self) -> bool {
+        false
+    }
+    pub fn possible_extensions() -> Vec<&'static str> {
+        vec!["mp4", "mov"]
+    }
+    pub fn frame_readout_time(&self) -> Option<f64> {
+        None
+    }
+    pub fn normalize_imu_orientation(v: String) -> String {
+        v
+    }
+
+    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+        // Regular subtitles are common in phone videos, so we also require the OSD-like text somewhere in the read part of `mdat`
+        if memmem::find(buffer, b"tx3g").is_some() && Self::count_key_values(buffer) >= 8 {
+            Some(Self::default())
+        } else {
+            None
+        }
+    }
+
+    fn count_key_values(buffer: &[u8]) -> usize {
+        memchr::memchr_iter(b':', buffer).filter(|&i| {
+            i > 0 && buffer[i - 1].is_ascii_alphabetic() && buffer.get(i + 1).map(|x| x.is_ascii_digit() || *x == b'-').unwrap_or_default()
+        }).count()
+    }
+
+    /// Numeric `key:value` pairs of a single subtitle line. Supports `key:value`, `key: value` and `[key : value]`, HTML tags are ignored
+    pub fn parse_line(text: &str) -> Vec<(String, f64)> {
+        let mut clean = String::with_capacity(text.len());
+        let mut in_tag = false;
+        for c in text.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => { in_tag = false; clean.push(' '); },
+                '[' | ']' | ',' | ';' | '|' if !in_tag => clean.push(' '),
+                _ if !in_tag => clean.push(c),
+                _ => { }
+            }
+        }
+        let clean = clean.replace(" :", ":").replace(": ", ":");
+
+        let mut ret = Vec::new();
+        for token in clean.split_whitespace() {
+            let Some((key, value)) = token.split_once(':') else { continue; };
+            if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') { continue; }
+            if let Some(v) = Self::parse_number(value) {
+                ret.push((key.to_owned(), v));
+            }
+        }
+        ret
+    }
+
+    // Leading number of `v`, with the unit suffix removed. Fractions like `1/100.0` are evaluated
+    fn parse_number(v: &str) -> Option<f64> {
+        if let Some((a, b)) = v.split_once('/') {
+            let (a, b) = (Self::parse_number(a)?, Self::parse_number(b)?);
+            return if b != 0.0 { Some(a / b) } else { None };
+        }
+        let end = v.char_indices().find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && (*c == '-' || *c == '+')))).map(|x| x.0).unwrap_or(v.len());
+        v[..end].parse::<f64>().ok()
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        let mut series: BTreeMap<String, Vec<TimeScalar<f64>>> = BTreeMap::new();
+        let mut lines = Vec::new();
+        let mut last_timestamp = 0.0;
+
+        util::get_metadata_track_samples_with_subtitles(stream, size, true, true, |info: SampleInfo, data: &[u8], file_position: u64, _video_md: Option<&VideoMetadata>| {
+            if size > 0 {
+                progress_cb(file_position as f64 / size as f64);
+            }
+            let Ok(text) = std::str::from_utf8(data) else { return; };
+            let values = Self::parse_line(text);
+            if values.is_empty() { return; }
+
+            let t = info.timestamp_ms / 1000.0;
+            last_timestamp = info.timestamp_ms + info.duration_ms;
+            for (key, v) in values {
+                series.entry(key).or_default().push(TimeScalar { t, v });
+            }
+            lines.push(TimeScalar { t, v: serde_json::Value::String(text.to_owned()) });
+        }, cancel_flag)?;
+
+        if series.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "No telemetry found in the subtitle tracks"));
+        }
+
+        let group = GroupId::Custom("OSD".into());
+        let mut map = GroupedTagMap::new();
+        util::insert_tag(&mut map, tag!(parsed group.clone(), TagId::Data, "Subtitle text", Vec_TimeScalar_Json, |v| format!("{:?}", v), lines, vec![]));
+        for (key, v) in series {
+            util::insert_tag(&mut map, tag!(parsed group.clone(), TagId::Custom(key.clone()), key.as_str(), Vec_TimeScalar_f64, |v| format!("{:?}", v), v, vec![]));
+        }
+
+        Ok(vec![
+            SampleInfo { timestamp_ms: 0.0, duration_ms: last_timestamp, tag_map: Some(map), ..Default::default() }
+        ])
+    }
+}
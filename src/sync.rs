@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// Estimation of the time offset between the gyroscope and the video.
+// The caller provides the per-frame camera motion (eg. rotations estimated with optical flow, converted to angular velocity),
+// and the offset is found by correlating it with the resampled gyroscope data.
+// For logs recorded separately from the video (eg. blackbox), `align_to_paired_video` gives a rough offset from the start times of both,
+// which can be used as `center_offset_ms` to search only a narrow range around it.
+// The pairing reads the file system, so it's not available with the `no-fs` feature
+
+#[cfg(not(feature = "no-fs"))]
+use std::path::{ Path, PathBuf };
+
+use crate::tags_impl::*;
+use crate::{ util, Input };
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Offsets from `center_offset_ms - max_offset_ms` to `center_offset_ms + max_offset_ms` are evaluated
+    pub max_offset_ms: f64,
+    /// Center of the evaluated range, eg. `VideoAlignment::offset_ms`
+    pub center_offset_ms: f64,
+    /// Step of the search over the whole range
+    pub coarse_step_ms: f64,
+    /// Step of the refinement around the best coarse offset
+    pub fine_step_ms: f64,
+    /// Minimum number of motion samples which have to overlap with the gyroscope data for an offset to be considered
+    pub min_overlap: usize,
+}
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            max_offset_ms: 2000.0,
+            center_offset_ms: 0.0,
+            coarse_step_ms: 10.0,
+            fine_step_ms: 0.5,
+            min_overlap: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncResult {
+    /// Offset to add to the video timestamps to get the matching gyroscope timestamps, in milliseconds
+    pub offset_ms: f64,
+    /// Normalized cross-correlation at the found offset, from -1 to 1
+    pub correlation: f64,
+}
+
+/// Linearly interpolated value of `data` at `t` (in seconds). `data` has to be sorted by timestamp.
+/// Returns `None` if `t` is outside of the data range
+pub fn interpolate(data: &[TimeVector3<f64>], t: f64) -> Option<[f64; 3]> {
+    let i = data.partition_point(|x| x.t < t);
+    let b = data.get(i)?;
+    if b.t == t { return Some([b.x, b.y, b.z]); }
+    let a = data.get(i.checked_sub(1)?)?;
+    let f = (t - a.t) / (b.t - a.t);
+    Some([
+        a.x + (b.x - a.x) * f,
+        a.y + (b.y - a.y) * f,
+        a.z + (b.z - a.z) * f,
+    ])
+}
+
+/// Normalized cross-correlation of `motion` with `gyro` shifted by `offset_ms`. Both series have to be in the same axes and units
+fn correlation(gyro: &[TimeVector3<f64>], motion: &[TimeVector3<f64>], offset_ms: f64, min_overlap: usize) -> Option<f64> {
+    let pairs = motion.iter().filter_map(|m| {
+        let g = interpolate(gyro, m.t + offset_ms / 1000.0)?;
+        Some((g, [m.x, m.y, m.z]))
+    }).collect::<Vec<_>>();
+    if pairs.len() < min_overlap.max(2) { return None; }
+
+    let n = pairs.len() as f64;
+    let mut mean_g = [0.0; 3];
+    let mut mean_m = [0.0; 3];
+    for (g, m) in &pairs {
+        for (mean, v) in mean_g.iter_mut().zip(g) { *mean += v / n; }
+        for (mean, v) in mean_m.iter_mut().zip(m) { *mean += v / n; }
+    }
+    let (mut cov, mut var_g, mut var_m) = (0.0, 0.0, 0.0);
+    for (g, m) in &pairs {
+        for (((g, m), mg), mm) in g.iter().zip(m).zip(&mean_g).zip(&mean_m) {
+            let dg = g - mg;
+            let dm = m - mm;
+            cov += dg * dm;
+            var_g += dg * dg;
+            var_m += dm * dm;
+        }
+    }
+    if var_g <= 0.0 || var_m <= 0.0 { return None; }
+    Some(cov / (var_g * var_m).sqrt())
+}
+
+fn search(gyro: &[TimeVector3<f64>], motion: &[TimeVector3<f64>], from_ms: f64, to_ms: f64, step_ms: f64, min_overlap: usize) -> Option<SyncResult> {
+    let steps = ((to_ms - from_ms) / step_ms).floor() as usize;
+    (0..=steps)
+        .map(|i| from_ms + i as f64 * step_ms)
+        .filter_map(|offset_ms| Some(SyncResult { offset_ms, correlation: correlation(gyro, motion, offset_ms, min_overlap)? }))
+        .max_by(|a, b| a.correlation.total_cmp(&b.correlation))
+}
+
+/// Finds the offset between the gyroscope data and the per-frame angular velocity estimated from the video.
+/// Timestamps of both series are in seconds, the values have to use the same axes and units.
+/// Returns `None` if the series don't overlap at any evaluated offset or have no motion
+pub fn estimate_offset(gyro: &[TimeVector3<f64>], motion: &[TimeVector3<f64>], options: &SyncOptions) -> Option<SyncResult> {
+    if gyro.len() < 2 || motion.is_empty() || options.coarse_step_ms <= 0.0 || options.fine_step_ms <= 0.0 { return None; }
+
+    let mut gyro = gyro.to_vec();
+    gyro.sort_by(|a, b| a.t.total_cmp(&b.t));
+
+    let (from_ms, to_ms) = (options.center_offset_ms - options.max_offset_ms, options.center_offset_ms + options.max_offset_ms);
+    let coarse = search(&gyro, motion, from_ms, to_ms, options.coarse_step_ms, options.min_overlap)?;
+    let fine = search(&gyro, motion, coarse.offset_ms - options.coarse_step_ms, coarse.offset_ms + options.coarse_step_ms, options.fine_step_ms, options.min_overlap);
+    Some(fine.filter(|x| x.correlation >= coarse.correlation).unwrap_or(coarse))
+}
+
+/// Same as `estimate_offset`, using the gyroscope data of `input` from `util::normalized_imu`.
+/// `motion` has to be in deg/s, in the same axes as the normalized gyroscope
+pub fn estimate_input_offset(input: &Input, motion: &[TimeVector3<f64>], options: &SyncOptions) -> Option<SyncResult> {
+    let imu = util::normalized_imu(input, None).ok()?;
+    let gyro = imu.iter().filter_map(|x| {
+        let g = x.gyro?;
+        Some(TimeVector3 { t: x.timestamp_ms / 1000.0, x: g[0], y: g[1], z: g[2] })
+    }).collect::<Vec<_>>();
+    estimate_offset(&gyro, motion, options)
+}
+
+#[cfg(not(feature = "no-fs"))]
+#[derive(Debug, Clone, Default)]
+pub struct VideoAlignment {
+    pub video_path: PathBuf,
+    /// Offset to add to the (stretched) video timestamps to get the matching gyroscope timestamps, in milliseconds. Same meaning as `SyncResult::offset_ms`
+    pub offset_ms: f64,
+    /// Factor to multiply the video timestamps with before adding the offset.
+    /// Videos stored with a fractional frame rate (29.97, 59.94 etc.) are assumed to be captured at the integer one, which is common for FPV cameras
+    pub stretch: f64,
+    /// Start of the video and of the log, as unix timestamps in seconds
+    pub video_start: f64,
+    pub log_start: f64,
+    /// `log_header` if the log start is from the log (eg. blackbox `Log start datetime`), `file_time` if it's from the modification time of the log file
+    pub log_start_source: &'static str,
+}
+
+/// Video next to `log_path` with the same file name, eg. `LOG00001.bbl` -> `LOG00001.mp4`
+#[cfg(not(feature = "no-fs"))]
+pub fn paired_video(log_path: &Path) -> Option<PathBuf> {
+    ["mp4", "MP4", "mov", "MOV"].iter()
+        .map(|ext| log_path.with_extension(ext))
+        .find(|x| x.is_file())
+}
+
+#[cfg(not(feature = "no-fs"))]
+fn modified_time(path: &Path) -> Option<f64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs_f64())
+}
+
+/// Aligns the log `input` (parsed from `log_path`) with the video returned by `paired_video`, from the start times of both.
+/// Video files are written until the end of the recording, so the video start is the modification time minus the duration.
+/// The log start is taken from the `Log start datetime` header if the flight controller had the time, otherwise from the modification time of the log in the same way.
+/// File times have a precision of seconds at best, so the result should be refined with `estimate_offset` with the offset as `center_offset_ms`
+#[cfg(not(feature = "no-fs"))]
+pub fn align_to_paired_video(input: &Input, log_path: &Path) -> Option<VideoAlignment> {
+    let video_path = paired_video(log_path)?;
+    let video_size = std::fs::metadata(&video_path).ok()?.len() as usize;
+    let mut file = std::io::BufReader::new(std::fs::File::open(&video_path).ok()?);
+    let md = util::get_video_metadata(&mut file, video_size).ok()?;
+
+    let stretch = if md.fps > 0.0 && (md.fps * 1.001 - md.fps.round()).abs() < 0.01 { // NTSC rates, N / 1.001
+        md.fps / md.fps.round()
+    } else {
+        1.0
+    };
+    let video_start = modified_time(&video_path)? - md.duration_s * stretch;
+
+    let samples = input.samples.as_ref()?;
+    let first = samples.iter().find(|x| x.tag_map.is_some())?;
+    let last = samples.iter().rev().find(|x| x.tag_map.is_some())?;
+    let header_start = first.tag_map.as_ref()
+        .and_then(|map| map.get(&GroupId::Default)?.get_t(TagId::Metadata) as Option<&serde_json::Value>)
+        .and_then(|md| chrono::DateTime::parse_from_rfc3339(md.get("Log start datetime")?.as_str()?.trim()).ok())
+        .map(|dt| dt.timestamp_micros() as f64 / 1_000_000.0)
+        .filter(|x| *x > 0.0); // Year 0000 if the flight controller didn't have the time
+    let (log_start, log_start_source) = match header_start {
+        Some(v) => (v, "log_header"),
+        None => (modified_time(log_path)? - (last.timestamp_ms + last.duration_ms - first.timestamp_ms) / 1000.0, "file_time")
+    };
+
+    Some(VideoAlignment {
+        video_path,
+        offset_ms: (video_start - log_start) * 1000.0,
+        stretch,
+        video_start,
+        log_start,
+        log_start_source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(t: f64) -> TimeVector3<f64> {
+        TimeVector3 { t, x: (3.1 * t).sin() + 0.5 * (7.3 * t).sin(), y: (2.3 * t).cos(), z: (5.7 * t).sin() * (1.1 * t).cos() }
+    }
+    // Gyroscope at 1 kHz for 10 s, and the motion at 30 fps from 1 s to 9 s, delayed by `shift_ms`
+    fn series(shift_ms: f64) -> (Vec<TimeVector3<f64>>, Vec<TimeVector3<f64>>) {
+        let gyro = (0..10000).map(|i| signal(i as f64 / 1000.0)).collect();
+        let motion = (30..270).map(|i| {
+            let t = i as f64 / 30.0;
+            TimeVector3 { t, ..signal(t + shift_ms / 1000.0) }
+        }).collect();
+        (gyro, motion)
+    }
+
+    #[test]
+    fn interpolate_edges() {
+        let data = [TimeVector3 { t: 0.0, x: 0.0, y: 0.0, z: 0.0 }, TimeVector3 { t: 1.0, x: 2.0, y: 4.0, z: 6.0 }];
+        assert_eq!(interpolate(&data, 0.0), Some([0.0, 0.0, 0.0]));
+        assert_eq!(interpolate(&data, 0.5), Some([1.0, 2.0, 3.0]));
+        assert_eq!(interpolate(&data, 1.0), Some([2.0, 4.0, 6.0]));
+        assert_eq!(interpolate(&data, -0.1), None);
+        assert_eq!(interpolate(&data, 1.1), None);
+        assert_eq!(interpolate(&[], 0.0), None);
+    }
+
+    #[test]
+    fn coarse_offset() {
+        let (gyro, motion) = series(250.0);
+        let res = estimate_offset(&gyro, &motion, &SyncOptions { fine_step_ms: 10.0, ..Default::default() }).unwrap();
+        assert!((res.offset_ms - 250.0).abs() < 1e-9, "{res:?}");
+        assert!(res.correlation > 0.999);
+    }
+
+    #[test]
+    fn fine_offset() {
+        let (gyro, motion) = series(123.4);
+        let coarse = estimate_offset(&gyro, &motion, &SyncOptions { fine_step_ms: 10.0, ..Default::default() }).unwrap();
+        assert!((coarse.offset_ms - 120.0).abs() < 1e-9, "{coarse:?}");
+        let fine = estimate_offset(&gyro, &motion, &SyncOptions::default()).unwrap();
+        assert!((fine.offset_ms - 123.4).abs() <= 0.5, "{fine:?}");
+        assert!(fine.correlation >= coarse.correlation);
+    }
+
+    #[test]
+    fn no_overlap_or_motion() {
+        let (gyro, mut motion) = series(0.0);
+        let far = motion.iter().map(|x| TimeVector3 { t: x.t + 100.0, ..x.clone() }).collect::<Vec<_>>();
+        assert!(estimate_offset(&gyro, &far, &SyncOptions::default()).is_none());
+
+        for m in motion.iter_mut() { (m.x, m.y, m.z) = (1.0, 1.0, 1.0); }
+        assert!(estimate_offset(&gyro, &motion, &SyncOptions::default()).is_none());
+    }
+}
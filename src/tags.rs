@@ -1,95 +1,6 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 // Copyright © 2021 Adrian <adrian.eddy at gmail>
 
-declare_groups! {
-    Default,
-    Exposure,
-    Autofocus,
-    Colors,
-    Lens,
-    GPS,
-    Gyroscope,
-    Accelerometer,
-    Magnetometer,
-    Quaternion,
-    Position3D,
-    IBIS,
-    Imager,
-    LensOSS,
-    GravityVector,
-    CameraOrientation,
-    ImageOrientation,
-    Cooke,
-}
-
-declare_ids! {
-    Metadata,
-    IrisFStop,
-    IrisTStop,
-    FocusDistance,
-    MacroEnabled,
-    FocalLength,
-    LensZoom35mm,
-    LensZoomNative,
-    OpticalZoomPercent,
-    LensAttributes,
-    IrisRingPosition,
-    FocusRingPosition,
-    ZoomRingPosition,
-    ColorPrimaries,
-    CodingEquation,
-    CaptureGammaEquation,
-    AutoExposureMode,
-    AutoFocusMode,
-    ColorCorrectionSetting,
-    NDFilterSetting,
-    SensorWidth,
-    SensorHeight,
-    FrameRate,
-    SensorReadoutMode,
-    ShutterAngle,
-    ShutterSpeed,
-    ISOValue,
-    AutoWBMode,
-    WhiteBalance,
-    MasterBlackLevel,
-    KneePoint,
-    KneeSlope,
-    LuminanceDynamicRange,
-    CameraAttributes,
-    ColorMatrix,
-    GroupIdentifier,
-    ImageStabilizer,
-    CaptureTimestamp,
-    FirstFrameTimestamp,
-    FrameReadoutTime,
-    ExposureTime,
-    TimeOffset,
-    SensorSizePixels,
-    PixelPitch,
-    CaptureAreaOrigin,
-    CaptureAreaSize,
-    Shading,
-    Distortion,
-
-    Name,
-    Enabled,
-    Data,
-    Data2,
-    Unit,
-    Matrix,
-    Temperature,
-    Scale,
-    Frequency,
-    TimestampMs,
-    TimestampUs,
-    Offset,
-    Count,
-    Orientation,
-    OrientationIn,
-    OrientationOut,
-}
-
 declare_types! {
      u8: u8,   i8: i8,
     u16: u16, i16: i16,
@@ -104,11 +15,15 @@ declare_types! {
     f64x3:  (f64, f64, f64),
 
     GpsData: GpsData,
+    DigitalLens: DigitalLens,
 
     Vec_Quaternioni16: Vec<Quaternion<i16>>,
     Vec_TimeScalar_f64: Vec<TimeScalar<f64>>,
     Vec_TimeScalar_i64: Vec<TimeScalar<i64>>,
+    Vec_TimeScalar_bool: Vec<TimeScalar<bool>>,
     Vec_GpsData: Vec<GpsData>,
+    Vec_CameraEvent: Vec<CameraEvent>,
+    Vec_MarkerData: Vec<MarkerData>,
 
     Json:                serde_json::Value,
     Vec_Json:            Vec<serde_json::Value>,
@@ -4,98 +4,15 @@
 use serde::Serialize;
 use std::collections::*;
 
-macro_rules! declare_groups {
-    ($($field:ident),*,) => {
-        #[allow(dead_code)]
-        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
-        pub enum GroupId {
-            $($field,)*
-            UnknownGroup(u32),
-            Custom(String),
-            Any // For filtering, shouldn't be used directly
-        }
-        impl Serialize for GroupId {
-            fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
-                match self {
-                    GroupId::UnknownGroup(x) => s.serialize_str(&format!("0x{:x}", x)),
-                    GroupId::Custom(x)       => s.serialize_str(x),
-                    GroupId::Any             => s.serialize_str("*"),
-                    $(GroupId::$field        => s.serialize_str(stringify!($field)),)*
-                }
-            }
-        }
-        impl std::fmt::Display for GroupId {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                match self {
-                    GroupId::UnknownGroup(x) => f.write_str(&format!("0x{:x}", x)),
-                    GroupId::Custom(x)       => f.write_str(x),
-                    GroupId::Any             => f.write_str("*"),
-                    $(GroupId::$field        => f.write_str(stringify!($field)),)*
-                }
-            }
-        }
-        impl std::str::FromStr for GroupId {
-            type Err = std::num::ParseIntError;
+// Ids and the plain value types are in the `no_std` core crate, so they can be shared with the firmware of the recorders
+pub use telemetry_parser_core::*;
 
-            fn from_str(s: &str) -> Result<Self, Self::Err> {
-                Ok(match s {
-                    $(stringify!($field) => GroupId::$field,)*
-                    "*" => GroupId::Any,
-                    _ if s.starts_with("0x") => GroupId::UnknownGroup(u32::from_str_radix(&s[2..], 16)?),
-                    _ => GroupId::Custom(s.to_string())
-                })
-            }
-        }
-    }
-}
-
-macro_rules! declare_ids {
-    ($($field:ident),*,) => {
-        #[allow(dead_code)]
-        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
-        pub enum TagId {
-            $($field,)*
-            Unknown(u32),
-            File(String),
-            Custom(String),
-            Any // For filtering, shouldn't be used directly
-        }
-        impl Serialize for TagId {
-            fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
-                match self {
-                    TagId::Unknown(x)      => s.serialize_str(&format!("0x{:x}", x)),
-                    TagId::Custom(x)       => s.serialize_str(x),
-                    TagId::File(x)         => s.serialize_str(x),
-                    TagId::Any             => s.serialize_str("*"),
-                    $(TagId::$field        => s.serialize_str(stringify!($field)),)*
-                }
-            }
-        }
-        impl std::fmt::Display for TagId {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                match self {
-                    TagId::Unknown(x)      => f.write_str(&format!("0x{:x}", x)),
-                    TagId::Custom(x)       => f.write_str(x),
-                    TagId::File(x)         => f.write_str(x),
-                    TagId::Any             => f.write_str("*"),
-                    $(TagId::$field        => f.write_str(stringify!($field)),)*
-                }
-            }
-        }
-        impl std::str::FromStr for TagId {
-            type Err = std::num::ParseIntError;
-
-            fn from_str(s: &str) -> Result<Self, Self::Err> {
-                Ok(match s {
-                    $(stringify!($field) => TagId::$field,)*
-                    "*" => TagId::Any,
-                    _ if s.starts_with("0x") => TagId::Unknown(s.parse::<u32>()?),
-                    _ => TagId::Custom(s.to_string())
-                })
-            }
-        }
-    }
-}
+// Element count for `TagValue::len`
+pub trait ValueLen { fn value_len(&self) -> Option<usize> { None } }
+impl<T> ValueLen for Vec<T> { fn value_len(&self) -> Option<usize> { Some(self.len()) } }
+macro_rules! single_values { ($($type:ty),*) => { $(impl ValueLen for $type { })* }; }
+single_values!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64, String, bool, (u32, u32), (f32, f32), (u32, u32, u32, u32), (f64, f64, f64),
+               GpsData, DigitalLens, serde_json::Value, Vector3<i8>, Vector3<i16>, Vector3<i32>);
 
 macro_rules! declare_types {
     ($($field:ident:$type:ty),*,) => {
@@ -114,6 +31,15 @@ macro_rules! declare_types {
                 }
             }
         }
+        impl TagValue {
+            /// Decodes the raw data, if it's not parsed yet
+            pub fn ensure_parsed(&self) {
+                match &self {
+                    $(TagValue::$field(t) => { let _ = t.get(); },)*
+                    TagValue::Unknown(_) => { }
+                }
+            }
+        }
         impl Serialize for TagValue {
             fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
                 match &self {
@@ -134,6 +60,24 @@ macro_rules! declare_types {
             }
         }*/
         pub trait GetWithType<T> { fn get_t(&self, k: TagId) -> Option<&T>; }
+        pub trait TagValueAs<T> { fn value_as(&self) -> Option<&T>; }
+        impl TagValue {
+            /// Name of the variant, eg. `Vec_TimeVector3_f64`
+            pub fn type_name(&self) -> &'static str {
+                match &self {
+                    $(TagValue::$field(_) => stringify!($field),)*
+                    TagValue::Unknown(_) => "Unknown",
+                }
+            }
+            /// Number of elements of the `Vec_*` values, `None` for the single values
+            #[allow(clippy::len_without_is_empty)]
+            pub fn len(&self) -> Option<usize> {
+                match &self {
+                    $(TagValue::$field(t) => t.get().value_len(),)*
+                    TagValue::Unknown(_) => None,
+                }
+            }
+        }
         $(
             impl std::convert::TryInto<$type> for TagValue {
                 type Error = &'static str;
@@ -144,6 +88,14 @@ macro_rules! declare_types {
                     Err("Unknown TagValue")
                 }
             }
+            impl TagValueAs<$type> for TagValue {
+                fn value_as(&self) -> Option<&$type> {
+                    if let TagValue::$field(vv) = self {
+                        return Some(vv.get());
+                    }
+                    None
+                }
+            }
             impl GetWithType<$type> for TagMap {
                 fn get_t(&self, k: TagId) -> Option<&$type> {
                     if let Some(v) = self.get(&k) {
@@ -168,18 +120,6 @@ macro_rules! declare_types {
 
 include!("tags.rs");
 
-#[derive(Debug, Clone, Serialize)]
-#[allow(non_camel_case_types)]
-pub enum Scalar {
-    u8(u8),   i8(i8),
-    u16(u16), i16(i16),
-    u32(u32), i32(i32),
-    u64(u64), i64(i64),
-    f32(f32), f64(f64),
-    String(String),
-    bool(bool),
-}
-
 #[derive(Debug, Clone)]
 pub struct TagDescription {
     pub group: GroupId,
@@ -188,14 +128,27 @@ pub struct TagDescription {
     pub description: String,
     pub value: TagValue,
 }
+impl TagDescription {
+    /// Formatted value of the tag. Unlike `value.to_string()`, this never panics;
+    /// a failure in the format function or in the lazy parsing of the value is returned as an error
+    pub fn format(&self) -> Result<String, String> {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.value.to_string())).map_err(|e| {
+            e.downcast_ref::<&str>().map(|x| x.to_string())
+                .or_else(|| e.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| format!("Failed to format {}/{}", self.group, self.id))
+        })
+    }
+}
 
 type ParseFn<T> = fn(&mut std::io::Cursor::<&[u8]>) -> std::io::Result<T>;
+type ModifyFn<T> = std::sync::Arc<dyn Fn(&mut T) + Send + Sync>;
 
 #[derive(Clone)]
 pub struct ValueType<T> {
     parse_fn: Option<ParseFn<T>>,
     format_fn: fn(&T) -> String,
-    parsed_value: std::cell::OnceCell<T>,
+    parsed_value: std::sync::OnceLock<T>,
+    pending: Vec<ModifyFn<T>>,
     pub raw_data: Vec<u8>
 }
 impl<T: Default> ValueType<T> {
@@ -204,159 +157,157 @@ impl<T: Default> ValueType<T> {
             parse_fn: Some(parse_fn),
             format_fn,
             raw_data,
-            parsed_value: std::cell::OnceCell::new()
+            parsed_value: std::sync::OnceLock::new(),
+            pending: Vec::new()
         }
     }
     pub fn new_parsed(format_fn: fn(&T) -> String, parsed_value: T, raw_data: Vec<u8>) -> ValueType<T> {
-        let v = std::cell::OnceCell::new();
+        let v = std::sync::OnceLock::new();
         let _ = v.set(parsed_value);
         ValueType {
             parse_fn: None,
             format_fn,
             raw_data,
-            parsed_value: v
+            parsed_value: v,
+            pending: Vec::new()
         }
     }
     pub fn get(&self) -> &T {
         self.parsed_value.get_or_init(|| {
             let mut tag_slice = std::io::Cursor::new(&self.raw_data[..]);
-            match (self.parse_fn.expect("value not parsed"))(&mut tag_slice) {
-                Ok(v) => { return v; },
+            let mut v = match (self.parse_fn.expect("value not parsed"))(&mut tag_slice) {
+                Ok(v) => v,
                 Err(e) => {
                     log::error!("Parsing error {:?}: {}", e, pretty_hex::pretty_hex(&self.raw_data));
-                    return T::default();
+                    T::default()
                 }
+            };
+            for f in &self.pending {
+                f(&mut v);
             }
+            v
         })
     }
     pub fn get_mut(&mut self) -> &mut T {
+        let _ = self.get(); // make sure it's parsed
         self.parsed_value.get_mut().unwrap()
     }
-}
-
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct Vector3<T> {
-    pub x: T,
-    pub y: T,
-    pub z: T,
-}
-impl<T: std::convert::Into<f64>> Vector3<T> {
-    pub fn into_scaled(self, raw2unit: &f64, unit2deg: &f64) -> Vector3<f64> {
-        Vector3 {
-            x: self.x.into() / raw2unit * unit2deg,
-            y: self.y.into() / raw2unit * unit2deg,
-            z: self.z.into() / raw2unit * unit2deg,
-        }
+    pub fn is_parsed(&self) -> bool {
+        self.parsed_value.get().is_some()
     }
-}
-impl Vector3<f64> {
-    pub fn orient(&self, io: &[u8]) -> Vector3<f64> {
-        let map = |o: u8| -> f64 {
-            match o as char {
-                'X' => self.x, 'x' => -self.x,
-                'Y' => self.y, 'y' => -self.y,
-                'Z' => self.z, 'z' => -self.z,
-                err => { panic!("Invalid orientation {}", err); }
-            }
-        };
-        Vector3 { x: map(io[0]), y: map(io[1]), z: map(io[2]) }
-    }
-}
-#[derive(Debug, Clone, Serialize, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub struct TimeVector3<T, TT = T> {
-    pub t: TT,
-    pub x: T,
-    pub y: T,
-    pub z: T,
-}
-impl<T: std::convert::Into<f64>> TimeVector3<T> {
-    pub fn into_scaled(self, raw2unit: &f64, unit2deg: &f64) -> Vector3<f64> {
-        Vector3 {
-            x: self.x.into() / raw2unit * unit2deg,
-            y: self.y.into() / raw2unit * unit2deg,
-            z: self.z.into() / raw2unit * unit2deg,
+    /// Modifies the value in place. If the value wasn't parsed yet, the modification is deferred until the first access,
+    /// so post-processing (timestamp offsets, rotations etc.) doesn't force decoding of the raw data
+    pub fn modify<F: Fn(&mut T) + Send + Sync + 'static>(&mut self, f: F) {
+        match self.parsed_value.get_mut() {
+            Some(v) => f(v),
+            None => self.pending.push(std::sync::Arc::new(f))
         }
     }
 }
+
+/// Point of interest in the recording, stored in `GroupId::Markers`
 #[derive(Debug, Clone, Serialize, Default)]
-pub struct TimeArray8<T> {
-    pub t: f64,
-    pub v: [T; 8]
-}
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct TimeArray4<T> {
-    pub t: f64,
-    pub v: [T; 4]
+pub struct MarkerData {
+    pub t: f64, // in seconds from the start of the clip
+    pub kind: String, // eg. "HiLight", "SyncBeep", "CameraTrigger"
+    pub label: String,
+    pub payload: serde_json::Value, // format specific details, `null` if there are none
 }
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct TimeArray2<T> {
-    pub t: f64,
-    pub v: [T; 2]
+
+#[derive(Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+pub struct Dimension {
+    pub w: u32,
+    pub h: u32,
 }
+
 #[derive(Debug, Clone, Serialize, Default)]
-pub struct TimeScalar<T> {
-    pub t: f64,
-    pub v: T
+pub struct FisheyeParams {
+    pub camera_matrix: [[f64; 3]; 3],
+    pub distortion_coeffs: Vec<f64>,
 }
-#[derive(Debug, Copy, Clone, Serialize, Default)]
-pub struct Quaternion<T> {
-    pub w: T,
-    pub x: T,
-    pub y: T,
-    pub z: T,
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncSettings {
+    pub initial_offset: f64,
+    pub initial_offset_inv: bool,
+    pub search_size: f64,
+    pub max_sync_points: u32,
+    pub every_nth_frame: u32,
+    pub time_per_syncpoint: f64,
+    pub do_autosync: bool,
 }
-impl<T: Copy + std::ops::Mul<Output = T> + std::ops::Sub<Output = T> + std::ops::Add<Output = T>> std::ops::Mul for Quaternion<T> {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self {
+impl Default for SyncSettings {
+    fn default() -> Self {
         Self {
-            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
-            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
-            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
-            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w
+            initial_offset: 0.0,
+            initial_offset_inv: false,
+            search_size: 0.5,
+            max_sync_points: 5,
+            every_nth_frame: 1,
+            time_per_syncpoint: 0.6,
+            do_autosync: false,
         }
     }
 }
-impl<T: Copy + std::ops::Sub<Output = T>> std::ops::Sub for Quaternion<T> {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
+
+// Lens profile in the Gyroflow format. Stored in `GroupId::Lens`/`TagId::Data` as JSON, use `to_json()` to create the tag value
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct LensProfile {
+    pub calibrated_by: String,
+    pub camera_brand: String,
+    pub camera_model: String,
+    pub calib_dimension: Dimension,
+    pub orig_dimension: Dimension,
+    pub output_dimension: Dimension,
+    pub frame_readout_time: Option<f64>,
+    pub official: bool,
+    pub asymmetrical: bool,
+    pub fisheye_params: FisheyeParams,
+    pub distortion_model: String,
+    pub sync_settings: SyncSettings,
+    pub calibrator_version: String,
+}
+impl LensProfile {
+    pub fn new(brand: &str, model: &str, width: u32, height: u32) -> Self {
+        let dim = Dimension { w: width, h: height };
         Self {
-            w: self.w - rhs.w,
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z
+            calibrated_by: brand.to_owned(),
+            camera_brand: brand.to_owned(),
+            camera_model: model.to_owned(),
+            calib_dimension: dim,
+            orig_dimension: dim,
+            output_dimension: dim,
+            official: true,
+            distortion_model: "opencv_fisheye".into(),
+            calibrator_version: "---".into(),
+            ..Default::default()
         }
     }
-}
-impl<T: Copy + std::ops::Neg<Output = T>> std::ops::Neg for Quaternion<T> {
-    type Output = Self;
-    fn neg(self) -> Self {
-        Self { w: -self.w, x: -self.x, y: -self.y, z: -self.z }
+    pub fn with_output_dimension(mut self, width: u32, height: u32) -> Self {
+        self.output_dimension = Dimension { w: width, h: height };
+        self
     }
-}
-impl<T: Copy + std::ops::Mul<Output = T> + std::ops::Add<Output = T>> Quaternion<T> {
-    pub fn norm_squared(&self) -> T {
-        self.w * self.w +
-        self.x * self.x +
-        self.y * self.y +
-        self.z * self.z
+    pub fn with_frame_readout_time(mut self, frame_readout_time: Option<f64>) -> Self {
+        self.frame_readout_time = frame_readout_time;
+        self
+    }
+    pub fn with_camera_matrix(mut self, camera_matrix: [[f64; 3]; 3]) -> Self {
+        self.fisheye_params.camera_matrix = camera_matrix;
+        self
+    }
+    pub fn with_distortion(mut self, model: &str, coeffs: Vec<f64>, asymmetrical: bool) -> Self {
+        self.distortion_model = model.to_owned();
+        self.fisheye_params.distortion_coeffs = coeffs;
+        self.asymmetrical = asymmetrical;
+        self
+    }
+    pub fn with_sync_settings(mut self, sync_settings: SyncSettings) -> Self {
+        self.sync_settings = sync_settings;
+        self
+    }
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
     }
-}
-
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct TimeQuaternion<T> {
-    pub t: f64,
-    pub v: Quaternion<T>
-}
-
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct GpsData {
-    pub is_acquired: bool,
-    pub unix_timestamp: f64,
-    pub lat: f64,
-    pub lon: f64,
-    pub speed: f64, // in km/h
-    pub track: f64,
-    pub altitude: f64, // in m
 }
 
 #[macro_export]
@@ -377,3 +328,36 @@ macro_rules! tag {
 
 pub type TagMap = BTreeMap<TagId, TagDescription>;
 pub type GroupedTagMap = BTreeMap<GroupId, TagMap>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagQueryError {
+    MissingGroup(GroupId),
+    MissingTag(GroupId, TagId),
+    WrongType { group: GroupId, tag: TagId, expected: &'static str, found: &'static str },
+}
+impl std::fmt::Display for TagQueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TagQueryError::MissingGroup(g)   => write!(f, "Group {g} not found"),
+            TagQueryError::MissingTag(g, t)  => write!(f, "Tag {g}/{t} not found"),
+            TagQueryError::WrongType { group, tag, expected, found } => write!(f, "Tag {group}/{tag} is {found}, expected {expected}"),
+        }
+    }
+}
+impl std::error::Error for TagQueryError { }
+
+/// Typed access to the tags, which reports why the tag couldn't be read.
+/// Example: `map.query::<Vec<TimeVector3<f64>>>(GroupId::Gyroscope, TagId::Data)`
+pub trait TagQuery {
+    fn query<T>(&self, group: GroupId, tag: TagId) -> Result<&T, TagQueryError> where TagValue: TagValueAs<T>;
+}
+impl TagQuery for GroupedTagMap {
+    fn query<T>(&self, group: GroupId, tag: TagId) -> Result<&T, TagQueryError> where TagValue: TagValueAs<T> {
+        let Some(g) = self.get(&group) else { return Err(TagQueryError::MissingGroup(group)); };
+        let Some(desc) = g.get(&tag) else { return Err(TagQueryError::MissingTag(group, tag)); };
+        match desc.value.value_as() {
+            Some(v) => Ok(v),
+            None => Err(TagQueryError::WrongType { expected: std::any::type_name::<T>(), found: desc.value.type_name(), group, tag })
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+// Copyright © 2024 Adrian <adrian.eddy at gmail>
+
+// PX4 flight logs (.ulg), https://docs.px4.io/main/en/dev_log/ulog_file_format.html
+// The definitions section has the formats of all topics (`name:type field;type field;...`), then each subscription (topic + instance)
+// gets a message id and data messages are the raw structs, little-endian and packed in the order of the format.
+// Used topics:
+// - `sensor_combined`: gyroscope (rad/s) and accelerometer (m/s²) of the primary IMU. The accelerometer timestamp is relative to the gyroscope one
+// - `sensor_gyro`, `sensor_accel`: the individual IMUs, used if there's no `sensor_combined`
+// - `vehicle_attitude`: quaternion (w, x, y, z), rotation from the FRD body frame to NED
+// - `vehicle_gps_position` (older firmwares) or `sensor_gps`
+// All topics share the boot time clock in µs, so the timestamps are relative to the start of the log given in the header.
+// Topics with multiple instances use the first one in the main groups and the others are stored in `<topic>[<instance>]` groups
+
+use std::collections::BTreeMap;
+use std::io::*;
+use std::sync::{ Arc, atomic::{ AtomicBool, Ordering } };
+use byteorder::{ ReadBytesExt, LittleEndian };
+
+use crate::tags_impl::*;
+use crate::*;
+
+#[derive(Default)]
+pub struct ULog {
+    pub model: Option<String>
+}
+
+fn basic_size(typ: &str) -> Option<usize> {
+    match typ {
+        "int8_t" | "uint8_t" | "bool" | "char" => Some(1),
+        "int16_t" | "uint16_t" => Some(2),
+        "int32_t" | "uint32_t" | "float" => Some(4),
+        "int64_t" | "uint64_t" | "double" => Some(8),
+        _ => None
+    }
+}
+
+// `float[3] gyro_rad` -> ("float", 3, "gyro_rad")
+fn parse_field(field: &str) -> Option<(String, usize, String)> {
+    let (typ, name) = field.trim().split_once(' ')?;
+    match typ.split_once('[') {
+        Some((typ, count)) => Some((typ.to_owned(), count.trim_end_matches(']').parse().ok()?, name.to_owned())),
+        None => Some((typ.to_owned(), 1, name.to_owned()))
+    }
+}
+
+// Size of a format in bytes, nested formats are resolved recursively
+fn format_size(name: &str, formats: &BTreeMap<String, Vec<(String, usize, String)>>, depth: usize) -> Option<usize> {
+    if depth > 16 { return None; }
+    formats.get(name)?.iter().map(|(typ, count, _)| {
+        Some(count * basic_size(typ).or_else(|| format_size(typ, formats, depth + 1))?)
+    }).sum()
+}
+
+// Offsets of the top-level fields of a topic
+#[derive(Default)]
+struct Layout {
+    fields: BTreeMap<String, (String, usize)>, // name -> (type, offset)
+}
+impl Layout {
+    fn new(name: &str, formats: &BTreeMap<String, Vec<(String, usize, String)>>) -> Self {
+        let mut ret = Self::default();
+        let mut offset = 0;
+        for (typ, count, field) in formats.get(name).into_iter().flatten() {
+            let Some(size) = basic_size(typ).or_else(|| format_size(typ, formats, 0)) else { break; };
+            ret.fields.insert(field.clone(), (typ.clone(), offset));
+            offset += size * count;
+        }
+        ret
+    }
+    // `index`-th element of a numeric field
+    fn get(&self, data: &[u8], name: &str, index: usize) -> Option<f64> {
+        let (typ, offset) = self.fields.get(name)?;
+        let size = basic_size(typ)?;
+        let mut d = data.get(offset + index * size..offset + (index + 1) * size)?;
+        Some(match typ.as_str() {
+            "int8_t"   => d.read_i8().ok()? as f64,
+            "uint8_t" | "bool" | "char" => d.read_u8().ok()? as f64,
+            "int16_t"  => d.read_i16::<LittleEndian>().ok()? as f64,
+            "uint16_t" => d.read_u16::<LittleEndian>().ok()? as f64,
+            "int32_t"  => d.read_i32::<LittleEndian>().ok()? as f64,
+            "uint32_t" => d.read_u32::<LittleEndian>().ok()? as f64,
+            "int64_t"  => d.read_i64::<LittleEndian>().ok()? as f64,
+            "uint64_t" => d.read_u64::<LittleEndian>().ok()? as f64,
+            "float"    => d.read_f32::<LittleEndian>().ok()? as f64,
+            "double"   => d.read_f64::<LittleEndian>().ok()?,
+            _ => return None
+        })
+    }
+    // Array field with 3 elements
+    fn vector3(&self, data: &[u8], name: &str, t: f64) -> Option<TimeVector3<f64>> {
+        Some(TimeVector3 { t, x: self.get(data, name, 0)?, y: self.get(data, name, 1)?, z: self.get(data, name, 2)? })
+    }
+    // `x`, `y` and `z` fields
+    fn xyz(&self, data: &[u8], t: f64) -> Option<TimeVector3<f64>> {
+        Some(TimeVector3 { t, x: self.get(data, "x", 0)?, y: self.get(data, "y", 0)?, z: self.get(data, "z", 0)? })
+    }
+}
+
+#[derive(Default)]
+struct Topic {
+    gyro: Vec<TimeVector3<f64>>,
+    accl: Vec<TimeVector3<f64>>,
+    quat: Vec<TimeQuaternion<f64>>,
+    gps: Vec<GpsData>,
+}
+
+impl ULog {
+    pub fn camera_type(&self) -> String {
+        "PX4".to_owned()
+    }
+    pub fn has_accurate_timestamps(&self) -> bool {
+        false
+    }
+    pub fn possible_extensions() -> Vec<&'static str> {
+        vec!["ulg"]
+    }
+    pub fn frame_readout_time(&self) -> Option<f64> {
+        None
+    }
+    pub fn normalize_imu_orientation(v: String) -> String {
+        v
+    }
+
+    pub fn detect<P: AsRef<std::path::Path>>(buffer: &[u8], _filepath: P) -> Option<Self> {
+        if buffer.starts_with(b"ULog\x01\x12\x35") {
+            return Some(Self::default());
+        }
+        None
+    }
+
+    pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
+        let mut stream = BufReader::with_capacity(1024 * 1024, stream);
+
+        let mut magic = [0u8; 8];
+        stream.read_exact(&mut magic)?;
+        let start_timestamp = stream.read_u64::<LittleEndian>()?;
+        let time = |us: f64| (us - start_timestamp as f64) / 1_000_000.0;
+
+        let mut formats = BTreeMap::<String, Vec<(String, usize, String)>>::new();
+        let mut subscriptions = BTreeMap::<u16, (String, u8, Layout)>::new();
+        let mut topics = BTreeMap::<(String, u8), Topic>::new();
+        let mut info = serde_json::Map::new();
+        let mut position = 16usize;
+        let mut payload = Vec::new();
+
+        while let Ok(msg_size) = stream.read_u16::<LittleEndian>() {
+            let Ok(msg_type) = stream.read_u8() else { break; }; // Truncated log
+            payload.resize(msg_size as usize, 0);
+            if stream.read_exact(&mut payload).is_err() { break; } // Truncated log
+            position += 3 + msg_size as usize;
+
+            match msg_type {
+                b'F' => {
+                    let s = String::from_utf8_lossy(&payload);
+                    if let Some((name, fields)) = s.split_once(':') {
+                        formats.insert(name.to_owned(), fields.split(';').filter_map(parse_field).collect());
+                    }
+                },
+                b'I' => {
+                    // Key is `type name`, only the strings are kept
+                    let key_len = *payload.first().unwrap_or(&0) as usize;
+                    if let Some(key) = payload.get(1..1 + key_len) {
+                        let key = String::from_utf8_lossy(key);
+                        if let Some((_, name)) = key.split_once(' ').filter(|(typ, _)| typ.starts_with("char[")) {
+                            let value = String::from_utf8_lossy(&payload[1 + key_len..]).trim_end_matches('\0').to_owned();
+                            if name == "ver_hw" { self.model = Some(value.clone()); }
+                            info.insert(name.to_owned(), value.into());
+                        }
+                    }
+                },
+                b'A' if payload.len() > 3 => {
+                    let multi_id = payload[0];
+                    let msg_id = u16::from_le_bytes([payload[1], payload[2]]);
+                    let name = String::from_utf8_lossy(&payload[3..]).trim_end_matches('\0').to_owned();
+                    let layout = Layout::new(&name, &formats);
+                    subscriptions.insert(msg_id, (name, multi_id, layout));
+                },
+                b'D' if payload.len() > 2 => {
+                    let msg_id = u16::from_le_bytes([payload[0], payload[1]]);
+                    let Some((name, multi_id, layout)) = subscriptions.get(&msg_id) else { continue; };
+                    let data = &payload[2..];
+                    let Some(timestamp) = layout.get(data, "timestamp", 0) else { continue; };
+                    // Time of the measurement, if the topic has it (the timestamp is the time of publishing)
+                    let t = time(layout.get(data, "timestamp_sample", 0).unwrap_or(timestamp));
+                    let topic = || (name.clone(), *multi_id);
+
+                    match name.as_str() {
+                        "sensor_combined" => {
+                            let entry = topics.entry(topic()).or_default();
+                            if let Some(v) = layout.vector3(data, "gyro_rad", t) {
+                                entry.gyro.push(v);
+                            }
+                            let relative = layout.get(data, "accelerometer_timestamp_relative", 0).unwrap_or_default();
+                            if relative != i32::MAX as f64 { // RELATIVE_TIMESTAMP_INVALID
+                                if let Some(v) = layout.vector3(data, "accelerometer_m_s2", t + relative / 1_000_000.0) {
+                                    entry.accl.push(v);
+                                }
+                            }
+                        },
+                        "sensor_gyro" => if let Some(v) = layout.xyz(data, t) {
+                            topics.entry(topic()).or_default().gyro.push(v);
+                        },
+                        "sensor_accel" => if let Some(v) = layout.xyz(data, t) {
+                            topics.entry(topic()).or_default().accl.push(v);
+                        },
+                        "vehicle_attitude" => {
+                            let q = (0..4).map(|i| layout.get(data, "q", i)).collect::<Option<Vec<f64>>>();
+                            if let Some(q) = q {
+                                topics.entry(topic()).or_default().quat.push(TimeQuaternion {
+                                    t: t * 1000.0,
+                                    v: util::multiply_quats((q[0], q[1], q[2], q[3]), (0.5, -0.5, -0.5, 0.5)),
+                                });
+                            }
+                        },
+                        "vehicle_gps_position" | "sensor_gps" => {
+                            // Newer firmwares store the position as degrees and metres, older as 1e-7 degrees and millimetres
+                            let lat = layout.get(data, "latitude_deg", 0).or_else(|| Some(layout.get(data, "lat", 0)? / 1e7));
+                            let lon = layout.get(data, "longitude_deg", 0).or_else(|| Some(layout.get(data, "lon", 0)? / 1e7));
+                            let alt = layout.get(data, "altitude_msl_m", 0).or_else(|| Some(layout.get(data, "alt", 0)? / 1000.0));
+                            if let (Some(lat), Some(lon)) = (lat, lon) {
+                                let fix = layout.get(data, "fix_type", 0).unwrap_or_default();
+                                let utc = layout.get(data, "time_utc_usec", 0).filter(|x| *x > 0.0);
+                                topics.entry(topic()).or_default().gps.push(GpsData {
+                                    is_acquired: fix >= 2.0,
+                                    unix_timestamp: utc.map(|x| x / 1_000_000.0).unwrap_or_default(),
+                                    lat,
+                                    lon,
+                                    speed: layout.get(data, "vel_m_s", 0).unwrap_or_default() * 3.6, // m/s to km/h
+                                    track: layout.get(data, "cog_rad", 0).unwrap_or_default().to_degrees().rem_euclid(360.0),
+                                    altitude: alt.unwrap_or_default(),
+                                    dop: layout.get(data, "hdop", 0),
+                                    fix: Some(fix as u8),
+                                    ..Default::default()
+                                });
+                            }
+                        },
+                        _ => { }
+                    }
+                },
+                _ => { }
+            }
+
+            if cancel_flag.load(Ordering::Relaxed) { break; }
+            if size > 0 {
+                progress_cb(position as f64 / size as f64);
+            }
+        }
+
+        let mut map = GroupedTagMap::new();
+        if !info.is_empty() {
+            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Metadata", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::Value::Object(info), vec![]));
+        }
+
+        // Main groups use `sensor_combined`, or the first instance of the individual sensors
+        let first = |names: &[&str], has: fn(&Topic) -> bool| -> Option<(String, u8)> {
+            names.iter().find_map(|name| topics.iter().find(|(k, v)| k.0 == *name && has(v)).map(|(k, _)| k.clone()))
+        };
+        let main_gyro = first(&["sensor_combined", "sensor_gyro"], |x| !x.gyro.is_empty());
+        let main_accl = first(&["sensor_combined", "sensor_accel"], |x| !x.accl.is_empty());
+        let main_quat = first(&["vehicle_attitude"], |x| !x.quat.is_empty());
+        let main_gps  = first(&["vehicle_gps_position", "sensor_gps"], |x| !x.gps.is_empty());
+
+        for ((name, multi_id), topic) in topics {
+            let key = Some((name.clone(), multi_id));
+            let group = |main: &Option<(String, u8)>, main_group: GroupId| if *main == key { main_group } else { GroupId::Custom(format!("{name}[{multi_id}]")) };
+            let has_gyro = !topic.gyro.is_empty();
+            if has_gyro {
+                let g = group(&main_gyro, GroupId::Gyroscope);
+                util::insert_tag(&mut map, tag!(parsed g.clone(), TagId::Data,        "Gyroscope data",  Vec_TimeVector3_f64, |v| format!("{:?}", v), topic.gyro, vec![]));
+                util::insert_tag(&mut map, tag!(parsed g.clone(), TagId::Unit,        "Gyroscope unit",  String, |v| v.to_string(), "rad/s".into(), Vec::new()));
+                util::insert_tag(&mut map, tag!(parsed g,         TagId::Orientation, "IMU orientation", String, |v| v.to_string(), "zyx".into(), Vec::new()));
+            }
+            if !topic.accl.is_empty() {
+                // `sensor_combined` has both in the same topic, so the instance group gets a suffix
+                let g = match group(&main_accl, GroupId::Accelerometer) {
+                    GroupId::Custom(x) if has_gyro => GroupId::Custom(format!("{x} accelerometer")),
+                    g => g
+                };
+                util::insert_tag(&mut map, tag!(parsed g.clone(), TagId::Data,        "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), topic.accl, vec![]));
+                util::insert_tag(&mut map, tag!(parsed g.clone(), TagId::Unit,        "Accelerometer unit", String, |v| v.to_string(), "m/s²".into(), Vec::new()));
+                util::insert_tag(&mut map, tag!(parsed g,         TagId::Orientation, "IMU orientation",    String, |v| v.to_string(), "zyx".into(), Vec::new()));
+            }
+            if !topic.quat.is_empty() {
+                let g = group(&main_quat, GroupId::Quaternion);
+                if g == GroupId::Quaternion {
+                    util::insert_quaternion_convention(&mut map, &util::QuaternionConvention {
+                        source: "quaternion",
+                        component_order: "wxyz",
+                        handedness: "right",
+                        post_rotation: Some((0.5, -0.5, -0.5, 0.5)),
+                        ..Default::default()
+                    });
+                }
+                util::insert_tag(&mut map, tag!(parsed g, TagId::Data, "Quaternion data", Vec_TimeQuaternion_f64, |v| format!("{:?}", v), topic.quat, vec![]));
+            }
+            if !topic.gps.is_empty() {
+                let g = group(&main_gps, GroupId::GPS);
+                util::insert_tag(&mut map, tag!(parsed g, TagId::Data, "GPS data", Vec_GpsData, |v| format!("{:?}", v), topic.gps, vec![]));
+            }
+        }
+
+        Ok(vec![
+            SampleInfo { tag_map: Some(map), ..Default::default() }
+        ])
+    }
+}
@@ -25,6 +25,8 @@ pub struct SampleInfo {
     pub timestamp_ms: f64,
     pub duration_ms: f64,
     pub video_rotation: Option<i32>,
+    pub file_position: u64, // Offset of the media sample in the source file
+    pub sample_size: usize, // Size of the media sample in the source file, in bytes
     pub tag_map: Option<GroupedTagMap>
 }
 
@@ -38,11 +40,17 @@ pub fn get_mp4_good_size<T: Read + Seek>(stream: &mut T, size: u64) -> Result<us
         let mut name = [0u8; 4];
         let name_read_ok = stream.read_exact(&mut name).is_ok();
         let name_good = name_read_ok && name.iter().all(|x| x.is_ascii() && *x > 13);
-        if len == 1 { // Large box
+        let header_size = if len == 1 { // Large box
             len = stream.read_u64::<BigEndian>()?;
+            16
+        } else {
+            8
+        };
+        if len == 0 { // Box extends to the end of the file
+            len = size - start_pos;
         }
         let end_pos = start_pos + len;
-        let size_good = size >= end_pos;
+        let size_good = size >= end_pos && len >= header_size;
         if name_good && size_good {
             good_size = end_pos as usize;
             stream.seek(SeekFrom::Start(end_pos))?;
@@ -106,8 +114,8 @@ impl<R: Read + Seek> Seek for PatchingLimitingStream<R> {
         if res.is_ok() {
             self.total = match pos {
                 SeekFrom::Start(pos) => pos as usize,
-                SeekFrom::End(pos) => self.stream_size.saturating_add(pos as usize),
-                SeekFrom::Current(pos) => self.total.saturating_add(pos as usize),
+                SeekFrom::End(pos) => self.stream_size.saturating_add_signed(pos as isize),
+                SeekFrom::Current(pos) => self.total.saturating_add_signed(pos as isize),
             };
         }
         res
@@ -143,7 +151,8 @@ pub fn parse_mp4<T: Read + Seek>(stream: &mut T, size: usize) -> mp4parse::Resul
         if let Some(pos) = memchr::memmem::find(&all, b"mdat") {
             let how_much_less = (size - all.len()) as u64;
             let mut len = (&all[pos-4..]).read_u32::<BigEndian>()? as u64;
-            if len == 1 {
+            let large = len == 1;
+            if large {
                 len = (&all[pos+4..]).read_u64::<BigEndian>()?;
             }
             if how_much_less > len {
@@ -155,8 +164,8 @@ pub fn parse_mp4<T: Read + Seek>(stream: &mut T, size: usize) -> mp4parse::Resul
                 }
                 return mp4parse::read_mp4(stream);
             } else {
-                if len == 1 { // Large box
-                    len = (&all[pos+4..]).read_u64::<BigEndian>()? - how_much_less;
+                if large { // Large box
+                    len -= how_much_less;
                     all[pos+4..pos+12].copy_from_slice(&len.to_be_bytes());
                 } else {
                     len -= how_much_less;
@@ -166,7 +175,7 @@ pub fn parse_mp4<T: Read + Seek>(stream: &mut T, size: usize) -> mp4parse::Resul
 
             if let Ok(good_size) = get_mp4_good_size(&mut std::io::Cursor::new(&all), all.len() as u64) {
                 if all.len() > good_size {
-                    log::warn!("Garbage found at the end of the file, removing {} bytes from the end.", all.len() - good_size);
+                    crate::parse_warning!("Garbage found at the end of the file, removing {} bytes from the end.", all.len() - good_size);
                     all.resize(good_size, 0);
                 }
             }
@@ -190,13 +199,14 @@ pub fn get_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, typ: mp
 
     let mut video_md = None;
     let mut video_rotation = None;
+    let selected_track = selected_metadata_track().filter(|_| matches!(typ, mp4parse::TrackType::Metadata | mp4parse::TrackType::Unknown));
 
     for x in &ctx.tracks {
         if x.track_type == mp4parse::TrackType::Video && video_md.is_none() {
             video_md = get_video_metadata_from_track(x).ok();
             video_rotation = video_md.as_ref().map(|x| x.rotation);
         }
-        if x.track_type == typ {
+        if x.track_type == typ && selected_track.map(|i| i == track_index).unwrap_or(true) {
             if let Some(timescale) = x.timescale {
                 // if let Some(ref stts) = x.stts {
                 //     sample_delta = stts.samples[0].sample_delta;
@@ -206,10 +216,13 @@ pub fn get_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, typ: mp
                 if let Some(samples) = mp4parse::unstable::create_sample_table(&x, 0.into()) {
                     let mut sample_data = Vec::new();
                     let mut sample_index = 0u64;
+                    let mut stats = TrackStats { track_index, ..Default::default() };
+                    let mut time_range = (f64::MAX, f64::MIN);
                     for s in samples {
                         if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
 
-                        let mut sample_size = (s.end_offset.0 - s.start_offset.0) as usize;
+                        let full_sample_size = (s.end_offset.0 - s.start_offset.0) as usize;
+                        let mut sample_size = full_sample_size;
                         if let Some(max_sample_size) = max_sample_size {
                             if sample_size > max_sample_size {
                                 sample_size = max_sample_size;
@@ -219,6 +232,9 @@ pub fn get_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, typ: mp
                         let end_comp_ms   = mp4parse::unstable::track_time_to_us(mp4parse::TrackScaledTime::<i64>(s.end_composition.0,   x.id), mp4parse::TrackTimeScale::<i64>(timescale.0 as i64, timescale.1)).ok_or(mp4parse::Error::InvalidData(mp4parse::Status::MvhdBadTimescale))?.0 as f64 / 1000.0;
                         let sample_timestamp_ms = start_comp_ms;
                         let sample_duration_ms = end_comp_ms - start_comp_ms;
+                        stats.packets += 1;
+                        stats.total_bytes += full_sample_size as u64;
+                        time_range = (time_range.0.min(start_comp_ms), time_range.1.max(end_comp_ms));
                         if sample_size > 4 {
                             if sample_data.len() != sample_size {
                                 sample_data.resize(sample_size, 0u8);
@@ -227,12 +243,16 @@ pub fn get_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, typ: mp
                             stream.seek(SeekFrom::Start(s.start_offset.0 as u64))?;
                             stream.read_exact(&mut sample_data[..])?;
 
-                            callback(SampleInfo { sample_index, track_index, timestamp_ms: sample_timestamp_ms, duration_ms: sample_duration_ms, tag_map: None, video_rotation }, &sample_data, s.start_offset.0 as u64, video_md.as_ref());
+                            callback(SampleInfo { sample_index, track_index, timestamp_ms: sample_timestamp_ms, duration_ms: sample_duration_ms, tag_map: None, video_rotation, file_position: s.start_offset.0 as u64, sample_size: full_sample_size }, &sample_data, s.start_offset.0 as u64, video_md.as_ref());
 
                             //timestamp_ms += duration_ms;
                             sample_index += 1;
                         }
                     }
+                    if stats.packets > 0 {
+                        stats.duration_ms = time_range.1 - time_range.0;
+                        add_track_stats(stats);
+                    }
                     if single {
                         break;
                     }
@@ -244,11 +264,172 @@ pub fn get_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, typ: mp
     Ok(ctx)
 }
 
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct TrackStats {
+    pub track_index: usize,
+    pub packets: usize,
+    pub total_bytes: u64,
+    /// Time covered by the packets, in milliseconds
+    pub duration_ms: f64,
+}
+impl TrackStats {
+    pub fn average_packet_size(&self) -> f64 {
+        if self.packets == 0 { return 0.0; }
+        self.total_bytes as f64 / self.packets as f64
+    }
+    pub fn packets_per_second(&self) -> f64 {
+        if self.duration_ms <= 0.0 { return 0.0; }
+        self.packets as f64 / (self.duration_ms / 1000.0)
+    }
+    /// In bits per second
+    pub fn bitrate(&self) -> f64 {
+        if self.duration_ms <= 0.0 { return 0.0; }
+        self.total_bytes as f64 * 8.0 / (self.duration_ms / 1000.0)
+    }
+}
+
+/// Groups read by one of the parsers, see `Input::sources`
+#[derive(Debug, Clone)]
+pub struct FormatSource {
+    /// Name of the `SupportedFormats` variant
+    pub format: String,
+    pub groups: Vec<GroupId>,
+}
+impl FormatSource {
+    pub(crate) fn new<'a>(format: &str, samples: impl Iterator<Item = &'a SampleInfo>) -> Self {
+        let groups = samples.filter_map(|x| x.tag_map.as_ref()).flat_map(|x| x.keys().cloned()).collect::<BTreeSet<_>>();
+        Self { format: format.into(), groups: groups.into_iter().collect() }
+    }
+}
+
+thread_local! {
+    static TRACK_STATS: std::cell::RefCell<Vec<TrackStats>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+// Tracks read multiple times (eg. GoPro chapters) are accumulated into one entry
+fn add_track_stats(stats: TrackStats) {
+    TRACK_STATS.with_borrow_mut(|all| {
+        match all.iter_mut().find(|x| x.track_index == stats.track_index) {
+            Some(x) => {
+                x.packets += stats.packets;
+                x.total_bytes += stats.total_bytes;
+                x.duration_ms += stats.duration_ms;
+            },
+            None => all.push(stats)
+        }
+    });
+}
+/// Returns the statistics of the tracks read by `get_track_samples` on this thread since the last call
+pub(crate) fn take_track_stats() -> Vec<TrackStats> {
+    TRACK_STATS.with_borrow_mut(std::mem::take)
+}
+
 pub fn get_metadata_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, single: bool, callback: F, cancel_flag: Arc<AtomicBool>) -> Result<MediaContext>
     where F: FnMut(SampleInfo, &[u8], u64, Option<&VideoMetadata>)
 {
     get_track_samples(stream, size, mp4parse::TrackType::Metadata, single, None, callback, cancel_flag)
 }
+/// Like `get_metadata_track_samples`, but with `include_subtitles` also reads the text subtitle tracks (`tx3g`), which some cameras use to store the telemetry.
+/// Subtitle samples are passed to `callback` as the UTF-8 text, without the length prefix
+pub fn get_metadata_track_samples_with_subtitles<F, T: Read + Seek>(stream: &mut T, size: usize, single: bool, include_subtitles: bool, mut callback: F, cancel_flag: Arc<AtomicBool>) -> Result<MediaContext>
+    where F: FnMut(SampleInfo, &[u8], u64, Option<&VideoMetadata>)
+{
+    let ctx = get_track_samples(stream, size, mp4parse::TrackType::Metadata, single, None, &mut callback, cancel_flag.clone())?;
+    if include_subtitles {
+        // Subtitle tracks are not recognized by mp4parse, so all unknown tracks are checked
+        get_track_samples(stream, size, mp4parse::TrackType::Unknown, false, None, |info, data, file_position, video_md| {
+            if let Some(text) = subtitle_text(data) {
+                callback(info, text.as_bytes(), file_position, video_md);
+            }
+        }, cancel_flag)?;
+    }
+    Ok(ctx)
+}
+/// Text of a `tx3g` subtitle sample: big endian u16 length followed by the UTF-8 text and optional style boxes
+pub fn subtitle_text(data: &[u8]) -> Option<&str> {
+    let len = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+    std::str::from_utf8(data.get(2..2 + len)?).ok()
+}
+
+/// Track of an MP4/MOV file which can contain telemetry, see `metadata_tracks`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct MetadataTrack {
+    /// Index of the track in the file, to be used in `InputOptions::metadata_track_index`
+    pub track_index: usize,
+    /// Handler type from the `hdlr` box, eg. "meta" or "text"
+    pub handler_type: String,
+    /// Handler name, eg. "GoPro MET" or "CameraMetadataMediaHandler"
+    pub handler_name: String,
+    /// Fourcc of the sample description, eg. "gpmd", "rtmd" or "camm"
+    pub codec: String,
+}
+
+// Child boxes of an MP4 container box, as (fourcc, contents without the header)
+pub(crate) fn mp4_children(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut ret = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let (size, header_size) = match (&data[pos..]).read_u32::<BigEndian>().unwrap_or_default() {
+            1 => (data.get(pos + 8..).and_then(|mut x| x.read_u64::<BigEndian>().ok()).unwrap_or_default() as usize, 16),
+            0 => (data.len() - pos, 8),
+            x => (x as usize, 8)
+        };
+        if size < header_size || pos + size > data.len() { break; }
+        ret.push((&data[pos + 4..pos + 8], &data[pos + header_size..pos + size]));
+        pos += size;
+    }
+    ret
+}
+// Contents of the first box found at `path` inside of the container, eg. `[b"mdia", b"minf", b"stbl"]`
+pub(crate) fn mp4_find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, typ| mp4_children(data).into_iter().find(|x| x.0 == *typ).map(|x| x.1))
+}
+
+// Contents of the top level `moov` box, without reading the rest of the file
+pub(crate) fn read_moov<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<u8>> {
+    let mut moov = None;
+    stream.seek(SeekFrom::Start(0))?;
+    while let Ok((typ, offs, box_size, header_size)) = read_box(stream) {
+        if typ == fourcc("moov") {
+            let mut data = vec![0u8; (box_size - header_size as u64) as usize];
+            stream.read_exact(&mut data)?;
+            moov = Some(data);
+            break;
+        }
+        if offs + box_size >= size as u64 { break; }
+        stream.seek(SeekFrom::Start(offs + box_size))?;
+    }
+    stream.seek(SeekFrom::Start(0))?;
+    moov.ok_or_else(|| Error::new(ErrorKind::NotFound, "moov box not found"))
+}
+
+/// Lists the tracks which aren't video, audio or timecode, so the caller can choose which one to parse with `InputOptions::metadata_track_index`
+pub fn metadata_tracks<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<MetadataTrack>> {
+    let moov = read_moov(stream, size)?;
+
+    let mut ret = Vec::new();
+    // Track indexes follow the order of `trak` boxes, same as `MediaContext::tracks`
+    for (track_index, (_, trak)) in mp4_children(&moov).into_iter().filter(|x| x.0 == b"trak").enumerate() {
+        let Some(hdlr) = mp4_find(trak, &[b"mdia", b"hdlr"]) else { continue; };
+        let handler_type = String::from_utf8_lossy(hdlr.get(8..12).unwrap_or_default()).into_owned();
+        if ["vide", "soun", "tmcd", "hint"].contains(&handler_type.as_str()) { continue; }
+
+        // QuickTime stores the name with a length prefix, MP4 as a null terminated string
+        let mut name = hdlr.get(24..).unwrap_or_default();
+        if let Some(&len) = name.first() {
+            if len as usize + 1 == name.len() || (len > 0 && len < 0x20) { name = &name[1..]; }
+        }
+        let handler_name = String::from_utf8_lossy(name.split(|x| *x == 0).next().unwrap_or_default()).trim().to_owned();
+
+        let codec = mp4_find(trak, &[b"mdia", b"minf", b"stbl", b"stsd"])
+            .and_then(|x| x.get(12..16))
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .unwrap_or_default();
+
+        ret.push(MetadataTrack { track_index, handler_type, handler_name, codec });
+    }
+    Ok(ret)
+}
+
 pub fn get_other_track_samples<F, T: Read + Seek>(stream: &mut T, size: usize, single: bool, callback: F, cancel_flag: Arc<AtomicBool>) -> Result<MediaContext>
     where F: FnMut(SampleInfo, &[u8], u64, Option<&VideoMetadata>)
 {
@@ -606,6 +787,31 @@ pub fn multiply_quats(p: (f64, f64, f64, f64), q: (f64, f64, f64, f64)) -> Quate
     }
 }
 
+/// How the quaternions in `Quaternion/Data` were derived from the values stored in the file,
+/// so they can be converted back to the original convention. Stored as `Quaternion/Convention`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct QuaternionConvention {
+    /// Representation stored in the file, eg. `angle_axis` or `quaternion`
+    pub source: &'static str,
+    /// Order of the stored components
+    pub component_order: &'static str,
+    /// Handedness of the coordinate system of the stored values, `right` or `left`
+    pub handedness: &'static str,
+    /// Stored components which were negated
+    pub negated: &'static [&'static str],
+    /// Rotation (w, x, y, z) multiplied on the left: `q = pre * stored * post`
+    pub pre_rotation: Option<(f64, f64, f64, f64)>,
+    /// Rotation (w, x, y, z) multiplied on the right
+    pub post_rotation: Option<(f64, f64, f64, f64)>,
+    /// Whether the sign of some quaternions was flipped to keep the series continuous (q and -q are the same rotation)
+    pub sign_unwrapped: bool,
+}
+
+pub fn insert_quaternion_convention(map: &mut GroupedTagMap, convention: &QuaternionConvention) {
+    let v = serde_json::to_value(convention).unwrap_or_default();
+    insert_tag(map, crate::tag!(parsed GroupId::Quaternion, TagId::Convention, "Quaternion convention", Json, |v| serde_json::to_string(v).unwrap_or_default(), v, vec![]));
+}
+
 pub fn find_between_with_offset(buffer: &[u8], from: &[u8], to: u8, offset: i32) -> Option<String> {
     let pos = memmem::find(buffer, from)?;
     let end = memchr::memchr(to, &buffer[pos+from.len()..])?;
@@ -621,7 +827,14 @@ pub fn find_from_to(buffer: &[u8], from: &[u8], to: &[u8]) -> Option<String> {
     Some(String::from_utf8_lossy(&buffer[pos..pos+from.len()+end+to.len()]).into())
 }
 
-pub fn insert_tag(map: &mut GroupedTagMap, tag: TagDescription) {
+// Finds JPEG data embedded in `buffer`, from the SOI marker to the last EOI marker
+pub fn find_jpeg(buffer: &[u8]) -> Option<&[u8]> {
+    let start = memmem::find(buffer, &[0xFF, 0xD8, 0xFF])?;
+    let end = memmem::rfind(&buffer[start..], &[0xFF, 0xD9])?;
+    Some(&buffer[start..start + end + 2])
+}
+
+pub fn insert_tag(map: &mut GroupedTagMap, mut tag: TagDescription) {
     let whitelist_item = WhitelistItem((tag.group.clone(), tag.id.clone()));
     let whitelist = TAG_WHITELIST.read().unwrap();
     if !whitelist.is_empty() && !whitelist.contains(&whitelist_item) {
@@ -629,12 +842,478 @@ pub fn insert_tag(map: &mut GroupedTagMap, tag: TagDescription) {
     }
 
     let group_map = map.entry(tag.group.clone()).or_insert_with(TagMap::new);
+    if matches!(tag.id, TagId::Custom(_) | TagId::Unknown(_)) && group_map.contains_key(&tag.id) {
+        if let Some(namespace) = TAG_MERGE_NAMESPACE.with_borrow(|x| x.clone()) {
+            tag.id = tag.id.namespaced(&namespace);
+        }
+    }
     group_map.insert(tag.id.clone(), tag);
 }
 
+/// Inserts the lens serial number, owner and firmware version as standard `GroupId::Lens` tags, regardless of how the format names them
+pub fn insert_lens_identity(map: &mut GroupedTagMap, serial: Option<&str>, owner: Option<&str>, firmware: Option<&str>) {
+    let clean = |v: Option<&str>| v.map(|x| x.trim().to_owned()).filter(|x| !x.is_empty());
+    if let Some(v) = clean(serial)   { insert_tag(map, crate::tag!(parsed GroupId::Lens, TagId::SerialNumber, "Lens serial number", String, |v| v.clone(), v, vec![])); }
+    if let Some(v) = clean(owner)    { insert_tag(map, crate::tag!(parsed GroupId::Lens, TagId::OwnerName,    "Lens owner name",    String, |v| v.clone(), v, vec![])); }
+    if let Some(v) = clean(firmware) { insert_tag(map, crate::tag!(parsed GroupId::Lens, TagId::Firmware,     "Lens firmware",      String, |v| v.clone(), v, vec![])); }
+}
+
+/// Inserts the serial number of the camera as the standard `Default/SerialNumber` tag
+pub fn insert_camera_serial(map: &mut GroupedTagMap, serial: Option<&str>) {
+    if let Some(v) = serial.map(|x| x.trim_matches(|c: char| c.is_whitespace() || c == '\0')).filter(|x| !x.is_empty()) {
+        insert_tag(map, crate::tag!(parsed GroupId::Default, TagId::SerialNumber, "Camera serial number", String, |v| v.clone(), v.to_owned(), vec![]));
+    }
+}
+
+// Parts of the tag ids, descriptions and JSON keys (lowercase) which identify the camera, the owner or the location
+const IDENTIFYING_NAMES: &[&str] = &["serial", "owner", "ssid", "wifi", "wi-fi", "bluetooth", "network name", "mac address", "gps", "latitude", "longitude"];
+const IDENTIFYING_KEYS: &[&str] = &["sn", "lat", "lon", "lng", "mac"];
+
+fn is_identifying(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    IDENTIFYING_KEYS.contains(&name.as_str()) || IDENTIFYING_NAMES.iter().any(|x| name.contains(x))
+}
+fn anonymize_json(v: &mut serde_json::Value) {
+    match v {
+        serde_json::Value::Object(o) => {
+            o.retain(|k, _| !is_identifying(k));
+            o.values_mut().for_each(anonymize_json);
+        },
+        serde_json::Value::Array(a) => a.iter_mut().for_each(anonymize_json),
+        _ => { }
+    }
+}
+
+/// Removes the location and identification from the tag map: GPS groups, serial numbers, owner names and network names.
+/// Tags are matched by their id and description, so it works the same for standard and format specific tags,
+/// and JSON values (eg. `Default/Metadata`) are cleaned recursively
+pub fn anonymize_tag_map(map: &mut GroupedTagMap) {
+    map.remove(&GroupId::GPS);
+    map.retain(|g, _| !matches!(g, GroupId::Custom(x) if is_identifying(x)));
+    for tags in map.values_mut() {
+        tags.retain(|id, desc| {
+            !matches!(id, TagId::SerialNumber | TagId::OwnerName)
+            && !matches!(id, TagId::Custom(x) if is_identifying(x))
+            && !is_identifying(&desc.description)
+        });
+        for desc in tags.values_mut() {
+            match &mut desc.value {
+                TagValue::Json(v) => { anonymize_json(v.get_mut()); v.raw_data.clear(); },
+                TagValue::Vec_TimeScalar_Json(v) => { v.get_mut().iter_mut().for_each(|x| anonymize_json(&mut x.v)); v.raw_data.clear(); },
+                _ => { }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagMergePolicy {
+    /// Inserted tag replaces the existing one with the same id
+    #[default]
+    Overwrite,
+    /// If a `Custom` or `Unknown` tag with the same id already exists, both are kept
+    /// and the inserted one is stored under an id namespaced with the format name (eg. `GoPro::0x1234`)
+    KeepBoth,
+}
+
+thread_local! {
+    static TAG_MERGE_NAMESPACE: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+pub(crate) fn set_tag_merge_policy(policy: TagMergePolicy, namespace: &str) {
+    TAG_MERGE_NAMESPACE.set(match policy {
+        TagMergePolicy::Overwrite => None,
+        TagMergePolicy::KeepBoth => Some(namespace.to_owned()),
+    });
+}
+
+/// Lists the tags which are stored more than once in the same group, as (group, id, namespaced ids of the other values)
+pub fn duplicate_tags(map: &GroupedTagMap) -> Vec<(GroupId, TagId, Vec<TagId>)> {
+    let mut ret = Vec::new();
+    for (group, tags) in map {
+        let mut duplicates = BTreeMap::<&TagId, Vec<TagId>>::new();
+        for id in tags.keys() {
+            if id.namespace().is_some() && tags.contains_key(id.base()) {
+                duplicates.entry(id.base()).or_default().push(id.clone());
+            }
+        }
+        ret.extend(duplicates.into_iter().map(|(id, list)| (group.clone(), id.clone(), list)));
+    }
+    ret
+}
+
+pub type TimeMapFn = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+
+/// How `Input::merge` combines the telemetry of two files, eg. a video and a log of external IMU (gcsv, bbl)
+#[derive(Clone)]
+pub struct MergePolicy {
+    /// Groups taken from the secondary input. All other groups are taken from the primary input
+    pub secondary_groups: Vec<GroupId>,
+    /// Added to the timestamps of the secondary input, in milliseconds
+    pub time_offset_ms: f64,
+    /// Maps the timestamps of the secondary input (in milliseconds, after `time_offset_ms`) to the timeline of the primary input.
+    /// Useful to correct a clock drift or apply an offset found by synchronization
+    pub time_map: Option<TimeMapFn>,
+}
+impl Default for MergePolicy {
+    fn default() -> Self {
+        Self {
+            secondary_groups: vec![GroupId::Gyroscope, GroupId::Accelerometer, GroupId::Magnetometer, GroupId::Quaternion],
+            time_offset_ms: 0.0,
+            time_map: None,
+        }
+    }
+}
+impl std::fmt::Debug for MergePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergePolicy")
+            .field("secondary_groups", &self.secondary_groups)
+            .field("time_offset_ms", &self.time_offset_ms)
+            .field("time_map", &self.time_map.is_some())
+            .finish()
+    }
+}
+
+// Applies `f` to the timestamps of all time series in `map`. `f` takes and returns milliseconds
+pub(crate) fn map_timestamps(map: &mut TagMap, f: &TimeMapFn) {
+    macro_rules! seconds {
+        ($v:expr) => {{
+            let f = f.clone();
+            $v.modify(move |v| for x in v.iter_mut() { x.t = f(x.t * 1000.0) / 1000.0; });
+        }};
+    }
+    for desc in map.values_mut() {
+        match &mut desc.value {
+            TagValue::Vec_TimeVector3_f64(v)    => seconds!(v),
+            TagValue::Vec_TimeVector3_i64f64(v) => seconds!(v),
+            TagValue::Vec_TimeScalar_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeScalar_i64(v)     => seconds!(v),
+            TagValue::Vec_TimeScalar_bool(v)    => seconds!(v),
+            TagValue::Vec_TimeScalar_Json(v)    => seconds!(v),
+            TagValue::Vec_TimeArray2_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeArray4_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeArray8_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeVector3_f32(v) => {
+                let f = f.clone();
+                v.modify(move |v| for x in v.iter_mut() { x.t = (f(x.t as f64 * 1000.0) / 1000.0) as f32; });
+            },
+            // Quaternion timestamps are in milliseconds
+            TagValue::Vec_TimeQuaternion_f64(v) => {
+                let f = f.clone();
+                v.modify(move |v| for x in v.iter_mut() { x.t = f(x.t); });
+            },
+            _ => ()
+        }
+    }
+}
+
+// Keeps only the readings between `start_ms` (inclusive) and `end_ms`, with the timestamps relative to `start_ms`
+pub(crate) fn slice_timestamps(map: &mut TagMap, start_ms: f64, end_ms: f64) {
+    macro_rules! seconds {
+        ($v:expr) => {{
+            $v.modify(move |v| {
+                v.retain(|x| x.t * 1000.0 >= start_ms && x.t * 1000.0 < end_ms);
+                for x in v.iter_mut() { x.t -= start_ms / 1000.0; }
+            });
+        }};
+    }
+    for desc in map.values_mut() {
+        match &mut desc.value {
+            TagValue::Vec_TimeVector3_f64(v)    => seconds!(v),
+            TagValue::Vec_TimeVector3_i64f64(v) => seconds!(v),
+            TagValue::Vec_TimeScalar_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeScalar_i64(v)     => seconds!(v),
+            TagValue::Vec_TimeScalar_bool(v)    => seconds!(v),
+            TagValue::Vec_TimeScalar_Json(v)    => seconds!(v),
+            TagValue::Vec_TimeArray2_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeArray4_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeArray8_f64(v)     => seconds!(v),
+            TagValue::Vec_TimeVector3_f32(v) => {
+                v.modify(move |v| {
+                    v.retain(|x| x.t as f64 * 1000.0 >= start_ms && x.t as f64 * 1000.0 < end_ms);
+                    for x in v.iter_mut() { x.t -= (start_ms / 1000.0) as f32; }
+                });
+            },
+            // Quaternion timestamps are in milliseconds
+            TagValue::Vec_TimeQuaternion_f64(v) => {
+                v.modify(move |v| {
+                    v.retain(|x| x.t >= start_ms && x.t < end_ms);
+                    for x in v.iter_mut() { x.t -= start_ms; }
+                });
+            },
+            _ => ()
+        }
+    }
+}
+
+// Order of `Input::samples`: by `timestamp_ms`, samples with the same timestamp (eg. from different tracks or parts of the recording)
+// by `track_index` and then by `sample_index`, so the result doesn't depend on the order in which the parser found them.
+// The first sample stays first if it's at 0 ms without duration, that's where the parsers put the metadata of the whole file
+pub fn sort_samples<S: std::borrow::Borrow<SampleInfo>>(samples: &mut [S]) {
+    let first = samples.first().is_some_and(|x| x.borrow().timestamp_ms == 0.0 && x.borrow().duration_ms == 0.0) as usize;
+    samples[first..].sort_by(|a, b| {
+        let (a, b) = (a.borrow(), b.borrow());
+        a.timestamp_ms.total_cmp(&b.timestamp_ms)
+            .then(a.track_index.cmp(&b.track_index))
+            .then(a.sample_index.cmp(&b.sample_index))
+    });
+}
+
+// `StartOffset` of each group with a time series: time of the video (in seconds from its first frame) at `t = 0` of the group,
+// so the video time of a reading is `t + offset`. With accurate timestamps the readings are already in the video time and the offset is 0,
+// otherwise the first reading is at the start of the sample which contains it, like in `normalized_imu`.
+// Readings without their own time (eg. GoPro and Sony raw IMU) start at the first sample with them. GPS has unix timestamps, so it's skipped
+pub(crate) fn insert_start_offsets(samples: &mut [SampleInfo], accurate_timestamps: bool) {
+    let mut done = BTreeSet::new();
+    for info in samples.iter_mut() {
+        let Some(map) = info.tag_map.as_mut() else { continue; };
+        for (group, tags) in map.iter_mut() {
+            if *group == GroupId::GPS || done.contains(group) { continue; }
+            if tags.contains_key(&TagId::StartOffset) { done.insert(group.clone()); continue; }
+            let Some(data) = tags.get(&TagId::Data) else { continue; };
+            // Time of the first reading in seconds, `None` if the readings don't have their own time
+            let first_t = match &data.value {
+                TagValue::Vec_TimeVector3_f64(v)    => v.get().first().map(|x| Some(x.t)),
+                TagValue::Vec_TimeQuaternion_f64(v) => v.get().first().map(|x| Some(x.t / 1000.0)),
+                TagValue::Vec_TimeScalar_f64(v)     => v.get().first().map(|x| Some(x.t)),
+                TagValue::Vec_Vector3_i16(v)        => v.get().first().map(|_| None),
+                TagValue::Vec_Vector3_i32(v)        => v.get().first().map(|_| None),
+                _ => None
+            };
+            let Some(first_t) = first_t else { continue; };
+            let offset = match first_t {
+                None => info.timestamp_ms / 1000.0,
+                Some(_) if accurate_timestamps => 0.0,
+                Some(t) => info.timestamp_ms / 1000.0 - t,
+            };
+            tags.insert(TagId::StartOffset, crate::tag!(parsed group.clone(), TagId::StartOffset, "Start offset", f64, |v| format!("{:.6} s", v), offset, vec![]));
+            done.insert(group.clone());
+        }
+    }
+}
+
+// Uniformly decimate the `Data` tag of each group to roughly `max_samples_per_group` readings in total.
+// Each bucket of readings is reduced to its minimum and maximum, so peaks are still visible in plots.
+pub fn decimate_samples(samples: &mut [SampleInfo], max_samples_per_group: usize) {
+    fn decimate<T: Clone>(v: &mut Vec<T>, bucket: usize, key: impl Fn(&T) -> f64) {
+        let mut out = Vec::with_capacity(v.len() / bucket * 2 + 2);
+        for chunk in v.chunks(bucket) {
+            let (mut min_i, mut max_i) = (0, 0);
+            for (i, x) in chunk.iter().enumerate() {
+                let k = key(x);
+                if k < key(&chunk[min_i]) { min_i = i; }
+                if k > key(&chunk[max_i]) { max_i = i; }
+            }
+            out.push(chunk[min_i.min(max_i)].clone());
+            if min_i != max_i {
+                out.push(chunk[min_i.max(max_i)].clone());
+            }
+        }
+        *v = out;
+    }
+    fn magnitude(x: f64, y: f64, z: f64) -> f64 { (x * x + y * y + z * z).sqrt() }
+
+    macro_rules! for_each_data {
+        ($value:expr, $v:ident, $key:ident, $body:expr) => {
+            match $value {
+                TagValue::Vec_Vector3_i16(t)        => { let $v = t; let $key = |x: &Vector3<i16>|        magnitude(x.x as f64, x.y as f64, x.z as f64); $body }
+                TagValue::Vec_Vector3_f32(t)        => { let $v = t; let $key = |x: &Vector3<f32>|        magnitude(x.x as f64, x.y as f64, x.z as f64); $body }
+                TagValue::Vec_Vector3_f64(t)        => { let $v = t; let $key = |x: &Vector3<f64>|        magnitude(x.x, x.y, x.z); $body }
+                TagValue::Vec_TimeVector3_f64(t)    => { let $v = t; let $key = |x: &TimeVector3<f64>|    magnitude(x.x, x.y, x.z); $body }
+                TagValue::Vec_TimeScalar_f64(t)     => { let $v = t; let $key = |x: &TimeScalar<f64>|     x.v; $body }
+                TagValue::Vec_TimeQuaternion_f64(t) => { let $v = t; let $key = |x: &TimeQuaternion<f64>| x.v.w; $body }
+                _ => None
+            }
+        };
+    }
+
+    let mut totals = BTreeMap::<GroupId, usize>::new();
+    for map in samples.iter().filter_map(|x| x.tag_map.as_ref()) {
+        for (group, tags) in map {
+            if let Some(tag) = tags.get(&TagId::Data) {
+                if let Some(len) = for_each_data!(&tag.value, v, _key, Some(v.get().len())) {
+                    *totals.entry(group.clone()).or_default() += len;
+                }
+            }
+        }
+    }
+
+    for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+        for (group, tags) in map.iter_mut() {
+            let total = totals.get(group).copied().unwrap_or_default();
+            if total <= max_samples_per_group.max(1) { continue; }
+            // Two readings are kept from each bucket
+            let bucket = ((total as f64 / max_samples_per_group.max(1) as f64) * 2.0).ceil() as usize;
+
+            if let Some(tag) = tags.get_mut(&TagId::Data) {
+                for_each_data!(&mut tag.value, v, key, {
+                    let _ = v.get(); // make sure it's parsed
+                    decimate(v.get_mut(), bucket, key);
+                    v.raw_data = Vec::new();
+                    Some(())
+                });
+            }
+        }
+    }
+}
+
+// Splits the samples of formats which store all readings at once (eg. in a trailer) into samples of `bucket_ms`,
+// so they have the per-chunk timing like the MP4-based formats. `timestamp_ms` and `duration_ms` of each sample cover its readings, in their time base.
+// Groups with time-based `Data` are split and their other tags (unit, orientation, scale) are copied to every sample.
+// The `Default` group and the other groups stay in the first sample, except `Default/DeviceID`, which is copied to identify the device of every sample.
+// Nothing is split with `probe_only`, because it requires decoding of the data
+pub fn split_samples_by_time(samples: Vec<SampleInfo>, bucket_ms: f64) -> Vec<SampleInfo> {
+    macro_rules! for_each_time_series {
+        ($value:expr, $v:ident, $time:ident, $wrap:ident, $body:expr) => {
+            match $value {
+                TagValue::Vec_TimeVector3_f64(t)    => { let $v = t; let $wrap = TagValue::Vec_TimeVector3_f64;    let $time = |x: &TimeVector3<f64>|    x.t * 1000.0; $body }
+                TagValue::Vec_TimeScalar_f64(t)     => { let $v = t; let $wrap = TagValue::Vec_TimeScalar_f64;     let $time = |x: &TimeScalar<f64>|     x.t * 1000.0; $body }
+                TagValue::Vec_TimeQuaternion_f64(t) => { let $v = t; let $wrap = TagValue::Vec_TimeQuaternion_f64; let $time = |x: &TimeQuaternion<f64>| x.t; $body }
+                _ => None
+            }
+        };
+    }
+    if is_probe_only() || bucket_ms <= 0.0 { return samples; }
+
+    let mut ret = Vec::with_capacity(samples.len());
+    for mut sample in samples {
+        let Some(mut map) = sample.tag_map.take() else { ret.push(sample); continue; };
+
+        let mut range: Option<(f64, f64)> = None;
+        for (group, tags) in &map {
+            if group == &GroupId::Default { continue; }
+            let Some(tag) = tags.get(&TagId::Data) else { continue; };
+            let group_range = for_each_time_series!(&tag.value, v, time, _wrap, {
+                let v = v.get();
+                Some((time(v.first()?), time(v.last()?)))
+            });
+            if let Some((first, last)) = group_range {
+                range = Some(range.map_or((first, last), |(a, b)| (a.min(first), b.max(last))));
+            }
+        }
+        let Some((first, last)) = range.filter(|(a, b)| b - a >= bucket_ms) else {
+            sample.tag_map = Some(map);
+            ret.push(sample);
+            continue;
+        };
+        let count = ((last - first) / bucket_ms).floor() as usize + 1;
+        let bucket = |t: f64| (((t - first) / bucket_ms).max(0.0) as usize).min(count - 1);
+
+        let mut maps = vec![GroupedTagMap::new(); count];
+        let device_id = map.get(&GroupId::Default).and_then(|x| x.get(&TagId::Custom("DeviceID".into()))).cloned();
+        for (group, mut tags) in map {
+            let mut data = tags.remove(&TagId::Data);
+            let split = data.as_mut().filter(|_| group != GroupId::Default).and_then(|tag| {
+                for_each_time_series!(&mut tag.value, v, time, wrap, {
+                    let mut chunks = vec![Vec::new(); count];
+                    for x in std::mem::take(v.get_mut()) {
+                        chunks[bucket(time(&x))].push(x);
+                    }
+                    v.raw_data = Vec::new();
+                    let empty = v.clone();
+                    Some(chunks.into_iter().map(|chunk| {
+                        let mut value = empty.clone();
+                        *value.get_mut() = chunk;
+                        TagDescription { group: tag.group.clone(), id: tag.id.clone(), native_id: tag.native_id, description: tag.description.clone(), value: wrap(value) }
+                    }).collect::<Vec<_>>())
+                })
+            });
+            match split {
+                Some(descs) => {
+                    for (m, desc) in maps.iter_mut().zip(descs) {
+                        let is_empty = for_each_time_series!(&desc.value, v, _time, _wrap, Some(v.get().is_empty())).unwrap_or_default();
+                        if is_empty { continue; }
+                        let mut group_tags = tags.clone();
+                        group_tags.insert(TagId::Data, desc);
+                        m.insert(group.clone(), group_tags);
+                    }
+                },
+                None => {
+                    if let Some(data) = data {
+                        tags.insert(TagId::Data, data);
+                    }
+                    maps[0].insert(group, tags);
+                }
+            }
+        }
+
+        for (i, mut m) in maps.into_iter().enumerate() {
+            if m.is_empty() { continue; }
+            if let Some(id) = device_id.as_ref() {
+                m.entry(GroupId::Default).or_default().entry(id.id.clone()).or_insert_with(|| id.clone());
+            }
+            let timestamp_ms = first + i as f64 * bucket_ms;
+            ret.push(SampleInfo {
+                timestamp_ms,
+                duration_ms: (last - timestamp_ms).clamp(0.0, bucket_ms),
+                tag_map: Some(m),
+                ..sample.clone()
+            });
+        }
+    }
+    for (i, s) in ret.iter_mut().enumerate() {
+        s.sample_index = i as u64;
+    }
+    ret
+}
+
 pub fn create_csv_map<'a, 'b>(row: &'b csv::StringRecord, headers: &'a Vec<String>) -> BTreeMap<&'a str, &'b str> {
     headers.iter().zip(row).map(|(a, b)| (&a[..], b.trim())).collect()
 }
+/// Column mapping of a CSV log from a logger which isn't supported natively.
+/// Can be passed in `InputOptions::csv_schema` or stored next to the CSV as `<name>.schema.json` / `<name>.schema.yaml`
+/// (and `<name>.schema.toml` with the `toml-schema` feature)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CsvSchema {
+    /// Name used as the camera model
+    pub model: Option<String>,
+    /// Column with the timestamps
+    pub time_column: String,
+    /// Multiplier converting the timestamps to seconds, eg. 0.001 for milliseconds
+    pub time_scale: f64,
+    /// Column names of the x, y, z axes
+    pub gyro: Option<[String; 3]>,
+    pub accl: Option<[String; 3]>,
+    pub magn: Option<[String; 3]>,
+    pub gyro_unit: String,
+    pub accl_unit: String,
+    pub magn_unit: String,
+    /// IMU orientation, eg. `XYZ`
+    pub orientation: String,
+    pub delimiter: char,
+    /// Number of lines before the header row
+    pub skip_lines: usize,
+}
+impl Default for CsvSchema {
+    fn default() -> Self {
+        Self {
+            model: None,
+            time_column: "time".into(),
+            time_scale: 1.0,
+            gyro: None,
+            accl: None,
+            magn: None,
+            gyro_unit: "deg/s".into(),
+            accl_unit: "m/s²".into(),
+            magn_unit: "μT".into(),
+            orientation: "XYZ".into(),
+            delimiter: ',',
+            skip_lines: 0,
+        }
+    }
+}
+
+thread_local! {
+    static CSV_SCHEMA: std::cell::RefCell<Option<CsvSchema>> = const { std::cell::RefCell::new(None) };
+}
+pub(crate) fn set_csv_schema(schema: Option<CsvSchema>) {
+    CSV_SCHEMA.set(schema);
+}
+/// Schema passed in `InputOptions::csv_schema` for the file being detected on this thread
+pub(crate) fn csv_schema() -> Option<CsvSchema> {
+    CSV_SCHEMA.with_borrow(|x| x.clone())
+}
+
 pub fn create_csv_map_hdr<'a, 'b>(row: &'b csv::StringRecord, headers: &'a csv::StringRecord) -> BTreeMap<&'a str, &'b str> {
     headers.iter().zip(row).map(|(a, b)| (a, b)).collect()
 }
@@ -662,6 +1341,145 @@ pub struct VideoMetadata {
     pub rotation: i32
 }
 
+/// Image geometry values needed for stabilization, collected from the `Imager` group
+#[derive(Default, Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ImagerGeometry {
+    pub capture_area_origin: Option<(f32, f32)>,
+    pub capture_area_size: Option<(f32, f32)>,
+    pub sensor_size_pixels: Option<(u32, u32)>,
+    pub pixel_pitch: Option<(u32, u32)>,
+    pub frame_readout_time: Option<f64>, // in ms
+    /// Horizontal stretch needed to de-squeeze the image of an anamorphic lens, eg. 2.0
+    pub anamorphic_squeeze: Option<f64>,
+}
+
+/// Returns the imager geometry valid at `samples[sample_index]`.
+/// Values can change mid-file, so the last value seen up to and including that sample is used
+pub fn imager_geometry(samples: &[SampleInfo], sample_index: usize) -> ImagerGeometry {
+    let mut ret = ImagerGeometry::default();
+    let mut readout_times: Option<&Vec<TimeScalar<f64>>> = None;
+    for info in samples.iter().take(sample_index.saturating_add(1)) {
+        let Some(imager) = info.tag_map.as_ref().and_then(|x| x.get(&GroupId::Imager)) else { continue; };
+        if let Some(v) = imager.get_t(TagId::CaptureAreaOrigin) as Option<&(f32, f32)> { ret.capture_area_origin = Some(*v); }
+        if let Some(v) = imager.get_t(TagId::CaptureAreaSize)   as Option<&(f32, f32)> { ret.capture_area_size   = Some(*v); }
+        if let Some(v) = imager.get_t(TagId::SensorSizePixels)  as Option<&(u32, u32)> { ret.sensor_size_pixels  = Some(*v); }
+        if let Some(v) = imager.get_t(TagId::PixelPitch)        as Option<&(u32, u32)> { ret.pixel_pitch         = Some(*v); }
+        if let Some(v) = imager.get_t(TagId::FrameReadoutTime)  as Option<&f64>        { ret.frame_readout_time  = Some(*v); }
+        if let Some(v) = imager.get_t(TagId::AnamorphicSqueeze) as Option<&f64>        { ret.anamorphic_squeeze  = Some(*v).filter(|x| *x > 0.0); }
+        if let Some(v) = imager.get_t(TagId::FrameReadoutTimes) as Option<&Vec<TimeScalar<f64>>> { readout_times = Some(v); }
+    }
+    if let (Some(series), Some(info)) = (readout_times, samples.get(sample_index)) {
+        let t = info.timestamp_ms / 1000.0;
+        if let Some(v) = series.iter().take_while(|x| x.t <= t).last().or(series.first()) {
+            ret.frame_readout_time = Some(v.v);
+        }
+    }
+    ret
+}
+
+// Sensor database from the JSON file in the `TELEMETRY_PARSER_SENSOR_DB` environment variable, loaded once
+fn env_sensor_database() -> Option<&'static serde_json::Value> {
+    static DB: std::sync::OnceLock<Option<serde_json::Value>> = std::sync::OnceLock::new();
+    DB.get_or_init(|| {
+        if cfg!(feature = "no-fs") { return None; }
+        let path = std::env::var_os("TELEMETRY_PARSER_SENSOR_DB")?;
+        let db = std::fs::read(&path).map_err(|e| e.to_string()).and_then(|x| serde_json::from_slice::<serde_json::Value>(&x).map_err(|e| e.to_string()));
+        db.map_err(|e| log::warn!("Failed to load the sensor database {path:?}: {e}")).ok()
+    }).as_ref()
+}
+
+/// Pixel pitch of the camera from the sensor database supplied by the user: `InputOptions::sensor_database`, and then the JSON file
+/// in the `TELEMETRY_PARSER_SENSOR_DB` environment variable. The database is `{"<camera type>": {"<model>": {"pixel_pitch": [x, y]}}}`,
+/// with the camera type and model as reported by `Input` and the pixel pitch in nanometers, like the `PixelPitch` tag.
+/// Eg. `{"RED": {"V-RAPTOR XL 8K VV": {"pixel_pitch": [5000, 5000]}}}`
+pub fn sensor_database_pixel_pitch(db: Option<&serde_json::Value>, camera_type: &str, model: &str) -> Option<(u32, u32)> {
+    [db, env_sensor_database()].into_iter().flatten().find_map(|db| {
+        let pp = db.get(camera_type)?.get(model)?.get("pixel_pitch")?.as_array()?;
+        let x = pp.first()?.as_u64()? as u32;
+        Some((x, pp.get(1).and_then(|y| y.as_u64()).map(|y| y as u32).unwrap_or(x)))
+    })
+}
+
+// The pixel pitch from the sensor database replaces the built-in one of the parser, or is added to the first sample for the models the parser doesn't know
+pub(crate) fn apply_sensor_database(samples: &mut Vec<SampleInfo>, db: Option<&serde_json::Value>, camera_type: &str, model: Option<&str>) {
+    let Some(pp) = model.and_then(|m| sensor_database_pixel_pitch(db, camera_type, m)) else { return; };
+    let mut found = false;
+    for map in samples.iter_mut().filter_map(|x| x.tag_map.as_mut()) {
+        if map.get(&GroupId::Imager).is_some_and(|x| x.contains_key(&TagId::PixelPitch)) {
+            insert_tag(map, crate::tag!(parsed GroupId::Imager, TagId::PixelPitch, "Pixel pitch", u32x2, |v| format!("{v:?}"), pp, vec![]));
+            found = true;
+        }
+    }
+    if !found {
+        if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+            samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+        }
+        if let Some(map) = samples[0].tag_map.as_mut() {
+            insert_tag(map, crate::tag!(parsed GroupId::Imager, TagId::PixelPitch, "Pixel pitch", u32x2, |v| format!("{v:?}"), pp, vec![]));
+        }
+    }
+}
+
+/// Lowest gyroscope rate (in Hz) which is still usable for stabilization
+pub const MIN_GYRO_SAMPLE_RATE: f64 = 50.0;
+
+/// Answer to "can this file be stabilized?", see `suitability_report`
+#[derive(Default, Debug, Clone, serde::Serialize)]
+pub struct SuitabilityReport {
+    pub suitable: bool,
+    pub has_gyroscope: bool,
+    pub has_quaternions: bool,
+    pub has_orientation: bool,
+    /// Average gyroscope rate in Hz
+    pub gyro_sample_rate: Option<f64>,
+    pub timestamps_monotonic: bool,
+    /// Human readable reasons of the verdict. Missing IMU orientation is reported here, but doesn't make the file unsuitable
+    pub reasons: Vec<String>,
+}
+
+/// Checks whether the parsed telemetry has everything needed for stabilization:
+/// gyroscope or quaternions, gyroscope rate of at least `MIN_GYRO_SAMPLE_RATE`, increasing timestamps and the IMU orientation
+pub fn suitability_report(input: &crate::Input) -> SuitabilityReport {
+    let mut ret = SuitabilityReport { timestamps_monotonic: true, ..Default::default() };
+    for map in input.samples.iter().flatten().filter_map(|x| x.tag_map.as_ref()) {
+        if let Some(gyro) = map.get(&GroupId::Gyroscope) {
+            ret.has_gyroscope |= gyro.contains_key(&TagId::Data);
+            ret.has_orientation |= gyro.contains_key(&TagId::Orientation);
+        }
+        if let Some(quats) = map.get(&GroupId::Quaternion) {
+            ret.has_quaternions |= quats.contains_key(&TagId::Data);
+        }
+    }
+
+    if ret.has_gyroscope {
+        let imu = normalized_imu(input, None).unwrap_or_default();
+        let gyro = imu.iter().filter(|x| x.gyro.is_some()).map(|x| x.timestamp_ms).collect::<Vec<f64>>();
+        ret.timestamps_monotonic = gyro.windows(2).all(|x| x[1] > x[0]);
+        if let (Some(first), Some(last)) = (gyro.first(), gyro.last()) {
+            if last > first {
+                ret.gyro_sample_rate = Some((gyro.len() - 1) as f64 / ((last - first) / 1000.0));
+            }
+        }
+        if !ret.timestamps_monotonic {
+            ret.reasons.push("Gyroscope timestamps are not increasing".into());
+        }
+        match ret.gyro_sample_rate {
+            Some(rate) if rate < MIN_GYRO_SAMPLE_RATE => ret.reasons.push(format!("Gyroscope rate is too low ({:.1} Hz, at least {} Hz is needed)", rate, MIN_GYRO_SAMPLE_RATE)),
+            None => ret.reasons.push("Not enough gyroscope samples".into()),
+            _ => { }
+        }
+        if !ret.has_orientation {
+            ret.reasons.push("IMU orientation is not reported, XYZ will be assumed".into());
+        }
+    } else if !ret.has_quaternions {
+        ret.reasons.push("No gyroscope or quaternion data".into());
+    }
+
+    let gyro_usable = ret.has_gyroscope && ret.timestamps_monotonic && ret.gyro_sample_rate.map(|x| x >= MIN_GYRO_SAMPLE_RATE).unwrap_or_default();
+    ret.suitable = gyro_usable || ret.has_quaternions;
+    ret
+}
+
 pub fn get_video_metadata_from_track(track: &mp4parse::Track) -> Result<VideoMetadata> {
     let mut duration_sec = 0.0;
     if let Some(d) = track.duration {
@@ -742,16 +1560,26 @@ pub const fn fourcc(s: &str) -> u32 {
     let s = s.as_bytes();
     (s[3] as u32) | ((s[2] as u32) << 8) | ((s[1] as u32) << 16) | ((s[0] as u32) << 24)
 }
+// Returns (type, offset, size, header size) of the box at the current position.
+// Handles 64-bit sizes (size == 1) and boxes extending to the end of the file (size == 0)
 pub fn read_box<R: Read + Seek>(reader: &mut R) -> Result<(u32, u64, u64, i64)> {
     let pos = reader.stream_position()?;
     let size = reader.read_u32::<BigEndian>()?;
     let typ = reader.read_u32::<BigEndian>()?;
-    if size == 1 {
-        let largesize = reader.read_u64::<BigEndian>()?;
-        Ok((typ, pos, largesize, 16))
-    } else {
-        Ok((typ, pos, size as u64, 8))
+    let (size, header_size) = match size {
+        1 => (reader.read_u64::<BigEndian>()?, 16),
+        0 => {
+            let data_pos = reader.stream_position()?;
+            let end = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(data_pos))?;
+            (end - pos, 8)
+        },
+        _ => (size as u64, 8)
+    };
+    if size < header_size as u64 {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Invalid box size {size} at {pos}")));
     }
+    Ok((typ, pos, size, header_size))
 }
 
 static mut LOAD_GYRO_ONLY: bool = false;
@@ -762,6 +1590,586 @@ pub fn get_load_gyro_only() -> bool{
     unsafe { LOAD_GYRO_ONLY }
 }
 
+// Some cameras change the readout time mid-file (sensor mode switch, variable crop),
+// so parsers collect it per frame and expose the changes as `Imager/FrameReadoutTimes`
+pub fn push_readout_time(series: &mut Vec<TimeScalar<f64>>, timestamp_ms: f64, readout_time_ms: f64) {
+    if series.last().map(|x| (x.v - readout_time_ms).abs() > 1e-6).unwrap_or(true) {
+        series.push(TimeScalar { t: timestamp_ms / 1000.0, v: readout_time_ms });
+    }
+}
+pub fn insert_readout_time_series(samples: &mut Vec<SampleInfo>, series: Vec<TimeScalar<f64>>) {
+    if series.is_empty() { return; }
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        insert_tag(map, crate::tag!(parsed GroupId::Imager, TagId::FrameReadoutTimes, "Frame readout times", Vec_TimeScalar_f64, |v| format!("{:?}", v), series, vec![]));
+    }
+}
+
+/// Adds `markers` to `Markers/Data` of the map, together with the ones already there, ordered by time
+pub fn insert_markers(map: &mut GroupedTagMap, mut markers: Vec<MarkerData>) {
+    if markers.is_empty() { return; }
+    let existing = map.get_mut(&GroupId::Markers).and_then(|x| x.get_mut(&TagId::Data));
+    if let Some(TagDescription { value: TagValue::Vec_MarkerData(v), .. }) = existing {
+        v.get_mut().extend(markers);
+        v.get_mut().sort_by(|a, b| a.t.total_cmp(&b.t));
+    } else {
+        markers.sort_by(|a, b| a.t.total_cmp(&b.t));
+        insert_tag(map, crate::tag!(parsed GroupId::Markers, TagId::Data, "Markers", Vec_MarkerData, |v| format!("{:?}", v), markers, vec![]));
+    }
+}
+
+/// Adds `events` to `Events/Data`, keeping the ones already stored by the parser or previous processing steps
+pub fn insert_events(map: &mut GroupedTagMap, mut events: Vec<CameraEvent>) {
+    if events.is_empty() { return; }
+    let existing = map.get_mut(&GroupId::Events).and_then(|x| x.get_mut(&TagId::Data));
+    if let Some(TagDescription { value: TagValue::Vec_CameraEvent(v), .. }) = existing {
+        v.get_mut().extend(events);
+        v.get_mut().sort_by(|a, b| a.t.total_cmp(&b.t));
+    } else {
+        events.sort_by(|a, b| a.t.total_cmp(&b.t));
+        insert_tag(map, crate::tag!(parsed GroupId::Events, TagId::Data, "Camera events", Vec_CameraEvent, |v| format!("{:?}", v), events, vec![]));
+    }
+}
+
+/// Reports a telemetry sample which couldn't be decoded and returns the marker of the gap it leaves in the data.
+/// Parsers skip such samples and continue with the next one, the markers are added to the file with `insert_gap_markers`
+pub fn corrupted_sample(info: &SampleInfo, error: &dyn std::fmt::Debug) -> MarkerData {
+    crate::parse_warning!(at info.file_position; "Corrupted sample {} at {:.3} s: {error:?}", info.sample_index, info.timestamp_ms / 1000.0);
+    MarkerData {
+        t: info.timestamp_ms / 1000.0,
+        kind: "Gap".into(),
+        label: "Corrupted sample".into(),
+        payload: serde_json::json!({
+            "track_index": info.track_index,
+            "sample_index": info.sample_index,
+            "file_position": info.file_position,
+            "duration": info.duration_ms / 1000.0,
+            "error": format!("{error:?}"),
+        }),
+    }
+}
+pub fn insert_gap_markers(samples: &mut Vec<SampleInfo>, markers: Vec<MarkerData>) {
+    if markers.is_empty() { return; }
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        insert_markers(map, markers);
+    }
+}
+
+/// Markers of all samples, ordered by time
+pub fn get_markers(samples: &[SampleInfo]) -> Vec<MarkerData> {
+    let mut ret = samples.iter()
+        .filter_map(|x| x.tag_map.as_ref()?.get(&GroupId::Markers)?.get_t(TagId::Data) as Option<&Vec<MarkerData>>)
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| a.t.total_cmp(&b.t));
+    ret
+}
+
+/// Copy of `value` with the keys of all objects sorted, so the serialized JSON doesn't depend on the order in which the parsers inserted them.
+/// Without it, objects keep the insertion order (`preserve_order`), which usually follows the order in the file
+pub fn canonical_json(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(o) => {
+            let mut keys = o.keys().collect::<Vec<_>>();
+            keys.sort();
+            serde_json::Value::Object(keys.into_iter().map(|k| (k.clone(), canonical_json(&o[k]))).collect())
+        },
+        serde_json::Value::Array(a) => serde_json::Value::Array(a.iter().map(canonical_json).collect()),
+        v => v.clone()
+    }
+}
+pub fn canonical_json_string(value: &serde_json::Value) -> String {
+    canonical_json(value).to_string()
+}
+
+/// Stores the start of the recording in `GroupId::Default`: `RecordingStartLocal` (camera clock, without a time zone),
+/// `RecordingStartUtc` and `TimezoneOffsetMinutes` (local - UTC). Pass what the format has, `recording_start` derives the rest
+pub fn insert_recording_start(map: &mut GroupedTagMap, local: Option<chrono::NaiveDateTime>, utc: Option<chrono::NaiveDateTime>, offset_minutes: Option<i32>) {
+    if let Some(v) = local {
+        insert_tag(map, crate::tag!(parsed GroupId::Default, TagId::RecordingStartLocal, "Recording start (local time)", String, |v| v.clone(), v.format("%Y-%m-%dT%H:%M:%S").to_string(), vec![]));
+    }
+    if let Some(v) = utc {
+        insert_tag(map, crate::tag!(parsed GroupId::Default, TagId::RecordingStartUtc, "Recording start (UTC)", String, |v| v.clone(), v.format("%Y-%m-%dT%H:%M:%SZ").to_string(), vec![]));
+    }
+    if let Some(v) = offset_minutes {
+        insert_tag(map, crate::tag!(parsed GroupId::Default, TagId::TimezoneOffsetMinutes, "Time zone offset", i32, |v| format!("{:+03}:{:02}", v / 60, (v % 60).abs()), v, vec![]));
+    }
+}
+
+/// Start of the recording, reconciled from the `RecordingStart*` tags, see `recording_start`
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RecordingStart {
+    /// `YYYY-MM-DDTHH:MM:SS` in the time zone of the camera
+    pub local: Option<String>,
+    /// `YYYY-MM-DDTHH:MM:SSZ`
+    pub utc: Option<String>,
+    /// Local time - UTC
+    pub timezone_offset_minutes: Option<i32>,
+    /// Values which were derived from the other two
+    pub derived: Vec<&'static str>,
+    /// Inconsistencies between the stored values. When all three are present and don't agree, the stored values are kept as they are
+    pub issues: Vec<String>,
+}
+
+/// Reconciles the local time, UTC and time zone offset of the recording start. If only two of them are stored, the third is derived.
+/// If all three are stored, they are checked against each other. Offsets are expected in 15 minute steps and within ±14 hours, like the real time zones
+pub fn recording_start(samples: &[SampleInfo]) -> Option<RecordingStart> {
+    let map = samples.iter().filter_map(|x| x.tag_map.as_ref()?.get(&GroupId::Default)).find(|x| {
+        x.contains_key(&TagId::RecordingStartLocal) || x.contains_key(&TagId::RecordingStartUtc) || x.contains_key(&TagId::TimezoneOffsetMinutes)
+    })?;
+    let parse = |id: TagId| -> Option<chrono::NaiveDateTime> {
+        let v = map.get_t(id) as Option<&String>;
+        chrono::NaiveDateTime::parse_from_str(v?.trim_end_matches('Z'), "%Y-%m-%dT%H:%M:%S").ok()
+    };
+    let mut local  = parse(TagId::RecordingStartLocal);
+    let mut utc    = parse(TagId::RecordingStartUtc);
+    let mut offset = (map.get_t(TagId::TimezoneOffsetMinutes) as Option<&i32>).copied();
+
+    let mut ret = RecordingStart::default();
+    let plausible = |minutes: i64| minutes % 15 == 0 && minutes.abs() <= 14 * 60;
+    if let Some(o) = offset {
+        if !plausible(o as i64) {
+            ret.issues.push(format!("Time zone offset of {o} minutes isn't a real time zone"));
+        }
+    }
+    match (local, utc, offset) {
+        (Some(l), Some(u), Some(o)) => {
+            let diff = (l - u).num_minutes();
+            if diff != o as i64 {
+                ret.issues.push(format!("Local time is {diff} minutes from UTC, but the time zone offset is {o} minutes"));
+            }
+        },
+        (Some(l), Some(u), None) => {
+            let diff = (l - u).num_seconds() as f64 / 60.0;
+            let rounded = (diff / 15.0).round() as i64 * 15;
+            if !plausible(rounded) {
+                ret.issues.push(format!("Local time is {diff:.0} minutes from UTC, the time zone offset can't be derived"));
+            } else {
+                if (diff - rounded as f64).abs() > 1.0 {
+                    ret.issues.push(format!("Local time is {diff:.1} minutes from UTC, the clocks are not in sync"));
+                }
+                offset = Some(rounded as i32);
+                ret.derived.push("timezone_offset_minutes");
+            }
+        },
+        (Some(l), None, Some(o)) => { utc   = Some(l - chrono::Duration::minutes(o as i64)); ret.derived.push("utc"); },
+        (None, Some(u), Some(o)) => { local = Some(u + chrono::Duration::minutes(o as i64)); ret.derived.push("local"); },
+        _ => { }
+    }
+
+    ret.local = local.map(|x| x.format("%Y-%m-%dT%H:%M:%S").to_string());
+    ret.utc = utc.map(|x| x.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    ret.timezone_offset_minutes = offset;
+    Some(ret)
+}
+
+/// Assembles the white balance of each frame into `GroupId::WhiteBalance` in the first sample: `Temperature` (kelvin) and `Tint`,
+/// as `Vec_TimeScalar_f64` with timestamps in seconds. Formats store it differently, so `Colors/WhiteBalance`, `Colors/Tint`,
+/// GoPro `WBAL` and, for the formats which store it there (RED, DJI), the `white_balance_*` keys of the per-frame `Default/Metadata` JSON are all checked
+pub fn collect_white_balance(samples: &mut Vec<SampleInfo>, camera_type: &str) {
+    fn scalars(v: &TagValue) -> Vec<f64> {
+        match v {
+            TagValue::u16(v) => vec![*v.get() as f64],
+            TagValue::i16(v) => vec![*v.get() as f64],
+            TagValue::u32(v) => vec![*v.get() as f64],
+            TagValue::f32(v) => vec![*v.get() as f64],
+            TagValue::f64(v) => vec![*v.get()],
+            TagValue::Vec_u16(v) => v.get().iter().map(|x| *x as f64).collect(),
+            TagValue::Vec_i16(v) => v.get().iter().map(|x| *x as f64).collect(),
+            TagValue::Vec_u32(v) => v.get().iter().map(|x| *x as f64).collect(),
+            TagValue::Vec_f32(v) => v.get().iter().map(|x| *x as f64).collect(),
+            _ => Vec::new()
+        }
+    }
+    fn numbers(v: &serde_json::Value, out: &mut Vec<f64>) {
+        match v {
+            serde_json::Value::Number(n) => out.extend(n.as_f64()),
+            serde_json::Value::Array(a) => a.iter().for_each(|x| numbers(x, out)),
+            serde_json::Value::Object(o) => o.values().for_each(|x| numbers(x, out)),
+            _ => { }
+        }
+    }
+    fn find_key<'a>(v: &'a serde_json::Value, keys: &[&str]) -> Option<&'a serde_json::Value> {
+        match v {
+            serde_json::Value::Object(o) => keys.iter().find_map(|k| o.get(*k)).or_else(|| o.values().find_map(|x| find_key(x, keys))),
+            serde_json::Value::Array(a) => a.iter().find_map(|x| find_key(x, keys)),
+            _ => None
+        }
+    }
+    let metadata_wb = camera_type.starts_with("RED") || camera_type == "DJI";
+    let mut kelvin = Vec::new();
+    let mut tint = Vec::new();
+    for info in samples.iter() {
+        let Some(map) = info.tag_map.as_ref() else { continue; };
+        if map.contains_key(&GroupId::WhiteBalance) { return; } // Already provided by the parser
+
+        let tag_values = |group: GroupId, id: TagId| -> Vec<f64> {
+            map.get(&group).and_then(|x| x.get(&id)).map(|x| scalars(&x.value)).unwrap_or_default()
+        };
+        let md = map.get(&GroupId::Default).filter(|_| metadata_wb).and_then(|x| x.get_t(TagId::Metadata) as Option<&serde_json::Value>);
+        let md_values = |keys: &[&str]| -> Vec<f64> {
+            let mut ret = Vec::new();
+            if let Some(v) = md.and_then(|x| find_key(x, keys)) {
+                numbers(v, &mut ret);
+            }
+            ret
+        };
+
+        let mut k = tag_values(GroupId::Colors, TagId::WhiteBalance);
+        if k.is_empty() { k = tag_values(GroupId::Custom("WhiteBalanceTemperature".into()), TagId::Data); }
+        if k.is_empty() { k = md_values(&["white_balance_kelvin", "white_balance_cct"]); }
+        let mut t = tag_values(GroupId::Colors, TagId::Tint);
+        if t.is_empty() { t = md_values(&["white_balance_tint"]); }
+
+        // Multiple values in one sample (eg. GoPro) are spread evenly over its duration
+        let push = |series: &mut Vec<TimeScalar<f64>>, values: Vec<f64>| {
+            let step = info.duration_ms / values.len().max(1) as f64;
+            for (i, v) in values.into_iter().enumerate() {
+                series.push(TimeScalar { t: (info.timestamp_ms + i as f64 * step) / 1000.0, v });
+            }
+        };
+        push(&mut kelvin, k.into_iter().filter(|x| *x > 0.0).collect());
+        push(&mut tint, t);
+    }
+    if kelvin.is_empty() && tint.is_empty() { return; }
+
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        if !kelvin.is_empty() {
+            insert_tag(map, crate::tag!(parsed GroupId::WhiteBalance, TagId::Temperature, "White balance temperature", Vec_TimeScalar_f64, |v| format!("{:?}", v), kelvin, vec![]));
+            insert_tag(map, crate::tag!(parsed GroupId::WhiteBalance, TagId::Unit, "White balance temperature unit", String, |v| v.to_string(), "K".into(), vec![]));
+        }
+        if !tint.is_empty() {
+            insert_tag(map, crate::tag!(parsed GroupId::WhiteBalance, TagId::Tint, "White balance tint", Vec_TimeScalar_f64, |v| format!("{:?}", v), tint, vec![]));
+        }
+    }
+}
+
+/// Inserts the device temperature (°C) series to the first sample as `Temperature/Data`, with timestamps in seconds
+pub(crate) fn insert_temperature(samples: &mut Vec<SampleInfo>, temperature: Vec<TimeScalar<f64>>) {
+    if temperature.is_empty() { return; }
+
+    if samples.first().map(|x| x.tag_map.is_none()).unwrap_or(true) {
+        samples.insert(0, SampleInfo { tag_map: Some(GroupedTagMap::new()), ..Default::default() });
+    }
+    if let Some(map) = samples[0].tag_map.as_mut() {
+        insert_tag(map, crate::tag!(parsed GroupId::Temperature, TagId::Unit, "Unit", String, |v| v.to_string(), "°C".into(), vec![]));
+        insert_tag(map, crate::tag!(parsed GroupId::Temperature, TagId::Data, "Device temperature", Vec_TimeScalar_f64, |v| format!("{:?}", v), temperature, vec![]));
+    }
+}
+
+thread_local! {
+    static ALLOW_SALVAGE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_allow_salvage(v: bool) {
+    ALLOW_SALVAGE.set(v);
+}
+/// Whether the parsers should try to recover the metadata from files without a valid `moov` box
+pub fn is_salvage_allowed() -> bool {
+    ALLOW_SALVAGE.get()
+}
+
+thread_local! {
+    static GROWING_FILE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_growing_file(v: bool) {
+    GROWING_FILE.set(v);
+}
+/// Whether the file is still being written, so the parsers should stop at the last complete packet instead of failing
+pub fn is_growing_file() -> bool {
+    GROWING_FILE.get()
+}
+
+thread_local! {
+    static MERGE_CHAPTERS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_merge_chapters(v: bool) {
+    MERGE_CHAPTERS.set(v);
+}
+/// Whether the parsers should also read the following chapters of a split recording, see `InputOptions::merge_chapters`
+pub fn is_chapter_merging_enabled() -> bool {
+    MERGE_CHAPTERS.get()
+}
+
+thread_local! {
+    static ALIGN_DEVICE_CLOCKS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_align_device_clocks(v: bool) {
+    ALIGN_DEVICE_CLOCKS.set(v);
+}
+/// Whether the parsers of multi-device logs should shift the timestamps of each device by the estimated clock offset
+pub fn is_device_clock_alignment_enabled() -> bool {
+    ALIGN_DEVICE_CLOCKS.get()
+}
+
+thread_local! {
+    static METADATA_TRACK_INDEX: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+pub(crate) fn set_metadata_track_index(v: Option<usize>) {
+    METADATA_TRACK_INDEX.set(v);
+}
+/// Metadata track selected in `InputOptions::metadata_track_index`, the other metadata tracks are skipped by `get_track_samples`
+pub fn selected_metadata_track() -> Option<usize> {
+    METADATA_TRACK_INDEX.get()
+}
+
+thread_local! {
+    static PROBE_ONLY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_probe_only(v: bool) {
+    PROBE_ONLY.set(v);
+}
+/// Whether the file is parsed with `InputOptions::probe_only`, so the parsers shouldn't decode the lazily parsed tags
+pub fn is_probe_only() -> bool {
+    PROBE_ONLY.get()
+}
+
+thread_local! {
+    static MAP_TO_MEDIA_TIME: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+pub(crate) fn set_map_to_media_time(v: bool) {
+    MAP_TO_MEDIA_TIME.set(v);
+}
+/// Whether the parsers should convert the timestamps of clips played at a different speed (TimeWarp, slow motion) to the playback time
+pub fn is_media_time_enabled() -> bool {
+    MAP_TO_MEDIA_TIME.get()
+}
+
+/// Sets the thread-local parser options from `InputOptions` for the duration of the parsing and resets all of them when dropped,
+/// so they don't leak to the next file parsed on this thread, also when the parsing returns early with an error or panics.
+/// The options are valid only while the parser runs, so lazily parsed tags have to capture the values they need when they are created.
+/// Parts of the parsing which run on other threads get the options with `WorkerContext`
+pub(crate) struct ParseContext;
+impl ParseContext {
+    pub fn new(options: &crate::InputOptions) -> Self {
+        set_csv_schema(options.csv_schema.clone());
+        set_allow_salvage(options.allow_salvage);
+        set_align_device_clocks(options.align_device_clocks);
+        set_metadata_track_index(options.metadata_track_index);
+        set_probe_only(options.probe_only);
+        set_map_to_media_time(options.map_to_media_time);
+        set_growing_file(options.growing_file);
+        set_merge_chapters(options.merge_chapters);
+        Self
+    }
+    /// Prepares the per-format state before `parse` of the detected format
+    pub fn begin_format(&self, options: &crate::InputOptions, camera_type: &str) {
+        if options.validate_checksums {
+            begin_checksum_validation();
+        } else {
+            let _ = end_checksum_validation();
+        }
+        set_tag_merge_policy(options.tag_merge_policy, camera_type);
+        let _ = take_track_stats();
+        let _ = take_parse_warnings();
+    }
+}
+impl Drop for ParseContext {
+    fn drop(&mut self) {
+        set_csv_schema(None);
+        set_allow_salvage(false);
+        set_align_device_clocks(false);
+        set_metadata_track_index(None);
+        set_probe_only(false);
+        set_map_to_media_time(false);
+        set_growing_file(false);
+        set_merge_chapters(false);
+        set_tag_merge_policy(TagMergePolicy::Overwrite, "");
+        let _ = end_checksum_validation();
+    }
+}
+
+/// The parser options of this thread, for parts of the parsing which run on other threads (eg. the RDX decoding of `red`).
+/// `run` sets them on the worker thread and returns what the worker reported (warnings, checksums and track stats),
+/// which has to be added back on the parsing thread with `WorkerReport::merge`, so it ends up in the `Input`
+#[cfg(not(feature = "single-thread"))]
+#[derive(Clone)]
+pub(crate) struct WorkerContext {
+    csv_schema: Option<CsvSchema>,
+    allow_salvage: bool,
+    align_device_clocks: bool,
+    metadata_track_index: Option<usize>,
+    probe_only: bool,
+    map_to_media_time: bool,
+    growing_file: bool,
+    merge_chapters: bool,
+    tag_merge_namespace: Option<String>,
+    validate_checksums: bool,
+}
+#[cfg(not(feature = "single-thread"))]
+#[must_use]
+pub(crate) struct WorkerReport {
+    warnings: Vec<ParseWarning>,
+    track_stats: Vec<TrackStats>,
+    checksums: Option<ChecksumStats>,
+}
+#[cfg(not(feature = "single-thread"))]
+impl WorkerContext {
+    pub fn capture() -> Self {
+        Self {
+            csv_schema: csv_schema(),
+            allow_salvage: is_salvage_allowed(),
+            align_device_clocks: is_device_clock_alignment_enabled(),
+            metadata_track_index: selected_metadata_track(),
+            probe_only: is_probe_only(),
+            map_to_media_time: is_media_time_enabled(),
+            growing_file: is_growing_file(),
+            merge_chapters: is_chapter_merging_enabled(),
+            tag_merge_namespace: TAG_MERGE_NAMESPACE.with_borrow(|x| x.clone()),
+            validate_checksums: is_checksum_validation_enabled(),
+        }
+    }
+    pub fn run<R>(&self, f: impl FnOnce() -> R) -> (R, WorkerReport) {
+        let _reset = ParseContext;
+        set_csv_schema(self.csv_schema.clone());
+        set_allow_salvage(self.allow_salvage);
+        set_align_device_clocks(self.align_device_clocks);
+        set_metadata_track_index(self.metadata_track_index);
+        set_probe_only(self.probe_only);
+        set_map_to_media_time(self.map_to_media_time);
+        set_growing_file(self.growing_file);
+        set_merge_chapters(self.merge_chapters);
+        TAG_MERGE_NAMESPACE.set(self.tag_merge_namespace.clone());
+        if self.validate_checksums {
+            begin_checksum_validation();
+        }
+        let _ = take_track_stats();
+        let _ = take_parse_warnings();
+
+        let ret = f();
+        (ret, WorkerReport { warnings: take_parse_warnings(), track_stats: take_track_stats(), checksums: end_checksum_validation() })
+    }
+}
+#[cfg(not(feature = "single-thread"))]
+impl WorkerReport {
+    pub fn merge(self) {
+        PARSE_WARNINGS.with_borrow_mut(|all| all.extend(self.warnings));
+        for stats in self.track_stats {
+            add_track_stats(stats);
+        }
+        if let (Some(mut stats), Some(worker)) = (CHECKSUM_STATS.get(), self.checksums) {
+            stats.valid += worker.valid;
+            stats.invalid += worker.invalid;
+            CHECKSUM_STATS.set(Some(stats));
+        }
+    }
+}
+
+/// Progress and cancellation check for the row loops of streaming text parsers. The progress is reported every 10000 rows,
+/// `position` is the byte offset of the current row. Returns `true` if the parsing was cancelled
+pub fn row_progress<F: Fn(f64)>(row: u64, position: u64, size: usize, progress_cb: &F, cancel_flag: &AtomicBool) -> bool {
+    if row % 10000 != 0 { return false; }
+    if size > 0 {
+        progress_cb((position as f64 / size as f64).min(1.0));
+    }
+    cancel_flag.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Scans the whole file for `magic` and calls `cb` with the file position and up to `max_chunk_len` bytes starting at each match.
+/// Used to recover metadata packets from `mdat` when the `moov` box is missing (eg. file recovered after a crash)
+pub fn salvage_chunks<T: Read + Seek, F: FnMut(u64, &[u8])>(stream: &mut T, size: usize, magic: &[u8], max_chunk_len: usize, cancel_flag: &Arc<AtomicBool>, mut cb: F) -> Result<()> {
+    const BLOCK_SIZE: usize = 16*1024*1024;
+    let mut buf = Vec::with_capacity(BLOCK_SIZE + max_chunk_len);
+    let mut offset = 0u64;
+    while (offset as usize) < size {
+        if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) { break; }
+        stream.seek(SeekFrom::Start(offset))?;
+        buf.clear();
+        stream.by_ref().take((BLOCK_SIZE + max_chunk_len) as u64).read_to_end(&mut buf)?;
+        if buf.is_empty() { break; }
+
+        // Matches in the overlapping part are handled with the next block
+        let search_len = BLOCK_SIZE.min(buf.len());
+        for pos in memmem::find_iter(&buf[..(search_len + magic.len() - 1).min(buf.len())], magic) {
+            if pos >= search_len { break; }
+            cb(offset + pos as u64, &buf[pos..(pos + max_chunk_len).min(buf.len())]);
+        }
+        offset += BLOCK_SIZE as u64;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct ChecksumStats {
+    pub valid: usize,
+    pub invalid: usize,
+}
+impl ChecksumStats {
+    pub fn corruption_ratio(&self) -> f64 {
+        let total = self.valid + self.invalid;
+        if total == 0 { 0.0 } else { self.invalid as f64 / total as f64 }
+    }
+}
+
+// Parsing of a single file happens on one thread, so the counters are per-thread
+thread_local! {
+    static CHECKSUM_STATS: std::cell::Cell<Option<ChecksumStats>> = const { std::cell::Cell::new(None) };
+}
+pub(crate) fn begin_checksum_validation() {
+    CHECKSUM_STATS.set(Some(ChecksumStats::default()));
+}
+pub(crate) fn end_checksum_validation() -> Option<ChecksumStats> {
+    CHECKSUM_STATS.take()
+}
+/// Whether the parsers should verify checksums of packets/frames and report them with `report_checksum`
+pub fn is_checksum_validation_enabled() -> bool {
+    CHECKSUM_STATS.get().is_some()
+}
+pub fn report_checksum(valid: bool) {
+    if let Some(mut stats) = CHECKSUM_STATS.get() {
+        if valid { stats.valid += 1; } else { stats.invalid += 1; }
+        CHECKSUM_STATS.set(Some(stats));
+        if !valid {
+            crate::parse_warning!("Invalid checksum");
+        }
+    }
+}
+
+/// Warning reported by a parser, collected in `Input::warnings`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWarning {
+    /// Parser module which reported the warning, eg. `gopro` or `sony::mxf`
+    pub module: String,
+    /// Offset in the file, if known
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+thread_local! {
+    static PARSE_WARNINGS: std::cell::RefCell<Vec<ParseWarning>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+pub fn report_warning(module: &str, offset: Option<u64>, message: String) {
+    let module = module.split_once("::").map(|x| x.1).unwrap_or(module).to_owned();
+    PARSE_WARNINGS.with_borrow_mut(|all| all.push(ParseWarning { module, offset, message }));
+}
+/// Returns the warnings reported on this thread since the last call
+pub(crate) fn take_parse_warnings() -> Vec<ParseWarning> {
+    PARSE_WARNINGS.with_borrow_mut(std::mem::take)
+}
+
+// Logs the warning with `log::warn!` and stores it for `Input::warnings`
+#[macro_export]
+macro_rules! parse_warning {
+    (at $offset:expr; $($arg:tt)+) => {{
+        let message = format!($($arg)+);
+        log::warn!("{message}");
+        $crate::util::report_warning(module_path!(), Some($offset as u64), message);
+    }};
+    ($($arg:tt)+) => {{
+        let message = format!($($arg)+);
+        log::warn!("{message}");
+        $crate::util::report_warning(module_path!(), None, message);
+    }};
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct WhitelistItem(pub (GroupId, TagId));
 
@@ -81,16 +81,25 @@ impl Vuze {
 
                     match serde_yaml::from_str(calib.trim()) as serde_yaml::Result<serde_json::Value> {
                         Ok(calib) if calib.get("CamModel_V2_Set").is_some() => {
-                            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Calibration", Json, |v| serde_json::to_string(v).unwrap(), calib.clone(), vec![]));
+                            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Metadata, "Calibration", Json, |v| serde_json::to_string(v).unwrap_or_default(), calib.clone(), vec![]));
 
-                            if let Some(profile) = self.get_lens_profile(&calib["CamModel_V2_Set"], width, height) {
-                                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap(), profile, vec![]));
+                            let cameras = Self::cameras(&calib["CamModel_V2_Set"]);
+                            let profiles = cameras.iter().filter_map(|(name, cam)| Some((name.clone(), self.get_lens_profile(cam, width, height)?))).collect::<serde_json::Map<_, _>>();
+                            if let Some(profile) = profiles.get("CAM_0") {
+                                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Data, "Lens profile", Json, |v| serde_json::to_string(v).unwrap_or_default(), profile.clone(), vec![]));
                             } else {
-                                log::warn!("Failed to get lens profile");
+                                crate::parse_warning!("Failed to get lens profile");
+                            }
+                            if profiles.len() > 1 {
+                                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Custom("CameraProfiles".into()), "Lens profile of each camera", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::Value::Object(profiles), vec![]));
+                            }
+                            let extrinsics = Self::rig_extrinsics(&cameras);
+                            if !extrinsics.is_empty() {
+                                util::insert_tag(&mut map, tag!(parsed GroupId::Lens, TagId::Custom("RigExtrinsics".into()), "Rotation and translation of each camera", Json, |v| serde_json::to_string(v).unwrap_or_default(), serde_json::Value::Array(extrinsics), vec![]));
                             }
                         },
-                        Err(e) => log::warn!("Failed to parse YAML: {}\n{}", e, &calib),
-                        _ => log::warn!("Failed to parse YAML: {}", &calib)
+                        Err(e) => crate::parse_warning!("Failed to parse YAML: {}\n{}", e, &calib),
+                        _ => crate::parse_warning!("Failed to parse YAML: {}", &calib)
                     }
                 }
                 if typ == fourcc("bmdt") { // IMU data
@@ -143,11 +152,11 @@ impl Vuze {
                                 last_timestamp = ts as f64 / 1000.0;
 
                                 if gx.abs() > 360.0 || gy.abs() > 360.0 || gz.abs() > 360.0 {
-                                    log::warn!("Invalid gyro value {gx:.4} {gy:.4} {gz:.4}");
+                                    crate::parse_warning!("Invalid gyro value {gx:.4} {gy:.4} {gz:.4}");
                                     continue;
                                 }
                                 if ax.abs() > 10.0 || ay.abs() > 10.0 || az.abs() > 10.0 {
-                                    log::warn!("Invalid accel value {ax:.4} {ay:.4} {az:.4}");
+                                    crate::parse_warning!("Invalid accel value {ax:.4} {ay:.4} {az:.4}");
                                     continue;
                                 }
 
@@ -178,7 +187,7 @@ impl Vuze {
                                 // println!("0x20: {_unkh1} {_unkh2} {} | {:.4} {:.4} | {} {} {} {}", _ts, _unkf1, _unkf2, _unk1, _unk2, _unk3, _unk4);
                             },
                             _ => {
-                                log::warn!("Unknown Vuze tag: {:04x}", len);
+                                crate::parse_warning!("Unknown Vuze tag: {:04x}", len);
                                 break;
                             }
                         }
@@ -199,44 +208,78 @@ impl Vuze {
         util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
         util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
 
-        Ok(vec![
+        Ok(util::split_samples_by_time(vec![
             SampleInfo { timestamp_ms: 0.0, duration_ms: last_timestamp, tag_map: Some(map), ..Default::default() }
-        ])
+        ], 1000.0))
+    }
+
+    // `CAM_0` ... `CAM_7` entries of the calibration, ordered by the camera number
+    fn cameras(data: &serde_json::Value) -> Vec<(String, &serde_json::Value)> {
+        let mut ret = data.as_object().into_iter().flatten()
+            .filter_map(|(k, v)| Some((k.strip_prefix("CAM_")?.parse::<u32>().ok()?, k.clone(), v)))
+            .collect::<Vec<_>>();
+        ret.sort_by_key(|x| x.0);
+        ret.into_iter().map(|(_, k, v)| (k, v)).collect()
+    }
+
+    // Values of an OpenCV matrix (`rows`, `cols` and `data`) or a plain list
+    fn matrix(v: Option<&serde_json::Value>) -> Option<Vec<f64>> {
+        let v = v?;
+        let data = v.get("data").unwrap_or(v).as_array()?;
+        Some(data.iter().filter_map(|x| x.as_f64()).collect())
     }
 
-    fn get_lens_profile(&self, data: &serde_json::Value, width: i32, height: i32) -> Option<serde_json::Value> {
+    // `R` and `T` of each camera map the rig coordinates to the camera coordinates: `x_cam = R * x_rig + T`.
+    // For stitching, the pose relative to CAM_0 is also computed: `R_rel = R * R_0^T` and `T_rel = T - R_rel * T_0`
+    fn rig_extrinsics(cameras: &[(String, &serde_json::Value)]) -> Vec<serde_json::Value> {
+        let pose = |cam: &serde_json::Value| -> Option<([[f64; 3]; 3], [f64; 3])> {
+            let r = Self::matrix(cam.get("R"))?;
+            let t = Self::matrix(cam.get("T").or_else(|| cam.get("t")))?;
+            if r.len() != 9 || t.len() != 3 { return None; }
+            Some(([[r[0], r[1], r[2]], [r[3], r[4], r[5]], [r[6], r[7], r[8]]], [t[0], t[1], t[2]]))
+        };
+        let Some((r0, t0)) = cameras.first().and_then(|x| pose(x.1)) else { return Vec::new(); };
+
+        cameras.iter().filter_map(|(name, cam)| {
+            let (r, t) = pose(cam)?;
+            let mut r_rel = [[0.0; 3]; 3];
+            for (i, row) in r_rel.iter_mut().enumerate() {
+                for (j, v) in row.iter_mut().enumerate() {
+                    *v = (0..3).map(|k| r[i][k] * r0[j][k]).sum();
+                }
+            }
+            let t_rel: Vec<f64> = (0..3).map(|i| t[i] - (0..3).map(|k| r_rel[i][k] * t0[k]).sum::<f64>()).collect();
+            Some(serde_json::json!({
+                "camera": name,
+                "rotation": r,
+                "translation": t,
+                "rotation_from_cam0": r_rel,
+                "translation_from_cam0": t_rel,
+            }))
+        }).collect()
+    }
+
+    fn get_lens_profile(&self, cam: &serde_json::Value, width: i32, height: i32) -> Option<serde_json::Value> {
         let model = self.model.clone()?;
-        let matrix = data.get("CAM_0")?.get("K")?.get("data")?.as_array()?.into_iter().filter_map(|x| x.as_f64()).collect::<Vec<f64>>();
-        let coeffs = data.get("CAM_0")?.get("DistortionCoeffs")?.as_array()?.into_iter().filter_map(|x| x.as_f64()).collect::<Vec<f64>>();
+        let matrix = Self::matrix(cam.get("K"))?;
+        let coeffs = Self::matrix(cam.get("DistortionCoeffs"))?;
         if matrix.len() != 9 { return None; }
         if coeffs.len() < 4  { return None; }
 
-        Some(serde_json::json!({
-            "calibrated_by": "Vuze",
-            "camera_brand": "Vuze",
-            "camera_model": model,
-            "calib_dimension": { "w": width, "h": height },
-            "orig_dimension":  { "w": width, "h": height },
-            "frame_readout_time": 0.0,
-            "official": true,
-            "fisheye_params": {
-              "camera_matrix": [
+        // Per-camera image size, if the calibration has it
+        let (width, height) = match Self::matrix(cam.get("ImageSize")).as_deref() {
+            Some([w, h]) => (*w as i32, *h as i32),
+            _ => (width, height)
+        };
+
+        Some(LensProfile::new("Vuze", &model, width as u32, height as u32)
+            .with_frame_readout_time(Some(0.0))
+            .with_camera_matrix([
                 [ matrix[0], matrix[1], matrix[2] ],
                 [ matrix[3], matrix[4], matrix[5] ],
                 [ matrix[6], matrix[7], matrix[8] ]
-              ],
-              "distortion_coeffs": coeffs
-            },
-            "sync_settings": {
-              "initial_offset": 0,
-              "initial_offset_inv": false,
-              "search_size": 0.5,
-              "max_sync_points": 5,
-              "every_nth_frame": 1,
-              "time_per_syncpoint": 0.6,
-              "do_autosync": false
-            },
-            "calibrator_version": "---"
-        }))
+            ])
+            .with_distortion("opencv_fisheye", coeffs, false)
+            .to_json())
     }
 }
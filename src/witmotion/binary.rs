@@ -6,141 +6,249 @@ use std::io::*;
 use crate::tags_impl::*;
 use crate::*;
 use byteorder::{ReadBytesExt, BigEndian, LittleEndian};
+use std::collections::BTreeMap;
 
 pub fn parse<T: Read + Seek>(stream: &mut T, _size: usize) -> Result<Vec<SampleInfo>> {
     let mut stream = std::io::BufReader::new(stream);
 
-    let mut gyro = Vec::new();
-    let mut accl = Vec::new();
-    let mut angl = Vec::new();
-    let mut magn = Vec::new();
-    let mut quat = Vec::new();
+    let mut decoder = Decoder::new();
+    while decoder.packet(&mut stream).is_ok() { }
 
-    let mut has_any_time = false;
+    Ok(vec![
+        SampleInfo { timestamp_ms: decoder.first_timestamp, duration_ms: decoder.last_timestamp, tag_map: Some(decoder.tag_map()), ..Default::default() }
+    ])
+}
+
+/// Log of an RS485 bus with several sensors, which are read by the master with Modbus RTU.
+/// Each request is the device address, 0x03, first register and register count (big endian) and CRC,
+/// answered with the address, 0x03, byte count, the registers and CRC. The readings are split per device address, one sample each
+pub fn parse_modbus<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleInfo>> {
+    let mut bytes = Vec::with_capacity(size);
+    stream.read_to_end(&mut bytes)?;
+
+    let mut requests = BTreeMap::<u8, (u16, u16)>::new();
+    let mut devices = BTreeMap::<u8, Decoder>::new();
+    let mut pos = 0;
+    while pos + 5 <= bytes.len() {
+        let (address, function) = (bytes[pos], bytes[pos + 1]);
+        if function == 0x03 {
+            if let Some(frame) = bytes.get(pos..pos + 8).filter(|x| is_modbus_request(x)) {
+                requests.insert(address, (u16::from_be_bytes([frame[2], frame[3]]), u16::from_be_bytes([frame[4], frame[5]])));
+                pos += 8;
+                continue;
+            }
+            let len = bytes[pos + 2] as usize;
+            if let Some(frame) = bytes.get(pos..pos + 5 + len).filter(|x| modbus_crc(&x[..3 + len]) == u16::from_le_bytes([x[3 + len], x[4 + len]])) {
+                // Registers of a response can be decoded only if the request with the first register was logged
+                if let Some((first, _)) = requests.remove(&address).filter(|x| x.1 as usize * 2 == len) {
+                    let values = frame[3..3 + len].chunks_exact(2).map(|x| i16::from_be_bytes([x[0], x[1]])).collect::<Vec<_>>();
+                    devices.entry(address).or_insert_with(Decoder::new).registers(first, &values);
+                }
+                pos += 5 + len;
+                continue;
+            }
+        }
+        pos += 1;
+    }
+
+    Ok(devices.into_iter().map(|(address, mut decoder)| {
+        let mut map = decoder.tag_map();
+        util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceID".into()), "Device ID", String, |v| v.to_string(), format!("0x{address:02x}"), vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceAddress".into()), "Device address", u8, |v| format!("0x{:02x}", v), address, vec![]));
+        SampleInfo { duration_ms: decoder.last_timestamp * 1000.0, tag_map: Some(map), ..Default::default() }
+    }).collect())
+}
+
+pub(crate) fn is_modbus_request(frame: &[u8]) -> bool {
+    frame.len() >= 8 && frame[1] == 0x03 && modbus_crc(&frame[..6]) == u16::from_le_bytes([frame[6], frame[7]])
+}
+
+// CRC-16/MODBUS, stored little endian after the frame
+fn modbus_crc(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &x| {
+        (0..8).fold(crc ^ x as u16, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 })
+    })
+}
+
+const DEFAULT_STEP: f64 = 1.0 / 200.0; // 200 Hz
+
+/// Incremental decoder of the packets, shared by `parse` and the `live` module.
+/// Decoded readings are accumulated until `tag_map` takes them
+pub(crate) struct Decoder {
+    gyro: Vec<TimeVector3<f64>>,
+    accl: Vec<TimeVector3<f64>>,
+    angl: Vec<TimeVector3<f64>>,
+    magn: Vec<TimeVector3<i64, f64>>,
+    quat: Vec<TimeQuaternion<f64>>,
+    has_any_time: bool,
+    last_timestamp: f64,
+    first_timestamp: f64,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            gyro: Vec::new(),
+            accl: Vec::new(),
+            angl: Vec::new(),
+            magn: Vec::new(),
+            quat: Vec::new(),
+            has_any_time: false,
+            last_timestamp: -DEFAULT_STEP,
+            first_timestamp: 0.0,
+        }
+    }
 
-    let default_step = 1.0 / 200.0; // 200 Hz
+    #[cfg(feature = "live")]
+    pub fn has_readings(&self) -> bool {
+        !self.gyro.is_empty() || !self.accl.is_empty() || !self.angl.is_empty() || !self.magn.is_empty() || !self.quat.is_empty()
+    }
 
-    let mut last_timestamp = -default_step;
-    let mut first_timestamp = 0.0;
-    while let Ok(tag) = stream.read_u16::<BigEndian>() {
+    /// Decodes one packet: tag (0x55 and the type), 8 bytes of data and the checksum.
+    /// Packets with invalid checksum are skipped, `UnexpectedEof` means the packet is incomplete
+    pub fn packet<T: Read>(&mut self, stream: &mut T) -> Result<()> {
+        let tag = stream.read_u16::<BigEndian>()?;
+        if !matches!(tag, 0x5550..=0x5554 | 0x5559) {
+            crate::parse_warning!("Unknown tag! 0x{:02x}", tag);
+            return Ok(());
+        }
+        let mut d = match checksum(tag, stream, 8) {
+            Ok(d) => d,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(e),
+            Err(_) => return Ok(())
+        };
         match tag {
             0x5550 => { // Time Output
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    has_any_time = true;
-                    let yy = d.read_u8()? as i32 + 2000;
-                    let mm = d.read_u8()? as u32;
-                    let dd = d.read_u8()? as u32;
-                    let h  = d.read_u8()? as u32;
-                    let m  = d.read_u8()? as u32;
-                    let s  = d.read_u8()? as u32;
-                    let ms = d.read_u16::<LittleEndian>()? as u32;
-
-                    last_timestamp = chrono::NaiveDate::from_ymd_opt(yy, mm, dd).and_then(|x| x.and_hms_milli_opt(h, m, s, ms)).unwrap_or_default().and_utc().timestamp_millis() as f64 / 1000.0;
-
-                    if first_timestamp == 0.0 {
-                        first_timestamp = last_timestamp;
-                    }
-                    last_timestamp = last_timestamp - first_timestamp;
+                self.has_any_time = true;
+                let yy = d.read_u8()? as i32 + 2000;
+                let mm = d.read_u8()? as u32;
+                let dd = d.read_u8()? as u32;
+                let h  = d.read_u8()? as u32;
+                let m  = d.read_u8()? as u32;
+                let s  = d.read_u8()? as u32;
+                let ms = d.read_u16::<LittleEndian>()? as u32;
+
+                self.last_timestamp = chrono::NaiveDate::from_ymd_opt(yy, mm, dd).and_then(|x| x.and_hms_milli_opt(h, m, s, ms)).unwrap_or_default().and_utc().timestamp_millis() as f64 / 1000.0;
+
+                if self.first_timestamp == 0.0 {
+                    self.first_timestamp = self.last_timestamp;
                 }
+                self.last_timestamp = self.last_timestamp - self.first_timestamp;
             }
             0x5551 => { // Acceleration Output
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    if !has_any_time {
-                        last_timestamp += default_step;
-                    }
-                    accl.push(TimeVector3 {
-                        t: last_timestamp as f64,
-                        x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0,
-                        y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0,
-                        z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0
-                    });
-                    let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
+                if !self.has_any_time {
+                    self.last_timestamp += DEFAULT_STEP;
                 }
+                self.accl.push(TimeVector3 {
+                    t: self.last_timestamp as f64,
+                    x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0,
+                    y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0,
+                    z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 16.0
+                });
+                let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
             }
             0x5552 => { // Angular Velocity Output (gyro)
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    gyro.push(TimeVector3 {
-                        t: last_timestamp as f64,
-                        x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0,
-                        y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0,
-                        z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0
-                    });
-                    let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
-                }
+                self.gyro.push(TimeVector3 {
+                    t: self.last_timestamp as f64,
+                    x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0,
+                    y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0,
+                    z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 2000.0
+                });
+                let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
             }
             0x5553 => { // Angle Output
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    angl.push(TimeVector3 {
-                        t: last_timestamp as f64,
-                        x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0, // Roll
-                        y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0, // Pitch
-                        z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0  // Yaw
-                    });
-                    let _v = d.read_u16::<LittleEndian>()?; // version
-                }
+                self.angl.push(TimeVector3 {
+                    t: self.last_timestamp as f64,
+                    x: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0, // Roll
+                    y: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0, // Pitch
+                    z: d.read_i16::<LittleEndian>()? as f64 / 32768.0 * 180.0  // Yaw
+                });
+                let _v = d.read_u16::<LittleEndian>()?; // version
             }
             0x5554 => { // Magnetic Output
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    magn.push(TimeVector3 {
-                        t: last_timestamp as f64,
-                        x: d.read_i16::<LittleEndian>()? as i64,
-                        y: d.read_i16::<LittleEndian>()? as i64,
-                        z: d.read_i16::<LittleEndian>()? as i64
-                    });
-                    let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
-                }
+                self.magn.push(TimeVector3 {
+                    t: self.last_timestamp as f64,
+                    x: d.read_i16::<LittleEndian>()? as i64,
+                    y: d.read_i16::<LittleEndian>()? as i64,
+                    z: d.read_i16::<LittleEndian>()? as i64
+                });
+                let _t = d.read_u16::<LittleEndian>()? / 100; // Temperature (°C)
             }
             0x5559 => { // Quaternion
-                if let Ok(mut d) = checksum(tag, &mut stream, 8) {
-                    quat.push(TimeQuaternion {
-                        t: last_timestamp as f64 * 1000.0,
-                        v: Quaternion {
-                            w: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
-                            x: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
-                            y: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
-                            z: d.read_i16::<LittleEndian>()? as f64 / 32768.0
-                        }
-                    });
-                }
-            }
-            _ => {
-                log::warn!("Unknown tag! 0x{:02x}", tag);
+                self.quat.push(TimeQuaternion {
+                    t: self.last_timestamp as f64 * 1000.0,
+                    v: Quaternion {
+                        w: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
+                        x: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
+                        y: d.read_i16::<LittleEndian>()? as f64 / 32768.0,
+                        z: d.read_i16::<LittleEndian>()? as f64 / 32768.0
+                    }
+                });
             }
+            _ => { }
+        }
+        Ok(())
+    }
+
+    /// Decodes the registers read from one device on an RS485 bus, `values` start at register `first`.
+    /// Every read is one reading, `DEFAULT_STEP` after the previous one. The scales are the same as of the packets
+    pub fn registers(&mut self, first: u16, values: &[i16]) {
+        self.last_timestamp += DEFAULT_STEP;
+        let t = self.last_timestamp;
+        let raw = |r: u16| values.get(r.checked_sub(first)? as usize).copied();
+        let reg = |r: u16| raw(r).map(|x| x as f64 / 32768.0);
+        let vec3 = |r: u16, scale: f64| Some(TimeVector3 { t, x: reg(r)? * scale, y: reg(r + 1)? * scale, z: reg(r + 2)? * scale });
+
+        self.accl.extend(vec3(0x34, 16.0));
+        self.gyro.extend(vec3(0x37, 2000.0));
+        self.angl.extend(vec3(0x3D, 180.0));
+        if let (Some(x), Some(y), Some(z)) = (raw(0x3A), raw(0x3B), raw(0x3C)) {
+            self.magn.push(TimeVector3 { t, x: x as i64, y: y as i64, z: z as i64 });
+        }
+        if let (Some(w), Some(x), Some(y), Some(z)) = (reg(0x51), reg(0x52), reg(0x53), reg(0x54)) {
+            self.quat.push(TimeQuaternion { t: t * 1000.0, v: Quaternion { w, x, y, z } });
         }
     }
 
-    let mut map = GroupedTagMap::new();
+    /// Takes the decoded readings
+    pub fn tag_map(&mut self) -> GroupedTagMap {
+        let accl = std::mem::take(&mut self.accl);
+        let gyro = std::mem::take(&mut self.gyro);
+        let angl = std::mem::take(&mut self.angl);
+        let magn = std::mem::take(&mut self.magn);
+        let quat = std::mem::take(&mut self.quat);
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
+        let mut map = GroupedTagMap::new();
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
 
-    let imu_orientation = "ZYx";
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_i64f64, |v| format!("{:?}", v), magn, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()));
+        let imu_orientation = "ZYx";
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()),        TagId::Data, "Angle data", Vec_TimeVector3_f64, |v| format!("{:?}", v), angl, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()),        TagId::Unit, "Angle unit", String, |v| v.to_string(), "deg".into(),  Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_i64f64, |v| format!("{:?}", v), magn, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,   TagId::Data, "Quaternion data",   Vec_TimeQuaternion_f64,  |v| format!("{:?}", v), quat, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()),        TagId::Data, "Angle data", Vec_TimeVector3_f64, |v| format!("{:?}", v), angl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()),        TagId::Unit, "Angle unit", String, |v| v.to_string(), "deg".into(),  Vec::new()));
 
-    Ok(vec![
-        SampleInfo { timestamp_ms: first_timestamp as f64, duration_ms: last_timestamp as f64, tag_map: Some(map), ..Default::default() }
-    ])
+        util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,   TagId::Data, "Quaternion data",   Vec_TimeQuaternion_f64,  |v| format!("{:?}", v), quat, vec![]));
+        map
+    }
 }
 
-fn checksum<T: Read + Seek>(tag: u16, stream: &mut T, item_size: u64) -> Result<Cursor<Vec<u8>>> {
+fn checksum<T: Read>(tag: u16, stream: &mut T, item_size: u64) -> Result<Cursor<Vec<u8>>> {
     let mut buf = vec![0u8; item_size as usize];
     stream.read_exact(&mut buf)?;
     let sum  = stream.read_u8()?;
 
     let init: u8 = ((tag & 0xff) as u8) + ((tag >> 8) & 0xff) as u8;
     let calculated_sum = buf.iter().fold(init, |sum, &x| sum.wrapping_add(x));
+    crate::util::report_checksum(calculated_sum == sum);
 
     if calculated_sum == sum {
         Ok(Cursor::new(buf))
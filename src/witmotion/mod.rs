@@ -7,7 +7,7 @@ use std::sync::{ Arc, atomic::AtomicBool };
 use crate::*;
 use memchr::memmem;
 
-mod binary;
+pub(crate) mod binary;
 mod txt;
 mod txt2;
 mod txt3;
@@ -17,6 +17,7 @@ mod txt4;
 enum Format {
     #[default]
     Binary,
+    Modbus,
     Txt,
     Txt2,
     Txt3,
@@ -50,6 +51,10 @@ impl WitMotion {
         if buffer.len() > 11 && (buffer[0..2] == [0x55, 0x50] || buffer[0..2] == [0x55, 0x51]) && buffer[11] == 0x55 {
             return Some(Self { format: Format::Binary, model: None });
         }
+        // RS485 bus log, starting with a register read request
+        if binary::is_modbus_request(buffer) {
+            return Some(Self { format: Format::Modbus, model: None });
+        }
         if memmem::find(buffer, b"Time(s)").is_some() && memmem::find(buffer, b"AngleX(deg)").is_some() {
             return Some(Self { format: Format::Txt, model: None });
         }
@@ -68,6 +73,7 @@ impl WitMotion {
     pub fn parse<T: Read + Seek, F: Fn(f64)>(&mut self, stream: &mut T, size: usize, _progress_cb: F, _cancel_flag: Arc<AtomicBool>) -> Result<Vec<SampleInfo>> {
         match self.format {
             Format::Binary => binary::parse(stream, size),
+            Format::Modbus => binary::parse_modbus(stream, size),
             Format::Txt    => txt::parse(stream, size),
             Format::Txt2   => txt2::parse(stream, size),
             Format::Txt3   => txt3::parse(stream, size),
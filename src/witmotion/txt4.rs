@@ -9,14 +9,8 @@ use crate::*;
 pub fn parse<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleInfo>> {
     let mut headers: Option<Vec<String>> = None;
 
-    let mut gyro = Vec::new();
-    let mut accl = Vec::new();
-    let mut angl = Vec::new();
-    let mut magn = Vec::new();
-    let mut quat = Vec::new();
-
-    let mut last_timestamp = 0.0;
-    let mut first_timestamp = 0.0;
+    // Several sensors can be recorded on one bus, in that case each row has the device name with its address, eg. `WT901(50)`
+    let mut devices = std::collections::BTreeMap::<String, Device>::new();
 
     let mut buffer = Vec::with_capacity(size);
     stream.read_to_end(&mut buffer).unwrap();
@@ -51,10 +45,12 @@ pub fn parse<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleIn
 
             if let Ok(ts) = chrono::NaiveDateTime::parse_from_str(map.get("Chip Time()").unwrap_or(&""), "%Y-%m-%d %-H:%-M:%-S:%3f") {
                 let ts = ts.and_utc().timestamp_millis() as f64 / 1000.0;
-                if first_timestamp == 0.0 {
-                    first_timestamp = ts;
+                let device = devices.entry(map.get("Device name").map(|x| x.to_string()).unwrap_or_default()).or_default();
+                if device.first_timestamp == 0.0 {
+                    device.first_timestamp = ts;
                 }
-                last_timestamp = ts;
+                device.last_timestamp = ts;
+                let Device { gyro, accl, angl, magn, quat, .. } = device;
 
                 crate::try_block!({
                     accl.push(TimeVector3 {
@@ -105,27 +101,50 @@ pub fn parse<T: Read + Seek>(stream: &mut T, size: usize) -> Result<Vec<SampleIn
         }
     }
 
-    let mut map = GroupedTagMap::new();
+    if devices.is_empty() {
+        devices.insert(String::new(), Device::default());
+    }
+    let with_address = devices.len() > 1 || devices.keys().any(|x| !x.is_empty());
+
+    Ok(devices.into_iter().map(|(name, Device { gyro, accl, angl, magn, quat, first_timestamp, last_timestamp })| {
+        let mut map = GroupedTagMap::new();
+
+        if with_address {
+            util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceID".into()), "Device ID", String, |v| v.to_string(), name.clone(), vec![]));
+            if let Some(address) = name.rsplit_once('(').and_then(|(_, x)| u8::from_str_radix(x.trim_end_matches(')'), 16).ok()) {
+                util::insert_tag(&mut map, tag!(parsed GroupId::Default, TagId::Custom("DeviceAddress".into()), "Device address", u8, |v| format!("0x{:02x}", v), address, vec![]));
+            }
+        }
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Data, "Accelerometer data", Vec_TimeVector3_f64, |v| format!("{:?}", v), accl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Data, "Gyroscope data",     Vec_TimeVector3_f64, |v| format!("{:?}", v), gyro, vec![]));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Unit, "Accelerometer unit", String, |v| v.to_string(), "g".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Unit, "Gyroscope unit",     String, |v| v.to_string(), "deg/s".into(), Vec::new()));
 
-    let imu_orientation = "ZYx";
-    util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
+        let imu_orientation = "ZYx";
+        util::insert_tag(&mut map, tag!(parsed GroupId::Gyroscope,     TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Accelerometer, TagId::Orientation, "IMU orientation", String, |v| v.to_string(), imu_orientation.into(), Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_i64f64, |v| format!("{:?}", v), magn, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Data, "Magnetometer data", Vec_TimeVector3_i64f64, |v| format!("{:?}", v), magn, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Magnetometer,  TagId::Unit, "Magnetometer unit", String, |v| v.to_string(), "μT".into(), Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()), TagId::Data, "Angle data", Vec_TimeVector3_f64, |v| format!("{:?}", v), angl, vec![]));
-    util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()), TagId::Unit, "Angle unit", String, |v| v.to_string(), "deg".into(),  Vec::new()));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()), TagId::Data, "Angle data", Vec_TimeVector3_f64, |v| format!("{:?}", v), angl, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Custom("Angle".into()), TagId::Unit, "Angle unit", String, |v| v.to_string(), "deg".into(),  Vec::new()));
 
-    util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,             TagId::Data, "Quaternion data",   Vec_TimeArray4_f64,  |v| format!("{:?}", v), quat, vec![]));
+        util::insert_tag(&mut map, tag!(parsed GroupId::Quaternion,             TagId::Data, "Quaternion data",   Vec_TimeArray4_f64,  |v| format!("{:?}", v), quat, vec![]));
 
-    Ok(vec![
         SampleInfo { timestamp_ms: first_timestamp as f64, duration_ms: (last_timestamp - first_timestamp) as f64, tag_map: Some(map), ..Default::default() }
-    ])
+    }).collect())
+}
+
+#[derive(Default)]
+struct Device {
+    gyro: Vec<TimeVector3<f64>>,
+    accl: Vec<TimeVector3<f64>>,
+    angl: Vec<TimeVector3<f64>>,
+    magn: Vec<TimeVector3<i64, f64>>,
+    quat: Vec<TimeArray4<f64>>,
+    first_timestamp: f64,
+    last_timestamp: f64,
 }